@@ -1,3 +1,7 @@
+use std::fmt::{self, Display};
+
+use units::Unit;
+
 #[derive(Debug, Clone)]
 pub enum ReplTree {
     Expr(Expr),
@@ -19,20 +23,359 @@ pub struct Expr {
 #[derive(Debug, Clone)]
 pub enum ExprType {
     Binary(BinOp, Box<Expr>, Box<Expr>),
-    NumLit(f64),
+    /// The value, and the literal as written, which exact modes read without
+    /// rounding it to a float.
+    NumLit(f64, String),
+    /// The imaginary part of an imaginary literal such as `4i`.
+    ImagLit(f64),
+    /// A literal with a unit suffix, like `9.81 m/s^2`.
+    WithUnit(Box<Expr>, Unit),
+    /// `x to km/h`.
+    ConvertTo(Box<Expr>, Unit),
+    BoolLit(bool),
+    StrLit(String),
     Var(String),
+    Unary(UnOp, Box<Expr>),
     Assign(String, Box<Expr>),
     FuncCall(String, Vec<Expr>),
-    FuncDef(String, Vec<String>, Block)
+    FuncDef(String, Vec<String>, Block),
+    /// A missing else branch is `Block::Empty`.
+    If(Box<Expr>, Block, Block),
+    ListLit(Vec<Expr>),
+    Index(Box<Expr>, Box<Expr>),
+    /// `xs[a:b]`, where either bound may be left out.
+    Slice(Box<Expr>, Option<Box<Expr>>, Option<Box<Expr>>),
+    RecordLit(Vec<(String, Expr)>),
+    Field(Box<Expr>, String),
+    /// `p.a.b = value`: the variable, the path of fields, and the value.
+    AssignField(String, Vec<String>, Box<Expr>),
+    TupleLit(Vec<Expr>),
+    /// `(a, b) = value` or `[a, b] = value`.
+    Destructure(Pattern, Box<Expr>),
+    /// `solve lhs = rhs for x`: the two sides and the unknown.
+    Solve(Box<Expr>, Box<Expr>, String),
+    /// `sum(i, a, b, term)` or `prod(i, a, b, term)`: the operator that
+    /// combines the terms, the index, its bounds and the term, which is
+    /// evaluated once for each `i` from `a` to `b`.
+    Iterated(BinOp, String, Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+/// The name that `ExprType::Iterated` with `op` is written with.
+pub fn iterated_name(op: BinOp) -> &'static str {
+    if op == BinOp::Times {
+        "prod"
+    } else {
+        "sum"
+    }
 }
 
-#[derive(Debug, Copy, Clone)]
+/// The arguments of `sum(i, a, b, term)` read as an ordinary call, for
+/// when a function of that name hides the notation.
+pub(crate) fn iterated_args(
+    line: usize,
+    index: &str,
+    lo: &Expr,
+    hi: &Expr,
+    term: &Expr,
+) -> Vec<Expr> {
+    let index = Expr {
+        line,
+        expr_type: ExprType::Var(String::from(index)),
+    };
+    vec![index, lo.clone(), hi.clone(), term.clone()]
+}
+
+/// The left-hand side of a destructuring assignment. Tuple and list
+/// patterns both match tuples and lists.
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Name(String),
+    Tuple(Vec<Pattern>),
+    List(Vec<Pattern>),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum BinOp {
     Plus,
     Minus,
     Times,
     Slash,
-    Exp
+    Exp,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    PlusMinus,
+}
+
+impl BinOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinOp::Plus => "+",
+            BinOp::Minus => "-",
+            BinOp::Times => "*",
+            BinOp::Slash => "/",
+            BinOp::Exp => "^",
+            BinOp::Equal => "==",
+            BinOp::NotEqual => "!=",
+            BinOp::Less => "<",
+            BinOp::LessEqual => "<=",
+            BinOp::Greater => ">",
+            BinOp::GreaterEqual => ">=",
+            BinOp::PlusMinus => "±",
+        }
+    }
+
+    pub fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            BinOp::Equal
+                | BinOp::NotEqual
+                | BinOp::Less
+                | BinOp::LessEqual
+                | BinOp::Greater
+                | BinOp::GreaterEqual
+        )
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum UnOp {
+    Neg,
+    Not,
+}
+
+impl UnOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            UnOp::Neg => "-",
+            UnOp::Not => "!",
+        }
+    }
+}
+
+/// How tightly each kind of expression binds when written as source. An
+/// operand that binds more loosely than its place needs is parenthesized.
+fn precedence(e: &Expr) -> u8 {
+    match e.expr_type {
+        ExprType::Assign(..)
+        | ExprType::AssignField(..)
+        | ExprType::Destructure(..)
+        | ExprType::FuncDef(..)
+        | ExprType::If(..)
+        | ExprType::Solve(..) => 0,
+        ExprType::ConvertTo(..) => 1,
+        ExprType::Binary(op, ..) => match op {
+            _ if op.is_comparison() => 2,
+            BinOp::PlusMinus => 3,
+            BinOp::Plus | BinOp::Minus => 4,
+            BinOp::Times | BinOp::Slash => 5,
+            _ => 7,
+        },
+        // A unit binds to its number before `^` does, so `(2 m)^2` needs
+        // its parentheses.
+        ExprType::Unary(..) | ExprType::WithUnit(..) => 6,
+        ExprType::NumLit(_, ref lit) if lit.starts_with('-') => 6,
+        ExprType::Index(..) | ExprType::Slice(..) | ExprType::Field(..) => 8,
+        _ => 9,
+    }
+}
+
+fn fmt_operand(f: &mut fmt::Formatter, e: &Expr, min: u8) -> fmt::Result {
+    if precedence(e) < min {
+        write!(f, "({})", e)
+    } else {
+        write!(f, "{}", e)
+    }
+}
+
+/// The right operand of `^`, which may only be negated without parentheses.
+fn fmt_exponent(f: &mut fmt::Formatter, e: &Expr) -> fmt::Result {
+    match e.expr_type {
+        ExprType::Unary(UnOp::Neg, ref inner) => {
+            write!(f, "-")?;
+            fmt_exponent(f, inner)
+        }
+        ExprType::NumLit(_, ref lit) if lit.starts_with('-') => write!(f, "{}", lit),
+        _ => fmt_operand(f, e, 8),
+    }
+}
+
+fn fmt_list<T: Display>(f: &mut fmt::Formatter, items: &[T]) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+/// Writes the expression as source that parses back to the same tree.
+impl Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.expr_type {
+            ExprType::Binary(op, ref a, ref b) => {
+                let p = precedence(self);
+                fmt_operand(f, a, p)?;
+                if op == BinOp::Exp {
+                    write!(f, "^")?;
+                    fmt_exponent(f, b)
+                } else {
+                    write!(f, " {} ", op.symbol())?;
+                    fmt_operand(f, b, p + 1)
+                }
+            }
+            ExprType::NumLit(_, ref lit) => write!(f, "{}", lit),
+            ExprType::ImagLit(x) => write!(f, "{}i", x),
+            ExprType::WithUnit(ref e, ref unit) => write!(f, "{} {}", e, unit),
+            ExprType::ConvertTo(ref e, ref unit) => {
+                fmt_operand(f, e, 1)?;
+                write!(f, " to {}", unit)
+            }
+            ExprType::BoolLit(b) => write!(f, "{}", b),
+            ExprType::StrLit(ref s) => {
+                write!(f, "\"")?;
+                for c in s.chars() {
+                    match c {
+                        '\n' => write!(f, "\\n")?,
+                        '\t' => write!(f, "\\t")?,
+                        '"' | '\\' => write!(f, "\\{}", c)?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            ExprType::Var(ref name) => write!(f, "{}", name),
+            ExprType::Unary(op, ref e) => {
+                write!(f, "{}", op.symbol())?;
+                fmt_operand(f, e, 6)
+            }
+            ExprType::Assign(ref name, ref e) => write!(f, "{} = {}", name, e),
+            ExprType::FuncCall(ref name, ref args) => {
+                write!(f, "{}(", name)?;
+                fmt_list(f, args)?;
+                write!(f, ")")
+            }
+            ExprType::FuncDef(ref name, ref params, ref body) => {
+                write!(f, "def {}(", name)?;
+                fmt_list(f, params)?;
+                write!(f, ") {}", body)
+            }
+            ExprType::If(ref cond, ref then_block, ref else_block) => {
+                write!(f, "if {} {}", cond, then_block)?;
+                match *else_block {
+                    Block::Empty => Ok(()),
+                    Block::Exprs(_) => write!(f, " else {}", else_block),
+                }
+            }
+            ExprType::ListLit(ref items) => {
+                write!(f, "[")?;
+                fmt_list(f, items)?;
+                write!(f, "]")
+            }
+            ExprType::Index(ref e, ref index) => {
+                fmt_operand(f, e, 8)?;
+                write!(f, "[{}]", index)
+            }
+            ExprType::Slice(ref e, ref start, ref end) => {
+                fmt_operand(f, e, 8)?;
+                write!(f, "[")?;
+                if let Some(ref start) = *start {
+                    write!(f, "{}", start)?;
+                }
+                write!(f, ":")?;
+                if let Some(ref end) = *end {
+                    write!(f, "{}", end)?;
+                }
+                write!(f, "]")
+            }
+            ExprType::RecordLit(ref fields) => {
+                write!(f, "{{")?;
+                for (i, (name, e)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", name, e)?;
+                }
+                write!(f, "}}")
+            }
+            ExprType::Field(ref e, ref name) => {
+                fmt_operand(f, e, 8)?;
+                write!(f, ".{}", name)
+            }
+            ExprType::AssignField(ref name, ref path, ref e) => {
+                write!(f, "{}", name)?;
+                for field in path.iter() {
+                    write!(f, ".{}", field)?;
+                }
+                write!(f, " = {}", e)
+            }
+            ExprType::TupleLit(ref items) => {
+                write!(f, "(")?;
+                fmt_list(f, items)?;
+                if items.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")
+            }
+            ExprType::Destructure(ref pattern, ref e) => write!(f, "{} = {}", pattern, e),
+            ExprType::Solve(ref lhs, ref rhs, ref var) => {
+                write!(f, "solve ")?;
+                fmt_operand(f, lhs, 3)?;
+                write!(f, " = ")?;
+                fmt_operand(f, rhs, 3)?;
+                write!(f, " for {}", var)
+            }
+            ExprType::Iterated(op, ref index, ref lo, ref hi, ref term) => write!(
+                f,
+                "{}({}, {}, {}, {})",
+                iterated_name(op),
+                index,
+                lo,
+                hi,
+                term
+            ),
+        }
+    }
+}
+
+impl Display for Block {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Block::Empty => write!(f, "{{}}"),
+            Block::Exprs(ref exprs) => {
+                write!(f, "{{ ")?;
+                for e in exprs.iter() {
+                    write!(f, "{} ", e)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Pattern::Name(ref name) => write!(f, "{}", name),
+            Pattern::Tuple(ref items) => {
+                write!(f, "(")?;
+                fmt_list(f, items)?;
+                if items.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")
+            }
+            Pattern::List(ref items) => {
+                write!(f, "[")?;
+                fmt_list(f, items)?;
+                write!(f, "]")
+            }
+        }
+    }
 }
 
 /*
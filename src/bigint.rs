@@ -0,0 +1,599 @@
+use std::cmp::Ordering;
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+/// An arbitrary-precision integer, stored as a sign and a magnitude of
+/// base-2^32 digits with the least significant first. Zero has no digits and
+/// is never negative.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigInt {
+    neg: bool,
+    mag: Vec<u32>,
+}
+
+const BASE: u64 = 1 << 32;
+
+/// The largest power of ten that fits in a digit, for converting to and from
+/// decimal.
+const DEC_BASE: u32 = 1_000_000_000;
+const DEC_DIGITS: usize = 9;
+
+impl BigInt {
+    pub fn zero() -> Self {
+        BigInt {
+            neg: false,
+            mag: Vec::new(),
+        }
+    }
+
+    pub fn one() -> Self {
+        BigInt::from(1)
+    }
+
+    fn from_mag(neg: bool, mut mag: Vec<u32>) -> Self {
+        trim(&mut mag);
+        BigInt {
+            neg: neg && !mag.is_empty(),
+            mag,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mag.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.neg
+    }
+
+    pub fn is_one(&self) -> bool {
+        !self.neg && self.mag == [1]
+    }
+
+    pub fn abs(&self) -> Self {
+        BigInt {
+            neg: false,
+            mag: self.mag.clone(),
+        }
+    }
+
+    pub fn neg(&self) -> Self {
+        BigInt::from_mag(!self.neg, self.mag.clone())
+    }
+
+    /// The number of bits in the magnitude.
+    pub fn bit_len(&self) -> usize {
+        match self.mag.last() {
+            Some(&top) => self.mag.len() * 32 - top.leading_zeros() as usize,
+            None => 0,
+        }
+    }
+
+    pub fn add(&self, other: &BigInt) -> Self {
+        if self.neg == other.neg {
+            return BigInt::from_mag(self.neg, add_mag(&self.mag, &other.mag));
+        }
+        match cmp_mag(&self.mag, &other.mag) {
+            Ordering::Less => BigInt::from_mag(other.neg, sub_mag(&other.mag, &self.mag)),
+            _ => BigInt::from_mag(self.neg, sub_mag(&self.mag, &other.mag)),
+        }
+    }
+
+    pub fn sub(&self, other: &BigInt) -> Self {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &BigInt) -> Self {
+        BigInt::from_mag(self.neg != other.neg, mul_mag(&self.mag, &other.mag))
+    }
+
+    /// Truncating division and the remainder, which has the sign of `self`.
+    /// Returns `None` when dividing by zero.
+    pub fn div_rem(&self, other: &BigInt) -> Option<(BigInt, BigInt)> {
+        if other.is_zero() {
+            return None;
+        }
+        let (q, r) = div_rem_mag(&self.mag, &other.mag);
+        Some((
+            BigInt::from_mag(self.neg != other.neg, q),
+            BigInt::from_mag(self.neg, r),
+        ))
+    }
+
+    /// Division rounding towards negative infinity.
+    pub fn div_floor(&self, other: &BigInt) -> Option<BigInt> {
+        let (q, r) = self.div_rem(other)?;
+        if !r.is_zero() && r.neg != other.neg {
+            Some(q.sub(&BigInt::one()))
+        } else {
+            Some(q)
+        }
+    }
+
+    pub fn gcd(&self, other: &BigInt) -> Self {
+        let mut a = self.abs();
+        let mut b = other.abs();
+        while !b.is_zero() {
+            let r = a.div_rem(&b).unwrap().1;
+            a = b;
+            b = r;
+        }
+        a
+    }
+
+    pub fn pow(&self, mut exp: u32) -> Self {
+        let mut base = self.clone();
+        let mut acc = BigInt::one();
+        while exp > 0 {
+            if exp & 1 == 1 {
+                acc = acc.mul(&base);
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = base.mul(&base);
+            }
+        }
+        acc
+    }
+
+    /// Multiplies by `2^bits`.
+    pub fn shl(&self, bits: usize) -> Self {
+        let mut mag = vec![0; bits / 32];
+        mag.extend(shl_bits(&self.mag, (bits % 32) as u32));
+        BigInt::from_mag(self.neg, mag)
+    }
+
+    /// The integer square root of a non-negative number, rounded down.
+    pub fn isqrt(&self) -> Option<Self> {
+        if self.neg {
+            return None;
+        }
+        if self.is_zero() {
+            return Some(BigInt::zero());
+        }
+        // Newton's method from a power of two above the root.
+        let mut x = BigInt::one().shl(self.bit_len() / 2 + 1);
+        loop {
+            let sum = x.add(&self.div_rem(&x).unwrap().0);
+            let y = BigInt::from_mag(false, shr_bits(&sum.mag, 1));
+            if y.cmp(&x) != Ordering::Less {
+                return Some(x);
+            }
+            x = y;
+        }
+    }
+
+    /// The nearest f64, or an infinity if out of range.
+    pub fn to_f64(&self) -> f64 {
+        let bits = self.bit_len();
+        let x = if bits <= 64 {
+            self.mag
+                .iter()
+                .rev()
+                .fold(0.0, |acc, &d| acc * BASE as f64 + d as f64)
+        } else {
+            // Keep the top 64 bits, plus a sticky bit so that rounding is
+            // still correct.
+            let shift = bits - 64;
+            let top = shr_bits(&self.mag[shift / 32..], (shift % 32) as u32);
+            let mut top = top
+                .iter()
+                .rev()
+                .fold(0u64, |acc, &d| (acc << 32) | d as u64);
+            let dropped = self.mag[..shift / 32].iter().any(|&d| d != 0)
+                || self.mag[shift / 32] & ((1 << (shift % 32)) - 1) != 0;
+            if dropped {
+                top |= 1;
+            }
+            scale_by_pow2(top as f64, shift as i32)
+        };
+        if self.neg {
+            -x
+        } else {
+            x
+        }
+    }
+}
+
+/// `x * 2^exp`, in steps so that intermediate powers do not overflow.
+pub fn scale_by_pow2(mut x: f64, mut exp: i32) -> f64 {
+    while exp > 1000 {
+        x *= 2f64.powi(1000);
+        exp -= 1000;
+    }
+    while exp < -1000 {
+        x *= 2f64.powi(-1000);
+        exp += 1000;
+    }
+    x * 2f64.powi(exp)
+}
+
+impl From<i64> for BigInt {
+    fn from(x: i64) -> Self {
+        let m = x.unsigned_abs();
+        BigInt::from_mag(x < 0, vec![m as u32, (m >> 32) as u32])
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.neg, other.neg) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_mag(&self.mag, &other.mag),
+            (true, true) => cmp_mag(&other.mag, &self.mag),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Parses an optionally signed string of decimal digits.
+impl FromStr for BigInt {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        let (neg, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s),
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(());
+        }
+        let mut mag = Vec::new();
+        let first = digits.len() % DEC_DIGITS;
+        let mut start = 0;
+        for end in (first..=digits.len()).step_by(DEC_DIGITS) {
+            if end > start {
+                let chunk: u32 = digits[start..end].parse().unwrap();
+                let scale = 10u32.pow((end - start) as u32);
+                mul_small_add(&mut mag, scale, chunk);
+            }
+            start = end;
+        }
+        Ok(BigInt::from_mag(neg, mag))
+    }
+}
+
+impl Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        let mut chunks = Vec::new();
+        let mut mag = self.mag.clone();
+        while !mag.is_empty() {
+            let (q, r) = div_rem_small(&mag, DEC_BASE);
+            chunks.push(r);
+            mag = q;
+        }
+        if self.neg {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", chunks.last().unwrap())?;
+        for chunk in chunks.iter().rev().skip(1) {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+fn trim(mag: &mut Vec<u32>) {
+    while mag.last() == Some(&0) {
+        mag.pop();
+    }
+}
+
+fn cmp_mag(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut sum = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0u64;
+    for (i, &d) in long.iter().enumerate() {
+        let s = d as u64 + short.get(i).map_or(0, |&d| d as u64) + carry;
+        sum.push(s as u32);
+        carry = s >> 32;
+    }
+    if carry > 0 {
+        sum.push(carry as u32);
+    }
+    sum
+}
+
+/// `a - b`, where `a >= b`.
+fn sub_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut diff = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, &d) in a.iter().enumerate() {
+        let t = d as i64 - b.get(i).map_or(0, |&d| d as i64) - borrow;
+        diff.push(t as u32);
+        borrow = if t < 0 { 1 } else { 0 };
+    }
+    trim(&mut diff);
+    diff
+}
+
+fn mul_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+    let mut prod = vec![0u32; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, &y) in b.iter().enumerate() {
+            let t = x as u64 * y as u64 + prod[i + j] as u64 + carry;
+            prod[i + j] = t as u32;
+            carry = t >> 32;
+        }
+        prod[i + b.len()] = carry as u32;
+    }
+    trim(&mut prod);
+    prod
+}
+
+fn mul_small_add(mag: &mut Vec<u32>, m: u32, a: u32) {
+    let mut carry = a as u64;
+    for d in mag.iter_mut() {
+        let t = *d as u64 * m as u64 + carry;
+        *d = t as u32;
+        carry = t >> 32;
+    }
+    if carry > 0 {
+        mag.push(carry as u32);
+    }
+}
+
+fn div_rem_small(a: &[u32], d: u32) -> (Vec<u32>, u32) {
+    let mut q = vec![0u32; a.len()];
+    let mut r = 0u64;
+    for i in (0..a.len()).rev() {
+        let t = (r << 32) | a[i] as u64;
+        q[i] = (t / d as u64) as u32;
+        r = t % d as u64;
+    }
+    trim(&mut q);
+    (q, r as u32)
+}
+
+fn shl_bits(a: &[u32], s: u32) -> Vec<u32> {
+    if s == 0 {
+        return a.to_vec();
+    }
+    let mut out = Vec::with_capacity(a.len() + 1);
+    let mut carry = 0u32;
+    for &d in a.iter() {
+        out.push((d << s) | carry);
+        carry = d >> (32 - s);
+    }
+    out.push(carry);
+    trim(&mut out);
+    out
+}
+
+fn shr_bits(a: &[u32], s: u32) -> Vec<u32> {
+    if s == 0 {
+        return a.to_vec();
+    }
+    let mut out = Vec::with_capacity(a.len());
+    for i in 0..a.len() {
+        let high = a.get(i + 1).map_or(0, |&d| d << (32 - s));
+        out.push((a[i] >> s) | high);
+    }
+    trim(&mut out);
+    out
+}
+
+/// Long division of magnitudes, following Knuth's Algorithm D.
+fn div_rem_mag(u: &[u32], v: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if cmp_mag(u, v) == Ordering::Less {
+        return (Vec::new(), u.to_vec());
+    }
+    if v.len() == 1 {
+        let (q, r) = div_rem_small(u, v[0]);
+        let mut r = vec![r];
+        trim(&mut r);
+        return (q, r);
+    }
+    // Normalize so that the divisor's top digit has its high bit set.
+    let s = v[v.len() - 1].leading_zeros();
+    let vn = shl_bits(v, s);
+    let mut un = shl_bits(u, s);
+    un.resize(u.len() + 1, 0);
+    let n = v.len();
+    let m = u.len() - n;
+    let mut q = vec![0u32; m + 1];
+    for j in (0..=m).rev() {
+        let num = ((un[j + n] as u64) << 32) | un[j + n - 1] as u64;
+        let mut qhat = num / vn[n - 1] as u64;
+        let mut rhat = num % vn[n - 1] as u64;
+        while qhat >= BASE || qhat * vn[n - 2] as u64 > ((rhat << 32) | un[j + n - 2] as u64) {
+            qhat -= 1;
+            rhat += vn[n - 1] as u64;
+            if rhat >= BASE {
+                break;
+            }
+        }
+        let mut borrow = 0i64;
+        let mut carry = 0u64;
+        for i in 0..n {
+            let p = qhat * vn[i] as u64 + carry;
+            carry = p >> 32;
+            let t = un[i + j] as i64 - borrow - (p & 0xffff_ffff) as i64;
+            un[i + j] = t as u32;
+            borrow = if t < 0 { 1 } else { 0 };
+        }
+        let t = un[j + n] as i64 - borrow - carry as i64;
+        un[j + n] = t as u32;
+        if t < 0 {
+            // The estimate was one too large; add the divisor back.
+            qhat -= 1;
+            let mut carry = 0u64;
+            for i in 0..n {
+                let s = un[i + j] as u64 + vn[i] as u64 + carry;
+                un[i + j] = s as u32;
+                carry = s >> 32;
+            }
+            un[j + n] = un[j + n].wrapping_add(carry as u32);
+        }
+        q[j] = qhat as u32;
+    }
+    trim(&mut q);
+    (q, shr_bits(&un[..n], s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(s: &str) -> BigInt {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn add_and_sub_carry_across_digits() {
+        let max = big("18446744073709551615"); // 2^64 - 1
+        assert_eq!(max.add(&BigInt::one()), big("18446744073709551616"));
+        assert_eq!(big("18446744073709551616").sub(&BigInt::one()), max);
+        assert_eq!(BigInt::one().sub(&big("18446744073709551616")), max.neg());
+        assert_eq!(big("-5").add(&big("3")), big("-2"));
+        assert_eq!(big("5").add(&big("-8")), big("-3"));
+        assert_eq!(big("-5").sub(&big("-5")), BigInt::zero());
+        assert!(!big("-5").sub(&big("-5")).is_negative());
+    }
+
+    #[test]
+    fn mul_with_mixed_signs() {
+        let a = big("12345678901234567890123456789");
+        let b = big("98765432109876543210");
+        let ab = big("1219326311370217952249657064223746380111126352690");
+        assert_eq!(a.mul(&b), ab);
+        assert_eq!(a.neg().mul(&b), ab.neg());
+        assert_eq!(a.neg().mul(&b.neg()), ab);
+        assert_eq!(a.mul(&BigInt::zero()), BigInt::zero());
+        assert!(!a.neg().mul(&BigInt::zero()).is_negative());
+    }
+
+    #[test]
+    fn div_rem_truncates() {
+        let cases = [
+            (7, 2, 3, 1),
+            (-7, 2, -3, -1),
+            (7, -2, -3, 1),
+            (-7, -2, 3, -1),
+            (6, -3, -2, 0),
+        ];
+        for &(a, b, q, r) in cases.iter() {
+            let (a, b) = (BigInt::from(a), BigInt::from(b));
+            assert_eq!(a.div_rem(&b), Some((BigInt::from(q), BigInt::from(r))));
+        }
+        assert_eq!(
+            BigInt::from(-7).div_floor(&BigInt::from(2)),
+            Some(BigInt::from(-4))
+        );
+        assert_eq!(BigInt::one().div_rem(&BigInt::zero()), None);
+    }
+
+    #[test]
+    fn div_rem_of_several_digits() {
+        let x = big("515377520732011331036461129765621272702107522001");
+        let y = big("-1180591620717411315769");
+        let (q, r) = x.div_rem(&y).unwrap();
+        assert_eq!(q, big("-436541740334249828670796276"));
+        assert_eq!(r, big("150535375992002245757"));
+        assert_eq!(q.mul(&y).add(&r), x);
+    }
+
+    #[test]
+    fn div_rem_adds_back_an_estimate_that_is_too_large() {
+        // The quotient digit estimated from the top digits is one too large
+        // here, so the divisor has to be added back.
+        let u = big("170141183420855150474555134919112130560");
+        let v = big("39614081257132168796771975169");
+        assert_eq!(
+            u.div_rem(&v),
+            Some((big("4294967294"), big("39614081257132168792477007874")))
+        );
+    }
+
+    #[test]
+    fn to_string_and_parse_round_trip() {
+        for s in [
+            "0",
+            "7",
+            "-7",
+            "1000000000",
+            "999999999",
+            "-1000000000000000000000000000001",
+            "118181386580595879976868414312001964434038548836769923458287039207",
+        ]
+        .iter()
+        {
+            assert_eq!(big(s).to_string(), *s);
+        }
+        assert_eq!(big("-0"), BigInt::zero());
+        assert_eq!(big("000123").to_string(), "123");
+        assert!("".parse::<BigInt>().is_err());
+        assert!("-".parse::<BigInt>().is_err());
+        assert!("12a".parse::<BigInt>().is_err());
+        assert!("+1".parse::<BigInt>().is_err());
+    }
+
+    #[test]
+    fn gcd_is_never_negative() {
+        let a = BigInt::one().shl(64).mul(&BigInt::from(15));
+        let b = BigInt::one().shl(40).mul(&BigInt::from(35));
+        assert_eq!(a.gcd(&b), big("5497558138880"));
+        assert_eq!(a.neg().gcd(&b), big("5497558138880"));
+        assert_eq!(a.neg().gcd(&b.neg()), big("5497558138880"));
+        assert_eq!(BigInt::zero().gcd(&BigInt::from(-4)), BigInt::from(4));
+        assert_eq!(BigInt::zero().gcd(&BigInt::zero()), BigInt::zero());
+    }
+
+    #[test]
+    fn large_powers() {
+        assert_eq!(
+            BigInt::from(2).pow(100),
+            big("1267650600228229401496703205376")
+        );
+        assert_eq!(
+            BigInt::from(3).pow(100),
+            big("515377520732011331036461129765621272702107522001")
+        );
+        assert_eq!(
+            BigInt::from(-7).pow(77),
+            big("-118181386580595879976868414312001964434038548836769923458287039207")
+        );
+        assert_eq!(BigInt::from(-7).pow(0), BigInt::one());
+        assert_eq!(BigInt::from(2).pow(100), BigInt::one().shl(100));
+    }
+
+    #[test]
+    fn isqrt_rounds_down() {
+        let x = BigInt::from(3).pow(100);
+        assert_eq!(x.mul(&x).isqrt(), Some(x.clone()));
+        assert_eq!(
+            x.mul(&x).sub(&BigInt::one()).isqrt(),
+            Some(x.sub(&BigInt::one()))
+        );
+        assert_eq!(BigInt::from(-1).isqrt(), None);
+    }
+
+    #[test]
+    fn to_f64_rounds_to_nearest() {
+        assert_eq!(BigInt::from(2).pow(100).to_f64(), 2f64.powi(100));
+        // 2^64 + 2^11 + 1 is just above halfway between two floats.
+        let x = BigInt::one().shl(64).add(&BigInt::from(2049));
+        assert_eq!(x.to_f64(), 18446744073709555712.0);
+        assert_eq!(x.neg().to_f64(), -18446744073709555712.0);
+        assert_eq!(BigInt::from(10).pow(400).to_f64(), f64::INFINITY);
+    }
+}
@@ -0,0 +1,743 @@
+use std::error::Error;
+use std::f64;
+use std::fmt::{self, Display};
+use std::rc::Rc;
+
+use ast::ReplTree;
+use complex::Complex;
+use decimal::Decimal;
+use dual::Dual;
+use format;
+use interval::Interval;
+use number::Number;
+use parser;
+use rational::Rational;
+use uncertain::Uncertain;
+use units::{Quantity, Unit};
+use value::{Record, Value};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Arity {
+    Fixed(usize),
+    /// At least the given number of arguments.
+    Variadic(usize),
+    /// Between the given numbers of arguments, inclusive.
+    Range(usize, usize),
+}
+
+impl Arity {
+    pub fn accepts(self, argc: usize) -> bool {
+        match self {
+            Arity::Fixed(n) => argc == n,
+            Arity::Variadic(min) => argc >= min,
+            Arity::Range(min, max) => argc >= min && argc <= max,
+        }
+    }
+
+    pub fn min(self) -> usize {
+        match self {
+            Arity::Fixed(n) | Arity::Variadic(n) | Arity::Range(n, _) => n,
+        }
+    }
+}
+
+pub struct Builtin {
+    pub name: &'static str,
+    pub arity: Arity,
+    pub func: fn(&[f64]) -> f64,
+}
+
+macro_rules! unary {
+    ($name: expr, $f: expr) => {
+        Builtin {
+            name: $name,
+            arity: Arity::Fixed(1),
+            func: |args| $f(args[0]),
+        }
+    };
+}
+
+/// The builtin function table shared by the tree-walking evaluator and the VM.
+/// The VM refers to builtins by their index in this table, so new entries
+/// should be appended.
+pub static BUILTINS: &[Builtin] = &[
+    unary!("sin", f64::sin),
+    unary!("cos", f64::cos),
+    unary!("tan", f64::tan),
+    unary!("asin", f64::asin),
+    unary!("acos", f64::acos),
+    unary!("atan", f64::atan),
+    Builtin {
+        name: "atan2",
+        arity: Arity::Fixed(2),
+        func: |args| args[0].atan2(args[1]),
+    },
+    unary!("sinh", f64::sinh),
+    unary!("cosh", f64::cosh),
+    unary!("tanh", f64::tanh),
+    unary!("asinh", f64::asinh),
+    unary!("acosh", f64::acosh),
+    unary!("atanh", f64::atanh),
+    unary!("sqrt", f64::sqrt),
+    unary!("cbrt", f64::cbrt),
+    unary!("exp", f64::exp),
+    unary!("ln", f64::ln),
+    unary!("log10", f64::log10),
+    unary!("log2", f64::log2),
+    Builtin {
+        name: "log",
+        arity: Arity::Fixed(2),
+        func: |args| args[1].log(args[0]),
+    },
+    unary!("abs", f64::abs),
+    unary!("sign", sign),
+    unary!("floor", f64::floor),
+    unary!("ceil", f64::ceil),
+    unary!("round", f64::round),
+    unary!("trunc", f64::trunc),
+    Builtin {
+        name: "min",
+        arity: Arity::Variadic(1),
+        func: |args| args[1..].iter().fold(args[0], |acc, x| acc.min(*x)),
+    },
+    Builtin {
+        name: "max",
+        arity: Arity::Variadic(1),
+        func: |args| args[1..].iter().fold(args[0], |acc, x| acc.max(*x)),
+    },
+    Builtin {
+        name: "hypot",
+        arity: Arity::Fixed(2),
+        func: |args| args[0].hypot(args[1]),
+    },
+    Builtin {
+        name: "clamp",
+        arity: Arity::Fixed(3),
+        func: |args| args[0].max(args[1]).min(args[2]),
+    },
+    unary!("arg", |x: f64| 0f64.atan2(x)),
+    unary!("conj", |x| x),
+    unary!("re", |x| x),
+    unary!("im", |x| 0.0 * x),
+];
+
+impl Builtin {
+    /// Wraps this builtin so it can be stored in an `EvalContext` registry.
+    pub fn to_native(&self) -> NativeFn {
+        let func = self.func;
+        let mut native = NativeFn::numeric(self.arity, move |args: &[f64]| Ok(func(args)));
+        native.set_pure(true);
+        native.builtin = Some(self.name);
+        native
+    }
+}
+
+/// A builtin over arbitrary values. These are only available to the
+/// evaluator, not the VM.
+pub struct ValueBuiltin {
+    pub name: &'static str,
+    pub arity: Arity,
+    pub pure: bool,
+    pub func: fn(&[Value]) -> NativeResult,
+}
+
+pub static VALUE_BUILTINS: &[ValueBuiltin] = &[
+    ValueBuiltin {
+        name: "len",
+        arity: Arity::Fixed(1),
+        pure: true,
+        func: len,
+    },
+    ValueBuiltin {
+        name: "divmod",
+        arity: Arity::Fixed(2),
+        pure: true,
+        func: divmod,
+    },
+    ValueBuiltin {
+        name: "keys",
+        arity: Arity::Fixed(1),
+        pure: true,
+        func: keys,
+    },
+    ValueBuiltin {
+        name: "has",
+        arity: Arity::Fixed(2),
+        pure: true,
+        func: has,
+    },
+    ValueBuiltin {
+        name: "format",
+        arity: Arity::Variadic(1),
+        pure: true,
+        func: format,
+    },
+    ValueBuiltin {
+        name: "print",
+        arity: Arity::Variadic(1),
+        pure: false,
+        func: print,
+    },
+    ValueBuiltin {
+        name: "float",
+        arity: Arity::Fixed(1),
+        pure: true,
+        func: float,
+    },
+    ValueBuiltin {
+        name: "decimal",
+        arity: Arity::Range(1, 2),
+        pure: true,
+        func: decimal,
+    },
+    ValueBuiltin {
+        name: "interval",
+        arity: Arity::Range(1, 2),
+        pure: true,
+        func: interval,
+    },
+    ValueBuiltin {
+        name: "lower",
+        arity: Arity::Fixed(1),
+        pure: true,
+        func: |args| expect_interval(args, 0).map(|x| Value::Num(x.lo)),
+    },
+    ValueBuiltin {
+        name: "upper",
+        arity: Arity::Fixed(1),
+        pure: true,
+        func: |args| expect_interval(args, 0).map(|x| Value::Num(x.hi)),
+    },
+    ValueBuiltin {
+        name: "nominal",
+        arity: Arity::Fixed(1),
+        pure: true,
+        func: |args| expect_uncertain(args, 0).map(|u| Value::Num(u.value())),
+    },
+    ValueBuiltin {
+        name: "uncertainty",
+        arity: Arity::Fixed(1),
+        pure: true,
+        func: |args| expect_uncertain(args, 0).map(|u| Value::Num(u.stddev())),
+    },
+    ValueBuiltin {
+        name: "simplify",
+        arity: Arity::Fixed(1),
+        pure: true,
+        func: simplify,
+    },
+];
+
+impl ValueBuiltin {
+    pub fn to_native(&self) -> NativeFn {
+        let mut native = NativeFn::new(self.arity, self.func);
+        native.set_pure(self.pure);
+        native
+    }
+}
+
+pub type NativeResult = Result<Value, NativeError>;
+
+type NumericBody = dyn Fn(&[f64]) -> Result<f64, NativeError>;
+
+type ValueBody = dyn Fn(&[Value]) -> NativeResult;
+
+enum NativeBody {
+    Numeric(Box<NumericBody>),
+    Values(Box<ValueBody>),
+}
+
+/// A function implemented in Rust and callable from formulas.
+pub struct NativeFn {
+    arity: Arity,
+    pure: bool,
+    body: NativeBody,
+    /// The name of the builtin this wraps, used to find its versions for
+    /// other kinds of numbers.
+    builtin: Option<&'static str>,
+}
+
+impl NativeFn {
+    /// Creates an impure native function over arbitrary values. The arity is
+    /// checked before `func` is called, so `func` may index its arguments
+    /// freely.
+    pub fn new<F>(arity: Arity, func: F) -> Self
+    where
+        F: Fn(&[Value]) -> NativeResult + 'static,
+    {
+        NativeFn {
+            arity,
+            pure: false,
+            body: NativeBody::Values(Box::new(func)),
+            builtin: None,
+        }
+    }
+
+    /// Creates an impure native function that takes and returns numbers.
+    /// Calling it with any other kind of value is an error.
+    pub fn numeric<F>(arity: Arity, func: F) -> Self
+    where
+        F: Fn(&[f64]) -> Result<f64, NativeError> + 'static,
+    {
+        NativeFn {
+            arity,
+            pure: false,
+            body: NativeBody::Numeric(Box::new(func)),
+            builtin: None,
+        }
+    }
+
+    pub fn arity(&self) -> Arity {
+        self.arity
+    }
+
+    /// Whether calls with the same arguments always give the same result
+    /// and have no side effects.
+    pub fn is_pure(&self) -> bool {
+        self.pure
+    }
+
+    pub fn set_pure(&mut self, pure: bool) -> &mut Self {
+        self.pure = pure;
+        self
+    }
+
+    pub fn is_numeric(&self) -> bool {
+        match self.body {
+            NativeBody::Numeric(_) => true,
+            NativeBody::Values(_) => false,
+        }
+    }
+
+    /// The name of the builtin this function wraps, if it is one.
+    pub fn builtin_name(&self) -> Option<&'static str> {
+        self.builtin
+    }
+
+    /// Calls the function. Builtins called with only rational or only
+    /// decimal arguments use their version for that kind of number, if
+    /// there is one and it has a result; otherwise, as for `sqrt(2)`, they
+    /// fall back to floats. Numeric functions given an interval or an
+    /// uncertain value compute with those throughout, and builtins given a
+    /// dual number carry its derivatives along. Quantities with units can be
+    /// passed to the builtins that keep their meaning, like `abs` and `sqrt`,
+    /// and otherwise must be dimensionless, as in `sin(30 deg)`.
+    pub fn call(&self, args: &[Value]) -> NativeResult {
+        if let Some(name) = self.builtin {
+            if let Some(val) =
+                call_as::<Rational>(name, args).or_else(|| call_as::<Decimal>(name, args))
+            {
+                return Ok(val);
+            }
+            if args.iter().any(|arg| matches!(*arg, Value::Quantity(_))) {
+                if let Some(result) = call_quantity(name, args) {
+                    return result;
+                }
+            }
+        }
+        if args.iter().any(|arg| matches!(*arg, Value::Complex(_))) {
+            if let Some(result) = self.call_complex(args) {
+                return result;
+            }
+        }
+        if self.is_numeric() {
+            if args.iter().any(|arg| matches!(*arg, Value::Interval(_))) {
+                return self.call_lifted::<Interval>(args);
+            }
+            if args.iter().any(|arg| matches!(*arg, Value::Uncertain(_))) {
+                return self.call_lifted::<Uncertain>(args);
+            }
+            if args.iter().any(|arg| matches!(*arg, Value::Dual(_))) {
+                return self.call_lifted::<Dual>(args);
+            }
+        }
+        match self.body {
+            NativeBody::Numeric(ref func) => {
+                let mut nums = Vec::with_capacity(args.len());
+                for (i, arg) in args.iter().enumerate() {
+                    match arg.as_num() {
+                        Some(x) => nums.push(x),
+                        None => {
+                            return Err(NativeError::InvalidArgument(
+                                i,
+                                format!("expected number, got {}", arg.type_name()),
+                            ))
+                        }
+                    }
+                }
+                func(&nums).map(Value::Num)
+            }
+            NativeBody::Values(ref func) => func(args),
+        }
+    }
+
+    /// Calls the complex version of a builtin, treating real arguments as
+    /// complex. Returns `None` if there is no complex version.
+    pub fn call_complex(&self, args: &[Value]) -> Option<NativeResult> {
+        let name = self.builtin?;
+        let mut zs = Vec::with_capacity(args.len());
+        for (i, arg) in args.iter().enumerate() {
+            match Complex::from_value(arg) {
+                Some(z) => zs.push(z),
+                None => {
+                    return Some(Err(NativeError::InvalidArgument(
+                        i,
+                        format!("expected number, got {}", arg.type_name()),
+                    )))
+                }
+            }
+        }
+        Complex::call_builtin(name, &zs).map(Ok)
+    }
+
+    /// Calls a numeric function with all its arguments converted to `N`.
+    fn call_lifted<N: Number>(&self, args: &[Value]) -> NativeResult {
+        let mut xs = Vec::with_capacity(args.len());
+        for (i, arg) in args.iter().enumerate() {
+            match N::from_value(arg) {
+                Some(x) => xs.push(x),
+                None => {
+                    return Err(NativeError::InvalidArgument(
+                        i,
+                        format!("expected number, got {}", arg.type_name()),
+                    ))
+                }
+            }
+        }
+        N::call_native(self, &xs, Default::default()).map(N::into_value)
+    }
+
+    /// Calls a numeric function without converting its arguments. Returns
+    /// `None` for functions created with `NativeFn::new`.
+    pub fn call_numeric(&self, args: &[f64]) -> Option<Result<f64, NativeError>> {
+        match self.body {
+            NativeBody::Numeric(ref func) => Some(func(args)),
+            NativeBody::Values(_) => None,
+        }
+    }
+}
+
+/// Calls the version of a builtin for `N` if all the arguments are of that
+/// kind.
+fn call_as<N: Number>(name: &str, args: &[Value]) -> Option<Value> {
+    let xs: Option<Vec<N>> = args.iter().map(N::from_value).collect();
+    N::call_builtin(name, &xs?)
+}
+
+/// Calls a builtin on quantities, with every argument in the unit of the
+/// first. Returns `None` for builtins that only take plain numbers.
+fn call_quantity(name: &str, args: &[Value]) -> Option<NativeResult> {
+    let func = BUILTINS[lookup(name)?].func;
+    let first = args[0].as_quantity()?;
+    let unit = match name {
+        "abs" | "floor" | "ceil" | "round" | "trunc" | "min" | "max" | "hypot" | "clamp" => {
+            Some(first.unit.clone())
+        }
+        "sign" => Some(Unit::one()),
+        "sqrt" => first.unit.powf(0.5),
+        "cbrt" => first.unit.powf(1.0 / 3.0),
+        _ => return None,
+    };
+    let unit = match unit {
+        Some(unit) => unit,
+        None => {
+            return Some(Err(NativeError::InvalidArgument(
+                0,
+                format!("cannot take '{}' of '{}'", name, first.unit),
+            )))
+        }
+    };
+    let mut xs = Vec::with_capacity(args.len());
+    for (i, arg) in args.iter().enumerate() {
+        match arg.as_quantity().and_then(|q| q.value_in(&first.unit)) {
+            Some(x) => xs.push(x),
+            None => {
+                let got = match arg.as_quantity() {
+                    Some(q) => q.unit.describe(),
+                    None => String::from(arg.type_name()),
+                };
+                return Some(Err(NativeError::InvalidArgument(
+                    i,
+                    format!("expected {}, got {}", first.unit.describe(), got),
+                )));
+            }
+        }
+    }
+    Some(Ok(Value::from(Quantity::new(func(&xs), unit))))
+}
+
+#[derive(Debug)]
+pub enum NativeError {
+    /// The argument at the given index is outside the function's domain.
+    InvalidArgument(usize, String),
+    /// Any other failure reported by the host.
+    Other(Box<dyn Error>),
+}
+
+impl Display for NativeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NativeError::InvalidArgument(i, msg) => write!(f, "invalid argument {}: {}", i, msg),
+            NativeError::Other(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for NativeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            NativeError::Other(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+pub fn lookup(name: &str) -> Option<usize> {
+    BUILTINS.iter().position(|b| b.name == name)
+}
+
+/// Like `f64::signum`, but zero (and NaN) map to themselves.
+pub fn sign(x: f64) -> f64 {
+    if x == 0.0 || x.is_nan() {
+        x
+    } else {
+        x.signum()
+    }
+}
+
+fn len(args: &[Value]) -> NativeResult {
+    match args[0] {
+        Value::List(ref items) | Value::Tuple(ref items) => Ok(Value::Num(items.len() as f64)),
+        Value::Str(ref s) => Ok(Value::Num(s.chars().count() as f64)),
+        Value::Record(ref record) => Ok(Value::Num(record.len() as f64)),
+        ref val => Err(NativeError::InvalidArgument(
+            0,
+            format!(
+                "expected list, tuple, string or record, got {}",
+                val.type_name()
+            ),
+        )),
+    }
+}
+
+/// `divmod(a, b)` is the tuple of `floor(a / b)` and the remainder, which
+/// has the sign of `b`.
+fn divmod(args: &[Value]) -> NativeResult {
+    if let (Value::Rational(a), Value::Rational(b)) = (&args[0], &args[1]) {
+        let q = match a.div(b) {
+            Some(q) => q.floor(),
+            None => {
+                return Err(NativeError::InvalidArgument(
+                    1,
+                    String::from("division by zero"),
+                ))
+            }
+        };
+        let r = a.sub(&b.mul(&q));
+        return Ok(Value::Tuple(Rc::new(vec![Value::from(q), Value::from(r)])));
+    }
+    if let (Value::Decimal(a), Value::Decimal(b)) = (&args[0], &args[1]) {
+        let q = match a.div(b) {
+            Some(q) => q.floor(),
+            None => {
+                return Err(NativeError::InvalidArgument(
+                    1,
+                    String::from("division by zero"),
+                ))
+            }
+        };
+        let r = a.sub(&b.mul(&q));
+        return Ok(Value::Tuple(Rc::new(vec![Value::from(q), Value::from(r)])));
+    }
+    let nums = [expect_num(args, 0)?, expect_num(args, 1)?];
+    let q = (nums[0] / nums[1]).floor();
+    Ok(Value::Tuple(Rc::new(vec![
+        Value::Num(q),
+        Value::Num(nums[0] - nums[1] * q),
+    ])))
+}
+
+fn expect_record(args: &[Value], i: usize) -> Result<&Record, NativeError> {
+    match args[i] {
+        Value::Record(ref record) => Ok(record),
+        ref val => Err(NativeError::InvalidArgument(
+            i,
+            format!("expected record, got {}", val.type_name()),
+        )),
+    }
+}
+
+/// The field names of a record, in order.
+fn keys(args: &[Value]) -> NativeResult {
+    Ok(Value::from(
+        expect_record(args, 0)?
+            .keys()
+            .map(Value::from)
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// `has(r, "name")`: whether the record has the field.
+fn has(args: &[Value]) -> NativeResult {
+    let record = expect_record(args, 0)?;
+    match args[1] {
+        Value::Str(ref name) => Ok(Value::Bool(record.get(name).is_some())),
+        ref val => Err(NativeError::InvalidArgument(
+            1,
+            format!("expected string, got {}", val.type_name()),
+        )),
+    }
+}
+
+/// `format(fmt, args...)`; see `format::format`.
+fn format(args: &[Value]) -> NativeResult {
+    match args[0] {
+        Value::Str(ref fmt) => format::format(fmt, &args[1..])
+            .map(Value::from)
+            .map_err(|msg| NativeError::InvalidArgument(0, msg)),
+        ref val => Err(NativeError::InvalidArgument(
+            0,
+            format!("expected format string, got {}", val.type_name()),
+        )),
+    }
+}
+
+/// `print(x)` prints any value as is, and `print(fmt, args...)` prints a
+/// formatted line.
+fn print(args: &[Value]) -> NativeResult {
+    if args.len() == 1 {
+        println!("{}", args[0]);
+    } else {
+        println!("{}", format(args)?);
+    }
+    Ok(Value::Nil)
+}
+
+fn expect_num(args: &[Value], i: usize) -> Result<f64, NativeError> {
+    args[i].as_num().ok_or_else(|| {
+        NativeError::InvalidArgument(i, format!("expected number, got {}", args[i].type_name()))
+    })
+}
+
+/// `float(x)` converts an exact number to the nearest float.
+fn float(args: &[Value]) -> NativeResult {
+    expect_num(args, 0).map(Value::Num)
+}
+
+/// `decimal(x, digits)` writes a number as a decimal string with the given
+/// number of digits after the point, 10 by default. Exact numbers are
+/// rounded exactly, and decimals by the rounding mode of their context.
+fn decimal(args: &[Value]) -> NativeResult {
+    let digits = if args.len() == 2 {
+        let digits = expect_num(args, 1)?;
+        if digits.fract() != 0.0 || !(0.0..=1000.0).contains(&digits) {
+            return Err(NativeError::InvalidArgument(
+                1,
+                String::from("digits must be an integer from 0 to 1000"),
+            ));
+        }
+        digits as usize
+    } else {
+        10
+    };
+    match args[0] {
+        Value::Rational(ref q) => Ok(Value::from(q.to_fixed(digits))),
+        Value::Decimal(ref d) => Ok(Value::from(d.to_fixed(digits))),
+        Value::Interval(x) => Ok(Value::from(x.to_fixed(digits))),
+        Value::Uncertain(ref u) => Ok(Value::from(u.to_fixed(digits))),
+        Value::Quantity(ref q) => Ok(Value::from(format!("{} {}", q.value.to_fixed(digits), q.unit))),
+        _ => Ok(Value::from(expect_num(args, 0)?.to_fixed(digits))),
+    }
+}
+
+/// `simplify("x * 1 + x")` simplifies a formula given as source, returning
+/// it as source again: `"2 * x"`.
+fn simplify(args: &[Value]) -> NativeResult {
+    let source = match args[0] {
+        Value::Str(ref source) => source,
+        ref val => {
+            return Err(NativeError::InvalidArgument(
+                0,
+                format!("expected string, got {}", val.type_name()),
+            ))
+        }
+    };
+    match parser::parse_repl(source) {
+        Ok(ReplTree::Expr(expr)) => Ok(Value::from(::simplify::simplify(&expr).to_string())),
+        Ok(ReplTree::Empty) => Err(NativeError::InvalidArgument(
+            0,
+            String::from("expected a formula"),
+        )),
+        Err(err) => Err(NativeError::InvalidArgument(0, err.to_string())),
+    }
+}
+
+/// Converts an argument to an interval. Real numbers become the smallest
+/// interval containing them.
+fn expect_interval(args: &[Value], i: usize) -> Result<Interval, NativeError> {
+    Interval::from_value(&args[i]).ok_or_else(|| {
+        NativeError::InvalidArgument(i, format!("expected number, got {}", args[i].type_name()))
+    })
+}
+
+fn expect_uncertain(args: &[Value], i: usize) -> Result<Uncertain, NativeError> {
+    Uncertain::from_value(&args[i]).ok_or_else(|| {
+        NativeError::InvalidArgument(i, format!("expected number, got {}", args[i].type_name()))
+    })
+}
+
+/// `interval(lo, hi)` is the interval from `lo` to `hi`, and `interval(x)`
+/// the smallest interval containing `x`. Exact bounds are rounded outward.
+fn interval(args: &[Value]) -> NativeResult {
+    let lo = expect_interval(args, 0)?;
+    let hi = expect_interval(args, args.len() - 1)?;
+    if lo.lo > hi.hi {
+        return Err(NativeError::InvalidArgument(
+            1,
+            String::from("upper bound is below lower bound"),
+        ));
+    }
+    Ok(Value::from(Interval::new(lo.lo, hi.hi)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str, args: &[f64]) -> f64 {
+        let builtin = &BUILTINS[lookup(name).unwrap()];
+        assert!(builtin.arity.accepts(args.len()));
+        (builtin.func)(args)
+    }
+
+    #[test]
+    fn names_are_unique() {
+        for (i, b) in BUILTINS.iter().enumerate() {
+            assert_eq!(lookup(b.name), Some(i), "{} is defined twice", b.name);
+        }
+        assert_eq!(lookup("nope"), None);
+    }
+
+    #[test]
+    fn arity() {
+        assert!(Arity::Fixed(2).accepts(2));
+        assert!(!Arity::Fixed(2).accepts(1));
+        assert!(!Arity::Variadic(1).accepts(0));
+        assert!(Arity::Variadic(1).accepts(5));
+        assert_eq!(Arity::Variadic(1).min(), 1);
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(call("log", &[2.0, 8.0]), 3.0);
+        assert_eq!(call("atan2", &[1.0, 0.0]), f64::consts::FRAC_PI_2);
+        assert_eq!(call("hypot", &[3.0, 4.0]), 5.0);
+        assert_eq!(call("min", &[3.0, -1.0, 2.0]), -1.0);
+        assert_eq!(call("max", &[3.0]), 3.0);
+        assert_eq!(call("clamp", &[5.0, 0.0, 1.0]), 1.0);
+        assert_eq!(call("clamp", &[-5.0, 0.0, 1.0]), 0.0);
+        assert_eq!(call("sign", &[-2.5]), -1.0);
+        assert_eq!(call("sign", &[0.0]), 0.0);
+        assert!(call("sign", &[f64::NAN]).is_nan());
+    }
+}
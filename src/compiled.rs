@@ -0,0 +1,557 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::f64;
+use std::rc::Rc;
+
+use ast;
+use builtins::NativeFn;
+use complex::Complex;
+use eval::EvalContext;
+use intrinsics;
+use number::Number;
+use parser::{self, MathParseError};
+use value::Value;
+
+/// An expression compiled once against an `EvalContext`, for evaluating many
+/// times with different parameter values.
+///
+/// Variable names are resolved ahead of time: parameters become argument
+/// slots, other globals are read from the context when compiling, and calls
+/// to `def` functions are inlined. Evaluation gives the same results as
+/// `EvalContext` would with the parameters bound as globals. Only numbers
+/// are supported, with booleans allowed as `if` conditions.
+///
+/// The expression computes with numbers of kind `N`, floats by default.
+/// Literals are read as that kind, using the context's settings for it, such
+/// as its decimal precision, and builtins use their version for that kind
+/// where they have one.
+pub struct CompiledExpr<N: Number = f64> {
+    root: Node<N>,
+    nparams: usize,
+    nctx: N::Context,
+    locals: RefCell<Vec<N>>,
+    /// Arguments of the native calls being evaluated.
+    stack: RefCell<Vec<N>>,
+}
+
+enum Node<N> {
+    Const(N),
+    Param(usize),
+    Local(usize),
+    Store(usize, Box<Node<N>>),
+    Seq(Box<[Node<N>]>),
+    Binary(ast::BinOp, Box<Node<N>>, Box<Node<N>>),
+    Neg(Box<Node<N>>),
+    If(Box<Cond<N>>, Box<Node<N>>, Box<Node<N>>),
+    Call(String, Rc<NativeFn>, Box<[Node<N>]>),
+    /// `sum` or `prod` of the term over the local from one bound to the
+    /// other.
+    Iterated(ast::BinOp, usize, Box<Node<N>>, Box<Node<N>>, Box<Node<N>>),
+}
+
+enum Cond<N> {
+    Const(bool),
+    Compare(ast::BinOp, Box<Node<N>>, Box<Node<N>>),
+    Not(Box<Cond<N>>),
+}
+
+impl<N: Number> CompiledExpr<N> {
+    pub fn new(
+        ctx: &EvalContext,
+        expr: &ast::Expr,
+        params: &[&str],
+    ) -> Result<Self, MathParseError> {
+        let nctx = N::context(ctx);
+        let mut compiler = Compiler {
+            ctx,
+            nctx,
+            scopes: vec![HashMap::new()],
+            nlocals: 0,
+            inlining: Vec::new(),
+        };
+        for (i, param) in params.iter().enumerate() {
+            compiler.scopes[0].insert(String::from(*param), Node::Param(i));
+        }
+        let root = compiler.compile_expr(expr)?;
+        let zero = N::from_f64(0.0, nctx).unwrap();
+        Ok(CompiledExpr {
+            root,
+            nparams: params.len(),
+            nctx,
+            locals: RefCell::new(vec![zero; compiler.nlocals]),
+            stack: RefCell::new(Vec::new()),
+        })
+    }
+
+    /// Parses and compiles a single expression.
+    pub fn parse(ctx: &EvalContext, source: &str, params: &[&str]) -> Result<Self, MathParseError> {
+        let vars = params.iter().cloned().chain(ctx.vars().map(|(name, _)| name));
+        let vars = vars.chain(ctx.functions().map(|(name, _)| name));
+        match parser::parse_repl_with_vars(source, vars)? {
+            ast::ReplTree::Expr(expr) => CompiledExpr::new(ctx, &expr, params),
+            ast::ReplTree::Empty => Err(MathParseError::ExpectedValue),
+        }
+    }
+
+    pub fn num_params(&self) -> usize {
+        self.nparams
+    }
+
+    /// Evaluates the expression with `args` bound to the parameters in order.
+    /// Errors, such as a native function failing or an exact division by
+    /// zero, are returned rather than giving NaN as `eval` does.
+    ///
+    /// Panics if `args` does not have one value per parameter.
+    pub fn try_eval(&self, args: &[N]) -> Result<N, MathParseError> {
+        assert_eq!(args.len(), self.nparams, "wrong number of arguments");
+        let mut eval = Eval {
+            args,
+            nctx: self.nctx,
+            locals: &mut self.locals.borrow_mut(),
+            stack: &mut self.stack.borrow_mut(),
+        };
+        let result = eval.node(&self.root);
+        eval.stack.clear();
+        result
+    }
+}
+
+impl CompiledExpr<f64> {
+    /// Evaluates the expression with `args` bound to the parameters in order.
+    /// A native function that fails makes the result NaN; use `try_eval` to
+    /// get the error instead.
+    ///
+    /// Panics if `args` does not have one value per parameter.
+    pub fn eval(&self, args: &[f64]) -> f64 {
+        self.try_eval(args).unwrap_or(f64::NAN)
+    }
+}
+
+struct Eval<'a, N: Number> {
+    args: &'a [N],
+    nctx: N::Context,
+    locals: &'a mut [N],
+    stack: &'a mut Vec<N>,
+}
+
+impl<'a, N: Number> Eval<'a, N> {
+    fn node(&mut self, node: &Node<N>) -> Result<N, MathParseError> {
+        match *node {
+            Node::Const(ref x) => Ok(x.clone()),
+            Node::Param(i) => Ok(self.args[i].clone()),
+            Node::Local(i) => Ok(self.locals[i].clone()),
+            Node::Store(i, ref value) => {
+                let x = self.node(value)?;
+                self.locals[i] = x.clone();
+                Ok(x)
+            }
+            Node::Seq(ref nodes) => {
+                let (last, init) = nodes.split_last().unwrap();
+                for node in init.iter() {
+                    self.node(node)?;
+                }
+                self.node(last)
+            }
+            Node::Binary(op, ref a, ref b) => {
+                let a = self.node(a)?;
+                let b = self.node(b)?;
+                apply_binop(op, &a, &b, self.nctx)
+            }
+            Node::Neg(ref a) => Ok(self.node(a)?.neg()),
+            Node::If(ref cond, ref a, ref b) => {
+                if self.cond(cond)? {
+                    self.node(a)
+                } else {
+                    self.node(b)
+                }
+            }
+            Node::Call(ref name, ref native, ref arg_nodes) => {
+                let base = self.stack.len();
+                for arg in arg_nodes.iter() {
+                    let x = self.node(arg)?;
+                    self.stack.push(x);
+                }
+                let result = N::call_native(native, &self.stack[base..], self.nctx)
+                    .map_err(|err| MathParseError::NativeFunctionError(name.clone(), err));
+                self.stack.truncate(base);
+                result
+            }
+            Node::Iterated(op, i, ref lo, ref hi, ref term) => {
+                let lo = self.node(lo)?;
+                let hi = self.node(hi)?;
+                let count =
+                    intrinsics::count_terms(ast::iterated_name(op), lo.to_f64(), hi.to_f64())?;
+                let one = N::from_f64(1.0, self.nctx).unwrap();
+                let mut acc = if op == ast::BinOp::Times {
+                    one.clone()
+                } else {
+                    N::from_f64(0.0, self.nctx).unwrap()
+                };
+                let mut index = lo;
+                for _ in 0..count {
+                    self.locals[i] = index.clone();
+                    let x = self.node(term)?;
+                    acc = apply_binop(op, &acc, &x, self.nctx)?;
+                    index = index.add(&one);
+                }
+                Ok(acc)
+            }
+        }
+    }
+
+    fn cond(&mut self, cond: &Cond<N>) -> Result<bool, MathParseError> {
+        match *cond {
+            Cond::Const(b) => Ok(b),
+            Cond::Compare(op, ref a, ref b) => {
+                let a = self.node(a)?;
+                let b = self.node(b)?;
+                Ok(compare(op, &a, &b))
+            }
+            Cond::Not(ref c) => Ok(!self.cond(c)?),
+        }
+    }
+}
+
+/// Applies an arithmetic operator. Powers with no result of kind `N` are
+/// taken in floating point and converted back.
+fn apply_binop<N: Number>(
+    op: ast::BinOp,
+    a: &N,
+    b: &N,
+    nctx: N::Context,
+) -> Result<N, MathParseError> {
+    match op {
+        ast::BinOp::Plus => Ok(a.add(b)),
+        ast::BinOp::Minus => Ok(a.sub(b)),
+        ast::BinOp::Times => Ok(a.mul(b)),
+        ast::BinOp::Slash => a.div(b),
+        ast::BinOp::Exp => match a.pow(b)? {
+            Some(x) => Ok(x),
+            None => {
+                let x = a.to_f64().powf(b.to_f64());
+                N::from_f64(x, nctx).ok_or_else(|| {
+                    MathParseError::TypeError(format!("no result for {} ^ {}", a, b))
+                })
+            }
+        },
+        _ => unreachable!(),
+    }
+}
+
+/// Applies a comparison. Numbers that cannot be ordered have already been
+/// rejected when compiling.
+fn compare<N: Number>(op: ast::BinOp, a: &N, b: &N) -> bool {
+    let ord = a.compare(b);
+    match op {
+        ast::BinOp::Equal => a == b,
+        ast::BinOp::NotEqual => a != b,
+        ast::BinOp::Less => ord == Some(Ordering::Less),
+        ast::BinOp::LessEqual => ord.is_some_and(|ord| ord != Ordering::Greater),
+        ast::BinOp::Greater => ord == Some(Ordering::Greater),
+        ast::BinOp::GreaterEqual => ord.is_some_and(|ord| ord != Ordering::Less),
+        _ => unreachable!(),
+    }
+}
+
+fn unsupported_bool() -> MathParseError {
+    MathParseError::UnsupportedByCompiler(String::from("boolean values outside of if conditions"))
+}
+
+struct Compiler<'c, N: Number> {
+    ctx: &'c EvalContext,
+    nctx: N::Context,
+    /// One scope for the top level, plus one per function being inlined.
+    /// Names map to `Param` or `Local` nodes.
+    scopes: Vec<HashMap<String, Node<N>>>,
+    nlocals: usize,
+    inlining: Vec<String>,
+}
+
+impl<'c, N: Number> Compiler<'c, N> {
+    fn new_local(&mut self) -> usize {
+        self.nlocals += 1;
+        self.nlocals - 1
+    }
+
+    fn lookup(&self, name: &str) -> Result<Node<N>, MathParseError> {
+        match self.scopes.last().unwrap().get(name) {
+            Some(&Node::Param(i)) => return Ok(Node::Param(i)),
+            Some(&Node::Local(i)) => return Ok(Node::Local(i)),
+            _ => (),
+        }
+        // Like the evaluator, function bodies only see their own frame and
+        // the predefined constants.
+        let global = if self.scopes.len() == 1 {
+            self.ctx.get_var(name)
+        } else {
+            match name {
+                "pi" => Some(Value::Num(f64::consts::PI)),
+                "e" => Some(Value::Num(f64::consts::E)),
+                _ => None,
+            }
+        };
+        let num = global.as_ref().and_then(|val| {
+            N::from_value(val).or_else(|| val.as_num().and_then(|x| N::from_f64(x, self.nctx)))
+        });
+        match (num, global) {
+            (Some(x), _) => Ok(Node::Const(x)),
+            (None, Some(val)) => Err(MathParseError::UnsupportedByCompiler(format!(
+                "{} variable '{}'",
+                val.type_name(),
+                name
+            ))),
+            (None, None) => Err(MathParseError::UnknownIdentifier(String::from(name))),
+        }
+    }
+
+    fn compile_block(&mut self, block: &ast::Block) -> Result<Node<N>, MathParseError> {
+        match *block {
+            ast::Block::Exprs(ref exprs) => {
+                let mut nodes = Vec::with_capacity(exprs.len());
+                for expr in exprs.iter() {
+                    nodes.push(self.compile_expr(expr)?);
+                }
+                if nodes.len() == 1 {
+                    Ok(nodes.pop().unwrap())
+                } else {
+                    Ok(Node::Seq(nodes.into_boxed_slice()))
+                }
+            }
+            ast::Block::Empty => Err(MathParseError::ExpectedValue),
+        }
+    }
+
+    fn compile_expr(&mut self, e: &ast::Expr) -> Result<Node<N>, MathParseError> {
+        match e.expr_type {
+            ast::ExprType::NumLit(n, ref lit) => Ok(Node::Const(
+                N::parse(lit, self.nctx)
+                    .or_else(|| N::from_f64(n, self.nctx))
+                    .unwrap(),
+            )),
+            ast::ExprType::BoolLit(_) => Err(unsupported_bool()),
+            ast::ExprType::Var(ref name) => self.lookup(name),
+            ast::ExprType::Binary(op, ..) if op.is_comparison() => Err(unsupported_bool()),
+            ast::ExprType::Binary(ast::BinOp::PlusMinus, ..) => Err(
+                MathParseError::UnsupportedByCompiler(String::from("uncertainties")),
+            ),
+            ast::ExprType::Binary(op, ref a, ref b) => {
+                match (self.compile_expr(a)?, self.compile_expr(b)?) {
+                    // An error such as an exact division by zero is left for
+                    // evaluation, which may not reach it.
+                    (Node::Const(a), Node::Const(b)) => match apply_binop(op, &a, &b, self.nctx) {
+                        Ok(x) => Ok(Node::Const(x)),
+                        Err(_) => Ok(Node::Binary(
+                            op,
+                            Box::new(Node::Const(a)),
+                            Box::new(Node::Const(b)),
+                        )),
+                    },
+                    (a, b) => Ok(Node::Binary(op, Box::new(a), Box::new(b))),
+                }
+            }
+            ast::ExprType::Assign(ref name, ref expr) => {
+                let value = self.compile_expr(expr)?;
+                let i = match self.scopes.last().unwrap().get(name) {
+                    Some(&Node::Local(i)) => i,
+                    _ => self.new_local(),
+                };
+                self.scopes
+                    .last_mut()
+                    .unwrap()
+                    .insert(name.clone(), Node::Local(i));
+                Ok(Node::Store(i, Box::new(value)))
+            }
+            ast::ExprType::Unary(ast::UnOp::Neg, ref a) => match self.compile_expr(a)? {
+                Node::Const(x) => Ok(Node::Const(x.neg())),
+                a => Ok(Node::Neg(Box::new(a))),
+            },
+            ast::ExprType::Unary(ast::UnOp::Not, _) => Err(unsupported_bool()),
+            ast::ExprType::If(ref cond, ref a, ref b) => match self.compile_cond(cond)? {
+                Cond::Const(true) => self.compile_block(a),
+                Cond::Const(false) => self.compile_block(b),
+                cond => Ok(Node::If(
+                    Box::new(cond),
+                    Box::new(self.compile_block(a)?),
+                    Box::new(self.compile_block(b)?),
+                )),
+            },
+            ast::ExprType::FuncCall(ref name, ref args) => self.compile_call(name, args),
+            ast::ExprType::StrLit(_) => Err(MathParseError::UnsupportedByCompiler(String::from(
+                "strings",
+            ))),
+            ast::ExprType::ListLit(_) | ast::ExprType::Index(..) | ast::ExprType::Slice(..) => {
+                Err(MathParseError::UnsupportedByCompiler(String::from("lists")))
+            }
+            ast::ExprType::ImagLit(x) => match N::from_value(&Value::Complex(Complex::new(0.0, x)))
+            {
+                Some(z) => Ok(Node::Const(z)),
+                None => Err(MathParseError::UnsupportedByCompiler(String::from(
+                    "complex numbers",
+                ))),
+            },
+            ast::ExprType::WithUnit(..) | ast::ExprType::ConvertTo(..) => Err(
+                MathParseError::UnsupportedByCompiler(String::from("units")),
+            ),
+            ast::ExprType::TupleLit(_) | ast::ExprType::Destructure(..) => Err(
+                MathParseError::UnsupportedByCompiler(String::from("tuples")),
+            ),
+            ast::ExprType::RecordLit(_)
+            | ast::ExprType::Field(..)
+            | ast::ExprType::AssignField(..) => Err(MathParseError::UnsupportedByCompiler(
+                String::from("records"),
+            )),
+            ast::ExprType::FuncDef(..) => Err(MathParseError::UnsupportedByCompiler(String::from(
+                "function definitions in compiled expressions",
+            ))),
+            ast::ExprType::Solve(..) => Err(MathParseError::UnsupportedByCompiler(String::from(
+                "solve",
+            ))),
+            ast::ExprType::Iterated(op, ref index, ref lo, ref hi, ref term) => {
+                // A function of the same name hides the notation.
+                let name = ast::iterated_name(op);
+                if self.ctx.native_rc(name).is_some() || self.ctx.user_fn(name).is_some() {
+                    let args = ast::iterated_args(e.line, index, lo, hi, term);
+                    return self.compile_call(name, &args);
+                }
+                let lo = self.compile_expr(lo)?;
+                let hi = self.compile_expr(hi)?;
+                // The index shadows any variable of the same name in the
+                // term only.
+                let i = self.new_local();
+                let shadowed = self
+                    .scopes
+                    .last_mut()
+                    .unwrap()
+                    .insert(index.clone(), Node::Local(i));
+                let term = self.compile_expr(term);
+                let scope = self.scopes.last_mut().unwrap();
+                match shadowed {
+                    Some(node) => {
+                        scope.insert(index.clone(), node);
+                    }
+                    None => {
+                        scope.remove(index);
+                    }
+                }
+                Ok(Node::Iterated(
+                    op,
+                    i,
+                    Box::new(lo),
+                    Box::new(hi),
+                    Box::new(term?),
+                ))
+            }
+        }
+    }
+
+    fn compile_cond(&mut self, e: &ast::Expr) -> Result<Cond<N>, MathParseError> {
+        match e.expr_type {
+            ast::ExprType::BoolLit(b) => Ok(Cond::Const(b)),
+            ast::ExprType::Unary(ast::UnOp::Not, ref c) => match self.compile_cond(c)? {
+                Cond::Const(b) => Ok(Cond::Const(!b)),
+                c => Ok(Cond::Not(Box::new(c))),
+            },
+            ast::ExprType::Binary(op, ..)
+                if !N::ORDERED && op != ast::BinOp::Equal && op != ast::BinOp::NotEqual =>
+            {
+                Err(MathParseError::TypeError(format!(
+                    "cannot apply '{}' to numbers that cannot be ordered",
+                    op.symbol()
+                )))
+            }
+            ast::ExprType::Binary(op, ref a, ref b) if op.is_comparison() => {
+                match (self.compile_expr(a)?, self.compile_expr(b)?) {
+                    (Node::Const(ref a), Node::Const(ref b)) => Ok(Cond::Const(compare(op, a, b))),
+                    (a, b) => Ok(Cond::Compare(op, Box::new(a), Box::new(b))),
+                }
+            }
+            _ => Err(MathParseError::UnsupportedByCompiler(String::from(
+                "if conditions other than comparisons",
+            ))),
+        }
+    }
+
+    fn compile_call(&mut self, name: &str, args: &[ast::Expr]) -> Result<Node<N>, MathParseError> {
+        if let Some(native) = self.ctx.native_rc(name) {
+            if !native.is_numeric() {
+                return Err(MathParseError::UnsupportedByCompiler(format!(
+                    "non-numeric function '{}'",
+                    name
+                )));
+            }
+            let arity = native.arity();
+            if !arity.accepts(args.len()) {
+                return Err(MathParseError::WrongNumberOfArguments(
+                    arity.min(),
+                    args.len(),
+                ));
+            }
+            let mut nodes = Vec::with_capacity(args.len());
+            for arg in args.iter() {
+                nodes.push(self.compile_expr(arg)?);
+            }
+            if native.is_pure() {
+                let consts: Vec<N> = nodes
+                    .iter()
+                    .filter_map(|node| match *node {
+                        Node::Const(ref x) => Some(x.clone()),
+                        _ => None,
+                    })
+                    .collect();
+                // As with operators, an error is left for evaluation.
+                if consts.len() == nodes.len() {
+                    if let Ok(x) = N::call_native(&native, &consts, self.nctx) {
+                        return Ok(Node::Const(x));
+                    }
+                }
+            }
+            return Ok(Node::Call(
+                String::from(name),
+                native,
+                nodes.into_boxed_slice(),
+            ));
+        }
+
+        if intrinsics::lookup(name).is_some() {
+            return Err(MathParseError::UnsupportedByCompiler(format!(
+                "non-numeric function '{}'",
+                name
+            )));
+        }
+        let func = match self.ctx.user_fn(name) {
+            Some(func) => func,
+            None => return Err(MathParseError::UnknownIdentifier(String::from(name))),
+        };
+        if func.params.len() != args.len() {
+            return Err(MathParseError::WrongNumberOfArguments(
+                func.params.len(),
+                args.len(),
+            ));
+        }
+        if self.inlining.iter().any(|f| f == name) {
+            return Err(MathParseError::UnsupportedByCompiler(format!(
+                "recursive call to '{}'",
+                name
+            )));
+        }
+
+        // Arguments are evaluated once, in the caller's scope, into fresh
+        // locals that the inlined body reads as its parameters.
+        let mut nodes = Vec::with_capacity(args.len() + 1);
+        let mut scope = HashMap::new();
+        for (param, arg) in func.params.iter().zip(args.iter()) {
+            let value = self.compile_expr(arg)?;
+            let i = self.new_local();
+            nodes.push(Node::Store(i, Box::new(value)));
+            scope.insert(param.clone(), Node::Local(i));
+        }
+        self.scopes.push(scope);
+        self.inlining.push(String::from(name));
+        let body = self.compile_block(&func.body);
+        self.inlining.pop();
+        self.scopes.pop();
+        nodes.push(body?);
+        Ok(Node::Seq(nodes.into_boxed_slice()))
+    }
+}
@@ -1,15 +1,91 @@
 use visitor::Visitor;
-use std::rc::Rc;
 use std::collections::HashMap;
 use ast;
-use std::mem::{size_of, transmute};
+use std::io::Write;
+use std::mem::size_of;
+use builtins;
+use parser::MathParseError;
 use vm;
 
 type Chunk = Vec<BCUnit>;
 
-pub fn compile(block: &ast::Block) -> Vec<u8> {
-    let main = Func::compile(block, &Vec::new());
-    unimplemented!()
+type CompileResult<T> = Result<T, MathParseError>;
+
+/// A program compiled to bytecode for the VM.
+#[derive(Debug, Clone)]
+pub struct Program {
+    code: Vec<u8>,
+    strings: Vec<String>,
+}
+
+impl Program {
+    pub fn run(&self) -> Result<(), MathParseError> {
+        vm::VM::new(&self.code, &self.strings).run()
+    }
+
+    /// Runs the program, writing what it prints to `out`.
+    pub fn run_with_output(&self, out: &mut dyn Write) -> Result<(), MathParseError> {
+        vm::VM::with_output(&self.code, &self.strings, Box::new(out)).run()
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.code
+    }
+}
+
+/// Compiles a file to bytecode. Functions must be defined at the top level,
+/// and each function or the main program can use at most 255 variables.
+/// Values are numbers or strings, and each variable must keep to one of the
+/// two; functions take and return numbers only.
+pub fn compile(block: &ast::Block) -> CompileResult<Program> {
+    let mut signatures = Signatures::new();
+    let mut defs: Vec<(&[String], &ast::Block)> = Vec::new();
+    if let ast::Block::Exprs(ref exprs) = *block {
+        for expr in exprs.iter() {
+            if let ast::ExprType::FuncDef(ref name, ref params, ref body) = expr.expr_type {
+                if signatures.contains_key(name) {
+                    return Err(MathParseError::UnsupportedByCompiler(format!(
+                        "redefinition of function '{}'",
+                        name
+                    )));
+                }
+                signatures.insert(name.clone(), (defs.len(), params.len()));
+                defs.push((params, body));
+            }
+        }
+    }
+
+    let mut strings = Vec::new();
+    let mut chunks = vec![Func::compile_main(&signatures, &mut strings, block)?];
+    for &(params, body) in defs.iter() {
+        chunks.push(Func::compile_def(&signatures, &mut strings, body, params)?);
+    }
+    Ok(Program {
+        code: link(&chunks),
+        strings,
+    })
+}
+
+/// Lays the main chunk out first, followed by each function, and resolves
+/// function constants to their start addresses.
+fn link(chunks: &[Chunk]) -> Vec<u8> {
+    let mut addresses = Vec::with_capacity(chunks.len());
+    let mut addr = 0;
+    for chunk in chunks.iter() {
+        addresses.push(addr);
+        addr += chunk.iter().map(|unit| unit.size()).sum::<usize>();
+    }
+    let mut program = Vec::with_capacity(addr);
+    for chunk in chunks.iter() {
+        for unit in chunk.iter() {
+            match *unit {
+                // Function 0 is the main chunk.
+                BCUnit::FuncConst(i) => BCUnit::FuncConst(addresses[i + 1]),
+                unit => unit,
+            }.push_to_vec(&mut program);
+        }
+    }
+    program
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -17,37 +93,53 @@ enum BCUnit {
     Byte(u8),
     Float64(f64),
     FuncConst(usize),
+    Usize(usize),
 }
 
 const USIZE_SIZE: usize = size_of::<usize>();
 
 impl BCUnit {
+    fn size(self) -> usize {
+        match self {
+            BCUnit::Byte(_) => 1,
+            BCUnit::Float64(_) => 8,
+            BCUnit::FuncConst(_) | BCUnit::Usize(_) => USIZE_SIZE,
+        }
+    }
+
     fn push_to_vec(self, v: &mut Vec<u8>) {
         use self::BCUnit::*;
         match self {
             Byte(b) => v.push(b),
-            Float64(f) => unsafe {
-                let oldlen = v.len();
-                v.reserve(8);
-                v.set_len(oldlen + 8);
-                *transmute::<_, *mut f64>(&mut v[oldlen]) = f;
-            },
-            FuncConst(i) => unsafe {
-                let oldlen = v.len();
-                v.reserve(USIZE_SIZE);
-                v.set_len(oldlen + USIZE_SIZE);
-                *transmute::<_, *mut usize>(&mut v[oldlen]) = i;
-            },
+            Float64(f) => v.extend_from_slice(&f.to_ne_bytes()),
+            FuncConst(i) | Usize(i) => v.extend_from_slice(&i.to_ne_bytes()),
+        }
+    }
+}
+
+/// Maps each function name to its index and number of parameters.
+type Signatures = HashMap<String, (usize, usize)>;
+
+/// The type of a value on the VM stack. Both take one 8-byte slot.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Type {
+    Num,
+    Str,
+}
+
+impl Type {
+    fn name(self) -> &'static str {
+        match self {
+            Type::Num => "number",
+            Type::Str => "string",
         }
     }
 }
 
 #[derive(Debug)]
 struct Scope {
-    vars: HashMap<String, usize>,
+    vars: HashMap<String, (usize, Type)>,
     varsc: usize,
-    funcs: HashMap<String, usize>,
-    funcsc: usize,
 }
 
 impl Scope {
@@ -55,80 +147,483 @@ impl Scope {
         Scope {
             vars: HashMap::new(),
             varsc: 0,
-            funcs: HashMap::new(),
-            funcsc: 0,
         }
     }
 
-    fn add_var(&mut self, name: &str) {
-        if let None = self.vars.insert(String::from(name), self.varsc) {
-            self.varsc += 1;
+    fn add_var(&mut self, name: &str, ty: Type) -> CompileResult<u8> {
+        if let Some(&(i, old_ty)) = self.vars.get(name) {
+            if old_ty != ty {
+                return Err(MathParseError::UnsupportedByCompiler(format!(
+                    "variable '{}' holding both a {} and a {}",
+                    name,
+                    old_ty.name(),
+                    ty.name()
+                )));
+            }
+            return Ok(i as u8);
         }
+        let i = self.add_slot()?;
+        self.vars.insert(String::from(name), (i as usize, ty));
+        Ok(i)
     }
 
-    fn add_func(&mut self, name: &str) {
-        if let None = self.vars.insert(String::from(name), self.funcsc) {
-            self.funcsc += 1;
+    /// Adds a slot that no name refers to.
+    fn add_slot(&mut self) -> CompileResult<u8> {
+        if self.varsc >= u8::MAX as usize {
+            return Err(MathParseError::UnsupportedByCompiler(String::from(
+                "more than 255 variables in one scope",
+            )));
         }
+        self.varsc += 1;
+        Ok((self.varsc - 1) as u8)
     }
 }
 
 #[derive(Debug)]
-struct Func {
+struct Func<'s> {
+    signatures: &'s Signatures,
+    /// The string constants of the whole program.
+    strings: &'s mut Vec<String>,
     scope: Scope,
     chunk: Chunk,
+    is_main: bool,
 }
 
-impl Func {
-    fn compile(funcs: Vec<Func>, b: &ast::Block, args: &Vec<String>) -> Self {
-        let mut this = Func {
+impl<'s> Func<'s> {
+    fn new(signatures: &'s Signatures, strings: &'s mut Vec<String>, is_main: bool) -> Self {
+        Func {
+            signatures,
+            strings,
             scope: Scope::new(),
             chunk: Chunk::new(),
+            is_main,
+        }
+    }
+
+    fn compile_main(
+        signatures: &'s Signatures,
+        strings: &'s mut Vec<String>,
+        b: &ast::Block,
+    ) -> CompileResult<Chunk> {
+        let mut this = Func::new(signatures, strings, true);
+        this.visit_block(b)?;
+        let mut chunk = vec![
+            BCUnit::Byte(vm::SET_CTX),
+            BCUnit::Byte(vm::ZERO_64_U8),
+            BCUnit::Byte(this.scope.varsc as u8),
+        ];
+        chunk.append(&mut this.chunk);
+        chunk.push(BCUnit::Byte(vm::EXIT));
+        Ok(chunk)
+    }
+
+    fn compile_def(
+        signatures: &'s Signatures,
+        strings: &'s mut Vec<String>,
+        b: &ast::Block,
+        params: &[String],
+    ) -> CompileResult<Chunk> {
+        let mut this = Func::new(signatures, strings, false);
+        for param in params.iter() {
+            this.scope.add_var(param, Type::Num)?;
+        }
+        match this.visit_block(b)? {
+            Some(Type::Num) => (),
+            Some(ty) => return Err(type_error(Type::Num, ty)),
+            None => return Err(MathParseError::ExpectedValue),
+        }
+        let mut chunk = vec![
+            BCUnit::Byte(vm::ENTER),
+            BCUnit::Byte(params.len() as u8),
+            BCUnit::Byte((this.scope.varsc - params.len()) as u8),
+        ];
+        chunk.append(&mut this.chunk);
+        chunk.push(BCUnit::Byte(vm::LEAVE_F64));
+        chunk.push(BCUnit::Byte(this.scope.varsc as u8));
+        Ok(chunk)
+    }
+
+    /// Compiles an expression that must leave a value on the stack.
+    fn compile_value(&mut self, e: &ast::Expr) -> CompileResult<Type> {
+        self.visit_expr(e)?.ok_or(MathParseError::ExpectedValue)
+    }
+
+    fn compile_num(&mut self, e: &ast::Expr) -> CompileResult<()> {
+        match self.compile_value(e)? {
+            Type::Num => Ok(()),
+            ty => Err(type_error(Type::Num, ty)),
+        }
+    }
+
+    fn compile_str(&mut self, e: &ast::Expr) -> CompileResult<()> {
+        match self.compile_value(e)? {
+            Type::Str => Ok(()),
+            ty => Err(type_error(Type::Str, ty)),
+        }
+    }
+
+    /// Compiles the arguments of `format` and formats them into the format
+    /// string already on the stack.
+    fn compile_format_args(&mut self, args: &[ast::Expr]) -> CompileResult<()> {
+        if args.len() > u8::MAX as usize {
+            return Err(MathParseError::UnsupportedByCompiler(String::from(
+                "more than 255 format arguments",
+            )));
+        }
+        let mut kinds = Vec::with_capacity(args.len());
+        for arg in args.iter() {
+            kinds.push(match self.compile_value(arg)? {
+                Type::Num => vm::KIND_F64,
+                Type::Str => vm::KIND_STR,
+            });
+        }
+        self.chunk.push(BCUnit::Byte(vm::FORMAT));
+        self.chunk.push(BCUnit::Byte(kinds.len() as u8));
+        self.chunk.extend(kinds.into_iter().map(BCUnit::Byte));
+        Ok(())
+    }
+
+    /// The size in bytes of the code compiled so far.
+    fn offset(&self) -> usize {
+        self.chunk.iter().map(|unit| unit.size()).sum()
+    }
+
+    /// Compiles `sum` or `prod` to a loop. The index and total live in
+    /// slots of their own, as does the number of terms left after the
+    /// current one, which the loop counts down to below zero.
+    fn compile_iterated(
+        &mut self,
+        op: ast::BinOp,
+        index: &str,
+        lo: &ast::Expr,
+        hi: &ast::Expr,
+        term: &ast::Expr,
+    ) -> CompileResult<()> {
+        use self::BCUnit::*;
+
+        let i = self.scope.add_slot()?;
+        let left = self.scope.add_slot()?;
+        let acc = self.scope.add_slot()?;
+        let (start, combine) = match op {
+            ast::BinOp::Times => (1.0, vm::MUL_F64),
+            _ => (0.0, vm::ADD_F64),
         };
-        this.visit_block(b);
-        this
+        self.compile_num(lo)?;
+        self.compile_num(hi)?;
+        self.chunk.extend_from_slice(&[
+            Byte(vm::CHECK_BOUNDS_F64),
+            Byte(combine),
+            Byte(vm::STORE_F64_U8),
+            Byte(left),
+            Byte(vm::POP_F64),
+            Byte(vm::STORE_F64_U8),
+            Byte(i),
+            Byte(vm::POP_F64),
+            Byte(vm::LOAD_F64_U8),
+            Byte(left),
+            Byte(vm::LOAD_F64_U8),
+            Byte(i),
+            Byte(vm::SUB_F64),
+            Byte(vm::STORE_F64_U8),
+            Byte(left),
+            Byte(vm::POP_F64),
+            Byte(vm::CONST_F64),
+            Float64(start),
+            Byte(vm::STORE_F64_U8),
+            Byte(acc),
+            Byte(vm::POP_F64),
+        ]);
+
+        let top = self.offset();
+        self.chunk.extend_from_slice(&[
+            Byte(vm::CONST_F64),
+            Float64(0.0),
+            Byte(vm::LOAD_F64_U8),
+            Byte(left),
+        ]);
+        let exit_jump = self.offset();
+        self.chunk.push(Byte(vm::JUMP_GT_F64));
+        let exit_operand = self.chunk.len();
+        self.chunk.push(Usize(0));
+
+        self.chunk.push(Byte(vm::LOAD_F64_U8));
+        self.chunk.push(Byte(acc));
+        // The index hides any variable of the same name in the term only.
+        let shadowed = self
+            .scope
+            .vars
+            .insert(String::from(index), (i as usize, Type::Num));
+        let compiled = self.compile_num(term);
+        match shadowed {
+            Some(var) => {
+                self.scope.vars.insert(String::from(index), var);
+            }
+            None => {
+                self.scope.vars.remove(index);
+            }
+        }
+        compiled?;
+        self.chunk.extend_from_slice(&[
+            Byte(combine),
+            Byte(vm::STORE_F64_U8),
+            Byte(acc),
+            Byte(vm::POP_F64),
+            Byte(vm::LOAD_F64_U8),
+            Byte(i),
+            Byte(vm::CONST_F64),
+            Float64(1.0),
+            Byte(vm::ADD_F64),
+            Byte(vm::STORE_F64_U8),
+            Byte(i),
+            Byte(vm::POP_F64),
+            Byte(vm::LOAD_F64_U8),
+            Byte(left),
+            Byte(vm::CONST_F64),
+            Float64(1.0),
+            Byte(vm::SUB_F64),
+            Byte(vm::STORE_F64_U8),
+            Byte(left),
+            Byte(vm::POP_F64),
+        ]);
+        let back_jump = self.offset();
+        self.chunk.push(Byte(vm::JUMP_BACK));
+        self.chunk.push(Usize(back_jump - top));
+
+        let exit = self.offset();
+        self.chunk[exit_operand] = Usize(exit - exit_jump);
+        self.chunk.push(Byte(vm::LOAD_F64_U8));
+        self.chunk.push(Byte(acc));
+        Ok(())
     }
 }
 
-impl Visitor<bool> for Func {
-    fn visit_block(&mut self, f: &ast::Block) -> bool {
+fn type_error(expected: Type, got: Type) -> MathParseError {
+    MathParseError::TypeError(format!("expected {}, got {}", expected.name(), got.name()))
+}
+
+impl<'s> Visitor<CompileResult<Option<Type>>> for Func<'s> {
+    fn visit_block(&mut self, f: &ast::Block) -> CompileResult<Option<Type>> {
         match *f {
-            ast::Block::Exprs(ref exprs) if exprs.len() == 0 => false,
             ast::Block::Exprs(ref exprs) => {
-                let mut should_pop = false;
+                let mut last = None;
                 for expr in exprs.iter() {
-                    if should_pop {
+                    if last.is_some() {
                         self.chunk.push(BCUnit::Byte(vm::POP_F64));
                     }
-                    should_pop = self.visit_expr(expr);
+                    last = self.visit_expr(expr)?;
                 }
-                should_pop
+                Ok(last)
             }
-            ast::Block::Empty => false,
+            ast::Block::Empty => Ok(None),
         }
     }
 
-    fn visit_repltree(&mut self, t: &ast::ReplTree) -> bool {
-        unimplemented!()
+    fn visit_repltree(&mut self, t: &ast::ReplTree) -> CompileResult<Option<Type>> {
+        match *t {
+            ast::ReplTree::Expr(ref expr) => self.visit_expr(expr),
+            ast::ReplTree::Empty => Ok(None),
+        }
     }
 
-    fn visit_expr(&mut self, e: &ast::Expr) -> bool {
+    fn visit_expr(&mut self, e: &ast::Expr) -> CompileResult<Option<Type>> {
         use ast::ExprType;
 
-        match e.expr_type {
-            ExprType::NumLit(num) => {
+        let ty = match e.expr_type {
+            ExprType::NumLit(num, _) => {
                 self.chunk.push(BCUnit::Byte(vm::CONST_F64));
                 self.chunk.push(BCUnit::Float64(num));
-                true
+                Type::Num
             }
-            ExprType::Binary(ref op, ref a, ref b) => {
-                self.visit_expr(a);
-                self.visit_expr(b);
-                self.chunk.push(BCUnit::Byte(vm::ADD_F64));
-                true
+            ExprType::StrLit(ref s) => {
+                let i = match self.strings.iter().position(|other| other == s) {
+                    Some(i) => i,
+                    None => {
+                        self.strings.push(s.clone());
+                        self.strings.len() - 1
+                    }
+                };
+                self.chunk.push(BCUnit::Byte(vm::CONST_STR));
+                self.chunk.push(BCUnit::Usize(i));
+                Type::Str
             }
-            ExprType::Var(_) => unimplemented!(),
-            ExprType::FuncCall(..) | ExprType::FuncDef(..) | ExprType::Assign(..) => unimplemented!()
-        }
+            ExprType::Binary(op, ref a, ref b) => {
+                let a_ty = self.compile_value(a)?;
+                let b_ty = self.compile_value(b)?;
+                match (a_ty, b_ty) {
+                    (Type::Num, Type::Num) => (),
+                    (Type::Str, Type::Str) if op == ast::BinOp::Plus => {
+                        self.chunk.push(BCUnit::Byte(vm::CONCAT_STR));
+                        return Ok(Some(Type::Str));
+                    }
+                    _ => {
+                        return Err(MathParseError::TypeError(format!(
+                            "cannot apply '{}' to {} and {}",
+                            op.symbol(),
+                            a_ty.name(),
+                            b_ty.name()
+                        )))
+                    }
+                }
+                self.chunk.push(BCUnit::Byte(match op {
+                    ast::BinOp::Plus => vm::ADD_F64,
+                    ast::BinOp::Minus => vm::SUB_F64,
+                    ast::BinOp::Times => vm::MUL_F64,
+                    ast::BinOp::Slash => vm::DIV_F64,
+                    ast::BinOp::Exp => vm::POW_F64,
+                    ast::BinOp::PlusMinus => {
+                        return Err(MathParseError::UnsupportedByCompiler(String::from(
+                            "uncertainties",
+                        )))
+                    }
+                    _ => {
+                        return Err(MathParseError::UnsupportedByCompiler(format!(
+                            "comparison operator '{}'",
+                            op.symbol()
+                        )))
+                    }
+                }));
+                Type::Num
+            }
+            ExprType::Unary(ast::UnOp::Neg, ref a) => {
+                self.compile_num(a)?;
+                self.chunk.push(BCUnit::Byte(vm::NEG_F64));
+                Type::Num
+            }
+            ExprType::BoolLit(_) | ExprType::Unary(ast::UnOp::Not, _) => {
+                return Err(MathParseError::UnsupportedByCompiler(String::from(
+                    "boolean values",
+                )))
+            }
+            ExprType::If(..) => {
+                return Err(MathParseError::UnsupportedByCompiler(String::from(
+                    "if expressions",
+                )))
+            }
+            ExprType::ListLit(_) | ExprType::Index(..) | ExprType::Slice(..) => {
+                return Err(MathParseError::UnsupportedByCompiler(String::from("lists")))
+            }
+            ExprType::ImagLit(_) => {
+                return Err(MathParseError::UnsupportedByCompiler(String::from(
+                    "complex numbers",
+                )))
+            }
+            ExprType::WithUnit(..) | ExprType::ConvertTo(..) => {
+                return Err(MathParseError::UnsupportedByCompiler(String::from("units")))
+            }
+            ExprType::TupleLit(_) | ExprType::Destructure(..) => {
+                return Err(MathParseError::UnsupportedByCompiler(String::from("tuples")))
+            }
+            ExprType::RecordLit(_) | ExprType::Field(..) | ExprType::AssignField(..) => {
+                return Err(MathParseError::UnsupportedByCompiler(String::from("records")))
+            }
+            ExprType::Solve(..) => {
+                return Err(MathParseError::UnsupportedByCompiler(String::from("solve")))
+            }
+            // A function of the same name hides the notation.
+            ExprType::Iterated(op, ref index, ref lo, ref hi, ref term)
+                if self.signatures.contains_key(ast::iterated_name(op)) =>
+            {
+                let args = ast::iterated_args(e.line, index, lo, hi, term);
+                let call = ast::Expr {
+                    line: e.line,
+                    expr_type: ExprType::FuncCall(String::from(ast::iterated_name(op)), args),
+                };
+                return self.visit_expr(&call);
+            }
+            ExprType::Iterated(op, ref index, ref lo, ref hi, ref term) => {
+                self.compile_iterated(op, index, lo, hi, term)?;
+                Type::Num
+            }
+            ExprType::Var(ref name) => match self.scope.vars.get(name) {
+                Some(&(i, ty)) => {
+                    self.chunk.push(BCUnit::Byte(vm::LOAD_F64_U8));
+                    self.chunk.push(BCUnit::Byte(i as u8));
+                    ty
+                }
+                None => {
+                    let val = match name.as_str() {
+                        "pi" => ::std::f64::consts::PI,
+                        "e" => ::std::f64::consts::E,
+                        _ => return Err(MathParseError::UnknownIdentifier(name.clone())),
+                    };
+                    self.chunk.push(BCUnit::Byte(vm::CONST_F64));
+                    self.chunk.push(BCUnit::Float64(val));
+                    Type::Num
+                }
+            },
+            ExprType::Assign(ref name, ref expr) => {
+                let ty = self.compile_value(expr)?;
+                let i = self.scope.add_var(name, ty)?;
+                self.chunk.push(BCUnit::Byte(vm::STORE_F64_U8));
+                self.chunk.push(BCUnit::Byte(i));
+                ty
+            }
+            ExprType::FuncCall(ref name, ref args) if name == "print" && !args.is_empty() => {
+                let ty = self.compile_value(&args[0])?;
+                if args.len() > 1 {
+                    if ty != Type::Str {
+                        return Err(type_error(Type::Str, ty));
+                    }
+                    self.compile_format_args(&args[1..])?;
+                }
+                self.chunk.push(BCUnit::Byte(match ty {
+                    Type::Num => vm::PRINT_F64,
+                    Type::Str => vm::PRINT_STR,
+                }));
+                ty
+            }
+            ExprType::FuncCall(ref name, ref args) if name == "format" && !args.is_empty() => {
+                self.compile_str(&args[0])?;
+                self.compile_format_args(&args[1..])?;
+                Type::Str
+            }
+            ExprType::FuncCall(ref name, ref args) if name == "len" && args.len() == 1 => {
+                self.compile_str(&args[0])?;
+                self.chunk.push(BCUnit::Byte(vm::LEN_STR));
+                Type::Num
+            }
+            ExprType::FuncCall(ref name, ref args) => {
+                if let Some(i) = builtins::lookup(name) {
+                    let arity = builtins::BUILTINS[i].arity;
+                    if !arity.accepts(args.len()) {
+                        return Err(MathParseError::WrongNumberOfArguments(
+                            arity.min(),
+                            args.len(),
+                        ));
+                    }
+                    if args.len() > u8::MAX as usize {
+                        return Err(MathParseError::UnsupportedByCompiler(String::from(
+                            "more than 255 arguments",
+                        )));
+                    }
+                    for arg in args.iter() {
+                        self.compile_num(arg)?;
+                    }
+                    self.chunk.push(BCUnit::Byte(vm::CALL_BUILTIN));
+                    self.chunk.push(BCUnit::Byte(i as u8));
+                    self.chunk.push(BCUnit::Byte(args.len() as u8));
+                } else if let Some(&(i, plen)) = self.signatures.get(name) {
+                    if plen != args.len() {
+                        return Err(MathParseError::WrongNumberOfArguments(plen, args.len()));
+                    }
+                    for arg in args.iter() {
+                        self.compile_num(arg)?;
+                    }
+                    self.chunk.push(BCUnit::Byte(vm::CALL));
+                    self.chunk.push(BCUnit::FuncConst(i));
+                } else {
+                    return Err(MathParseError::UnknownIdentifier(name.clone()));
+                }
+                Type::Num
+            }
+            // Top-level definitions are collected by `compile`.
+            ExprType::FuncDef(..) if self.is_main => return Ok(None),
+            ExprType::FuncDef(..) => {
+                return Err(MathParseError::UnsupportedByCompiler(String::from(
+                    "nested function definitions",
+                )))
+            }
+        };
+        Ok(Some(ty))
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,289 @@
+use std::f64;
+use std::fmt::{self, Display};
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use value::Value;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub fn new(re: f64, im: f64) -> Self {
+        Complex { re, im }
+    }
+
+    pub fn from_real(re: f64) -> Self {
+        Complex { re, im: 0.0 }
+    }
+
+    pub fn from_polar(r: f64, theta: f64) -> Self {
+        Complex::new(r * theta.cos(), r * theta.sin())
+    }
+
+    pub fn abs(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    pub fn arg(self) -> f64 {
+        self.im.atan2(self.re)
+    }
+
+    pub fn conj(self) -> Self {
+        Complex::new(self.re, -self.im)
+    }
+
+    pub fn is_nan(self) -> bool {
+        self.re.is_nan() || self.im.is_nan()
+    }
+
+    /// The principal square root, with a branch cut along the negative real
+    /// axis.
+    pub fn sqrt(self) -> Self {
+        let r = self.abs();
+        let re = ((r + self.re) / 2.0).sqrt();
+        let im = ((r - self.re) / 2.0).sqrt();
+        Complex::new(re, im.copysign(self.im))
+    }
+
+    pub fn exp(self) -> Self {
+        Complex::from_polar(self.re.exp(), self.im)
+    }
+
+    /// The principal natural logarithm.
+    pub fn ln(self) -> Self {
+        Complex::new(self.abs().ln(), self.arg())
+    }
+
+    pub fn sin(self) -> Self {
+        Complex::new(
+            self.re.sin() * self.im.cosh(),
+            self.re.cos() * self.im.sinh(),
+        )
+    }
+
+    pub fn cos(self) -> Self {
+        Complex::new(
+            self.re.cos() * self.im.cosh(),
+            -self.re.sin() * self.im.sinh(),
+        )
+    }
+
+    pub fn tan(self) -> Self {
+        self.sin() / self.cos()
+    }
+
+    pub fn sinh(self) -> Self {
+        Complex::new(
+            self.re.sinh() * self.im.cos(),
+            self.re.cosh() * self.im.sin(),
+        )
+    }
+
+    pub fn cosh(self) -> Self {
+        Complex::new(
+            self.re.cosh() * self.im.cos(),
+            self.re.sinh() * self.im.sin(),
+        )
+    }
+
+    pub fn tanh(self) -> Self {
+        self.sinh() / self.cosh()
+    }
+
+    /// Raises to an integer power by repeated squaring, which keeps results
+    /// such as `i^2` exact.
+    pub fn powi(self, n: i32) -> Self {
+        let mut base = if n < 0 {
+            Complex::from_real(1.0) / self
+        } else {
+            self
+        };
+        let mut n = n.unsigned_abs();
+        let mut acc = Complex::from_real(1.0);
+        while n > 0 {
+            if n & 1 == 1 {
+                acc = acc * base;
+            }
+            base = base * base;
+            n >>= 1;
+        }
+        acc
+    }
+
+    /// The principal value of `self ^ w`.
+    pub fn pow(self, w: Complex) -> Self {
+        if w.im == 0.0 && w.re.fract() == 0.0 && w.re.abs() <= i32::MAX as f64 {
+            return self.powi(w.re as i32);
+        }
+        if self.re == 0.0 && self.im == 0.0 {
+            return if w.re > 0.0 {
+                Complex::from_real(0.0)
+            } else {
+                Complex::new(f64::NAN, f64::NAN)
+            };
+        }
+        (w * self.ln()).exp()
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+
+    fn div(self, other: Complex) -> Complex {
+        let d = other.re * other.re + other.im * other.im;
+        Complex::new(
+            (self.re * other.re + self.im * other.im) / d,
+            (self.im * other.re - self.re * other.im) / d,
+        )
+    }
+}
+
+impl Neg for Complex {
+    type Output = Complex;
+
+    fn neg(self) -> Complex {
+        Complex::new(-self.re, -self.im)
+    }
+}
+
+impl Display for Complex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.im.is_sign_negative() && !self.im.is_nan() {
+            write!(f, "{}-{}i", self.re, -self.im)
+        } else {
+            write!(f, "{}+{}i", self.re, self.im)
+        }
+    }
+}
+
+/// The complex versions of the builtins that have them, used when a builtin
+/// is called with a complex argument. The arity has already been checked.
+pub fn builtin(name: &str) -> Option<fn(&[Complex]) -> Value> {
+    macro_rules! complex {
+        ($f: expr) => {
+            Some(|args: &[Complex]| Value::Complex($f(args[0])))
+        };
+    }
+    macro_rules! real {
+        ($f: expr) => {
+            Some(|args: &[Complex]| Value::Num($f(args[0])))
+        };
+    }
+    match name {
+        "sqrt" => complex!(Complex::sqrt),
+        "exp" => complex!(Complex::exp),
+        "ln" => complex!(Complex::ln),
+        "log10" => complex!(|z: Complex| z.ln() / Complex::from_real(f64::consts::LN_10)),
+        "log2" => complex!(|z: Complex| z.ln() / Complex::from_real(f64::consts::LN_2)),
+        "sin" => complex!(Complex::sin),
+        "cos" => complex!(Complex::cos),
+        "tan" => complex!(Complex::tan),
+        "sinh" => complex!(Complex::sinh),
+        "cosh" => complex!(Complex::cosh),
+        "tanh" => complex!(Complex::tanh),
+        "conj" => complex!(Complex::conj),
+        "abs" => real!(Complex::abs),
+        "arg" => real!(Complex::arg),
+        "re" => real!(|z: Complex| z.re),
+        "im" => real!(|z: Complex| z.im),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Complex, b: Complex) -> bool {
+        (a - b).abs() < 1e-12
+    }
+
+    #[test]
+    fn arithmetic() {
+        let a = Complex::new(1.0, 2.0);
+        let b = Complex::new(3.0, -1.0);
+        assert_eq!(a * b, Complex::new(5.0, 5.0));
+        assert!(close(a / b * b, a));
+        assert_eq!(a.conj(), Complex::new(1.0, -2.0));
+        assert_eq!(Complex::new(3.0, 4.0).abs(), 5.0);
+    }
+
+    #[test]
+    fn powers() {
+        let i = Complex::new(0.0, 1.0);
+        // Integer powers are exact.
+        assert_eq!(i.powi(2), Complex::from_real(-1.0));
+        assert_eq!(i.pow(Complex::from_real(3.0)), Complex::new(0.0, -1.0));
+        assert_eq!(i.powi(-1), Complex::new(0.0, -1.0));
+        assert!(close(
+            i.pow(i),
+            Complex::from_real((-f64::consts::FRAC_PI_2).exp())
+        ));
+        assert_eq!(
+            Complex::from_real(0.0).pow(Complex::from_real(0.5)),
+            Complex::from_real(0.0)
+        );
+        assert!(Complex::from_real(0.0)
+            .pow(Complex::new(-0.5, 0.0))
+            .is_nan());
+    }
+
+    #[test]
+    fn branch_cuts() {
+        assert_eq!(Complex::from_real(-4.0).sqrt(), Complex::new(0.0, 2.0));
+        assert_eq!(Complex::new(-4.0, -0.0).sqrt(), Complex::new(0.0, -2.0));
+        assert!(close(
+            Complex::from_real(-1.0).ln(),
+            Complex::new(0.0, f64::consts::PI)
+        ));
+        let z = Complex::new(0.3, -1.7);
+        assert!(close(z.ln().exp(), z));
+        assert!(close(z.sqrt() * z.sqrt(), z));
+    }
+
+    #[test]
+    fn trig() {
+        let z = Complex::new(0.5, 0.25);
+        let one = z.sin() * z.sin() + z.cos() * z.cos();
+        assert!(close(one, Complex::from_real(1.0)));
+        assert!(close(z.tan(), z.sin() / z.cos()));
+        assert!(close(z.tanh(), z.sinh() / z.cosh()));
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Complex::new(1.0, 2.0).to_string(), "1+2i");
+        assert_eq!(Complex::new(1.0, -2.5).to_string(), "1-2.5i");
+        assert_eq!(Complex::new(0.0, -0.0).to_string(), "0-0i");
+    }
+}
@@ -0,0 +1,591 @@
+use std::cmp::Ordering;
+use std::fmt::{self, Display};
+
+use bigint::BigInt;
+use rational::Rational;
+
+/// How results are rounded to the precision of a `DecimalContext`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Rounding {
+    /// To the nearest value, with ties going to an even last digit.
+    HalfEven,
+    /// To the nearest value, with ties going away from zero.
+    HalfUp,
+    /// Towards zero.
+    Truncate,
+}
+
+/// The precision, in significant digits, and rounding mode of decimal
+/// arithmetic.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DecimalContext {
+    pub precision: usize,
+    pub rounding: Rounding,
+}
+
+impl DecimalContext {
+    /// Panics if `precision` is zero.
+    pub fn new(precision: usize, rounding: Rounding) -> Self {
+        assert!(precision > 0, "decimal precision must be at least 1");
+        DecimalContext {
+            precision,
+            rounding,
+        }
+    }
+}
+
+/// 28 digits, rounding half to even.
+impl Default for DecimalContext {
+    fn default() -> Self {
+        DecimalContext::new(28, Rounding::HalfEven)
+    }
+}
+
+/// A base-10 floating-point number `coeff * 10^exp`, rounded to the
+/// precision of its context. Trailing zeros are removed from the
+/// coefficient, so each value has one representation. An operation uses the
+/// context of its left operand.
+#[derive(Debug, Clone)]
+pub struct Decimal {
+    coeff: BigInt,
+    exp: i64,
+    ctx: DecimalContext,
+}
+
+fn pow10(n: usize) -> BigInt {
+    BigInt::from(10).pow(n as u32)
+}
+
+fn digit_count(x: &BigInt) -> usize {
+    if x.is_zero() {
+        1
+    } else {
+        x.abs().to_string().len()
+    }
+}
+
+/// Divides `coeff` by `10^drop`, rounding by `mode`. `sticky` says whether
+/// `coeff` is itself inexact, with nonzero digits below its last one.
+fn round_digits(coeff: &BigInt, drop: usize, mode: Rounding, sticky: bool) -> BigInt {
+    if drop == 0 && !sticky {
+        return coeff.clone();
+    }
+    let divisor = pow10(drop);
+    let (q, r) = coeff.div_rem(&divisor).unwrap();
+    // An inexact coefficient that looks like a tie is above it.
+    let half = match r.abs().shl(1).cmp(&divisor) {
+        Ordering::Equal if sticky => Ordering::Greater,
+        half => half,
+    };
+    let round_away = match mode {
+        Rounding::Truncate => false,
+        Rounding::HalfUp => half != Ordering::Less,
+        Rounding::HalfEven => {
+            half == Ordering::Greater
+                || (half == Ordering::Equal && !q.div_rem(&BigInt::from(2)).unwrap().1.is_zero())
+        }
+    };
+    if !round_away {
+        q
+    } else if coeff.is_negative() {
+        q.sub(&BigInt::one())
+    } else {
+        q.add(&BigInt::one())
+    }
+}
+
+impl Decimal {
+    /// Rounds `coeff * 10^exp` to the precision of `ctx`.
+    fn round(coeff: BigInt, exp: i64, ctx: DecimalContext, sticky: bool) -> Self {
+        let digits = digit_count(&coeff);
+        let (mut coeff, mut exp) = if digits > ctx.precision || sticky {
+            let drop = digits.saturating_sub(ctx.precision);
+            (
+                round_digits(&coeff, drop, ctx.rounding, sticky),
+                exp + drop as i64,
+            )
+        } else {
+            (coeff, exp)
+        };
+        // Rounding up may have carried into an extra digit, as in 999 to
+        // 1000, and trailing zeros are always removed.
+        let ten = BigInt::from(10);
+        while !coeff.is_zero() {
+            let (q, r) = coeff.div_rem(&ten).unwrap();
+            if !r.is_zero() {
+                break;
+            }
+            coeff = q;
+            exp += 1;
+        }
+        if coeff.is_zero() {
+            exp = 0;
+        }
+        Decimal { coeff, exp, ctx }
+    }
+
+    pub fn from_int(n: BigInt, ctx: DecimalContext) -> Self {
+        Decimal::round(n, 0, ctx, false)
+    }
+
+    pub fn zero(ctx: DecimalContext) -> Self {
+        Decimal::from_int(BigInt::zero(), ctx)
+    }
+
+    /// Parses a decimal literal such as `12` or `0.125`, rounding it to the
+    /// precision of `ctx`.
+    pub fn parse(text: &str, ctx: DecimalContext) -> Option<Self> {
+        let (int, frac) = match text.find('.') {
+            Some(i) => (&text[..i], &text[i + 1..]),
+            None => (text, ""),
+        };
+        let mut digits = String::with_capacity(int.len() + frac.len());
+        digits.push_str(if int.is_empty() { "0" } else { int });
+        digits.push_str(frac);
+        Some(Decimal::round(
+            digits.parse().ok()?,
+            -(frac.len() as i64),
+            ctx,
+            false,
+        ))
+    }
+
+    /// The nearest decimal to a rational number.
+    pub fn from_rational(q: &Rational, ctx: DecimalContext) -> Self {
+        Decimal::from_int(q.numer().clone(), ctx)
+            .div(&Decimal::from_int(q.denom().clone(), ctx))
+            .unwrap()
+    }
+
+    pub fn context(&self) -> DecimalContext {
+        self.ctx
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.coeff.is_zero()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.coeff.is_negative()
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.exp >= 0
+    }
+
+    /// The coefficients of `self` and `other` scaled to a common exponent,
+    /// and that exponent.
+    fn align(&self, other: &Decimal) -> (BigInt, BigInt, i64) {
+        let exp = self.exp.min(other.exp);
+        (
+            self.coeff.mul(&pow10((self.exp - exp) as usize)),
+            other.coeff.mul(&pow10((other.exp - exp) as usize)),
+            exp,
+        )
+    }
+
+    pub fn add(&self, other: &Decimal) -> Self {
+        let (a, b, exp) = self.align(other);
+        Decimal::round(a.add(&b), exp, self.ctx, false)
+    }
+
+    pub fn sub(&self, other: &Decimal) -> Self {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &Decimal) -> Self {
+        Decimal::round(
+            self.coeff.mul(&other.coeff),
+            self.exp + other.exp,
+            self.ctx,
+            false,
+        )
+    }
+
+    /// `self / other`, or `None` when dividing by zero.
+    pub fn div(&self, other: &Decimal) -> Option<Self> {
+        if other.is_zero() {
+            return None;
+        }
+        // Scale the dividend so that the quotient has at least one digit
+        // more than the precision, for rounding.
+        let shift = (self.ctx.precision + digit_count(&other.coeff) + 1)
+            .saturating_sub(digit_count(&self.coeff));
+        let (q, r) = self.coeff.mul(&pow10(shift)).div_rem(&other.coeff)?;
+        let exp = self.exp - other.exp - shift as i64;
+        Some(Decimal::round(q, exp, self.ctx, !r.is_zero()))
+    }
+
+    pub fn neg(&self) -> Self {
+        Decimal {
+            coeff: self.coeff.neg(),
+            exp: self.exp,
+            ctx: self.ctx,
+        }
+    }
+
+    pub fn abs(&self) -> Self {
+        Decimal {
+            coeff: self.coeff.abs(),
+            exp: self.exp,
+            ctx: self.ctx,
+        }
+    }
+
+    /// Raises to an integer power by repeated squaring, rounding each step.
+    /// Returns `None` for a negative power of zero.
+    pub fn powi(&self, exp: i32) -> Option<Self> {
+        let mut base = self.clone();
+        let mut n = exp.unsigned_abs();
+        let mut acc = Decimal::from_int(BigInt::one(), self.ctx);
+        while n > 0 {
+            if n & 1 == 1 {
+                acc = acc.mul(&base);
+            }
+            n >>= 1;
+            if n > 0 {
+                base = base.mul(&base);
+            }
+        }
+        if exp < 0 {
+            Decimal::from_int(BigInt::one(), self.ctx).div(&acc)
+        } else {
+            Some(acc)
+        }
+    }
+
+    /// The square root, rounded to the precision, or `None` if negative.
+    pub fn sqrt(&self) -> Option<Self> {
+        if self.is_negative() {
+            return None;
+        }
+        // Scale to an even exponent with enough digits that the integer
+        // root has more than the precision.
+        let mut shift = (2 * self.ctx.precision + 2).saturating_sub(digit_count(&self.coeff));
+        if (self.exp - shift as i64) % 2 != 0 {
+            shift += 1;
+        }
+        let scaled = self.coeff.mul(&pow10(shift));
+        let root = scaled.isqrt()?;
+        let exact = root.mul(&root) == scaled;
+        Some(Decimal::round(
+            root,
+            (self.exp - shift as i64) / 2,
+            self.ctx,
+            !exact,
+        ))
+    }
+
+    /// Rounds to a multiple of `10^exp` by `mode`.
+    fn quantize(&self, exp: i64, mode: Rounding) -> Self {
+        if self.exp >= exp {
+            return self.clone();
+        }
+        let coeff = round_digits(&self.coeff, (exp - self.exp) as usize, mode, false);
+        Decimal::round(coeff, exp, self.ctx, false)
+    }
+
+    pub fn floor(&self) -> Self {
+        if self.is_integer() {
+            return self.clone();
+        }
+        let q = self.coeff.div_floor(&pow10(-self.exp as usize)).unwrap();
+        Decimal::from_int(q, self.ctx)
+    }
+
+    pub fn ceil(&self) -> Self {
+        self.neg().floor().neg()
+    }
+
+    pub fn trunc(&self) -> Self {
+        self.quantize(0, Rounding::Truncate)
+    }
+
+    /// Rounds to an integer by the rounding mode of the context.
+    pub fn round_int(&self) -> Self {
+        self.quantize(0, self.ctx.rounding)
+    }
+
+    pub fn signum(&self) -> Self {
+        Decimal::from_int(
+            BigInt::from(match self.coeff.cmp(&BigInt::zero()) {
+                Ordering::Less => -1,
+                Ordering::Equal => 0,
+                Ordering::Greater => 1,
+            }),
+            self.ctx,
+        )
+    }
+
+    /// The exact value as a fraction.
+    pub fn to_rational(&self) -> Rational {
+        if self.exp >= 0 {
+            Rational::from_int(self.coeff.mul(&pow10(self.exp as usize)))
+        } else {
+            Rational::new(self.coeff.clone(), pow10(-self.exp as usize)).unwrap()
+        }
+    }
+
+    /// The nearest f64.
+    pub fn to_f64(&self) -> f64 {
+        self.to_rational().to_f64()
+    }
+
+    /// Writes the value with `places` digits after the point, rounding by
+    /// the rounding mode of the context.
+    pub fn to_fixed(&self, places: usize) -> String {
+        let rounded = self.quantize(-(places as i64), self.ctx.rounding);
+        let coeff = rounded
+            .coeff
+            .mul(&pow10((rounded.exp + places as i64) as usize));
+        let mut text = coeff.abs().to_string();
+        if text.len() <= places {
+            text = format!("{}{}", "0".repeat(places + 1 - text.len()), text);
+        }
+        if places > 0 {
+            text.insert(text.len() - places, '.');
+        }
+        if coeff.is_negative() {
+            text.insert(0, '-');
+        }
+        text
+    }
+}
+
+/// Decimals are equal if they have the same value, whatever their context.
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Decimal) -> bool {
+        self.coeff == other.coeff && self.exp == other.exp
+    }
+}
+
+impl Eq for Decimal {}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Decimal) -> Ordering {
+        let (a, b, _) = self.align(other);
+        a.cmp(&b)
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Decimal) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Shows the value in plain notation without trailing zeros, like `0.3` or
+/// `1200`.
+impl Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let places = if self.exp < 0 { -self.exp as usize } else { 0 };
+        write!(f, "{}", self.to_fixed(places))
+    }
+}
+
+pub type DecimalFn = fn(&[Decimal]) -> Option<Decimal>;
+
+/// The decimal versions of the builtins that have them, used when a builtin
+/// is called with only decimal arguments. They give `None` when there is no
+/// decimal result, in which case the builtin falls back to floats. The arity
+/// has already been checked.
+pub fn builtin(name: &str) -> Option<DecimalFn> {
+    macro_rules! unary {
+        ($f: expr) => {
+            Some(|args: &[Decimal]| Some($f(&args[0])))
+        };
+    }
+    match name {
+        "abs" => unary!(Decimal::abs),
+        "sign" => unary!(Decimal::signum),
+        "floor" => unary!(Decimal::floor),
+        "ceil" => unary!(Decimal::ceil),
+        "round" => unary!(Decimal::round_int),
+        "trunc" => unary!(Decimal::trunc),
+        "sqrt" => Some(|args: &[Decimal]| args[0].sqrt()),
+        "min" => Some(|args: &[Decimal]| args.iter().min().cloned()),
+        "max" => Some(|args: &[Decimal]| args.iter().max().cloned()),
+        "clamp" => {
+            Some(|args: &[Decimal]| Some(args[0].clone().max(args[1].clone()).min(args[2].clone())))
+        }
+        "conj" | "re" => unary!(Decimal::clone),
+        "im" => Some(|args: &[Decimal]| Some(Decimal::zero(args[0].ctx))),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eval::EvalContext;
+    use parser;
+    use value::Value;
+
+    const MODES: [Rounding; 3] = [Rounding::HalfEven, Rounding::HalfUp, Rounding::Truncate];
+
+    fn ctx(precision: usize, rounding: Rounding) -> DecimalContext {
+        DecimalContext::new(precision, rounding)
+    }
+
+    fn dec(text: &str, ctx: DecimalContext) -> Decimal {
+        Decimal::parse(text, ctx).unwrap()
+    }
+
+    #[test]
+    fn round_digits_by_mode() {
+        // The coefficient, the results for each mode without and with the
+        // sticky bit set.
+        let cases = [
+            (124, [12, 12, 12], [12, 12, 12]),
+            (125, [12, 13, 12], [13, 13, 12]),
+            (135, [14, 14, 13], [14, 14, 13]),
+            (126, [13, 13, 12], [13, 13, 12]),
+            (-125, [-12, -13, -12], [-13, -13, -12]),
+            (-135, [-14, -14, -13], [-14, -14, -13]),
+        ];
+        for &(coeff, exact, inexact) in cases.iter() {
+            for (i, &mode) in MODES.iter().enumerate() {
+                let coeff = BigInt::from(coeff);
+                assert_eq!(
+                    round_digits(&coeff, 1, mode, false),
+                    BigInt::from(exact[i]),
+                    "{} by {:?}",
+                    coeff,
+                    mode
+                );
+                assert_eq!(
+                    round_digits(&coeff, 1, mode, true),
+                    BigInt::from(inexact[i]),
+                    "inexact {} by {:?}",
+                    coeff,
+                    mode
+                );
+            }
+        }
+        // Nothing dropped keeps every digit, whether or not more follow.
+        let coeff = BigInt::from(12);
+        for &mode in MODES.iter() {
+            assert_eq!(round_digits(&coeff, 0, mode, true), coeff);
+        }
+    }
+
+    #[test]
+    fn parse_rounds_literals() {
+        let expected = [
+            ("1.2345", ["1.23", "1.23", "1.23"]),
+            ("1.235", ["1.24", "1.24", "1.23"]),
+            ("1.245", ["1.24", "1.25", "1.24"]),
+            ("-1.245", ["-1.24", "-1.25", "-1.24"]),
+            ("9.995", ["10", "10", "9.99"]),
+            ("1200.0", ["1200", "1200", "1200"]),
+            (".5", ["0.5", "0.5", "0.5"]),
+        ];
+        for &(text, results) in expected.iter() {
+            for (i, &mode) in MODES.iter().enumerate() {
+                assert_eq!(
+                    dec(text, ctx(3, mode)).to_string(),
+                    results[i],
+                    "{} by {:?}",
+                    text,
+                    mode
+                );
+            }
+        }
+        assert!(Decimal::parse("1.2.3", ctx(3, Rounding::HalfEven)).is_none());
+    }
+
+    #[test]
+    fn div_rounds_with_the_remainder() {
+        // 1 / 0.7999 = 1.2501..., whose digits kept for rounding are 125
+        // with a remainder, so it is above the tie. The quotient takes the
+        // precision of the dividend.
+        let expected = [
+            ("0.7999", ["1.3", "1.3", "1.2"]),
+            ("0.8", ["1.2", "1.3", "1.2"]),
+        ];
+        for &(divisor, results) in expected.iter() {
+            for (i, &mode) in MODES.iter().enumerate() {
+                let divisor_ctx = ctx(10, mode);
+                let q = dec("1", ctx(2, mode))
+                    .div(&dec(divisor, divisor_ctx))
+                    .unwrap();
+                assert_eq!(q.to_string(), results[i], "1 / {} by {:?}", divisor, mode);
+            }
+        }
+        let c = DecimalContext::default();
+        assert_eq!(
+            dec("1", c).div(&dec("3", c)).unwrap().to_string(),
+            "0.3333333333333333333333333333"
+        );
+        assert!(dec("1", c).div(&Decimal::zero(c)).is_none());
+    }
+
+    #[test]
+    fn sqrt_rounds_with_the_remainder() {
+        // To one digit, so the roots of 6.25 and just above it are ties
+        // but for the remainder. The operands have more digits than that
+        // precision would keep.
+        let expected = [
+            (625, -2, ["2", "3", "2"]),
+            (62500001, -7, ["3", "3", "2"]),
+            (62499999, -7, ["2", "2", "2"]),
+        ];
+        for &(coeff, exp, results) in expected.iter() {
+            for (i, &mode) in MODES.iter().enumerate() {
+                let x = Decimal {
+                    coeff: BigInt::from(coeff),
+                    exp,
+                    ctx: ctx(1, mode),
+                };
+                let root = x.sqrt().unwrap();
+                assert_eq!(root.to_string(), results[i], "sqrt({}) by {:?}", x, mode);
+            }
+        }
+        let c = DecimalContext::default();
+        assert_eq!(
+            dec("2", c).sqrt().unwrap().to_string(),
+            "1.414213562373095048801688724"
+        );
+        assert_eq!(dec("0.0625", c).sqrt().unwrap().to_string(), "0.25");
+        assert!(dec("-1", c).sqrt().is_none());
+    }
+
+    #[test]
+    fn quantize_rounds_exact_ties_by_mode() {
+        let expected = [
+            ("0.125", ["0.12", "0.13", "0.12"]),
+            ("-0.125", ["-0.12", "-0.13", "-0.12"]),
+            ("0.135", ["0.14", "0.14", "0.13"]),
+            ("0.1251", ["0.13", "0.13", "0.12"]),
+            ("2", ["2.00", "2.00", "2.00"]),
+        ];
+        for &(x, results) in expected.iter() {
+            for (i, &mode) in MODES.iter().enumerate() {
+                assert_eq!(
+                    dec(x, ctx(10, mode)).to_fixed(2),
+                    results[i],
+                    "{} by {:?}",
+                    x,
+                    mode
+                );
+            }
+        }
+        let c = ctx(10, Rounding::HalfEven);
+        assert_eq!(dec("2.5", c).round_int().to_string(), "2");
+        assert_eq!(dec("3.5", c).round_int().to_string(), "4");
+        assert_eq!(dec("-2.7", c).trunc().to_string(), "-2");
+        assert_eq!(dec("-2.5", c).floor().to_string(), "-3");
+        assert_eq!(dec("-2.5", c).ceil().to_string(), "-2");
+    }
+
+    #[test]
+    fn tenths_add_exactly_in_decimal_mode() {
+        let c = DecimalContext::default();
+        assert_eq!(dec("0.1", c).add(&dec("0.2", c)), dec("0.3", c));
+
+        let mut context = EvalContext::new();
+        context.set_decimal_mode(Some(c));
+        let tree = parser::parse_repl("0.1 + 0.2 == 0.3").unwrap();
+        assert_eq!(context.eval_repltree(&tree).unwrap(), Value::Bool(true));
+    }
+}
@@ -0,0 +1,364 @@
+use ast::{BinOp, Block, Expr, ExprType, UnOp};
+use eval::EvalContext;
+use parser::MathParseError;
+
+/// Differentiates `expr` with respect to the variable `var`, giving a new
+/// expression for the derivative. The result is not simplified, so it may
+/// contain terms like `0 * x`.
+pub fn diff(expr: &Expr, var: &str) -> Result<Expr, MathParseError> {
+    Differentiator::new(var, None).diff(expr)
+}
+
+/// Differentiates expressions, looking through calls to functions defined
+/// with `def` in a context.
+pub(crate) struct Differentiator<'a> {
+    var: &'a str,
+    context: Option<&'a EvalContext>,
+    /// The functions being looked through, so that recursion is caught.
+    inlined: Vec<String>,
+}
+
+impl<'a> Differentiator<'a> {
+    pub fn new(var: &'a str, context: Option<&'a EvalContext>) -> Self {
+        Differentiator {
+            var,
+            context,
+            inlined: Vec::new(),
+        }
+    }
+
+    pub fn diff(&self, e: &Expr) -> Result<Expr, MathParseError> {
+        if !depends_on(e, self.var) {
+            return Ok(num(e, 0.0));
+        }
+        match e.expr_type {
+            ExprType::Var(_) => Ok(num(e, 1.0)),
+            ExprType::Binary(op, ref a, ref b) => self.diff_binary(e, op, a, b),
+            ExprType::Unary(UnOp::Neg, ref a) => Ok(unary(e, UnOp::Neg, self.diff(a)?)),
+            ExprType::FuncCall(ref name, ref args) => self.diff_call(e, name, args),
+            // The derivative of a sum is the sum of the derivatives, as long
+            // as the number of terms stays the same.
+            ExprType::Iterated(BinOp::Plus, ref index, ref lo, ref hi, ref term)
+                if !depends_on(lo, self.var) && !depends_on(hi, self.var) =>
+            {
+                Ok(Expr {
+                    line: e.line,
+                    expr_type: ExprType::Iterated(
+                        BinOp::Plus,
+                        index.clone(),
+                        lo.clone(),
+                        hi.clone(),
+                        Box::new(self.diff(term)?),
+                    ),
+                })
+            }
+            _ => Err(cannot(e)),
+        }
+    }
+
+    fn diff_binary(&self, e: &Expr, op: BinOp, a: &Expr, b: &Expr) -> Result<Expr, MathParseError> {
+        let (da, db) = (self.diff(a)?, self.diff(b)?);
+        Ok(match op {
+            BinOp::Plus | BinOp::Minus => binary(e, op, da, db),
+            BinOp::Times => binary(
+                e,
+                BinOp::Plus,
+                binary(e, BinOp::Times, da, b.clone()),
+                binary(e, BinOp::Times, a.clone(), db),
+            ),
+            BinOp::Slash => binary(
+                e,
+                BinOp::Slash,
+                binary(
+                    e,
+                    BinOp::Minus,
+                    binary(e, BinOp::Times, da, b.clone()),
+                    binary(e, BinOp::Times, a.clone(), db),
+                ),
+                binary(e, BinOp::Exp, b.clone(), num(e, 2.0)),
+            ),
+            // (a^b)' = b a^(b - 1) a' when b is constant,
+            // a^b ln(a) b' when a is, and a^b (b' ln(a) + b a' / a) otherwise.
+            BinOp::Exp if !depends_on(b, self.var) => {
+                let lower = match b.expr_type {
+                    ExprType::NumLit(n, _) => num(e, n - 1.0),
+                    _ => binary(e, BinOp::Minus, b.clone(), num(e, 1.0)),
+                };
+                binary(
+                    e,
+                    BinOp::Times,
+                    binary(e, BinOp::Times, b.clone(), binary(e, BinOp::Exp, a.clone(), lower)),
+                    da,
+                )
+            }
+            BinOp::Exp if !depends_on(a, self.var) => binary(
+                e,
+                BinOp::Times,
+                binary(e, BinOp::Times, e.clone(), call(e, "ln", vec![a.clone()])),
+                db,
+            ),
+            BinOp::Exp => binary(
+                e,
+                BinOp::Times,
+                e.clone(),
+                binary(
+                    e,
+                    BinOp::Plus,
+                    binary(e, BinOp::Times, db, call(e, "ln", vec![a.clone()])),
+                    binary(
+                        e,
+                        BinOp::Slash,
+                        binary(e, BinOp::Times, b.clone(), da),
+                        a.clone(),
+                    ),
+                ),
+            ),
+            _ => return Err(cannot(e)),
+        })
+    }
+
+    fn diff_call(&self, e: &Expr, name: &str, args: &[Expr]) -> Result<Expr, MathParseError> {
+        if let Some(outer) = builtin_derivative(e, name, args) {
+            let inner = self.diff(&args[0])?;
+            return Ok(binary(e, BinOp::Times, outer, inner));
+        }
+        match (name, args.len()) {
+            // atan2(y, x)' = (x y' - y x') / (x^2 + y^2)
+            ("atan2", 2) => {
+                let (y, x) = (&args[0], &args[1]);
+                Ok(binary(
+                    e,
+                    BinOp::Slash,
+                    binary(
+                        e,
+                        BinOp::Minus,
+                        binary(e, BinOp::Times, x.clone(), self.diff(y)?),
+                        binary(e, BinOp::Times, y.clone(), self.diff(x)?),
+                    ),
+                    binary(
+                        e,
+                        BinOp::Plus,
+                        binary(e, BinOp::Exp, x.clone(), num(e, 2.0)),
+                        binary(e, BinOp::Exp, y.clone(), num(e, 2.0)),
+                    ),
+                ))
+            }
+            // hypot(a, b)' = (a a' + b b') / hypot(a, b)
+            ("hypot", 2) => Ok(binary(
+                e,
+                BinOp::Slash,
+                binary(
+                    e,
+                    BinOp::Plus,
+                    binary(e, BinOp::Times, args[0].clone(), self.diff(&args[0])?),
+                    binary(e, BinOp::Times, args[1].clone(), self.diff(&args[1])?),
+                ),
+                e.clone(),
+            )),
+            // log(b, x) is ln(x) / ln(b).
+            ("log", 2) => self.diff(&binary(
+                e,
+                BinOp::Slash,
+                call(e, "ln", vec![args[1].clone()]),
+                call(e, "ln", vec![args[0].clone()]),
+            )),
+            _ => self.diff_user_fn(e, name, args),
+        }
+    }
+
+    /// Differentiates a call to a `def` function whose body is a single
+    /// expression, by substituting the arguments into the body.
+    fn diff_user_fn(&self, e: &Expr, name: &str, args: &[Expr]) -> Result<Expr, MathParseError> {
+        if self.inlined.iter().any(|f| f == name) {
+            return Err(cannot(e));
+        }
+        let func = match self.context.and_then(|context| context.user_fn(name)) {
+            Some(func) => func,
+            None => return Err(cannot(e)),
+        };
+        let body = match func.body {
+            Block::Exprs(ref exprs) if exprs.len() == 1 => &exprs[0],
+            _ => return Err(cannot(e)),
+        };
+        if func.params.len() != args.len() {
+            return Err(MathParseError::WrongNumberOfArguments(
+                func.params.len(),
+                args.len(),
+            ));
+        }
+        let inlined = substitute(body, &func.params, args).ok_or_else(|| cannot(e))?;
+        let mut inner = Differentiator {
+            var: self.var,
+            context: self.context,
+            inlined: self.inlined.clone(),
+        };
+        inner.inlined.push(String::from(name));
+        inner.diff(&inlined)
+    }
+}
+
+/// The derivative of a one-argument builtin at its argument, for the chain
+/// rule, or `None` if `name` is not one.
+fn builtin_derivative(e: &Expr, name: &str, args: &[Expr]) -> Option<Expr> {
+    if args.len() != 1 {
+        return None;
+    }
+    let u = &args[0];
+    let sq = |x: Expr| binary(e, BinOp::Exp, x, num(e, 2.0));
+    let recip = |x: Expr| binary(e, BinOp::Slash, num(e, 1.0), x);
+    let f = |name: &str| call(e, name, vec![u.clone()]);
+    Some(match name {
+        "sin" => f("cos"),
+        "cos" => unary(e, UnOp::Neg, f("sin")),
+        "tan" => recip(sq(f("cos"))),
+        "asin" => recip(call(
+            e,
+            "sqrt",
+            vec![binary(e, BinOp::Minus, num(e, 1.0), sq(u.clone()))],
+        )),
+        "acos" => unary(
+            e,
+            UnOp::Neg,
+            recip(call(
+                e,
+                "sqrt",
+                vec![binary(e, BinOp::Minus, num(e, 1.0), sq(u.clone()))],
+            )),
+        ),
+        "atan" => recip(binary(e, BinOp::Plus, num(e, 1.0), sq(u.clone()))),
+        "sinh" => f("cosh"),
+        "cosh" => f("sinh"),
+        "tanh" => recip(sq(f("cosh"))),
+        "asinh" => recip(call(
+            e,
+            "sqrt",
+            vec![binary(e, BinOp::Plus, sq(u.clone()), num(e, 1.0))],
+        )),
+        "acosh" => recip(call(
+            e,
+            "sqrt",
+            vec![binary(e, BinOp::Minus, sq(u.clone()), num(e, 1.0))],
+        )),
+        "atanh" => recip(binary(e, BinOp::Minus, num(e, 1.0), sq(u.clone()))),
+        "sqrt" => recip(binary(e, BinOp::Times, num(e, 2.0), f("sqrt"))),
+        "cbrt" => recip(binary(e, BinOp::Times, num(e, 3.0), sq(f("cbrt")))),
+        "exp" => f("exp"),
+        "ln" => recip(u.clone()),
+        "log10" => recip(binary(e, BinOp::Times, u.clone(), call(e, "ln", vec![num(e, 10.0)]))),
+        "log2" => recip(binary(e, BinOp::Times, u.clone(), call(e, "ln", vec![num(e, 2.0)]))),
+        "abs" => f("sign"),
+        // Steps are flat everywhere they are differentiable.
+        "sign" | "floor" | "ceil" | "round" | "trunc" => num(e, 0.0),
+        _ => return None,
+    })
+}
+
+/// Whether the expression refers to the variable `var`.
+pub(crate) fn depends_on(e: &Expr, var: &str) -> bool {
+    let block_depends = |block: &Block| match *block {
+        Block::Exprs(ref exprs) => exprs.iter().any(|e| depends_on(e, var)),
+        Block::Empty => false,
+    };
+    match e.expr_type {
+        ExprType::Var(ref name) => name == var,
+        ExprType::NumLit(..)
+        | ExprType::ImagLit(_)
+        | ExprType::BoolLit(_)
+        | ExprType::StrLit(_) => false,
+        ExprType::Binary(_, ref a, ref b) | ExprType::Index(ref a, ref b) => {
+            depends_on(a, var) || depends_on(b, var)
+        }
+        ExprType::Unary(_, ref a)
+        | ExprType::WithUnit(ref a, _)
+        | ExprType::ConvertTo(ref a, _)
+        | ExprType::Assign(_, ref a)
+        | ExprType::Field(ref a, _)
+        | ExprType::AssignField(_, _, ref a)
+        | ExprType::Destructure(_, ref a) => depends_on(a, var),
+        ExprType::FuncCall(_, ref args)
+        | ExprType::ListLit(ref args)
+        | ExprType::TupleLit(ref args) => args.iter().any(|e| depends_on(e, var)),
+        ExprType::FuncDef(..) => false,
+        ExprType::If(ref cond, ref a, ref b) => {
+            depends_on(cond, var) || block_depends(a) || block_depends(b)
+        }
+        ExprType::Slice(ref a, ref start, ref end) => {
+            depends_on(a, var)
+                || start.as_ref().is_some_and(|e| depends_on(e, var))
+                || end.as_ref().is_some_and(|e| depends_on(e, var))
+        }
+        ExprType::RecordLit(ref fields) => fields.iter().any(|(_, e)| depends_on(e, var)),
+        ExprType::Solve(ref a, ref b, ref unknown) => {
+            unknown != var && (depends_on(a, var) || depends_on(b, var))
+        }
+        ExprType::Iterated(_, ref index, ref lo, ref hi, ref term) => {
+            depends_on(lo, var) || depends_on(hi, var) || (index != var && depends_on(term, var))
+        }
+    }
+}
+
+/// Replaces the variables `params` in `e` by the expressions `args`. Gives
+/// `None` for expressions that could bind variables of their own.
+pub(crate) fn substitute(e: &Expr, params: &[String], args: &[Expr]) -> Option<Expr> {
+    let sub = |e: &Expr| substitute(e, params, args).map(Box::new);
+    let expr_type = match e.expr_type {
+        ExprType::Var(ref name) => match params.iter().position(|p| p == name) {
+            Some(i) => return Some(args[i].clone()),
+            None => ExprType::Var(name.clone()),
+        },
+        ExprType::NumLit(..)
+        | ExprType::ImagLit(_)
+        | ExprType::BoolLit(_)
+        | ExprType::StrLit(_) => e.expr_type.clone(),
+        ExprType::Binary(op, ref a, ref b) => ExprType::Binary(op, sub(a)?, sub(b)?),
+        ExprType::Unary(op, ref a) => ExprType::Unary(op, sub(a)?),
+        ExprType::WithUnit(ref a, ref unit) => ExprType::WithUnit(sub(a)?, unit.clone()),
+        ExprType::ConvertTo(ref a, ref unit) => ExprType::ConvertTo(sub(a)?, unit.clone()),
+        ExprType::FuncCall(ref name, ref call_args) => {
+            let call_args: Option<Vec<Expr>> = call_args
+                .iter()
+                .map(|e| substitute(e, params, args))
+                .collect();
+            ExprType::FuncCall(name.clone(), call_args?)
+        }
+        ExprType::Index(ref a, ref b) => ExprType::Index(sub(a)?, sub(b)?),
+        ExprType::Field(ref a, ref name) => ExprType::Field(sub(a)?, name.clone()),
+        _ => return None,
+    };
+    Some(Expr {
+        line: e.line,
+        expr_type,
+    })
+}
+
+fn cannot(e: &Expr) -> MathParseError {
+    MathParseError::CannotDifferentiate(e.to_string())
+}
+
+fn num(at: &Expr, x: f64) -> Expr {
+    Expr {
+        line: at.line,
+        expr_type: ExprType::NumLit(x, x.to_string()),
+    }
+}
+
+fn unary(at: &Expr, op: UnOp, a: Expr) -> Expr {
+    Expr {
+        line: at.line,
+        expr_type: ExprType::Unary(op, Box::new(a)),
+    }
+}
+
+fn binary(at: &Expr, op: BinOp, a: Expr, b: Expr) -> Expr {
+    Expr {
+        line: at.line,
+        expr_type: ExprType::Binary(op, Box::new(a), Box::new(b)),
+    }
+}
+
+fn call(at: &Expr, name: &str, args: Vec<Expr>) -> Expr {
+    Expr {
+        line: at.line,
+        expr_type: ExprType::FuncCall(String::from(name), args),
+    }
+}
@@ -0,0 +1,319 @@
+use std::f64;
+use std::fmt::{self, Display};
+
+use builtins;
+use intrinsics::Caller;
+use parser::MathParseError;
+use value::{Function, Value};
+
+/// A dual number `a + b₁ε₁ + … + bₙεₙ`, where each `εᵢ² = 0`, for
+/// forward-mode automatic differentiation.
+///
+/// Computing with a dual number whose `i`-th part is 1 carries along the
+/// exact partial derivative in the `i`-th variable of everything computed
+/// from it. Unlike symbolic differentiation this works through loops,
+/// branches and recursion, since only the path actually taken matters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dual {
+    value: f64,
+    /// The partial derivatives. Missing trailing parts are zero, so
+    /// constants have none.
+    derivs: Vec<f64>,
+}
+
+impl Dual {
+    pub fn new(value: f64, derivs: Vec<f64>) -> Self {
+        Dual { value, derivs }
+    }
+
+    pub fn constant(value: f64) -> Self {
+        Dual::new(value, Vec::new())
+    }
+
+    /// The `i`-th of `n` independent variables, at `value`.
+    pub fn variable(value: f64, i: usize, n: usize) -> Self {
+        let mut derivs = vec![0.0; n];
+        derivs[i] = 1.0;
+        Dual::new(value, derivs)
+    }
+
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    /// The partial derivative in the `i`-th variable.
+    pub fn deriv(&self, i: usize) -> f64 {
+        self.derivs.get(i).cloned().unwrap_or(0.0)
+    }
+
+    /// A value with the given partial derivatives in other values, by the
+    /// chain rule.
+    fn linear(value: f64, parts: &[(f64, &Dual)]) -> Self {
+        let len = parts
+            .iter()
+            .map(|&(_, x)| x.derivs.len())
+            .max()
+            .unwrap_or(0);
+        let mut derivs = vec![0.0; len];
+        for &(d, x) in parts.iter() {
+            // Skipping unused parts keeps `0 * inf` from making them NaN.
+            if x.derivs.iter().any(|&b| b != 0.0) {
+                for (acc, b) in derivs.iter_mut().zip(x.derivs.iter()) {
+                    *acc += d * b;
+                }
+            }
+        }
+        Dual::new(value, derivs)
+    }
+
+    /// Applies a function of one variable with derivative `d` at `self`.
+    fn chain(&self, value: f64, d: f64) -> Self {
+        Dual::linear(value, &[(d, self)])
+    }
+
+    pub fn add(&self, other: &Dual) -> Self {
+        Dual::linear(self.value + other.value, &[(1.0, self), (1.0, other)])
+    }
+
+    pub fn sub(&self, other: &Dual) -> Self {
+        Dual::linear(self.value - other.value, &[(1.0, self), (-1.0, other)])
+    }
+
+    pub fn mul(&self, other: &Dual) -> Self {
+        Dual::linear(
+            self.value * other.value,
+            &[(other.value, self), (self.value, other)],
+        )
+    }
+
+    pub fn div(&self, other: &Dual) -> Self {
+        let q = self.value / other.value;
+        Dual::linear(q, &[(1.0 / other.value, self), (-q / other.value, other)])
+    }
+
+    pub fn neg(&self) -> Self {
+        Dual::linear(-self.value, &[(-1.0, self)])
+    }
+
+    pub fn pow(&self, other: &Dual) -> Self {
+        let (x, y) = (self.value, other.value);
+        let p = x.powf(y);
+        // As with `powf`, `x^0` is 1 for every `x` and `0^y` is 0 for every
+        // positive `y`, so those are flat rather than giving `0 * inf`.
+        let dx = if y == 0.0 { 0.0 } else { y * x.powf(y - 1.0) };
+        let dy = if x == 0.0 && y > 0.0 { 0.0 } else { p * x.ln() };
+        Dual::linear(p, &[(dx, self), (dy, other)])
+    }
+
+    /// Writes the value and derivatives with `places` digits after the
+    /// point.
+    pub fn to_fixed(&self, places: usize) -> String {
+        self.write(|x| format!("{:.*}", places, x))
+    }
+
+    fn write<F: Fn(f64) -> String>(&self, part: F) -> String {
+        let mut s = part(self.value);
+        for (i, &b) in self.derivs.iter().enumerate() {
+            let sign = if b.is_sign_negative() && !b.is_nan() {
+                '-'
+            } else {
+                '+'
+            };
+            s.push_str(&format!(" {} {}ε", sign, part(b.abs())));
+            if self.derivs.len() > 1 {
+                s.push_str(&(i + 1).to_string());
+            }
+        }
+        s
+    }
+}
+
+/// Writes a dual number like `9 + 6ε`, or `2 + 1ε1 + 0ε2` with several
+/// parts.
+impl Display for Dual {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.write(|x| x.to_string()))
+    }
+}
+
+/// The dual versions of the builtins, with their exact derivatives. The
+/// arity has already been checked.
+pub fn builtin(name: &str) -> Option<fn(&[Dual]) -> Dual> {
+    macro_rules! unary {
+        ($f: expr, $d: expr) => {
+            Some(|args: &[Dual]| {
+                let x = args[0].value;
+                args[0].chain($f(x), $d(x))
+            })
+        };
+    }
+    /// Functions made of steps, which are flat wherever they are
+    /// differentiable.
+    macro_rules! steps {
+        ($f: expr) => {
+            Some(|args: &[Dual]| Dual::constant($f(args[0].value)))
+        };
+    }
+    match name {
+        "sin" => unary!(f64::sin, f64::cos),
+        "cos" => unary!(f64::cos, |x: f64| -x.sin()),
+        "tan" => unary!(f64::tan, |x: f64| 1.0 / x.cos().powi(2)),
+        "asin" => unary!(f64::asin, |x: f64| 1.0 / (1.0 - x * x).sqrt()),
+        "acos" => unary!(f64::acos, |x: f64| -1.0 / (1.0 - x * x).sqrt()),
+        "atan" => unary!(f64::atan, |x: f64| 1.0 / (1.0 + x * x)),
+        "atan2" => Some(|args: &[Dual]| {
+            let (y, x) = (args[0].value, args[1].value);
+            let r2 = x * x + y * y;
+            Dual::linear(y.atan2(x), &[(x / r2, &args[0]), (-y / r2, &args[1])])
+        }),
+        "sinh" => unary!(f64::sinh, f64::cosh),
+        "cosh" => unary!(f64::cosh, f64::sinh),
+        "tanh" => unary!(f64::tanh, |x: f64| 1.0 / x.cosh().powi(2)),
+        "asinh" => unary!(f64::asinh, |x: f64| 1.0 / (x * x + 1.0).sqrt()),
+        "acosh" => unary!(f64::acosh, |x: f64| 1.0 / (x * x - 1.0).sqrt()),
+        "atanh" => unary!(f64::atanh, |x: f64| 1.0 / (1.0 - x * x)),
+        "sqrt" => unary!(f64::sqrt, |x: f64| 0.5 / x.sqrt()),
+        "cbrt" => unary!(f64::cbrt, |x: f64| 1.0 / (3.0 * x.cbrt().powi(2))),
+        "exp" => unary!(f64::exp, f64::exp),
+        "ln" => unary!(f64::ln, |x: f64| 1.0 / x),
+        "log10" => unary!(f64::log10, |x: f64| 1.0 / (x * f64::consts::LN_10)),
+        "log2" => unary!(f64::log2, |x: f64| 1.0 / (x * f64::consts::LN_2)),
+        "log" => Some(|args: &[Dual]| {
+            let ln = builtin("ln").unwrap();
+            ln(&args[1..]).div(&ln(&args[..1]))
+        }),
+        "abs" => unary!(f64::abs, builtins::sign),
+        "sign" => steps!(builtins::sign),
+        "floor" => steps!(f64::floor),
+        "ceil" => steps!(f64::ceil),
+        "round" => steps!(f64::round),
+        "trunc" => steps!(f64::trunc),
+        "arg" => steps!(|x: f64| 0f64.atan2(x)),
+        "im" => steps!(|x: f64| 0.0 * x),
+        "conj" | "re" => Some(|args: &[Dual]| args[0].clone()),
+        // The argument that is picked passes on its derivative.
+        "min" => Some(|args: &[Dual]| {
+            args[1..].iter().fold(args[0].clone(), |acc, x| {
+                if x.value < acc.value {
+                    x.clone()
+                } else {
+                    acc
+                }
+            })
+        }),
+        "max" => Some(|args: &[Dual]| {
+            args[1..].iter().fold(args[0].clone(), |acc, x| {
+                if x.value > acc.value {
+                    x.clone()
+                } else {
+                    acc
+                }
+            })
+        }),
+        "clamp" => Some(|args: &[Dual]| {
+            let max = builtin("max").unwrap();
+            let min = builtin("min").unwrap();
+            min(&[max(&args[..2]), args[2].clone()])
+        }),
+        "hypot" => Some(|args: &[Dual]| {
+            let (x, y) = (args[0].value, args[1].value);
+            let h = x.hypot(y);
+            Dual::linear(h, &[(x / h, &args[0]), (y / h, &args[1])])
+        }),
+        _ => None,
+    }
+}
+
+/// The gradient of `func` at `point`, found by calling it once with a dual
+/// number for each argument. Functions of one variable give their
+/// derivative.
+pub(crate) fn gradient(
+    caller: &mut dyn Caller,
+    func: &Function,
+    point: &[f64],
+) -> Result<Vec<f64>, MathParseError> {
+    let n = point.len();
+    let args: Vec<Value> = point
+        .iter()
+        .enumerate()
+        .map(|(i, &x)| Value::from(Dual::variable(x, i, n)))
+        .collect();
+    match caller.call(func, &args)? {
+        Value::Dual(ref y) => Ok((0..n).map(|i| y.deriv(i)).collect()),
+        // A result that does not depend on the arguments is flat.
+        ref y if y.as_num().is_some() => Ok(vec![0.0; n]),
+        ref y => Err(MathParseError::TypeError(format!(
+            "cannot differentiate '{}', which returned {}",
+            func.name(),
+            y.type_name()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn var(x: f64) -> Dual {
+        Dual::variable(x, 0, 1)
+    }
+
+    #[test]
+    fn arithmetic() {
+        let x = var(3.0);
+        let y = x.mul(&x).add(&Dual::constant(2.0).mul(&x));
+        assert_eq!((y.value(), y.deriv(0)), (15.0, 8.0));
+        let q = Dual::constant(1.0).div(&x);
+        assert_eq!(q.deriv(0), -1.0 / 9.0);
+        assert_eq!(x.sub(&x).deriv(0), 0.0);
+        assert_eq!(x.neg().deriv(0), -1.0);
+    }
+
+    #[test]
+    fn powers() {
+        let cube = var(2.0).pow(&Dual::constant(3.0));
+        assert_eq!((cube.value(), cube.deriv(0)), (8.0, 12.0));
+        let exp = Dual::constant(2.0).pow(&var(3.0));
+        assert_eq!(exp.deriv(0), 8.0 * f64::consts::LN_2);
+        let self_pow = var(2.0).pow(&var(2.0));
+        assert_eq!(self_pow.deriv(0), 4.0 * (1.0 + f64::consts::LN_2));
+    }
+
+    #[test]
+    fn powers_at_zero() {
+        // d/dy 0^y is 0 for positive y, not 0 * ln(0).
+        let p = Dual::constant(0.0).pow(&var(1.0));
+        assert_eq!((p.value(), p.deriv(0)), (0.0, 0.0));
+        // d/dx x^0 is 0 everywhere, including at 0.
+        let p = var(0.0).pow(&Dual::constant(0.0));
+        assert_eq!((p.value(), p.deriv(0)), (1.0, 0.0));
+        let p = var(0.0).pow(&Dual::constant(1.0));
+        assert_eq!(p.deriv(0), 1.0);
+        let p = var(0.0).pow(&Dual::constant(2.0));
+        assert_eq!(p.deriv(0), 0.0);
+        // A negative power of zero is infinite, and so is its derivative.
+        assert!(!Dual::constant(0.0).pow(&var(-1.0)).deriv(0).is_finite());
+    }
+
+    #[test]
+    fn builtins() {
+        let sin = builtin("sin").unwrap();
+        assert_eq!(sin(&[var(0.0)]).deriv(0), 1.0);
+        let ln = builtin("ln").unwrap();
+        assert_eq!(ln(&[var(2.0)]).deriv(0), 0.5);
+        let floor = builtin("floor").unwrap();
+        assert_eq!(floor(&[var(2.5)]).deriv(0), 0.0);
+        let max = builtin("max").unwrap();
+        assert_eq!(max(&[var(1.0), Dual::constant(0.0)]).deriv(0), 1.0);
+        assert_eq!(max(&[var(-1.0), Dual::constant(0.0)]).deriv(0), 0.0);
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Dual::new(9.0, vec![6.0]).to_string(), "9 + 6ε");
+        assert_eq!(
+            Dual::new(2.0, vec![1.0, -0.5]).to_string(),
+            "2 + 1ε1 - 0.5ε2"
+        );
+    }
+}
@@ -1,26 +1,202 @@
 use std::collections::HashMap;
+use std::rc::Rc;
 use parser::MathParseError;
 use visitor::Visitor;
+use builtins::{self, Arity, NativeError, NativeFn};
+use value::{self, Function, Record, Value};
+use intrinsics::{self, Caller, IntrinsicError};
+use complex::Complex;
+use rational::Rational;
+use decimal::{Decimal, DecimalContext};
+use number::Number;
+use units::Quantity;
+use diff::Differentiator;
+use dual;
+use integrate;
+use minimize;
+use ode;
+use solve::{self, Options, Start};
 use ast;
 
 use std::f64;
 
-pub type EvalResult = Result<Option<f64>, MathParseError>;
+pub type EvalResult = Result<Value, MathParseError>;
 
 pub struct EvalContext {
     stack: Vec<StackFrame>,
+    natives: HashMap<String, Rc<NativeFn>>,
+    complex_mode: bool,
+    exact_mode: bool,
+    decimal_mode: Option<DecimalContext>,
 }
 
 #[derive(Debug)]
 pub struct StackFrame {
-    vars: HashMap<String, f64>,
-    funcs: HashMap<String, (Vec<String>, ast::Block)>,
+    vars: HashMap<String, Value>,
+    funcs: HashMap<String, Rc<UserFn>>,
+}
+
+/// A function defined with `def`.
+#[derive(Debug)]
+pub struct UserFn {
+    pub params: Vec<String>,
+    pub body: ast::Block,
 }
 
 impl EvalContext {
     pub fn new() -> Self {
+        let mut natives = HashMap::new();
+        for builtin in builtins::BUILTINS.iter() {
+            natives.insert(String::from(builtin.name), Rc::new(builtin.to_native()));
+        }
+        for builtin in builtins::VALUE_BUILTINS.iter() {
+            natives.insert(String::from(builtin.name), Rc::new(builtin.to_native()));
+        }
         EvalContext {
             stack: vec![StackFrame::new()],
+            natives,
+            complex_mode: false,
+            exact_mode: false,
+            decimal_mode: None,
+        }
+    }
+
+    /// Makes a Rust closure over numbers callable from formulas as `name`,
+    /// replacing any builtin or native function of the same name. The
+    /// function is treated as impure unless marked otherwise through the
+    /// returned reference.
+    pub fn register_fn<F>(&mut self, name: &str, arity: Arity, func: F) -> &mut NativeFn
+    where
+        F: Fn(&[f64]) -> Result<f64, NativeError> + 'static,
+    {
+        self.register_native(name, NativeFn::numeric(arity, func))
+    }
+
+    /// Like `register_fn`, for functions built with `NativeFn::new` that
+    /// take or return other kinds of values.
+    pub fn register_native(&mut self, name: &str, native: NativeFn) -> &mut NativeFn {
+        self.natives.insert(String::from(name), Rc::new(native));
+        Rc::get_mut(self.natives.get_mut(name).unwrap()).unwrap()
+    }
+
+    pub fn native_fn(&self, name: &str) -> Option<&NativeFn> {
+        self.natives.get(name).map(|native| native.as_ref())
+    }
+
+    pub(crate) fn native_rc(&self, name: &str) -> Option<Rc<NativeFn>> {
+        self.natives.get(name).cloned()
+    }
+
+    pub(crate) fn user_fn(&self, name: &str) -> Option<Rc<UserFn>> {
+        self.current_stack_frame()
+            .funcs
+            .get(name)
+            .or_else(|| self.global_stack_frame().funcs.get(name))
+            .cloned()
+    }
+
+    /// Differentiates `expr` with respect to `var`, like `diff`, but also
+    /// through calls to functions defined here whose body is a single
+    /// expression.
+    pub fn diff(&self, expr: &ast::Expr, var: &str) -> Result<ast::Expr, MathParseError> {
+        Differentiator::new(var, Some(self)).diff(expr)
+    }
+
+    /// The exact derivative of the function `name` at `x`, found by
+    /// evaluating it with a dual number. This works through branches,
+    /// loops and recursion, which `diff` cannot follow.
+    pub fn deriv(&mut self, name: &str, x: f64) -> Result<f64, MathParseError> {
+        Ok(self.grad(name, &[x])?[0])
+    }
+
+    /// The integral of the function `name` from `a` to `b`, and an estimate
+    /// of its error, as by the builtin `integrate`.
+    pub fn integrate(&mut self, name: &str, a: f64, b: f64) -> Result<(f64, f64), MathParseError> {
+        let func = self
+            .lookup_function(name)
+            .ok_or_else(|| MathParseError::UnknownIdentifier(String::from(name)))?;
+        let mut visitor = EvalVisitor { context: self };
+        integrate::integrate(
+            &mut |x| visitor.call_function(&func, &[x]),
+            a,
+            b,
+            integrate::DEFAULTS,
+        )
+    }
+
+    /// A minimum of the function `name` near `x0`, one argument per
+    /// coordinate, as by the builtin `minimize`. Gives where it is, the
+    /// value there and the number of steps taken.
+    pub fn minimize(&mut self, name: &str, x0: &[f64]) -> Result<(Vec<f64>, f64, usize), MathParseError> {
+        let func = self
+            .lookup_function(name)
+            .ok_or_else(|| MathParseError::UnknownIdentifier(String::from(name)))?;
+        let mut visitor = EvalVisitor { context: self };
+        let mut objective = |x: &[Value]| visitor.call_function(&func, x);
+        let min = if x0.len() == 1 {
+            minimize::minimize_scalar(&mut objective, Start::Guess(x0[0]), minimize::DEFAULTS)?
+        } else {
+            minimize::nelder_mead(&mut objective, x0.to_vec(), minimize::DEFAULTS)?
+        };
+        Ok((min.x, min.value, min.iterations))
+    }
+
+    /// Solves the system `y' = f(t, y)` given by the function `name` from
+    /// `y(t0) = y0` up to `t1`, as by the builtin `odesolve`. The function
+    /// takes `t` and a list of the components of `y`, and returns a list of
+    /// their derivatives.
+    pub fn odesolve(
+        &mut self,
+        name: &str,
+        t0: f64,
+        y0: &[f64],
+        t1: f64,
+    ) -> Result<Vec<(f64, Vec<f64>)>, MathParseError> {
+        let func = self
+            .lookup_function(name)
+            .ok_or_else(|| MathParseError::UnknownIdentifier(String::from(name)))?;
+        let mut visitor = EvalVisitor { context: self };
+        ode::solve(
+            &mut |t, y| {
+                let y = Value::from(y.iter().cloned().map(Value::Num).collect::<Vec<_>>());
+                let dy = visitor.call_function(&func, &[Value::Num(t), y])?;
+                dy.as_items()
+                    .and_then(|items| items.iter().map(Value::as_num).collect::<Option<Vec<_>>>())
+                    .filter(|dy| dy.len() == y0.len())
+                    .ok_or_else(|| {
+                        MathParseError::TypeError(format!(
+                            "expected function to return a list of {} numbers, got {}",
+                            y0.len(),
+                            dy
+                        ))
+                    })
+            },
+            t0,
+            y0.to_vec(),
+            ode::Output::Steps(t1),
+            ode::DEFAULTS,
+        )
+    }
+
+    /// The partial derivatives of the function `name` at `point`, one
+    /// argument per coordinate.
+    pub fn grad(&mut self, name: &str, point: &[f64]) -> Result<Vec<f64>, MathParseError> {
+        let func = self
+            .lookup_function(name)
+            .ok_or_else(|| MathParseError::UnknownIdentifier(String::from(name)))?;
+        let mut visitor = EvalVisitor { context: self };
+        dual::gradient(&mut visitor, &func, point)
+    }
+
+    /// Looks up a native, intrinsic or `def` function by name, as a value.
+    fn lookup_function(&self, name: &str) -> Option<Function> {
+        if let Some(native) = self.native_rc(name) {
+            Some(Function::Native(Rc::from(name), native))
+        } else if let Some(intrinsic) = intrinsics::lookup(name) {
+            Some(Function::Intrinsic(intrinsic))
+        } else {
+            self.user_fn(name)
+                .map(|func| Function::User(Rc::from(name), func))
         }
     }
 
@@ -42,14 +218,109 @@ impl EvalContext {
         self.stack.last_mut().unwrap()
     }
 
-    fn assign_var(&mut self, name: &str, value: f64) {
+    fn global_stack_frame(&self) -> &StackFrame {
+        &self.stack[0]
+    }
+
+    fn global_stack_frame_mut(&mut self) -> &mut StackFrame {
+        &mut self.stack[0]
+    }
+
+    fn assign_local(&mut self, name: &str, value: Value) {
         self.current_stack_frame_mut()
             .vars
             .insert(String::from(name), value);
     }
 
-    fn get_var(&self, name: &str) -> Option<f64> {
-        self.current_stack_frame().vars.get(name).map(|x| *x)
+    fn get_local(&self, name: &str) -> Option<Value> {
+        self.current_stack_frame().vars.get(name).cloned()
+    }
+
+    /// Binds a global variable, as if by a top-level assignment.
+    pub fn set_var<V: Into<Value>>(&mut self, name: &str, value: V) {
+        self.global_stack_frame_mut()
+            .vars
+            .insert(String::from(name), value.into());
+    }
+
+    pub fn get_var(&self, name: &str) -> Option<Value> {
+        self.global_stack_frame().vars.get(name).cloned()
+    }
+
+    /// Removes a global variable, returning its value if it was set.
+    pub fn remove_var(&mut self, name: &str) -> Option<Value> {
+        self.global_stack_frame_mut().vars.remove(name)
+    }
+
+    /// Iterates over all global variables, including the predefined
+    /// constants, in no particular order.
+    pub fn vars(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.global_stack_frame()
+            .vars
+            .iter()
+            .map(|(name, val)| (name.as_str(), val))
+    }
+
+    /// Iterates over the names and parameter lists of all functions defined
+    /// with `def`, in no particular order.
+    pub fn functions(&self) -> impl Iterator<Item = (&str, &[String])> {
+        self.global_stack_frame()
+            .funcs
+            .iter()
+            .map(|(name, func)| (name.as_str(), func.params.as_slice()))
+    }
+
+    /// Removes a function defined with `def`. Returns whether it existed.
+    pub fn remove_fn(&mut self, name: &str) -> bool {
+        self.global_stack_frame_mut().funcs.remove(name).is_some()
+    }
+
+    /// Discards all variables and `def` functions, restoring the predefined
+    /// constants. Registered native functions and modes are kept.
+    pub fn reset(&mut self) {
+        self.stack = vec![StackFrame::new()];
+    }
+
+    /// In complex mode, operators and builtins that have no real result for
+    /// real arguments, such as `sqrt(-1)` or `(-8)^(1/3)`, give the principal
+    /// complex result instead of NaN.
+    pub fn set_complex_mode(&mut self, on: bool) {
+        self.complex_mode = on;
+    }
+
+    pub fn complex_mode(&self) -> bool {
+        self.complex_mode
+    }
+
+    /// In exact mode, number literals are read as exact rationals, so that
+    /// `1/3 + 1/6` gives `1/2`. Arithmetic on them stays exact except for
+    /// non-integer powers, and builtins without an exact result, such as
+    /// `sqrt(2)` or `sin(1)`, give floats. `float` and `decimal` convert
+    /// results for display.
+    pub fn set_exact_mode(&mut self, on: bool) {
+        self.exact_mode = on;
+    }
+
+    pub fn exact_mode(&self) -> bool {
+        self.exact_mode
+    }
+
+    /// With a decimal context, number literals are read as base-10 decimals
+    /// with its precision and rounding mode, so that `0.1 + 0.2 == 0.3`.
+    /// This takes precedence over exact mode. As in exact mode, builtins
+    /// without a decimal version give floats.
+    pub fn set_decimal_mode(&mut self, ctx: Option<DecimalContext>) {
+        self.decimal_mode = ctx;
+    }
+
+    pub fn decimal_mode(&self) -> Option<DecimalContext> {
+        self.decimal_mode
+    }
+}
+
+impl Default for EvalContext {
+    fn default() -> Self {
+        EvalContext::new()
     }
 }
 
@@ -59,8 +330,8 @@ impl StackFrame {
             vars: HashMap::new(),
             funcs: HashMap::new(),
         };
-        c.vars.insert(String::from("pi"), f64::consts::PI);
-        c.vars.insert(String::from("e"), f64::consts::E);
+        c.vars.insert(String::from("pi"), Value::Num(f64::consts::PI));
+        c.vars.insert(String::from("e"), Value::Num(f64::consts::E));
         c
     }
 }
@@ -73,148 +344,514 @@ impl<'a> Visitor<EvalResult> for EvalVisitor<'a> {
     fn visit_block(&mut self, f: &ast::Block) -> EvalResult {
         match *f {
             ast::Block::Exprs(ref exprs) => {
-                let mut lastres: Option<f64> = None;
+                let mut lastres = Value::Nil;
                 for expr in exprs.iter() {
                     lastres = self.visit_expr(expr)?;
                 }
                 Ok(lastres)
             }
-            ast::Block::Empty => Ok(None),
+            ast::Block::Empty => Ok(Value::Nil),
         }
     }
 
     fn visit_repltree(&mut self, t: &ast::ReplTree) -> EvalResult {
         match *t {
             ast::ReplTree::Expr(ref expr) => self.visit_expr(expr),
-            ast::ReplTree::Empty => Ok(None),
+            ast::ReplTree::Empty => Ok(Value::Nil),
         }
     }
 
     fn visit_expr(&mut self, e: &ast::Expr) -> EvalResult {
         match e.expr_type {
-            ast::ExprType::Binary(ast::BinOp::Plus, ref expr1, ref expr2) => {
-                match (self.visit_expr(expr1)?, self.visit_expr(expr2)?) {
-                    (Some(a), Some(b)) => Ok(Some(a + b)),
-                    _ => Ok(None),
-                }
-            }
-            ast::ExprType::Binary(ast::BinOp::Minus, ref expr1, ref expr2) => {
-                match (self.visit_expr(expr1)?, self.visit_expr(expr2)?) {
-                    (Some(a), Some(b)) => Ok(Some(a - b)),
-                    _ => Ok(None),
-                }
-            }
-            ast::ExprType::Binary(ast::BinOp::Slash, ref expr1, ref expr2) => {
-                match (self.visit_expr(expr1)?, self.visit_expr(expr2)?) {
-                    (Some(a), Some(b)) => Ok(Some(a / b)),
-                    _ => Ok(None),
+            ast::ExprType::Binary(op, ref expr1, ref expr2) => {
+                let a = self.visit_expr(expr1)?;
+                let b = self.visit_expr(expr2)?;
+                let res = value::binary_op(op, &a, &b)?;
+                if self.context.complex_mode && is_nan(&res) && !is_nan(&a) && !is_nan(&b) {
+                    if let Some(x) = a.as_num() {
+                        let z = value::binary_op(op, &Value::from(Complex::from_real(x)), &b)?;
+                        if !is_complex_nan(&z) {
+                            return Ok(z);
+                        }
+                    }
                 }
+                Ok(res)
             }
-            ast::ExprType::Binary(ast::BinOp::Times, ref expr1, ref expr2) => {
-                match (self.visit_expr(expr1)?, self.visit_expr(expr2)?) {
-                    (Some(a), Some(b)) => Ok(Some(a * b)),
-                    _ => Ok(None),
-                }
+            ast::ExprType::Unary(op, ref expr) => value::unary_op(op, &self.visit_expr(expr)?),
+            ast::ExprType::NumLit(n, ref lit) => Ok(self.eval_literal(n, lit)),
+            ast::ExprType::ImagLit(n) => Ok(Value::from(Complex::new(0.0, n))),
+            ast::ExprType::WithUnit(ref expr, ref unit) => {
+                let x = self.visit_expr(expr)?.expect_num()?;
+                Ok(Value::Quantity(Rc::new(Quantity::new(x, unit.clone()))))
             }
-            ast::ExprType::Binary(ast::BinOp::Exp, ref expr1, ref expr2) => {
-                match (self.visit_expr(expr1)?, self.visit_expr(expr2)?) {
-                    (Some(a), Some(b)) => Ok(Some(a.powf(b))),
-                    _ => Ok(None),
+            ast::ExprType::ConvertTo(ref expr, ref unit) => {
+                let val = self.visit_expr(expr)?;
+                let q = val.as_quantity().ok_or_else(|| {
+                    MathParseError::TypeError(format!(
+                        "cannot convert {} to '{}'",
+                        val.type_name(),
+                        unit
+                    ))
+                })?;
+                match q.value_in(unit) {
+                    Some(x) => Ok(Value::Quantity(Rc::new(Quantity::new(x, unit.clone())))),
+                    None => Err(MathParseError::DimensionMismatch(
+                        q.unit.describe(),
+                        unit.describe(),
+                    )),
                 }
             }
-            ast::ExprType::NumLit(n) => Ok(Some(n)),
+            ast::ExprType::BoolLit(b) => Ok(Value::Bool(b)),
+            ast::ExprType::StrLit(ref s) => Ok(Value::from(s.as_str())),
             ast::ExprType::Assign(ref name, ref expr) => {
-                let val = self.visit_expr(expr);
-                if let Ok(Some(val)) = val {
-                    self.context.assign_var(name, val)
-                }
-                val
+                let val = self.visit_expr(expr)?;
+                self.context.assign_local(name, val.clone());
+                Ok(val)
             }
-            ast::ExprType::Var(ref name) => match self.context.get_var(name) {
-                Some(val) => Ok(Some(val)),
-                None => Err(MathParseError::UnknownIdentifier(name.clone())),
+            ast::ExprType::Var(ref name) => match self.context.get_local(name) {
+                Some(val) => Ok(val),
+                None => match self.context.lookup_function(name) {
+                    Some(func) => Ok(Value::Func(func)),
+                    None => Err(MathParseError::UnknownIdentifier(name.clone())),
+                },
             },
             ast::ExprType::FuncDef(ref name, ref params, ref block) => {
                 self.eval_funcdef(name, params, block)
             }
             ast::ExprType::FuncCall(ref name, ref args) => self.eval_function(name, args),
-            // _ => unimplemented!(),
+            ast::ExprType::Solve(ref lhs, ref rhs, ref var) => self.eval_solve(lhs, rhs, var),
+            ast::ExprType::Iterated(op, ref index, ref lo, ref hi, ref term) => {
+                let name = ast::iterated_name(op);
+                if let Some(func) = self.iterated_override(name) {
+                    let args = ast::iterated_args(e.line, index, lo, hi, term);
+                    let args = self.eval_args(&args)?;
+                    return self.call_function(&func, &args);
+                }
+                let lo = self.visit_expr(lo)?;
+                let hi = self.visit_expr(hi)?;
+                let saved = self.context.get_local(index);
+                let result = self.eval_iterated(op, index, lo, hi, term);
+                self.restore_local(index, saved);
+                result
+            }
+            ast::ExprType::If(ref cond, ref then_block, ref else_block) => {
+                if self.visit_expr(cond)?.expect_bool()? {
+                    self.visit_block(then_block)
+                } else {
+                    self.visit_block(else_block)
+                }
+            }
+            ast::ExprType::ListLit(ref items) => Ok(Value::from(self.eval_args(items)?)),
+            ast::ExprType::Index(ref expr, ref index) => {
+                let val = self.visit_expr(expr)?;
+                value::index(&val, &self.visit_expr(index)?)
+            }
+            ast::ExprType::Slice(ref expr, ref start, ref end) => {
+                let val = self.visit_expr(expr)?;
+                let start = match *start {
+                    Some(ref start) => Some(self.visit_expr(start)?),
+                    None => None,
+                };
+                let end = match *end {
+                    Some(ref end) => Some(self.visit_expr(end)?),
+                    None => None,
+                };
+                value::slice(&val, start.as_ref(), end.as_ref())
+            }
+            ast::ExprType::TupleLit(ref items) => Ok(Value::Tuple(Rc::new(self.eval_args(items)?))),
+            ast::ExprType::Destructure(ref pattern, ref expr) => {
+                let val = self.visit_expr(expr)?;
+                self.bind_pattern(pattern, &val)?;
+                Ok(val)
+            }
+            ast::ExprType::RecordLit(ref fields) => {
+                let mut record = Record::new();
+                for (name, expr) in fields.iter() {
+                    let val = self.visit_expr(expr)?;
+                    record.set(name, val);
+                }
+                Ok(Value::from(record))
+            }
+            ast::ExprType::Field(ref expr, ref name) => value::field(&self.visit_expr(expr)?, name),
+            ast::ExprType::AssignField(ref name, ref path, ref expr) => {
+                let record = match self.context.get_local(name) {
+                    Some(record) => record,
+                    None => return Err(MathParseError::UnknownIdentifier(name.clone())),
+                };
+                let val = self.visit_expr(expr)?;
+                let updated = value::with_field(&record, path, val.clone())?;
+                self.context.assign_local(name, updated);
+                Ok(val)
+            }
         }
     }
 }
 
 impl<'a> EvalVisitor<'a> {
-    fn eval_funcdef(&mut self, name: &str, params: &Vec<String>, block: &ast::Block) -> EvalResult {
+    fn eval_funcdef(&mut self, name: &str, params: &[String], block: &ast::Block) -> EvalResult {
+        let func = Rc::new(UserFn {
+            params: params.to_vec(),
+            body: block.clone(),
+        });
         self.context
             .current_stack_frame_mut()
             .funcs
-            .insert(name.to_string(), (params.clone(), block.clone()));
-        Ok(Some(0.0))
+            .insert(name.to_string(), func.clone());
+        Ok(Value::Func(Function::User(Rc::from(name), func)))
     }
 
-    fn eval_function(&mut self, name: &str, args: &Vec<ast::Expr>) -> EvalResult {
-        match name {
-            "sin" => {
-                if args.len() == 1 {
-                    match self.visit_expr(unsafe { args.get_unchecked(0) }) {
-                        Ok(Some(x)) => Ok(Some(x.sin())),
-                        _ => unimplemented!(),
-                    }
-                } else {
-                    unimplemented!()
+    /// Solves `lhs = rhs` for `var`, starting from its current value or 1.
+    /// The variable is bound while the sides are evaluated, and afterwards
+    /// holds what it did before.
+    fn eval_solve(&mut self, lhs: &ast::Expr, rhs: &ast::Expr, var: &str) -> EvalResult {
+        let saved = self.context.get_local(var);
+        let guess = saved.as_ref().and_then(Value::as_num).unwrap_or(1.0);
+        let root = solve::find_root(
+            &mut |x| {
+                self.context.assign_local(var, x);
+                let a = self.visit_expr(lhs)?;
+                let b = self.visit_expr(rhs)?;
+                value::binary_op(ast::BinOp::Minus, &a, &b)
+            },
+            Start::Guess(guess),
+            Options::default(),
+        );
+        self.restore_local(var, saved);
+        root.map(Value::Num)
+    }
+
+    /// Combines the values of `term` with `op` for each `index` from `lo`
+    /// up to `hi` in steps of 1, starting from 0 for a sum and 1 for a
+    /// product. Binds `index` in the current frame as it goes.
+    fn eval_iterated(
+        &mut self,
+        op: ast::BinOp,
+        index: &str,
+        lo: Value,
+        hi: Value,
+        term: &ast::Expr,
+    ) -> EvalResult {
+        let count = match (lo.as_num(), hi.as_num()) {
+            (Some(a), Some(b)) => intrinsics::count_terms(ast::iterated_name(op), a, b)?,
+            _ => {
+                return Err(MathParseError::TypeError(format!(
+                    "expected numbers as bounds, got {} and {}",
+                    lo, hi
+                )))
+            }
+        };
+        let one = self.eval_literal(1.0, "1");
+        let mut acc = if op == ast::BinOp::Times {
+            one.clone()
+        } else {
+            self.eval_literal(0.0, "0")
+        };
+        let mut i = lo;
+        for _ in 0..count {
+            self.context.assign_local(index, i.clone());
+            acc = value::binary_op(op, &acc, &self.visit_expr(term)?)?;
+            i = value::binary_op(ast::BinOp::Plus, &i, &one)?;
+        }
+        Ok(acc)
+    }
+
+    /// A function that hides `sum` or `prod` notation, so that `name(i, a,
+    /// b, term)` is an ordinary call to it: a function held in a variable,
+    /// a registered native or one defined with `def`.
+    fn iterated_override(&self, name: &str) -> Option<Function> {
+        if let Some(Value::Func(func)) = self.context.get_local(name) {
+            return Some(func);
+        }
+        match self.context.native_rc(name) {
+            Some(native) => Some(Function::Native(Rc::from(name), native)),
+            None => self
+                .context
+                .user_fn(name)
+                .map(|func| Function::User(Rc::from(name), func)),
+        }
+    }
+
+    /// Sets `var` back to what it held before it was bound for a while, or
+    /// removes it if it was not set.
+    fn restore_local(&mut self, var: &str, saved: Option<Value>) {
+        match saved {
+            Some(val) => self.context.assign_local(var, val),
+            None => {
+                self.context.current_stack_frame_mut().vars.remove(var);
+            }
+        }
+    }
+
+    /// A number literal, read according to the mode.
+    fn eval_literal(&self, n: f64, lit: &str) -> Value {
+        if self.context.decimal_mode.is_some() {
+            if let Some(d) = self.literal::<Decimal>(lit) {
+                return d;
+            }
+        }
+        if self.context.exact_mode {
+            if let Some(q) = self.literal::<Rational>(lit) {
+                return q;
+            }
+        }
+        Value::Num(n)
+    }
+
+    /// Reads a literal as a number of kind `N`, with the settings the
+    /// context has for it.
+    fn literal<N: Number>(&self, lit: &str) -> Option<Value> {
+        N::parse(lit, N::context(self.context)).map(N::into_value)
+    }
+
+    /// Assigns the parts of `val` to the names in `pattern`.
+    fn bind_pattern(&mut self, pattern: &ast::Pattern, val: &Value) -> Result<(), MathParseError> {
+        let patterns = match *pattern {
+            ast::Pattern::Name(ref name) => {
+                self.context.assign_local(name, val.clone());
+                return Ok(());
+            }
+            ast::Pattern::Tuple(ref patterns) | ast::Pattern::List(ref patterns) => patterns,
+        };
+        let items = match val.as_items() {
+            Some(items) => items,
+            None => {
+                return Err(MathParseError::TypeError(format!(
+                    "cannot destructure {}",
+                    val.type_name()
+                )))
+            }
+        };
+        if items.len() != patterns.len() {
+            return Err(MathParseError::TypeError(format!(
+                "cannot destructure {} of length {} into {} names",
+                val.type_name(),
+                items.len(),
+                patterns.len()
+            )));
+        }
+        for (pattern, item) in patterns.iter().zip(items.iter()) {
+            self.bind_pattern(pattern, item)?;
+        }
+        Ok(())
+    }
+
+    fn eval_args(&mut self, args: &[ast::Expr]) -> Result<Vec<Value>, MathParseError> {
+        let mut vals = Vec::with_capacity(args.len());
+        for arg in args.iter() {
+            vals.push(self.visit_expr(arg)?);
+        }
+        Ok(vals)
+    }
+
+    fn eval_function(&mut self, name: &str, args: &[ast::Expr]) -> EvalResult {
+        // A local variable holding a function shadows functions of the
+        // same name, so that functions can be passed as arguments.
+        let func = match self.context.get_local(name) {
+            Some(Value::Func(func)) => func,
+            _ => match self.context.lookup_function(name) {
+                Some(func) => func,
+                None => return Err(MathParseError::UnknownIdentifier(String::from(name))),
+            },
+        };
+        let args = self.eval_args(args)?;
+        self.call_function(&func, &args)
+    }
+
+    fn call_function(&mut self, func: &Function, args: &[Value]) -> EvalResult {
+        match func {
+            Function::Intrinsic(intrinsic) => {
+                if !intrinsic.arity.accepts(args.len()) {
+                    return Err(MathParseError::WrongNumberOfArguments(
+                        intrinsic.arity.min(),
+                        args.len(),
+                    ));
                 }
+                (intrinsic.func)(self, args).map_err(|err| match err {
+                    IntrinsicError::Native(err) => {
+                        MathParseError::NativeFunctionError(String::from(intrinsic.name), err)
+                    }
+                    IntrinsicError::Eval(err) => err,
+                })
             }
-            "cos" => {
-                if args.len() == 1 {
-                    match self.visit_expr(unsafe { args.get_unchecked(0) }) {
-                        Ok(Some(x)) => Ok(Some(x.cos())),
-                        _ => unimplemented!(),
+            Function::Native(name, native) => {
+                let arity = native.arity();
+                if !arity.accepts(args.len()) {
+                    return Err(MathParseError::WrongNumberOfArguments(
+                        arity.min(),
+                        args.len(),
+                    ));
+                }
+                let mut res = native.call(args);
+                if self.context.complex_mode
+                    && res.as_ref().is_ok_and(is_nan)
+                    && !args.iter().any(is_nan)
+                {
+                    match native.call_complex(args) {
+                        Some(Ok(ref z)) if is_complex_nan(z) => (),
+                        Some(complex_res) => res = complex_res,
+                        None => (),
                     }
-                } else {
-                    unimplemented!()
                 }
+                res.map_err(|err| MathParseError::NativeFunctionError(name.to_string(), err))
             }
-            "sqrt" => {
-                if args.len() == 1 {
-                    match self.visit_expr(unsafe { args.get_unchecked(0) }) {
-                        Ok(Some(x)) => Ok(Some(x.sqrt())),
-                        _ => unimplemented!(),
+            Function::User(_, func) => {
+                let plen = func.params.len();
+                let alen = args.len();
+                if plen == alen {
+                    let mut sf = StackFrame::new();
+                    for (param, val) in func.params.iter().zip(args.iter()) {
+                        sf.vars.insert(param.to_string(), val.clone());
                     }
+                    self.context.stack.push(sf);
+                    let res = self.visit_block(&func.body);
+                    self.context.stack.pop();
+                    res
                 } else {
-                    unimplemented!()
+                    Err(MathParseError::WrongNumberOfArguments(plen, alen))
                 }
             }
-            _ => {
-                let pb = self.context.current_stack_frame().funcs.get(name);
-                match pb {
-                    Some(&(ref params, ref block)) => {
-                        let _self = unsafe {
-                            let __self: *const Self = self;
-                            let ___self = __self as *mut Self;
-                            ___self.as_mut().unwrap()
-                        };
-                        let plen = params.len();
-                        let alen = args.len();
-                        if plen == alen {
-                            let mut sf = StackFrame::new();
-                            for (i, arg) in args.iter().enumerate() {
-                                match _self.visit_expr(arg)? {
-                                    Some(val) => sf.vars.insert(params[i].to_string(), val),
-                                    None => unimplemented!(),
-                                };
-                            }
-                            _self.context.stack.push(sf);
-                            let res = _self.visit_block(block);
-                            // println!("{:#?}", _self.context.stack);
-                            _self.context.stack.pop();
-                            res
-                        } else {
-                            Err(MathParseError::WrongNumberOfArguments(plen, alen))
-                        }
-                    }
-                    None => Err(MathParseError::UnknownIdentifier(String::from(name))),
-                }
+        }
+    }
+}
+
+/// Whether a value is a real NaN, which complex mode retries in complex.
+fn is_nan(val: &Value) -> bool {
+    val.as_num().is_some_and(f64::is_nan)
+}
+
+fn is_complex_nan(val: &Value) -> bool {
+    match *val {
+        Value::Complex(z) => z.is_nan(),
+        _ => false,
+    }
+}
+
+impl<'a> Caller for EvalVisitor<'a> {
+    fn call(&mut self, func: &Function, args: &[Value]) -> EvalResult {
+        self.call_function(func, args)
+    }
+
+    fn context(&self) -> &EvalContext {
+        self.context
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use builtins::NativeError;
+    use parser;
+
+    fn eval(source: &str) -> EvalResult {
+        EvalContext::new().eval_file(&parser::parse_file(source).unwrap())
+    }
+
+    #[test]
+    fn builtins() {
+        assert_eq!(eval("hypot(3, 4)").unwrap(), Value::Num(5.0));
+        assert_eq!(eval("max(1, 5, 3)").unwrap(), Value::Num(5.0));
+        assert_eq!(eval("log(2, 8)").unwrap(), Value::Num(3.0));
+    }
+
+    #[test]
+    fn builtin_arity() {
+        match eval("atan2(1)") {
+            Err(MathParseError::WrongNumberOfArguments(2, 1)) => (),
+            other => panic!("{:?}", other),
+        }
+        match eval("max()") {
+            Err(MathParseError::WrongNumberOfArguments(1, 0)) => (),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn user_functions_can_call_builtins() {
+        assert_eq!(eval("def f(x) { sqrt(x) + 1 }\nf(16)").unwrap(), Value::Num(5.0));
+    }
+
+    #[test]
+    fn registered_natives_are_callable() {
+        let mut context = EvalContext::new();
+        context.register_fn("double", Arity::Fixed(1), |args| Ok(args[0] * 2.0));
+        context.register_fn("sum", Arity::Variadic(0), |args| Ok(args.iter().sum()));
+        let block = parser::parse_file("double(sum(1, 2, 3)) + sum()").unwrap();
+        assert_eq!(context.eval_file(&block).unwrap(), Value::Num(12.0));
+        assert!(!context.native_fn("double").unwrap().is_pure());
+        assert!(context.native_fn("sin").unwrap().is_pure());
+    }
+
+    #[test]
+    fn natives_replace_builtins() {
+        let mut context = EvalContext::new();
+        context
+            .register_fn("sin", Arity::Fixed(1), |_| Ok(42.0))
+            .set_pure(true);
+        let block = parser::parse_file("sin(0)").unwrap();
+        assert_eq!(context.eval_file(&block).unwrap(), Value::Num(42.0));
+    }
+
+    #[test]
+    fn native_errors() {
+        let mut context = EvalContext::new();
+        context.register_fn("positive", Arity::Fixed(1), |args| {
+            if args[0] > 0.0 {
+                Ok(args[0])
+            } else {
+                Err(NativeError::InvalidArgument(0, String::from("must be positive")))
             }
+        });
+        let block = parser::parse_file("positive(0 - 1)").unwrap();
+        match context.eval_file(&block) {
+            Err(MathParseError::NativeFunctionError(ref name, NativeError::InvalidArgument(0, _)))
+                if name == "positive" => {}
+            other => panic!("{:?}", other),
+        }
+        let block = parser::parse_file("positive(1, 2)").unwrap();
+        match context.eval_file(&block) {
+            Err(MathParseError::WrongNumberOfArguments(1, 2)) => (),
+            other => panic!("{:?}", other),
         }
     }
+
+    #[test]
+    fn globals() {
+        let mut context = EvalContext::new();
+        context.set_var("r", 2.0);
+        let block = parser::parse_file("area = pi * r ^ 2\ndef twice(x) { 2 * x }").unwrap();
+        context.eval_file(&block).unwrap();
+        assert_eq!(context.get_var("area"), Some(Value::Num(f64::consts::PI * 4.0)));
+        assert_eq!(context.remove_var("r"), Some(Value::Num(2.0)));
+        assert_eq!(context.remove_var("r"), None);
+
+        let mut vars: Vec<&str> = context.vars().map(|(name, _)| name).collect();
+        vars.sort();
+        assert_eq!(vars, ["area", "e", "pi"]);
+        let functions: Vec<(&str, &[String])> = context.functions().collect();
+        assert_eq!(functions, [("twice", &[String::from("x")][..])]);
+
+        assert!(context.remove_fn("twice"));
+        assert!(!context.remove_fn("twice"));
+    }
+
+    #[test]
+    fn locals_do_not_leak_into_globals() {
+        let mut context = EvalContext::new();
+        let block = parser::parse_file("def f(x) { y = x\ny }\nf(3)").unwrap();
+        assert_eq!(context.eval_file(&block).unwrap(), Value::Num(3.0));
+        assert_eq!(context.get_var("x"), None);
+        assert_eq!(context.get_var("y"), None);
+    }
+
+    #[test]
+    fn reset_keeps_natives() {
+        let mut context = EvalContext::new();
+        context.register_fn("one", Arity::Fixed(0), |_| Ok(1.0));
+        context.set_var("x", 5.0);
+        context.reset();
+        assert_eq!(context.get_var("x"), None);
+        assert_eq!(context.get_var("pi"), Some(Value::Num(f64::consts::PI)));
+        let block = parser::parse_file("one()").unwrap();
+        assert_eq!(context.eval_file(&block).unwrap(), Value::Num(1.0));
+    }
 }
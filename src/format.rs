@@ -0,0 +1,150 @@
+use number::Number;
+use value::Value;
+
+/// How a `{...}` placeholder shows its argument.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Spec {
+    precision: Option<usize>,
+    exponent: bool,
+}
+
+impl Spec {
+    /// Parses the part of a placeholder between the braces.
+    fn parse(spec: &str) -> Result<Self, String> {
+        let mut result = Spec {
+            precision: None,
+            exponent: false,
+        };
+        if spec.is_empty() {
+            return Ok(result);
+        }
+        let invalid = || format!("invalid format spec '{{{}}}'", spec);
+        let mut rest = if let Some(rest) = spec.strip_prefix(':') {
+            rest
+        } else {
+            return Err(invalid());
+        };
+        if let Some(stripped) = rest.strip_suffix('e') {
+            result.exponent = true;
+            rest = stripped;
+        }
+        if let Some(digits) = rest.strip_prefix('.') {
+            result.precision = Some(digits.parse().map_err(|_| invalid())?);
+        } else if !rest.is_empty() {
+            return Err(invalid());
+        }
+        Ok(result)
+    }
+
+    fn apply(self, spec: &str, val: &Value, out: &mut String) -> Result<(), String> {
+        if self.precision.is_none() && !self.exponent {
+            out.push_str(&val.to_string());
+            return Ok(());
+        }
+        let text = match *val {
+            Value::Num(x) => self.apply_number(&x),
+            Value::Complex(z) => self.apply_number(&z),
+            Value::Rational(ref q) => self.apply_number(&**q),
+            Value::Decimal(ref d) => self.apply_number(&**d),
+            Value::Interval(x) => self.apply_number(&x),
+            Value::Uncertain(ref u) => self.apply_number(&**u),
+            Value::Dual(ref x) => self.apply_number(&**x),
+            Value::Quantity(ref q) => format!("{} {}", self.apply_number(&q.value), q.unit),
+            _ => {
+                return Err(format!(
+                    "expected number for '{{{}}}', got {}",
+                    spec,
+                    val.type_name()
+                ))
+            }
+        };
+        out.push_str(&text);
+        Ok(())
+    }
+
+    fn apply_number<N: Number>(self, x: &N) -> String {
+        match (self.precision, self.exponent) {
+            (Some(p), false) => x.to_fixed(p),
+            (precision, _) => x.to_sci(precision),
+        }
+    }
+}
+
+/// Replaces each `{}` placeholder in `fmt` with the next argument. A
+/// placeholder may give a precision as in `{:.3}`, ask for scientific
+/// notation with `{:e}`, or both as in `{:.2e}`. `{{` and `}}` stand for
+/// literal braces. There must be exactly one argument per placeholder.
+pub fn format(fmt: &str, args: &[Value]) -> Result<String, String> {
+    let mut out = String::with_capacity(fmt.len());
+    let mut args = args.iter();
+    let mut chars = fmt.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '{' if chars.peek().map(|&(_, c)| c) == Some('{') => {
+                chars.next();
+                out.push('{');
+            }
+            '{' => {
+                let end = match fmt[i..].find('}') {
+                    Some(end) => i + end,
+                    None => return Err(String::from("unclosed '{' in format string")),
+                };
+                let text = &fmt[i + 1..end];
+                match args.next() {
+                    Some(arg) => Spec::parse(text)?.apply(text, arg, &mut out)?,
+                    None => return Err(String::from("not enough arguments for format string")),
+                }
+                while let Some(&(j, _)) = chars.peek() {
+                    chars.next();
+                    if j == end {
+                        break;
+                    }
+                }
+            }
+            '}' if chars.peek().map(|&(_, c)| c) == Some('}') => {
+                chars.next();
+                out.push('}');
+            }
+            '}' => return Err(String::from("unmatched '}' in format string")),
+            c => out.push(c),
+        }
+    }
+    if args.next().is_some() {
+        return Err(String::from("too many arguments for format string"));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nums(xs: &[f64]) -> Vec<Value> {
+        xs.iter().map(|&x| Value::Num(x)).collect()
+    }
+
+    #[test]
+    fn placeholders() {
+        assert_eq!(
+            format("{} and {}", &nums(&[1.0, 2.5])).unwrap(),
+            "1 and 2.5"
+        );
+        assert_eq!(format("{:.3}", &nums(&[2.0 / 3.0])).unwrap(), "0.667");
+        assert_eq!(format("{:e}", &nums(&[1500.0])).unwrap(), "1.5e3");
+        assert_eq!(format("{:.2e}", &nums(&[1234.5])).unwrap(), "1.23e3");
+        assert_eq!(format("{{{}}}", &nums(&[1.0])).unwrap(), "{1}");
+        assert_eq!(format("no placeholders", &[]).unwrap(), "no placeholders");
+        assert_eq!(format("{}", &[Value::from("text")]).unwrap(), "text");
+    }
+
+    #[test]
+    fn errors() {
+        assert!(format("{}", &[]).is_err());
+        assert!(format("", &nums(&[1.0])).is_err());
+        assert!(format("{", &nums(&[1.0])).is_err());
+        assert!(format("}", &[]).is_err());
+        assert!(format("{:x}", &nums(&[1.0])).is_err());
+        assert!(format("{:.}", &nums(&[1.0])).is_err());
+        assert!(format("{:.2}", &[Value::from("text")]).is_err());
+    }
+}
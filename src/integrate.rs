@@ -0,0 +1,270 @@
+use std::f64;
+
+use parser::MathParseError;
+use solve::{Objective, Options};
+use value::Value;
+
+/// The nodes of the 15-point Kronrod rule on `[-1, 1]`, from the middle
+/// outward. Every other one, from the middle, is a node of the 7-point
+/// Gauss rule.
+static KRONROD_NODES: [f64; 8] = [
+    0.0,
+    0.207_784_955_007_898_48,
+    0.405_845_151_377_397_2,
+    0.586_087_235_467_691_1,
+    0.741_531_185_599_394_5,
+    0.864_864_423_359_769_1,
+    0.949_107_912_342_758_5,
+    0.991_455_371_120_812_6,
+];
+
+static KRONROD_WEIGHTS: [f64; 8] = [
+    0.209_482_141_084_727_82,
+    0.204_432_940_075_298_89,
+    0.190_350_578_064_785_42,
+    0.169_004_726_639_267_9,
+    0.140_653_259_715_525_92,
+    0.104_790_010_322_250_19,
+    0.063_092_092_629_978_56,
+    0.022_935_322_010_529_224,
+];
+
+/// The weights of the 7-point Gauss rule, for the nodes `0`, `2`, `4` and
+/// `6` above.
+static GAUSS_WEIGHTS: [f64; 4] = [
+    0.417_959_183_673_469_4,
+    0.381_830_050_505_118_9,
+    0.279_705_391_489_276_64,
+    0.129_484_966_168_869_7,
+];
+
+/// The defaults for `integrate`, which can afford to split the range many
+/// times.
+pub(crate) const DEFAULTS: Options = Options {
+    tol: 1e-10,
+    max_iter: 1000,
+    error: Some(false),
+};
+
+/// A piece of the range with its estimated integral and error.
+#[derive(Debug, Copy, Clone)]
+struct Piece {
+    a: f64,
+    b: f64,
+    value: f64,
+    error: f64,
+    /// The integral of `|f|`, against which the error is measured.
+    scale: f64,
+}
+
+/// Integrates `f` from `a` to `b`, giving the integral and an estimate of
+/// its error.
+///
+/// The range is split adaptively, always halving the piece with the
+/// largest error, until the errors add up to less than `tol` relative to
+/// the integral of `|f|`. Each piece is integrated with the 15-point
+/// Gauss-Kronrod rule, whose difference from the embedded 7-point Gauss
+/// rule estimates the error. Infinite bounds are mapped to a finite range
+/// by a change of variable.
+pub(crate) fn integrate(
+    f: &mut Objective,
+    a: f64,
+    b: f64,
+    options: Options,
+) -> Result<(f64, f64), MathParseError> {
+    if a.is_nan() || b.is_nan() {
+        return Err(MathParseError::TypeError(String::from(
+            "bounds of an integral cannot be NaN",
+        )));
+    }
+    if a == b {
+        return Ok((0.0, 0.0));
+    }
+    if a > b {
+        return integrate(f, b, a, options).map(|(value, error)| (-value, error));
+    }
+    match (a.is_finite(), b.is_finite()) {
+        (true, true) => adapt(&mut |x| at(f, x), a, b, options),
+        // x = a + t / (1 - t) for t in [0, 1).
+        (true, false) => adapt(
+            &mut |t| Ok(at(f, a + t / (1.0 - t))? / ((1.0 - t) * (1.0 - t))),
+            0.0,
+            1.0,
+            options,
+        ),
+        // x = b - (1 - t) / t for t in (0, 1].
+        (false, true) => adapt(
+            &mut |t| Ok(at(f, b - (1.0 - t) / t)? / (t * t)),
+            0.0,
+            1.0,
+            options,
+        ),
+        // x = t / (1 - t^2) for t in (-1, 1).
+        (false, false) => adapt(
+            &mut |t| {
+                let s = 1.0 - t * t;
+                Ok(at(f, t / s)? * (1.0 + t * t) / (s * s))
+            },
+            -1.0,
+            1.0,
+            options,
+        ),
+    }
+}
+
+fn at(f: &mut Objective, x: f64) -> Result<f64, MathParseError> {
+    let y = f(Value::Num(x))?;
+    match y.as_num() {
+        Some(y) if y.is_finite() => Ok(y),
+        Some(y) => Err(MathParseError::DidNotConverge(format!(
+            "the integrand is {} at {}",
+            y, x
+        ))),
+        None => Err(MathParseError::TypeError(format!(
+            "expected function to return a number, got {}",
+            y.type_name()
+        ))),
+    }
+}
+
+fn adapt(
+    f: &mut dyn FnMut(f64) -> Result<f64, MathParseError>,
+    a: f64,
+    b: f64,
+    options: Options,
+) -> Result<(f64, f64), MathParseError> {
+    let mut pieces = vec![kronrod(f, a, b)?];
+    loop {
+        let value: f64 = pieces.iter().map(|p| p.value).sum();
+        let error: f64 = pieces.iter().map(|p| p.error).sum();
+        let scale: f64 = pieces.iter().map(|p| p.scale).sum();
+        if error <= options.tol * scale {
+            return Ok((value, error));
+        }
+        if pieces.len() >= options.max_iter {
+            return Err(MathParseError::DidNotConverge(format!(
+                "integral is {} with an error of {} after splitting the range into {} pieces",
+                value, error, options.max_iter
+            )));
+        }
+        let worst = (0..pieces.len())
+            .max_by(|&i, &j| pieces[i].error.total_cmp(&pieces[j].error))
+            .unwrap();
+        let piece = pieces.swap_remove(worst);
+        let mid = piece.a + (piece.b - piece.a) / 2.0;
+        // A piece that cannot be split any further is as good as it gets.
+        if mid <= piece.a || mid >= piece.b {
+            pieces.push(Piece {
+                error: 0.0,
+                ..piece
+            });
+            continue;
+        }
+        pieces.push(kronrod(f, piece.a, mid)?);
+        pieces.push(kronrod(f, mid, piece.b)?);
+    }
+}
+
+/// Applies the Gauss-Kronrod rule to one piece.
+fn kronrod(
+    f: &mut dyn FnMut(f64) -> Result<f64, MathParseError>,
+    a: f64,
+    b: f64,
+) -> Result<Piece, MathParseError> {
+    let center = a + (b - a) / 2.0;
+    let half = (b - a) / 2.0;
+    let mut kronrod = 0.0;
+    let mut gauss = 0.0;
+    let mut scale = 0.0;
+    for (i, (&node, &weight)) in KRONROD_NODES.iter().zip(KRONROD_WEIGHTS.iter()).enumerate() {
+        let (y, abs) = if i == 0 {
+            let y = f(center)?;
+            (y, y.abs())
+        } else {
+            let (left, right) = (f(center - half * node)?, f(center + half * node)?);
+            (left + right, left.abs() + right.abs())
+        };
+        kronrod += weight * y;
+        scale += weight * abs;
+        if i % 2 == 0 {
+            gauss += GAUSS_WEIGHTS[i / 2] * y;
+        }
+    }
+    Ok(Piece {
+        a,
+        b,
+        value: kronrod * half,
+        error: ((kronrod - gauss) * half).abs(),
+        scale: scale * half.abs(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Integrand = fn(f64) -> f64;
+
+    fn integral(f: Integrand, a: f64, b: f64, tol: f64) -> (f64, f64) {
+        let options = Options { tol, ..DEFAULTS };
+        integrate(
+            &mut |x| Ok(Value::Num(f(x.as_num().unwrap()))),
+            a,
+            b,
+            options,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn closed_forms() {
+        let pi = f64::consts::PI;
+        let cases: [(Integrand, f64, f64, f64); 7] = [
+            (f64::sin, 0.0, pi, 2.0),
+            (|x| x * x, 0.0, 1.0, 1.0 / 3.0),
+            (|x| x * x, 1.0, 0.0, -1.0 / 3.0),
+            (|x| 4.0 / (1.0 + x * x), 0.0, 1.0, pi),
+            (|x| 1.0 / x.sqrt(), 0.0, 1.0, 2.0),
+            (|x| (-x).exp(), 0.0, f64::INFINITY, 1.0),
+            (
+                |x| (-x * x).exp(),
+                f64::NEG_INFINITY,
+                f64::INFINITY,
+                pi.sqrt(),
+            ),
+        ];
+        for &(f, a, b, exact) in cases.iter() {
+            let (value, error) = integral(f, a, b, 1e-10);
+            assert!(
+                (value - exact).abs() <= 1e-9 * exact.abs(),
+                "{} from {} to {}",
+                value,
+                a,
+                b
+            );
+            assert!(
+                error <= 1e-9 * exact.abs(),
+                "error {} from {} to {}",
+                error,
+                a,
+                b
+            );
+        }
+        assert_eq!(integral(f64::sin, 1.0, 1.0, 1e-10), (0.0, 0.0));
+    }
+
+    #[test]
+    fn error_estimate_bounds_the_actual_error() {
+        // A looser tolerance leaves an error large enough to measure.
+        let cases: [(Integrand, f64); 3] = [
+            (f64::sqrt, 2.0 / 3.0),
+            (|x| x.ln(), -1.0),
+            (|x| (10.0 * x).cos(), 0.1 * 10f64.sin()),
+        ];
+        for &(f, exact) in cases.iter() {
+            let (value, error) = integral(f, 0.0, 1.0, 1e-6);
+            assert!((value - exact).abs() <= error, "{} for {}", value, exact);
+            assert!(error <= 1e-6 * 2.0, "error {} for {}", error, exact);
+        }
+    }
+}
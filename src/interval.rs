@@ -0,0 +1,645 @@
+use std::cmp::Ordering;
+use std::f64;
+use std::fmt::{self, Display};
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use bigint::BigInt;
+use builtins;
+use rational::Rational;
+
+/// A closed interval of reals `[lo, hi]`, with bounds that may be infinite.
+///
+/// Every operation rounds outward, so the result always contains the exact
+/// result for every choice of points in the operands. Operations that are
+/// exact in floating point, like `[1, 2] + [3, 4]`, give exact bounds.
+/// Intervals with NaN bounds are empty, as for `sqrt` of a negative
+/// interval.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Interval {
+    pub lo: f64,
+    pub hi: f64,
+}
+
+/// Rounds an inexact result outward. `x` is the rounded result and `err`
+/// has the sign of the exact result minus `x`: zero if `x` is exact, and
+/// NaN if the sign is not known. Returns the bounds rounded down and up.
+fn bracket(x: f64, err: f64) -> (f64, f64) {
+    if x.is_nan() {
+        (x, x)
+    } else if x.is_infinite() {
+        // The exact result may be finite but too large.
+        if x > 0.0 {
+            (f64::MAX, x)
+        } else {
+            (x, -f64::MAX)
+        }
+    } else if err > 0.0 {
+        (x, x.next_up())
+    } else if err < 0.0 {
+        (x.next_down(), x)
+    } else if err == 0.0 {
+        (x, x)
+    } else {
+        (x.next_down(), x.next_up())
+    }
+}
+
+/// Results smaller than this may have lost bits to underflow, so their
+/// error terms cannot be trusted.
+const TINY: f64 = 1e-290;
+
+fn add_bracket(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    // The error of the sum, computed exactly (Knuth's TwoSum).
+    let bb = s - a;
+    let err = (a - (s - bb)) + (b - bb);
+    bracket(s, err)
+}
+
+fn mul_bracket(a: f64, b: f64) -> (f64, f64) {
+    // Zero times an infinite bound is zero here, since the bound itself is
+    // not reached.
+    if a == 0.0 || b == 0.0 {
+        return (0.0, 0.0);
+    }
+    let p = a * b;
+    let err = if p.abs() > TINY {
+        a.mul_add(b, -p)
+    } else {
+        f64::NAN
+    };
+    bracket(p, err)
+}
+
+fn div_bracket(a: f64, b: f64) -> (f64, f64) {
+    if a == 0.0 {
+        return (0.0, 0.0);
+    }
+    let q = a / b;
+    let err = if q.abs() > TINY && q.is_finite() && b.is_finite() {
+        // The exact quotient is q + r / b, where r = a - q * b exactly.
+        -q.mul_add(b, -a) * b.signum()
+    } else {
+        f64::NAN
+    };
+    bracket(q, err)
+}
+
+/// Rounds the result of a library function outward by one unit in the last
+/// place, which covers its error.
+fn widen(x: f64) -> (f64, f64) {
+    bracket(x, f64::NAN)
+}
+
+/// Calls a library function on a bound and rounds the result outward.
+/// Results of 0 or 1 at 0 or 1, like `sin(0)` and `exp(0)`, are exact.
+fn eval_bound(f: fn(f64) -> f64, x: f64) -> (f64, f64) {
+    let y = f(x);
+    if (x == 0.0 || x == 1.0) && (y == 0.0 || y == 1.0) {
+        (y, y)
+    } else {
+        widen(y)
+    }
+}
+
+fn min(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        f64::NAN
+    } else {
+        a.min(b)
+    }
+}
+
+fn max(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        f64::NAN
+    } else {
+        a.max(b)
+    }
+}
+
+impl Interval {
+    /// The interval `[lo, hi]`, which is empty if `lo > hi`.
+    pub fn new(lo: f64, hi: f64) -> Self {
+        if lo <= hi {
+            Interval { lo, hi }
+        } else {
+            Interval::empty()
+        }
+    }
+
+    pub fn point(x: f64) -> Self {
+        Interval::new(x, x)
+    }
+
+    pub fn empty() -> Self {
+        Interval {
+            lo: f64::NAN,
+            hi: f64::NAN,
+        }
+    }
+
+    pub fn entire() -> Self {
+        Interval {
+            lo: f64::NEG_INFINITY,
+            hi: f64::INFINITY,
+        }
+    }
+
+    /// The smallest interval of floats containing `q`.
+    pub fn from_rational(q: &Rational) -> Self {
+        let x = q.to_f64();
+        if x.is_infinite() {
+            return Interval::new(x.min(-f64::MAX), x.max(f64::MAX));
+        }
+        match Rational::from_f64(x).unwrap().cmp(q) {
+            Ordering::Less => Interval::new(x, x.next_up()),
+            Ordering::Equal => Interval::point(x),
+            Ordering::Greater => Interval::new(x.next_down(), x),
+        }
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.lo.is_nan()
+    }
+
+    pub fn contains(self, x: f64) -> bool {
+        self.lo <= x && x <= self.hi
+    }
+
+    /// The midpoint, or the finite bound of a half-infinite interval.
+    pub fn mid(self) -> f64 {
+        match (self.lo.is_finite(), self.hi.is_finite()) {
+            (true, true) => self.lo / 2.0 + self.hi / 2.0,
+            (true, false) => self.lo,
+            (false, true) => self.hi,
+            (false, false) => 0.0,
+        }
+    }
+
+    pub fn intersect(self, other: Interval) -> Self {
+        if self.is_empty() || other.is_empty() {
+            return Interval::empty();
+        }
+        Interval::new(self.lo.max(other.lo), self.hi.min(other.hi))
+    }
+
+    /// Applies an operation to each pair of bounds and takes the lowest and
+    /// highest results.
+    fn corners<F: Fn(f64, f64) -> (f64, f64)>(self, other: Interval, f: F) -> Self {
+        let results = [
+            f(self.lo, other.lo),
+            f(self.lo, other.hi),
+            f(self.hi, other.lo),
+            f(self.hi, other.hi),
+        ];
+        let lo = results.iter().fold(f64::INFINITY, |acc, r| min(acc, r.0));
+        let hi = results
+            .iter()
+            .fold(f64::NEG_INFINITY, |acc, r| max(acc, r.1));
+        Interval::new(lo, hi)
+    }
+
+    pub fn abs(self) -> Self {
+        if self.lo >= 0.0 {
+            self
+        } else if self.hi <= 0.0 {
+            -self
+        } else {
+            Interval::new(0.0, max(-self.lo, self.hi))
+        }
+    }
+
+    /// Raises to an integer power. Unlike repeated multiplication, even
+    /// powers of an interval containing zero do not go below zero.
+    pub fn powi(self, n: i32) -> Self {
+        if self.is_empty() {
+            return self;
+        }
+        if n < 0 {
+            return Interval::point(1.0) / self.powi(-n);
+        }
+        let n = n as u32;
+        let base = if n.is_multiple_of(2) {
+            self.abs()
+        } else {
+            self
+        };
+        Interval::new(pow_point(base.lo, n).lo, pow_point(base.hi, n).hi)
+    }
+
+    /// `self ^ other`. Non-integer powers are only taken of the part of the
+    /// base that is not negative.
+    pub fn pow(self, other: Interval) -> Self {
+        if other.lo == other.hi && other.lo.fract() == 0.0 && other.lo.abs() <= i32::MAX as f64 {
+            return self.powi(other.lo as i32);
+        }
+        let base = self.intersect(Interval::new(0.0, f64::INFINITY));
+        (other * base.ln()).exp()
+    }
+
+    pub fn sqrt(self) -> Self {
+        let x = self.intersect(Interval::new(0.0, f64::INFINITY));
+        let root = |x: f64| {
+            let r = x.sqrt();
+            if x == 0.0 || x.is_infinite() {
+                (r, r)
+            } else if x > TINY {
+                // The exact root is about r + (x - r * r) / (2 * r).
+                bracket(r, -r.mul_add(r, -x))
+            } else {
+                widen(r)
+            }
+        };
+        Interval::new(root(x.lo).0, root(x.hi).1)
+    }
+
+    pub fn exp(self) -> Self {
+        self.increasing(f64::exp, Interval::entire())
+            .intersect(Interval::new(0.0, f64::INFINITY))
+    }
+
+    pub fn ln(self) -> Self {
+        self.increasing(f64::ln, Interval::new(0.0, f64::INFINITY))
+    }
+
+    pub fn sin(self) -> Self {
+        self.periodic(f64::sin, f64::consts::FRAC_PI_2)
+    }
+
+    pub fn cos(self) -> Self {
+        self.periodic(f64::cos, 0.0)
+    }
+
+    pub fn tan(self) -> Self {
+        if self.is_empty() {
+            return self;
+        }
+        if self.hi - self.lo >= f64::consts::PI
+            || self.may_contain_phase(f64::consts::FRAC_PI_2, f64::consts::PI)
+        {
+            return Interval::entire();
+        }
+        self.increasing(f64::tan, Interval::entire())
+    }
+
+    /// The image under an increasing function with the given domain.
+    /// Library functions are assumed to be within one unit in the last place
+    /// of the exact result.
+    fn increasing(self, f: fn(f64) -> f64, domain: Interval) -> Self {
+        let x = self.intersect(domain);
+        Interval::new(eval_bound(f, x.lo).0, eval_bound(f, x.hi).1)
+    }
+
+    fn decreasing(self, f: fn(f64) -> f64, domain: Interval) -> Self {
+        let x = self.intersect(domain);
+        Interval::new(eval_bound(f, x.hi).0, eval_bound(f, x.lo).1)
+    }
+
+    /// The image under an increasing function that is computed exactly.
+    fn increasing_exact(self, f: fn(f64) -> f64) -> Self {
+        Interval::new(f(self.lo), f(self.hi))
+    }
+
+    /// The image under `sin` or `cos`, which peak at `peak` plus multiples
+    /// of `2 * pi`.
+    fn periodic(self, f: fn(f64) -> f64, peak: f64) -> Self {
+        if self.is_empty() {
+            return self;
+        }
+        let full = Interval::new(-1.0, 1.0);
+        if self.hi - self.lo >= 2.0 * f64::consts::PI
+            || !self.lo.is_finite()
+            || !self.hi.is_finite()
+        {
+            return full;
+        }
+        let (a, b) = (eval_bound(f, self.lo), eval_bound(f, self.hi));
+        let mut lo = min(a.0, b.0);
+        let mut hi = max(a.1, b.1);
+        let period = 2.0 * f64::consts::PI;
+        if self.may_contain_phase(peak, period) {
+            hi = 1.0;
+        }
+        if self.may_contain_phase(peak + f64::consts::PI, period) {
+            lo = -1.0;
+        }
+        Interval::new(lo, hi).intersect(full)
+    }
+
+    /// Whether the interval may contain `phase` plus a multiple of `period`.
+    /// Since `phase` and `period` are themselves rounded, this errs towards
+    /// yes near the edges.
+    fn may_contain_phase(self, phase: f64, period: f64) -> bool {
+        let k = ((self.lo - phase) / period).ceil();
+        let slack = 1e-12 * (1.0 + self.lo.abs().max(self.hi.abs()));
+        (-1..=1).any(|i| {
+            let x = phase + (k + i as f64) * period;
+            self.lo - slack <= x && x <= self.hi + slack
+        })
+    }
+
+    fn atan2(self, x: Interval) -> Self {
+        let y = self;
+        if y.is_empty() || x.is_empty() {
+            return Interval::empty();
+        }
+        // Away from the negative real axis, where the angle jumps, the
+        // extremes over the box are at its corners.
+        if y.contains(0.0) && x.lo <= 0.0 {
+            let pi = widen(f64::consts::PI).1;
+            return Interval::new(-pi, pi);
+        }
+        y.corners(x, |y, x| widen(y.atan2(x)))
+    }
+
+    fn min(self, other: Interval) -> Self {
+        Interval::new(min(self.lo, other.lo), min(self.hi, other.hi))
+    }
+
+    fn max(self, other: Interval) -> Self {
+        Interval::new(max(self.lo, other.lo), max(self.hi, other.hi))
+    }
+
+    /// Writes the interval with `places` digits after the point, rounding
+    /// the bounds outward.
+    pub fn to_fixed(self, places: usize) -> String {
+        format!(
+            "[{}, {}]",
+            fixed(self.lo, places, Rational::floor),
+            fixed(self.hi, places, Rational::ceil)
+        )
+    }
+}
+
+impl Add for Interval {
+    type Output = Interval;
+
+    fn add(self, other: Interval) -> Interval {
+        Interval::new(
+            add_bracket(self.lo, other.lo).0,
+            add_bracket(self.hi, other.hi).1,
+        )
+    }
+}
+
+impl Sub for Interval {
+    type Output = Interval;
+
+    fn sub(self, other: Interval) -> Interval {
+        self + -other
+    }
+}
+
+impl Mul for Interval {
+    type Output = Interval;
+
+    fn mul(self, other: Interval) -> Interval {
+        self.corners(other, mul_bracket)
+    }
+}
+
+/// Division by an interval containing zero gives the entire real line, or
+/// the empty interval if the divisor is exactly zero.
+impl Div for Interval {
+    type Output = Interval;
+
+    fn div(self, other: Interval) -> Interval {
+        if other.lo == 0.0 && other.hi == 0.0 {
+            Interval::empty()
+        } else if other.contains(0.0) {
+            if self.is_empty() {
+                Interval::empty()
+            } else {
+                Interval::entire()
+            }
+        } else {
+            self.corners(other, div_bracket)
+        }
+    }
+}
+
+impl Neg for Interval {
+    type Output = Interval;
+
+    fn neg(self) -> Interval {
+        Interval::new(-self.hi, -self.lo)
+    }
+}
+
+/// `x ^ n` as an interval, by repeated squaring.
+fn pow_point(x: f64, n: u32) -> Interval {
+    let mut result = Interval::point(1.0);
+    let mut base = Interval::point(x);
+    let mut n = n;
+    while n > 0 {
+        if !n.is_multiple_of(2) {
+            result = result * base;
+        }
+        base = base * base;
+        n /= 2;
+    }
+    result
+}
+
+/// Writes `x` with `places` digits after the point, rounding with `round`.
+fn fixed(x: f64, places: usize, round: fn(&Rational) -> Rational) -> String {
+    let q = match Rational::from_f64(x) {
+        Some(q) => q,
+        None => return format!("{}", x),
+    };
+    let scale = Rational::from_int(BigInt::from(10).pow(places as u32));
+    let scaled = round(&q.mul(&scale));
+    scaled.div(&scale).unwrap().to_decimal(places)
+}
+
+impl Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            write!(f, "[empty]")
+        } else {
+            write!(f, "[{}, {}]", self.lo, self.hi)
+        }
+    }
+}
+
+/// The interval versions of the builtins, so that intervals can be passed
+/// to any of them. The arity has already been checked.
+pub fn builtin(name: &str) -> Option<fn(&[Interval]) -> Interval> {
+    macro_rules! unary {
+        ($f: expr) => {
+            Some(|args: &[Interval]| $f(args[0]))
+        };
+    }
+    macro_rules! increasing {
+        ($f: expr, $lo: expr, $hi: expr) => {
+            Some(|args: &[Interval]| args[0].increasing($f, Interval::new($lo, $hi)))
+        };
+    }
+    const INF: f64 = f64::INFINITY;
+    match name {
+        "sin" => unary!(Interval::sin),
+        "cos" => unary!(Interval::cos),
+        "tan" => unary!(Interval::tan),
+        "asin" => increasing!(f64::asin, -1.0, 1.0),
+        "acos" => Some(|args: &[Interval]| args[0].decreasing(f64::acos, Interval::new(-1.0, 1.0))),
+        "atan" => increasing!(f64::atan, -INF, INF),
+        "atan2" => Some(|args: &[Interval]| args[0].atan2(args[1])),
+        "sinh" => increasing!(f64::sinh, -INF, INF),
+        "cosh" => Some(|args: &[Interval]| args[0].abs().increasing(f64::cosh, Interval::entire())),
+        "tanh" => increasing!(f64::tanh, -INF, INF),
+        "asinh" => increasing!(f64::asinh, -INF, INF),
+        "acosh" => increasing!(f64::acosh, 1.0, INF),
+        "atanh" => increasing!(f64::atanh, -1.0, 1.0),
+        "sqrt" => unary!(Interval::sqrt),
+        "cbrt" => increasing!(f64::cbrt, -INF, INF),
+        "exp" => unary!(Interval::exp),
+        "ln" => unary!(Interval::ln),
+        "log10" => increasing!(f64::log10, 0.0, INF),
+        "log2" => increasing!(f64::log2, 0.0, INF),
+        "log" => Some(|args: &[Interval]| args[1].ln() / args[0].ln()),
+        "abs" => unary!(Interval::abs),
+        "sign" => Some(|args: &[Interval]| args[0].increasing_exact(builtins::sign)),
+        "floor" => Some(|args: &[Interval]| args[0].increasing_exact(f64::floor)),
+        "ceil" => Some(|args: &[Interval]| args[0].increasing_exact(f64::ceil)),
+        "round" => Some(|args: &[Interval]| args[0].increasing_exact(f64::round)),
+        "trunc" => Some(|args: &[Interval]| args[0].increasing_exact(f64::trunc)),
+        "min" => Some(|args: &[Interval]| args[1..].iter().fold(args[0], |acc, x| acc.min(*x))),
+        "max" => Some(|args: &[Interval]| args[1..].iter().fold(args[0], |acc, x| acc.max(*x))),
+        "hypot" => Some(|args: &[Interval]| (args[0].powi(2) + args[1].powi(2)).sqrt()),
+        "clamp" => Some(|args: &[Interval]| args[0].max(args[1]).min(args[2])),
+        "arg" => Some(|args: &[Interval]| {
+            let pi = widen(f64::consts::PI);
+            match (args[0].lo >= 0.0, args[0].hi < 0.0) {
+                _ if args[0].is_empty() => Interval::empty(),
+                (true, _) => Interval::point(0.0),
+                (_, true) => Interval::new(pi.0, pi.1),
+                _ => Interval::new(0.0, pi.1),
+            }
+        }),
+        "conj" | "re" => unary!(|x| x),
+        "im" => Some(|args: &[Interval]| args[0] * Interval::point(0.0)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALUES: [f64; 12] = [
+        0.1,
+        0.2,
+        0.3,
+        1.0,
+        3.0,
+        -7.5,
+        1.0 / 3.0,
+        -2.0 / 3.0,
+        1e16,
+        -1e-5,
+        1e-300,
+        1e300,
+    ];
+
+    fn exact(x: f64) -> Rational {
+        Rational::from_f64(x).unwrap()
+    }
+
+    /// Whether `x` contains `q`, and is at most one float wide on each
+    /// side of it.
+    fn encloses_tightly(x: Interval, q: &Rational) -> bool {
+        let below = x.lo == f64::NEG_INFINITY || exact(x.lo) <= *q;
+        let above = x.hi == f64::INFINITY || exact(x.hi) >= *q;
+        let tight = x.lo == x.hi || x.lo.next_up() == x.hi;
+        below && above && tight
+    }
+
+    #[test]
+    fn add_sub_and_mul_contain_the_exact_result() {
+        for &a in VALUES.iter() {
+            for &b in VALUES.iter() {
+                let (x, y) = (Interval::point(a), Interval::point(b));
+                let (p, q) = (exact(a), exact(b));
+                assert!(encloses_tightly(x + y, &p.add(&q)), "{} + {}", a, b);
+                assert!(encloses_tightly(x - y, &p.sub(&q)), "{} - {}", a, b);
+                if (a * b).abs() > TINY && (a * b).is_finite() {
+                    assert!(encloses_tightly(x * y, &p.mul(&q)), "{} * {}", a, b);
+                } else {
+                    let product = x * y;
+                    assert!(product.lo <= a * b && a * b <= product.hi, "{} * {}", a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn div_contains_the_exact_result() {
+        for &a in VALUES.iter() {
+            for &b in VALUES.iter() {
+                let quotient = Interval::point(a) / Interval::point(b);
+                let q = a / b;
+                if q.abs() > TINY && q.is_finite() {
+                    let exact = exact(a).div(&exact(b)).unwrap();
+                    assert!(encloses_tightly(quotient, &exact), "{} / {}", a, b);
+                } else {
+                    assert!(quotient.lo <= q && q <= quotient.hi, "{} / {}", a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn exact_results_give_exact_bounds() {
+        let (x, y) = (Interval::new(1.0, 2.0), Interval::new(3.0, 4.0));
+        assert_eq!(x + y, Interval::new(4.0, 6.0));
+        assert_eq!(x - y, Interval::new(-3.0, -1.0));
+        assert_eq!(x * -y, Interval::new(-8.0, -3.0));
+        assert_eq!(y / Interval::point(2.0), Interval::new(1.5, 2.0));
+        assert_eq!(Interval::new(4.0, 9.0).sqrt(), Interval::new(2.0, 3.0));
+    }
+
+    #[test]
+    fn sums_of_tenths_are_enclosed() {
+        let tenth = Interval::from_rational(&Rational::parse("0.1").unwrap());
+        let three = Rational::parse("0.3").unwrap();
+        let sum = tenth + tenth + tenth;
+        assert!(exact(sum.lo) < three && three < exact(sum.hi));
+        assert!(!Interval::point(0.1 + 0.1 + 0.1).contains(0.3));
+    }
+
+    #[test]
+    fn sqrt_contains_the_exact_root() {
+        for &x in [2.0, 3.0, 0.1, 1e-5, 1e300, 1.0 / 3.0].iter() {
+            let root = Interval::point(x).sqrt();
+            let (lo, hi) = (exact(root.lo), exact(root.hi));
+            assert!(lo.mul(&lo) <= exact(x), "sqrt({})", x);
+            assert!(hi.mul(&hi) >= exact(x), "sqrt({})", x);
+            assert_eq!(root.lo.next_up(), root.hi, "sqrt({})", x);
+        }
+        // Only the part that is not negative has a root.
+        assert_eq!(Interval::new(-4.0, 4.0).sqrt(), Interval::new(0.0, 2.0));
+        assert!(Interval::new(-4.0, -1.0).sqrt().is_empty());
+    }
+
+    #[test]
+    fn overflow_keeps_a_finite_bound() {
+        let big = Interval::point(1e300);
+        assert_eq!(big * big, Interval::new(f64::MAX, f64::INFINITY));
+        assert_eq!(-big * big, Interval::new(f64::NEG_INFINITY, -f64::MAX));
+    }
+
+    #[test]
+    fn division_by_an_interval_containing_zero() {
+        let x = Interval::new(1.0, 2.0);
+        assert_eq!(x / Interval::new(-1.0, 1.0), Interval::entire());
+        assert_eq!(x / Interval::new(0.0, 1.0), Interval::entire());
+        assert_eq!(x / Interval::new(-1.0, 0.0), Interval::entire());
+        assert!((x / Interval::point(0.0)).is_empty());
+        assert!((Interval::empty() / Interval::new(-1.0, 1.0)).is_empty());
+        assert_eq!(
+            Interval::point(0.0) / Interval::new(2.0, 3.0),
+            Interval::point(0.0)
+        );
+    }
+}
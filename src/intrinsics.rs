@@ -0,0 +1,616 @@
+use std::cmp::Ordering;
+use std::rc::Rc;
+use std::slice;
+
+use ast::{BinOp, ReplTree};
+use builtins::{Arity, NativeError};
+use dual;
+use eval::EvalContext;
+use integrate;
+use minimize::{self, Minimum};
+use ode::{self, Output};
+use parser::{self, MathParseError};
+use solve::{self, Options, Start};
+use value::{self, Function, Value};
+
+/// Calls functions on behalf of an intrinsic.
+pub trait Caller {
+    fn call(&mut self, func: &Function, args: &[Value]) -> Result<Value, MathParseError>;
+
+    /// The context the intrinsic is called in, for its definitions.
+    fn context(&self) -> &EvalContext;
+}
+
+/// An error from an intrinsic: either a bad argument, or an error raised by
+/// a function it called, which is passed on unchanged.
+pub enum IntrinsicError {
+    Native(NativeError),
+    Eval(MathParseError),
+}
+
+impl From<NativeError> for IntrinsicError {
+    fn from(err: NativeError) -> Self {
+        IntrinsicError::Native(err)
+    }
+}
+
+impl From<MathParseError> for IntrinsicError {
+    fn from(err: MathParseError) -> Self {
+        IntrinsicError::Eval(err)
+    }
+}
+
+type IntrinsicResult = Result<Value, IntrinsicError>;
+
+/// A builtin that calls back into the evaluator, such as `map`. Like
+/// `ValueBuiltin`s these are only available to the evaluator.
+pub struct Intrinsic {
+    pub name: &'static str,
+    pub arity: Arity,
+    pub func: fn(&mut dyn Caller, &[Value]) -> IntrinsicResult,
+}
+
+pub static INTRINSICS: &[Intrinsic] = &[
+    Intrinsic {
+        name: "range",
+        arity: Arity::Range(1, 3),
+        func: range,
+    },
+    Intrinsic {
+        name: "map",
+        arity: Arity::Fixed(2),
+        func: map,
+    },
+    Intrinsic {
+        name: "filter",
+        arity: Arity::Fixed(2),
+        func: filter,
+    },
+    Intrinsic {
+        name: "reduce",
+        arity: Arity::Fixed(3),
+        func: reduce,
+    },
+    Intrinsic {
+        name: "sum",
+        arity: Arity::Fixed(1),
+        func: sum,
+    },
+    Intrinsic {
+        name: "prod",
+        arity: Arity::Fixed(1),
+        func: prod,
+    },
+    Intrinsic {
+        name: "any",
+        arity: Arity::Range(1, 2),
+        func: any,
+    },
+    Intrinsic {
+        name: "all",
+        arity: Arity::Range(1, 2),
+        func: all,
+    },
+    Intrinsic {
+        name: "zip",
+        arity: Arity::Variadic(1),
+        func: zip,
+    },
+    Intrinsic {
+        name: "sort",
+        arity: Arity::Range(1, 2),
+        func: sort,
+    },
+    Intrinsic {
+        name: "diff",
+        arity: Arity::Fixed(2),
+        func: diff,
+    },
+    Intrinsic {
+        name: "deriv",
+        arity: Arity::Fixed(2),
+        func: deriv,
+    },
+    Intrinsic {
+        name: "grad",
+        arity: Arity::Fixed(2),
+        func: grad,
+    },
+    Intrinsic {
+        name: "solve",
+        arity: Arity::Range(2, 4),
+        func: solve,
+    },
+    Intrinsic {
+        name: "integrate",
+        arity: Arity::Range(3, 4),
+        func: integrate,
+    },
+    Intrinsic {
+        name: "odesolve",
+        arity: Arity::Range(4, 5),
+        func: odesolve,
+    },
+    Intrinsic {
+        name: "minimize",
+        arity: Arity::Range(2, 4),
+        func: minimize,
+    },
+];
+
+pub fn lookup(name: &str) -> Option<&'static Intrinsic> {
+    INTRINSICS.iter().find(|intrinsic| intrinsic.name == name)
+}
+
+fn invalid(i: usize, msg: String) -> IntrinsicError {
+    IntrinsicError::Native(NativeError::InvalidArgument(i, msg))
+}
+
+fn expect_num(args: &[Value], i: usize) -> Result<f64, IntrinsicError> {
+    args[i]
+        .as_num()
+        .ok_or_else(|| invalid(i, format!("expected number, got {}", args[i].type_name())))
+}
+
+/// The items of a list or tuple argument.
+fn expect_list(args: &[Value], i: usize) -> Result<&[Value], IntrinsicError> {
+    args[i]
+        .as_items()
+        .ok_or_else(|| invalid(i, format!("expected list, got {}", args[i].type_name())))
+}
+
+fn expect_str(args: &[Value], i: usize) -> Result<&str, IntrinsicError> {
+    match args[i] {
+        Value::Str(ref s) => Ok(s),
+        ref val => Err(invalid(i, format!("expected string, got {}", val.type_name()))),
+    }
+}
+
+/// The items of a list or tuple argument that holds only numbers.
+fn expect_nums(args: &[Value], i: usize) -> Result<Vec<f64>, IntrinsicError> {
+    let items = expect_list(args, i)?;
+    let mut nums = Vec::with_capacity(items.len());
+    for item in items.iter() {
+        nums.push(item.as_num().ok_or_else(|| {
+            invalid(i, format!("expected list of numbers, got {}", item.type_name()))
+        })?);
+    }
+    Ok(nums)
+}
+
+fn expect_fn(args: &[Value], i: usize) -> Result<&Function, IntrinsicError> {
+    match args[i] {
+        Value::Func(ref func) => Ok(func),
+        ref val => Err(invalid(i, format!("expected function, got {}", val.type_name()))),
+    }
+}
+
+/// Splits off a trailing record of options for an iterative method, like
+/// `{tol: 0.001}`, from the other arguments. Options left out keep the
+/// values in `defaults`.
+fn split_options(args: &[Value], defaults: Options) -> Result<(&[Value], Options), IntrinsicError> {
+    match args.last() {
+        Some(Value::Record(ref record)) => {
+            let i = args.len() - 1;
+            let options = defaults.read(record).map_err(|msg| invalid(i, msg))?;
+            Ok((&args[..i], options))
+        }
+        _ => Ok((args, defaults)),
+    }
+}
+
+/// Like `split_options`, but also accepts a bare number after the first
+/// `n` arguments as the tolerance, as in `integrate(f, 0, 1, 0.001)`.
+fn split_tolerance(
+    args: &[Value],
+    n: usize,
+    defaults: Options,
+) -> Result<(&[Value], Options), IntrinsicError> {
+    match args.get(n).and_then(Value::as_num) {
+        Some(tol) if args.len() == n + 1 => {
+            let options = defaults.with_tol(tol).map_err(|msg| invalid(n, msg))?;
+            Ok((&args[..n], options))
+        }
+        _ => split_options(args, defaults),
+    }
+}
+
+/// The most items `range` will make, so that a mistyped bound is an error
+/// rather than an attempt to allocate all of memory. The terms of `sum` and
+/// `prod` notation are limited the same way.
+pub(crate) const MAX_RANGE: usize = 1 << 24;
+
+/// The number of terms in `sum(i, lo, hi, term)` or `prod(i, lo, hi,
+/// term)`, one for each step of 1 from `lo` up to at most `hi`. `name` is
+/// the notation's, for errors.
+pub(crate) fn count_terms(name: &str, lo: f64, hi: f64) -> Result<usize, MathParseError> {
+    if !lo.is_finite() || !hi.is_finite() {
+        return Err(MathParseError::TypeError(format!(
+            "expected finite bounds, got {} and {}",
+            lo, hi
+        )));
+    }
+    let count = (hi - lo).floor() + 1.0;
+    if count > MAX_RANGE as f64 {
+        return Err(MathParseError::NativeFunctionError(
+            String::from(name),
+            NativeError::InvalidArgument(
+                2,
+                format!("{} can have at most {} terms, not {}", name, MAX_RANGE, count),
+            ),
+        ));
+    }
+    Ok(count.max(0.0) as usize)
+}
+
+/// `range(n)` counts from 0 up to `n`, `range(a, b)` from `a` up to `b`, and
+/// `range(a, b, step)` by `step`. The end is never included.
+fn range(_: &mut dyn Caller, args: &[Value]) -> IntrinsicResult {
+    let end_index = if args.len() == 1 { 0 } else { 1 };
+    let start = if args.len() == 1 {
+        0.0
+    } else {
+        expect_num(args, 0)?
+    };
+    let end = expect_num(args, end_index)?;
+    let step = if args.len() == 3 {
+        expect_num(args, 2)?
+    } else {
+        1.0
+    };
+    if !start.is_finite() {
+        return Err(invalid(0, String::from("bounds must be finite")));
+    }
+    if !end.is_finite() {
+        return Err(invalid(end_index, String::from("bounds must be finite")));
+    }
+    if step == 0.0 || !step.is_finite() {
+        return Err(invalid(2, String::from("step must be finite and nonzero")));
+    }
+    let count = ((end - start) / step).ceil();
+    if count > MAX_RANGE as f64 {
+        // The step sets how fine the range is, so it is blamed when given.
+        let i = if args.len() == 3 { 2 } else { end_index };
+        return Err(invalid(
+            i,
+            format!("a range can have at most {} items, not {}", MAX_RANGE, count),
+        ));
+    }
+    let count = count.max(0.0) as usize;
+    // Exact or decimal bounds give items of the same kind.
+    if let Some(zero) = args[0].same_kind_int(0) {
+        let mut item = if args.len() == 1 {
+            zero
+        } else {
+            args[0].clone()
+        };
+        let step = if args.len() == 3 {
+            args[2].clone()
+        } else {
+            args[0].same_kind_int(1).unwrap()
+        };
+        let mut items = Vec::with_capacity(count);
+        for _ in 0..count {
+            let next = value::binary_op(BinOp::Plus, &item, &step)?;
+            items.push(item);
+            item = next;
+        }
+        return Ok(Value::from(items));
+    }
+    Ok(Value::from(
+        (0..count)
+            .map(|i| Value::Num(start + i as f64 * step))
+            .collect::<Vec<_>>(),
+    ))
+}
+
+fn map(caller: &mut dyn Caller, args: &[Value]) -> IntrinsicResult {
+    let func = expect_fn(args, 0)?;
+    let items = expect_list(args, 1)?;
+    let mut mapped = Vec::with_capacity(items.len());
+    for item in items.iter() {
+        mapped.push(caller.call(func, slice::from_ref(item))?);
+    }
+    Ok(Value::from(mapped))
+}
+
+fn filter(caller: &mut dyn Caller, args: &[Value]) -> IntrinsicResult {
+    let pred = expect_fn(args, 0)?;
+    let items = expect_list(args, 1)?;
+    let mut kept = Vec::new();
+    for item in items.iter() {
+        if caller.call(pred, slice::from_ref(item))?.expect_bool()? {
+            kept.push(item.clone());
+        }
+    }
+    Ok(Value::from(kept))
+}
+
+/// `reduce(f, init, xs)` folds `xs` from the left, starting with `init`.
+fn reduce(caller: &mut dyn Caller, args: &[Value]) -> IntrinsicResult {
+    let func = expect_fn(args, 0)?;
+    let items = expect_list(args, 2)?;
+    let mut acc = args[1].clone();
+    for item in items.iter() {
+        acc = caller.call(func, &[acc, item.clone()])?;
+    }
+    Ok(acc)
+}
+
+/// Folds the items with `op`, giving `empty` for an empty list.
+fn fold_op(op: BinOp, empty: Value, args: &[Value]) -> IntrinsicResult {
+    let items = expect_list(args, 0)?;
+    let mut acc = match items.first() {
+        Some(first) => first.clone(),
+        None => return Ok(empty),
+    };
+    for item in items[1..].iter() {
+        acc = value::binary_op(op, &acc, item)?;
+    }
+    Ok(acc)
+}
+
+fn sum(_: &mut dyn Caller, args: &[Value]) -> IntrinsicResult {
+    fold_op(BinOp::Plus, Value::Num(0.0), args)
+}
+
+fn prod(_: &mut dyn Caller, args: &[Value]) -> IntrinsicResult {
+    fold_op(BinOp::Times, Value::Num(1.0), args)
+}
+
+/// Applies the optional predicate of `any` and `all` to each item in turn,
+/// stopping once an item gives `stop`.
+fn find_bool(caller: &mut dyn Caller, args: &[Value], stop: bool) -> Result<bool, IntrinsicError> {
+    let (pred, items) = if args.len() == 2 {
+        (Some(expect_fn(args, 0)?), expect_list(args, 1)?)
+    } else {
+        (None, expect_list(args, 0)?)
+    };
+    for item in items.iter() {
+        let b = match pred {
+            Some(pred) => caller.call(pred, slice::from_ref(item))?,
+            None => item.clone(),
+        };
+        if b.expect_bool()? == stop {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// `any(xs)` or `any(p, xs)`.
+fn any(caller: &mut dyn Caller, args: &[Value]) -> IntrinsicResult {
+    Ok(Value::Bool(find_bool(caller, args, true)?))
+}
+
+/// `all(xs)` or `all(p, xs)`.
+fn all(caller: &mut dyn Caller, args: &[Value]) -> IntrinsicResult {
+    Ok(Value::Bool(!find_bool(caller, args, false)?))
+}
+
+/// Pairs up the items of the lists, stopping at the end of the shortest.
+fn zip(_: &mut dyn Caller, args: &[Value]) -> IntrinsicResult {
+    let mut lists = Vec::with_capacity(args.len());
+    for i in 0..args.len() {
+        lists.push(expect_list(args, i)?);
+    }
+    let len = lists.iter().map(|items| items.len()).min().unwrap_or(0);
+    Ok(Value::from(
+        (0..len)
+            .map(|i| Value::from(lists.iter().map(|items| items[i].clone()).collect::<Vec<_>>()))
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// `sort(xs)`, or `sort(xs, key)` to order the items by `key(x)`. The sort is
+/// stable.
+fn sort(caller: &mut dyn Caller, args: &[Value]) -> IntrinsicResult {
+    let items = expect_list(args, 0)?;
+    let mut keyed = Vec::with_capacity(items.len());
+    for item in items.iter() {
+        let key = if args.len() == 2 {
+            caller.call(expect_fn(args, 1)?, slice::from_ref(item))?
+        } else {
+            item.clone()
+        };
+        keyed.push((key, item.clone()));
+    }
+    let mut error = None;
+    keyed.sort_by(|a, b| match value::compare(&a.0, &b.0) {
+        Ok(ordering) => ordering,
+        Err(err) => {
+            error = error.take().or(Some(err));
+            Ordering::Equal
+        }
+    });
+    match error {
+        Some(err) => Err(IntrinsicError::Eval(err)),
+        None => Ok(Value::from(
+            keyed.into_iter().map(|(_, item)| item).collect::<Vec<_>>(),
+        )),
+    }
+}
+
+/// `diff("x^2", "x")` differentiates a formula given as source with respect
+/// to the named variable, returning the derivative as source. Calls to `def`
+/// functions whose body is a single expression are differentiated through.
+fn diff(caller: &mut dyn Caller, args: &[Value]) -> IntrinsicResult {
+    let var = expect_str(args, 1)?;
+    let expr = match parser::parse_repl(expect_str(args, 0)?) {
+        Ok(ReplTree::Expr(expr)) => expr,
+        Ok(ReplTree::Empty) => return Err(invalid(0, String::from("expected a formula"))),
+        Err(err) => return Err(invalid(0, err.to_string())),
+    };
+    Ok(Value::from(caller.context().diff(&expr, var)?.to_string()))
+}
+
+/// `deriv(f, x)` is the exact derivative of `f` at `x`, found with dual
+/// numbers.
+fn deriv(caller: &mut dyn Caller, args: &[Value]) -> IntrinsicResult {
+    let func = expect_fn(args, 0)?;
+    let x = expect_num(args, 1)?;
+    Ok(Value::Num(dual::gradient(caller, func, &[x])?[0]))
+}
+
+/// `grad(f, [x, y, ...])` is the list of partial derivatives of `f(x, y,
+/// ...)`, found with dual numbers.
+fn grad(caller: &mut dyn Caller, args: &[Value]) -> IntrinsicResult {
+    let func = expect_fn(args, 0)?;
+    let point = expect_nums(args, 1)?;
+    let partials = dual::gradient(caller, func, &point)?;
+    Ok(Value::from(
+        partials.into_iter().map(Value::Num).collect::<Vec<_>>(),
+    ))
+}
+
+/// `solve(f, guess)` finds a root of `f` near `guess`, and `solve(f, lo,
+/// hi)` one between `lo` and `hi`, where `f` must change sign. Either can
+/// end with options like `{tol: 0.000001, maxiter: 50}`.
+fn solve(caller: &mut dyn Caller, args: &[Value]) -> IntrinsicResult {
+    let func = expect_fn(args, 0)?;
+    let (args, options) = split_options(args, Options::default())?;
+    let start = match args.len() {
+        2 => Start::Guess(expect_num(args, 1)?),
+        3 => Start::Bracket(expect_num(args, 1)?, expect_num(args, 2)?),
+        n => return Err(invalid(n, String::from("expected options record"))),
+    };
+    let root = solve::find_root(
+        &mut |x| caller.call(func, slice::from_ref(&x)),
+        start,
+        options,
+    )?;
+    Ok(Value::Num(root))
+}
+
+/// `integrate(f, a, b)` is the integral of `f` from `a` to `b`, either of
+/// which may be infinite. A tolerance like `0.000001` or options like
+/// `{tol: 0.000001}` may follow, and with `{error: true}` the result is a
+/// tuple of the integral and an estimate of its error.
+fn integrate(caller: &mut dyn Caller, args: &[Value]) -> IntrinsicResult {
+    let func = expect_fn(args, 0)?;
+    let (args, options) = split_tolerance(args, 3, integrate::DEFAULTS)?;
+    if args.len() != 3 {
+        return Err(invalid(3, String::from("expected options record")));
+    }
+    let (a, b) = (expect_num(args, 1)?, expect_num(args, 2)?);
+    let (value, error) = integrate::integrate(
+        &mut |x| caller.call(func, slice::from_ref(&x)),
+        a,
+        b,
+        options,
+    )?;
+    if options.error == Some(true) {
+        Ok(Value::Tuple(Rc::new(vec![Value::Num(value), Value::Num(error)])))
+    } else {
+        Ok(Value::Num(value))
+    }
+}
+
+/// `odesolve(f, t0, y0, t1)` solves `y' = f(t, y)` from `y(t0) = y0` up to
+/// `t1`, giving a list of `[t, y]` rows, one after every step. For a system
+/// `y0` and the results of `f` are lists of the same length. In place of
+/// `t1` a list of times gives rows at just those times. A tolerance like
+/// `0.000001` or options like `{tol: 0.000001, maxiter: 500}` may follow,
+/// where `maxiter` limits the number of steps.
+fn odesolve(caller: &mut dyn Caller, args: &[Value]) -> IntrinsicResult {
+    let func = expect_fn(args, 0)?;
+    let (args, options) = split_tolerance(args, 4, ode::DEFAULTS)?;
+    if args.len() != 4 {
+        return Err(invalid(4, String::from("expected options record")));
+    }
+    let t0 = expect_num(args, 1)?;
+    let system = args[2].as_items().is_some();
+    let y0 = if system {
+        expect_nums(args, 2)?
+    } else {
+        vec![expect_num(args, 2)?]
+    };
+    let output = if args[3].as_items().is_some() {
+        Output::Times(expect_nums(args, 3)?)
+    } else {
+        Output::Steps(expect_num(args, 3)?)
+    };
+    let to_value = |y: &[f64]| {
+        if system {
+            Value::from(y.iter().cloned().map(Value::Num).collect::<Vec<_>>())
+        } else {
+            Value::Num(y[0])
+        }
+    };
+    let rows = ode::solve(
+        &mut |t, y| {
+            let dy = caller.call(func, &[Value::Num(t), to_value(y)])?;
+            let nums = match dy.as_items() {
+                Some(items) if system && items.len() == y.len() => {
+                    items.iter().map(Value::as_num).collect::<Option<Vec<_>>>()
+                }
+                None if !system => dy.as_num().map(|x| vec![x]),
+                _ => None,
+            };
+            nums.ok_or_else(|| {
+                MathParseError::TypeError(if system {
+                    format!(
+                        "expected function to return a list of {} numbers, got {}",
+                        y.len(),
+                        dy
+                    )
+                } else {
+                    format!("expected function to return a number, got {}", dy.type_name())
+                })
+            })
+        },
+        t0,
+        y0,
+        output,
+        options,
+    )?;
+    Ok(Value::from(
+        rows.into_iter()
+            .map(|(t, y)| Value::from(vec![Value::Num(t), to_value(&y)]))
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// `minimize(f, x0)` finds a minimum of `f` near `x0`, and `minimize(f, lo,
+/// hi)` one between `lo` and `hi`. For a function of several variables
+/// `x0` is a list of their starting values. Options like `{tol: 0.000001,
+/// maxiter: 500}` may follow, or after a list just a tolerance. A third
+/// number is always the end of a bracket, so `minimize(f, 0, 0.001)`
+/// searches between 0 and 0.001. The result is a tuple of where the
+/// minimum is, the value there and the number of steps taken.
+fn minimize(caller: &mut dyn Caller, args: &[Value]) -> IntrinsicResult {
+    let func = expect_fn(args, 0)?;
+    let (args, options) = match args.get(1).and_then(Value::as_items) {
+        Some(_) => split_tolerance(args, 2, minimize::DEFAULTS)?,
+        None => split_options(args, minimize::DEFAULTS)?,
+    };
+    let mut objective = |x: &[Value]| caller.call(func, x);
+    let (Minimum { x, value, iterations }, several) = match args.len() {
+        2 if args[1].as_items().is_some() => {
+            let x0 = expect_nums(args, 1)?;
+            if x0.is_empty() {
+                return Err(invalid(1, String::from("expected at least one variable")));
+            }
+            (minimize::nelder_mead(&mut objective, x0, options)?, true)
+        }
+        2 => {
+            let start = Start::Guess(expect_num(args, 1)?);
+            (minimize::minimize_scalar(&mut objective, start, options)?, false)
+        }
+        3 => {
+            let start = Start::Bracket(expect_num(args, 1)?, expect_num(args, 2)?);
+            (minimize::minimize_scalar(&mut objective, start, options)?, false)
+        }
+        n => return Err(invalid(n, String::from("expected options record"))),
+    };
+    let x = if several {
+        Value::from(x.into_iter().map(Value::Num).collect::<Vec<_>>())
+    } else {
+        Value::Num(x[0])
+    };
+    Ok(Value::Tuple(Rc::new(vec![
+        x,
+        Value::Num(value),
+        Value::Num(iterations as f64),
+    ])))
+}
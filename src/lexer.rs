@@ -3,11 +3,8 @@ use std::fmt::{self, Display};
 pub fn scan(source: String) -> Vec<Token> {
     let mut lexer = Lexer::new(source);
     let mut tokens: Vec<Token> = vec![];
-    loop {
-        match lexer.scan_token() {
-            Some(token) => tokens.push(token),
-            None => break,
-        }
+    while let Some(token) = lexer.scan_token() {
+        tokens.push(token);
     }
     tokens.push(Token {
         token_value: TokenValue::EOF,
@@ -46,8 +43,12 @@ impl Lexer {
                 '-' => Some(self.make_token(TokenValue::Minus)),
                 '*' => Some(self.make_token(TokenValue::Times)),
                 '^' => Some(self.make_token(TokenValue::Caret)),
+                '±' => Some(self.make_token(TokenValue::PlusMinus)),
                 '{' => Some(self.make_token(TokenValue::LeftBracket)),
                 '}' => Some(self.make_token(TokenValue::RightBracket)),
+                '[' => Some(self.make_token(TokenValue::LeftSquare)),
+                ']' => Some(self.make_token(TokenValue::RightSquare)),
+                ':' => Some(self.make_token(TokenValue::Colon)),
                 '!' => {
                     let token_value = if self.match_next('=') {
                         TokenValue::BangEqual
@@ -64,6 +65,22 @@ impl Lexer {
                     };
                     Some(self.make_token(token_value))
                 }
+                '<' => {
+                    let token_value = if self.match_next('=') {
+                        TokenValue::LessEqual
+                    } else {
+                        TokenValue::Less
+                    };
+                    Some(self.make_token(token_value))
+                }
+                '>' => {
+                    let token_value = if self.match_next('=') {
+                        TokenValue::GreaterEqual
+                    } else {
+                        TokenValue::Greater
+                    };
+                    Some(self.make_token(token_value))
+                }
                 ' ' => self.scan_token(),
                 '\n' => {
                     self.current_line += 1;
@@ -105,9 +122,7 @@ impl Lexer {
     }
 
     fn match_next(&mut self, expected: char) -> bool {
-        if self.is_at_end() {
-            false
-        } else if self.chars[self.current] != expected {
+        if self.is_at_end() || self.chars[self.current] != expected {
             false
         } else {
             self.current += 1;
@@ -134,27 +149,41 @@ impl Lexer {
     fn make_token(&self, token_value: TokenValue) -> Token {
         Token {
             token_value,
-            lexeme: (&self.chars[self.start..self.current]).iter().collect(),
+            lexeme: self.chars[self.start..self.current].iter().collect(),
             line: self.current_line,
         }
     }
 
+    /// Scans a string literal. `\n`, `\t`, `\"` and `\\` are escapes; a
+    /// backslash before any other character is kept as is.
     fn handle_string(&mut self) -> Option<Token> {
+        let mut string_value = String::new();
         loop {
             match self.peek() {
                 Some('"') => {
                     self.advance();
-                    let string_value: String = self.chars[(self.start + 1)..(self.current - 1)]
-                        .iter()
-                        .collect();
                     return Some(self.make_token(TokenValue::StringLiteral(string_value)));
                 }
-                Some('\n') => {
-                    self.current_line += 1;
+                Some('\\') => {
                     self.advance();
+                    match self.peek() {
+                        Some(c @ 'n') | Some(c @ 't') | Some(c @ '"') | Some(c @ '\\') => {
+                            self.advance();
+                            string_value.push(match c {
+                                'n' => '\n',
+                                't' => '\t',
+                                c => c,
+                            });
+                        }
+                        _ => string_value.push('\\'),
+                    }
                 }
-                Some(_) => {
+                Some(c) => {
+                    if c == '\n' {
+                        self.current_line += 1;
+                    }
                     self.advance();
+                    string_value.push(c);
                 }
                 None => {
                     println!("Unterminated string!");
@@ -162,8 +191,6 @@ impl Lexer {
                 }
             }
         }
-        #[allow(unreachable_code)]
-        self.scan_token()
     }
 
     fn handle_number(&mut self) -> Option<Token> {
@@ -185,9 +212,14 @@ impl Lexer {
                 } // None => unimplemented!()
             }
         }
-        Some(self.make_token(TokenValue::NumberLiteral(
-            self.chars[self.start..self.current].iter().collect(),
-        )))
+        let digits: String = self.chars[self.start..self.current].iter().collect();
+        // A trailing `i` makes an imaginary literal, as in `4i`, unless it
+        // starts a longer word.
+        if self.peek() == Some('i') && !self.peek2().is_some_and(is_id_char) {
+            self.advance();
+            return Some(self.make_token(TokenValue::ImaginaryLiteral(digits)));
+        }
+        Some(self.make_token(TokenValue::NumberLiteral(digits)))
     }
 
     fn handle_word(&mut self) -> Option<Token> {
@@ -202,20 +234,23 @@ impl Lexer {
             }
         }
         let text: String = self.chars[self.start..self.current].iter().collect();
-        if let Some(keyword_value) = KeywordValue::from(&text) {
-            Some(self.make_token(TokenValue::Keyword(keyword_value)))
-        } else {
-            Some(self.make_token(TokenValue::Identifier(text)))
+        match text.as_str() {
+            "true" => Some(self.make_token(TokenValue::True)),
+            "false" => Some(self.make_token(TokenValue::False)),
+            _ => match KeywordValue::from(&text) {
+                Some(keyword_value) => Some(self.make_token(TokenValue::Keyword(keyword_value))),
+                None => Some(self.make_token(TokenValue::Identifier(text))),
+            },
         }
     }
 }
 
 pub fn is_digit(c: char) -> bool {
-    c >= '0' && c <= '9'
+    c.is_ascii_digit()
 }
 
 pub fn is_id_start(c: char) -> bool {
-    (c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z') || c == '_'
+    c.is_ascii_lowercase() || c.is_ascii_uppercase() || c == '_'
 }
 
 pub fn is_id_char(c: char) -> bool {
@@ -236,6 +271,7 @@ impl Display for Token {
 }
 
 #[derive(Debug, Clone)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum TokenValue {
     LeftParen,
     RightParen,
@@ -244,6 +280,10 @@ pub enum TokenValue {
     BangEqual,
     Equal,
     EqualEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
     Comma,
     Period,
     Plus,
@@ -251,13 +291,18 @@ pub enum TokenValue {
     Slash,
     Times,
     Caret,
+    PlusMinus,
     LeftBracket,
     RightBracket,
+    LeftSquare,
+    RightSquare,
+    Colon,
 
     Identifier(String),
     Keyword(KeywordValue),
     StringLiteral(String),
     NumberLiteral(String),
+    ImaginaryLiteral(String),
 
     True,
     False,
@@ -266,16 +311,23 @@ pub enum TokenValue {
 }
 
 #[derive(Debug, Clone)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum KeywordValue {
     IF,
+    ELSE,
     DEF,
+    /// `to`, for converting a quantity to another unit. Being a keyword, it
+    /// can no longer name a variable.
+    TO,
 }
 
 impl KeywordValue {
     fn from(val: &str) -> Option<Self> {
         match val {
             "if" => Some(KeywordValue::IF),
+            "else" => Some(KeywordValue::ELSE),
             "def" => Some(KeywordValue::DEF),
+            "to" => Some(KeywordValue::TO),
             _ => None,
         }
     }
@@ -0,0 +1,54 @@
+//! A small language for mathematical formulas: a parser, a tree-walking
+//! evaluator, and a bytecode compiler with its VM.
+
+mod lexer;
+// The first versions of the parser, kept for reference.
+#[allow(dead_code)]
+mod math_parser;
+#[allow(dead_code)]
+mod math_parser_2;
+mod parser;
+pub mod ast;
+pub mod visitor;
+mod eval;
+mod vm;
+mod compiler;
+mod compiled;
+mod builtins;
+mod value;
+mod intrinsics;
+mod format;
+mod complex;
+mod bigint;
+mod rational;
+mod decimal;
+mod interval;
+mod uncertain;
+mod dual;
+mod solve;
+mod integrate;
+mod minimize;
+mod ode;
+mod units;
+mod diff;
+mod simplify;
+mod number;
+
+pub use lexer::{KeywordValue, Token, TokenValue};
+pub use parser::{parse_file, parse_repl, parse_repl_with_vars, MathParseError};
+pub use eval::{EvalContext, EvalResult};
+pub use compiler::{compile, Program};
+pub use compiled::CompiledExpr;
+pub use builtins::{Arity, NativeError, NativeFn, NativeResult};
+pub use value::{Function, Record, Value};
+pub use complex::Complex;
+pub use bigint::BigInt;
+pub use rational::Rational;
+pub use decimal::{Decimal, DecimalContext, Rounding};
+pub use interval::Interval;
+pub use number::Number;
+pub use uncertain::Uncertain;
+pub use dual::Dual;
+pub use units::{Quantity, Unit};
+pub use diff::diff;
+pub use simplify::simplify;
@@ -1,121 +1,59 @@
-#![allow(dead_code, unused_variables, unused_imports)]
-
-mod lexer;
-mod math_parser;
-mod math_parser_2;
-mod parser;
-mod ast;
-mod visitor;
-mod eval;
-mod vm;
-mod compiler;
+extern crate raffa_parser;
 
 use std::env::args;
-use std::io::Error;
-use std::io::Write;
-use std::io::{stdin, stdout, BufRead, BufReader, Read};
 use std::fs::File;
+use std::io::Write;
+use std::io::{stdin, stdout, BufRead, Read};
+use std::process::exit;
 
-fn main() {
-    // run_prompt_multiline();
-    // run_prompt_math();
-
-    let args: Vec<String> = args().collect();
-    run_file(&args[1]).unwrap();
+use raffa_parser::{parse_repl_with_vars, EvalContext, Value};
 
-    // let mut vm1 = vm::VM::new(&[
-    //     vm::CALL,
-    //     15,0,0,0,0,0,0,0,
-    //     vm::CONST_U8,
-    //     14,
-    //     vm::U8_2_F64,
-    //     vm::PRINT_F64,
-    //     vm::POP_F64,
-    //     vm::EXIT,
-    //     vm::SET_CTX,
-    //     vm::CONST_U8,
-    //     8,
-    //     vm::U8_2_F64,
-    //     vm::PRINT_F64,
-    //     vm::POP_F64,
-    //     vm::RET_CTX,
-    //     vm::RET
-    // ]);
-    // vm1.run();
-}
+const USAGE: &str = "Usage: raffa_parser [--eval] [FILE]
 
-fn run_prompt() {
-    let stdin = stdin();
-    let mut stdout = stdout();
-    let mut lines = stdin.lock().lines();
-    loop {
-        print!("> ");
-        stdout.flush().unwrap();
-        match lines.next() {
-            Some(Ok(line)) => run(line),
-            Some(Err(err)) => eprintln!("{}", err),
-            None => {
-                println!("Exiting.");
-                break;
-            }
-        };
-    }
-}
+Without FILE, starts a REPL. With FILE, compiles it and runs it on the VM,
+or evaluates it with the tree-walking evaluator if --eval is given.";
 
-fn run_prompt_multiline() {
-    let stdin = stdin();
-    let mut stdout = stdout();
-    let mut lines = stdin.lock().lines();
-    loop {
-        print!("> ");
-        stdout.flush().unwrap();
-        let mut buf = String::new();
-        loop {
-            match lines.next() {
-                Some(Ok(line)) => {
-                    buf.push_str(&line);
-                    buf.push('\n');
-                }
-                Some(Err(err)) => eprintln!("{}", err),
-                None => {
-                    break;
-                }
-            }
+fn main() {
+    let args: Vec<String> = args().skip(1).collect();
+    let res = match args.iter().map(|a| a.as_str()).collect::<Vec<_>>().as_slice() {
+        [] => {
+            run_prompt();
+            Ok(())
         }
-        run(buf);
-    }
-}
-
-fn run(source: String) {
-    let tokens = lexer::scan(source);
-    for token in &tokens {
-        println!("{:?}", token)
+        ["--eval", path] => eval_file(path),
+        [path] if !path.starts_with('-') => run_file(path),
+        _ => {
+            eprintln!("{}", USAGE);
+            exit(2);
+        }
+    };
+    if let Err(err) = res {
+        eprintln!("Error: {}", err);
+        exit(1);
     }
 }
 
-fn run_prompt_math() {
+fn run_prompt() {
     let stdin = stdin();
     let mut stdout = stdout();
     let mut lines = stdin.lock().lines();
-    let mut context = eval::EvalContext::new();
-    // let mut context = math_parser_2::EvalContext::new();
+    let mut context = EvalContext::new();
     loop {
         print!("> ");
         stdout.flush().unwrap();
         match lines.next() {
-            // Some(Ok(line)) => match context.eval(&line) {
-            Some(Ok(line)) => match parser::parse_repl(&line) {
-                Ok(x) => {
-                    println!("Parse tree: {:#?}", x);
-                    println!(
-                        "Eval result: {:?}",
-                        context
-                            .eval_repltree(&x)
-                            .map(|opt| opt.map(|num| format!("{:e}", num)))
-                    );
+            Some(Ok(line)) => {
+                let vars = context.vars().map(|(name, _)| name);
+                let vars = vars.chain(context.functions().map(|(name, _)| name));
+                match parse_repl_with_vars(&line, vars) {
+                    Ok(tree) => match context.eval_repltree(&tree) {
+                        Ok(Value::Nil) => (),
+                        Ok(val) => println!("{}", val),
+                        Err(err) => println!("Error: {}", err),
+                    },
+                    Err(err) => println!("Error: {}", err),
                 }
-                Err(err) => println!("Error: {}", err),
-            },
+            }
             Some(Err(err)) => eprintln!("{}", err),
             None => {
                 println!("Exiting.");
@@ -125,27 +63,24 @@ fn run_prompt_math() {
     }
 }
 
-fn run_file(path: &str) -> Result<(), Error> {
-    #[allow(unused_mut)]
-    let mut ctx = eval::EvalContext::new();
+fn read_file(path: &str) -> Result<String, Box<dyn std::error::Error>> {
     let mut file = File::open(path)?;
     let mut contents = String::new();
     file.read_to_string(&mut contents)?;
+    Ok(contents)
+}
 
-    // let res = parser::parse_file(&contents);
-    // println!("{:#?}", res);
-    if let Ok(tree) = parser::parse_file(&contents) {
-        println!("{:#?}", tree);
-        // let res = ctx.eval_file(&tree);
-        // let mapped = res.map(|opt| opt.map(|val| format!("{:e}", val)));
-        // println!("{:?}", mapped);
-        // println!("{:?}", res);
-        let mut compiled = compiler::compile(&tree);
-        println!("Compiled: {:?}", compiled);
-        let mut vm1 = vm::VM::new(&compiled);
-        vm1.run();
-    }
-    // println!("{:#?}", tree);
+fn run_file(path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let tree = raffa_parser::parse_file(&read_file(path)?)?;
+    raffa_parser::compile(&tree)?.run()?;
+    Ok(())
+}
 
+fn eval_file(path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let tree = raffa_parser::parse_file(&read_file(path)?)?;
+    let val = EvalContext::new().eval_file(&tree)?;
+    if !val.is_nil() {
+        println!("{}", val);
+    }
     Ok(())
 }
@@ -2,10 +2,12 @@ use std::fmt::{self, Display};
 use std::error::Error;
 use std::str::FromStr;
 
-pub type MathParseResult = Result<f64, MathParseError>;
+use number::Number;
+
+pub type MathParseResult<N = f64> = Result<N, MathParseError>;
 pub type MathScanResult = Result<Vec<Token>, MathParseError>;
 
-pub fn eval(expr: &str) -> MathParseResult {
+pub fn eval<N: Number>(expr: &str) -> MathParseResult<N> {
     let tokens = scan(expr)?;
     let mut parser = Parser::new(tokens);
     let res = parser.expression();
@@ -29,7 +31,7 @@ pub fn scan(expr: &str) -> MathScanResult {
             "(" => Token::Operator(LeftParen),
             ")" => Token::Operator(RightParen),
             c => match f64::from_str(c) {
-                Ok(n) => Token::Number(n),
+                Ok(_) => Token::Number(String::from(c)),
                 Err(_) => return Err(MathParseError::ScanError)
             },
         });
@@ -38,12 +40,12 @@ pub fn scan(expr: &str) -> MathScanResult {
 }
 
 pub fn is_digit(c: u8) -> bool {
-    c >= b'0' && c <= b'9'
+    c.is_ascii_digit()
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum Token {
-    Number(f64),
+    Number(String),
     Operator(Op),
 }
 
@@ -68,11 +70,11 @@ impl Parser {
     }
 
     fn current_token(&self) -> Option<Token> {
-        self.tokens.get(self.cursor).map(|t| *t)
+        self.tokens.get(self.cursor).cloned()
     }
 
     fn advance(&mut self) -> Option<Token> {
-        let t = self.tokens.get(self.cursor).map(|t| *t);
+        let t = self.tokens.get(self.cursor).cloned();
         self.cursor += 1;
         t
     }
@@ -81,18 +83,18 @@ impl Parser {
         self.cursor >= self.tokens.len()
     }
 
-    fn expression(&mut self) -> MathParseResult {
-        let mut x = self.mult()?;
+    fn expression<N: Number>(&mut self) -> MathParseResult<N> {
+        let mut x: N = self.mult()?;
         // while (self.current_token() == Operator(Op::LeftParen)) || (self.current_token() == Operator(Op::RightParen)){
         loop {
             match self.current_token() {
                 Some(Token::Operator(Op::Plus)) => {
                     self.advance();
-                    x = x + self.mult()?;
+                    x = x.add(&self.mult()?);
                 }
                 Some(Token::Operator(Op::Minus)) => {
                     self.advance();
-                    x = x - self.mult()?;
+                    x = x.sub(&self.mult()?);
                 }
                 _ => break,
             }
@@ -100,17 +102,17 @@ impl Parser {
         Ok(x)
     }
 
-    fn mult(&mut self) -> MathParseResult {
-        let mut x = self.factor()?;
+    fn mult<N: Number>(&mut self) -> MathParseResult<N> {
+        let mut x: N = self.factor()?;
         loop {
             match self.current_token() {
                 Some(Token::Operator(Op::Times)) => {
                     self.advance();
-                    x = x * self.factor()?;
+                    x = x.mul(&self.factor()?);
                 }
                 Some(Token::Operator(Op::Slash)) => {
                     self.advance();
-                    x = x / self.factor()?;
+                    x = x.div(&self.factor()?).map_err(|_| MathParseError::DivisionByZero)?;
                 }
                 _ => break,
             }
@@ -118,7 +120,7 @@ impl Parser {
         Ok(x)
     }
 
-    fn factor(&mut self) -> MathParseResult {
+    fn factor<N: Number>(&mut self) -> MathParseResult<N> {
         match self.current_token() {
             Some(Token::Operator(Op::LeftParen)) => {
                 self.advance();
@@ -132,9 +134,9 @@ impl Parser {
                     None => unexpected_end(),
                 }
             }
-            Some(Token::Number(x)) => {
+            Some(Token::Number(text)) => {
                 self.advance();
-                Ok(x)
+                N::parse(&text, Default::default()).ok_or(MathParseError::ScanError)
             }
             // Some(t) => panic_because_expected("'(' or number literal", t),
             Some(t) => Err(MathParseError::ExpectedButGot(String::from("'(' or number literal"), t)),
@@ -156,28 +158,23 @@ pub enum MathParseError {
     ScanError,
     ExpectedButGot(String, Token),
     UnexpectedEOF,
+    DivisionByZero,
 }
 
 impl Display for MathParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            &MathParseError::ExpectedButGot(ref e, g) => {
+            MathParseError::ScanError => write!(f, "Could not scan input."),
+            MathParseError::ExpectedButGot(e, g) => {
                 write!(f, "Expected '{}' but got '{:?}'", e, g)
             }
-            _ => write!(f, "{}", self.description()),
+            MathParseError::UnexpectedEOF => write!(f, "Unexpected end of input"),
+            MathParseError::DivisionByZero => write!(f, "Division by zero"),
         }
     }
 }
 
-impl Error for MathParseError {
-    fn description(&self) -> &str {
-        match self {
-            &MathParseError::ScanError => "Could not scan input.",
-            &MathParseError::ExpectedButGot(ref e, g) => "Unexpected token",
-            &MathParseError::UnexpectedEOF => "Unexpected end of input",
-        }
-    }
-}
+impl Error for MathParseError {}
 
 /*
 Grammar:
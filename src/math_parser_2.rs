@@ -5,22 +5,23 @@ use std::error::Error;
 use std::str::FromStr;
 
 use lexer::{self, Token, TokenValue};
+use number::Number;
 
-pub type MathParseResult = Result<f64, MathParseError>;
+pub type MathParseResult<N = f64> = Result<N, MathParseError>;
 // pub type MathScanResult = Result<Vec<Token>, MathParseError>;
 
-pub struct EvalContext {
-    vars: HashMap<String, f64>,
+pub struct EvalContext<N: Number = f64> {
+    vars: HashMap<String, N>,
 }
 
-impl EvalContext {
+impl<N: Number> EvalContext<N> {
     pub fn new() -> Self {
         EvalContext {
             vars: HashMap::new(),
         }
     }
 
-    pub fn eval(&mut self, expr: &str) -> MathParseResult {
+    pub fn eval(&mut self, expr: &str) -> MathParseResult<N> {
         // let tokens = scan(expr)?;
         let tokens = lexer::scan(String::from(expr));
         let mut parser = Parser::new(tokens, self);
@@ -76,14 +77,14 @@ impl EvalContext {
 //     RightParen,
 // }
 
-struct Parser<'a> {
+struct Parser<'a, N: Number> {
     tokens: Vec<Token>,
     cursor: usize,
-    context: &'a mut EvalContext,
+    context: &'a mut EvalContext<N>,
 }
 
-impl<'a> Parser<'a> {
-    fn new(tokens: Vec<Token>, context: &'a mut EvalContext) -> Self {
+impl<'a, N: Number> Parser<'a, N> {
+    fn new(tokens: Vec<Token>, context: &'a mut EvalContext<N>) -> Self {
         Parser {
             tokens,
             cursor: 0,
@@ -92,17 +93,17 @@ impl<'a> Parser<'a> {
     }
 
     fn current_token(&self) -> Option<Token> {
-        self.tokens.get(self.cursor).map(|t| t.clone())
+        self.tokens.get(self.cursor).cloned()
     }
 
     fn advance(&mut self) -> Option<Token> {
-        let t = self.tokens.get(self.cursor).map(|t| t.clone());
+        let t = self.tokens.get(self.cursor).cloned();
         self.cursor += 1;
         t
     }
 
     fn look_ahead(&self, offset: usize) -> Option<Token> {
-        self.tokens.get(self.cursor + offset).map(|t| t.clone())
+        self.tokens.get(self.cursor + offset).cloned()
     }
 
     fn is_at_end(&self) -> bool {
@@ -117,16 +118,16 @@ impl<'a> Parser<'a> {
         // self.cursor >= self.tokens.len()
     }
 
-    fn assign_variable(&mut self, name: String, value: f64) {
+    fn assign_variable(&mut self, name: String, value: N) {
         println!("Set variable '{}' to {}.", &name, value);
         self.context.vars.insert(name, value);
     }
 
-    fn get_variable(&self, name: &String) -> Option<f64> {
-        self.context.vars.get(name).map(|x| *x)
+    fn get_variable(&self, name: &String) -> Option<N> {
+        self.context.vars.get(name).cloned()
     }
     
-    fn expression(&mut self) -> MathParseResult {
+    fn expression(&mut self) -> MathParseResult<N> {
         match self.look_ahead(1) {
             Some(Token {
                 token_value: TokenValue::Equal,
@@ -136,7 +137,7 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn sum_expression(&mut self) -> MathParseResult {
+    fn sum_expression(&mut self) -> MathParseResult<N> {
         let mut x = self.mult()?;
         loop {
             match self.current_token() {
@@ -145,14 +146,14 @@ impl<'a> Parser<'a> {
                     ..
                 }) => {
                     self.advance();
-                    x = x + self.mult()?;
+                    x = x.add(&self.mult()?);
                 }
                 Some(Token {
                     token_value: TokenValue::Minus,
                     ..
                 }) => {
                     self.advance();
-                    x = x - self.mult()?;
+                    x = x.sub(&self.mult()?);
                 }
                 _ => break,
             }
@@ -160,7 +161,7 @@ impl<'a> Parser<'a> {
         Ok(x)
     }
 
-    fn assignment(&mut self) -> MathParseResult {
+    fn assignment(&mut self) -> MathParseResult<N> {
         match self.current_token() {
             Some(Token {
                 token_value: TokenValue::Identifier(name),
@@ -181,7 +182,7 @@ impl<'a> Parser<'a> {
                         //     Err(err) => Err(err)
                         // }
                         let expr_value = self.expression()?;
-                        self.assign_variable(name, expr_value);
+                        self.assign_variable(name, expr_value.clone());
                         Ok(expr_value)
                     },
                     Some(t) => {
@@ -201,7 +202,7 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn mult(&mut self) -> MathParseResult {
+    fn mult(&mut self) -> MathParseResult<N> {
         let mut x = self.factor()?;
         loop {
             match self.current_token() {
@@ -210,14 +211,14 @@ impl<'a> Parser<'a> {
                     ..
                 }) => {
                     self.advance();
-                    x = x * self.factor()?;
+                    x = x.mul(&self.factor()?);
                 }
                 Some(Token {
                     token_value: TokenValue::Slash,
                     ..
                 }) => {
                     self.advance();
-                    x = x / self.factor()?;
+                    x = x.div(&self.factor()?).map_err(|_| MathParseError::DivisionByZero)?;
                 }
                 _ => break,
             }
@@ -225,7 +226,7 @@ impl<'a> Parser<'a> {
         Ok(x)
     }
 
-    fn factor(&mut self) -> MathParseResult {
+    fn factor(&mut self) -> MathParseResult<N> {
         match self.current_token() {
             Some(Token {
                 token_value: TokenValue::LeftParen,
@@ -250,7 +251,10 @@ impl<'a> Parser<'a> {
                 ..
             }) => {
                 self.advance();
-                f64::from_str(&lit).map_err(|err| MathParseError::CouldNotParseFloat(err))
+                let x = f64::from_str(&lit).map_err(MathParseError::CouldNotParseFloat)?;
+                N::parse(&lit, Default::default())
+                    .or_else(|| N::from_f64(x, Default::default()))
+                    .ok_or(MathParseError::ScanError)
             },
             Some(Token {
                 token_value: TokenValue::Identifier(name),
@@ -283,37 +287,31 @@ pub enum MathParseError {
     ExpectedButGot(String, Token),
     UnexpectedEOF,
     CouldNotParseFloat(ParseFloatError),
-    UnknownIdentifier(String)
+    UnknownIdentifier(String),
+    DivisionByZero,
 }
 
 impl Display for MathParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            &MathParseError::ExpectedButGot(ref e, ref g) => {
+            MathParseError::ScanError => write!(f, "Could not scan input."),
+            MathParseError::ExpectedButGot(e, g) => {
                 write!(f, "Expected '{}' but got '{:?}'", e, g)
             }
-            &MathParseError::UnknownIdentifier(ref name) => {
-                 write!(f, "Unknown identifier '{}'", name)
+            MathParseError::UnexpectedEOF => write!(f, "Unexpected end of input"),
+            MathParseError::CouldNotParseFloat(_) => write!(f, "Could not parse float"),
+            MathParseError::UnknownIdentifier(name) => {
+                write!(f, "Unknown identifier '{}'", name)
             }
-            _ => write!(f, "{}", self.description()),
+            MathParseError::DivisionByZero => write!(f, "Division by zero"),
         }
     }
 }
 
 impl Error for MathParseError {
-    fn description(&self) -> &str {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
-            &MathParseError::ScanError => "Could not scan input.",
-            &MathParseError::ExpectedButGot(..) => "Unexpected token",
-            &MathParseError::UnexpectedEOF => "Unexpected end of input",
-            &MathParseError::CouldNotParseFloat(_) => "Could not parse float",
-            &MathParseError::UnknownIdentifier(_) => "Unknown identifier"
-        }
-    }
-
-    fn cause(&self) -> Option<&Error> {
-        match self {
-            &MathParseError::CouldNotParseFloat(ref pferr) => Some(pferr),
+            MathParseError::CouldNotParseFloat(pferr) => Some(pferr),
             _ => None,
         }
     }
@@ -0,0 +1,359 @@
+use std::f64;
+use std::mem;
+
+use parser::MathParseError;
+use solve::{Options, Start};
+use value::Value;
+
+/// A function of one or more numbers, given as values, to minimize.
+pub(crate) type Objective<'a> = dyn FnMut(&[Value]) -> Result<Value, MathParseError> + 'a;
+
+/// The defaults for `minimize`. A minimum can only be located to about the
+/// square root of the precision of its value, so the tolerance is looser
+/// than for roots.
+pub(crate) const DEFAULTS: Options = Options {
+    tol: 1e-8,
+    max_iter: 1000,
+    error: None,
+};
+
+/// Where a minimum was found, with the value there and the number of
+/// steps it took.
+#[derive(Debug, Clone)]
+pub(crate) struct Minimum {
+    pub x: Vec<f64>,
+    pub value: f64,
+    pub iterations: usize,
+}
+
+/// `2 - φ`, the fraction of a bracket at which the golden section divides
+/// it.
+const GOLDEN: f64 = 0.381_966_011_250_105_1;
+
+/// Writes a point like `[1, 2]`, or just `1` in one dimension.
+fn show(x: &[f64]) -> String {
+    let coords: Vec<String> = x.iter().map(|x| x.to_string()).collect();
+    if coords.len() == 1 {
+        coords[0].clone()
+    } else {
+        format!("[{}]", coords.join(", "))
+    }
+}
+
+fn at(f: &mut Objective, x: &[f64]) -> Result<f64, MathParseError> {
+    let args: Vec<Value> = x.iter().cloned().map(Value::Num).collect();
+    let y = f(&args)?;
+    match y.as_num() {
+        Some(y) if !y.is_nan() => Ok(y),
+        Some(y) => Err(MathParseError::DidNotConverge(format!(
+            "the objective is {} at {}",
+            y,
+            show(x)
+        ))),
+        None => Err(MathParseError::TypeError(format!(
+            "expected function to return a number, got {}",
+            y.type_name()
+        ))),
+    }
+}
+
+fn too_many(options: Options, x: f64) -> MathParseError {
+    MathParseError::DidNotConverge(format!(
+        "no minimum found from {} in {} steps",
+        x, options.max_iter
+    ))
+}
+
+/// Finds a minimum of a function of one variable with Brent's method,
+/// either between the ends of a bracket or near a guess, from which it
+/// first walks downhill in growing steps until the function rises again.
+pub(crate) fn minimize_scalar(
+    f: &mut Objective,
+    start: Start,
+    options: Options,
+) -> Result<Minimum, MathParseError> {
+    let mut iterations = 0;
+    let (lo, x, hi) = match start {
+        Start::Bracket(a, b) => (a.min(b), a + GOLDEN * (b - a), a.max(b)),
+        Start::Guess(x0) => {
+            let step = 0.1 * x0.abs().max(1.0);
+            let (mut a, mut fa) = (x0, at(f, &[x0])?);
+            let (mut b, mut fb) = (x0 + step, at(f, &[x0 + step])?);
+            if fb > fa {
+                mem::swap(&mut a, &mut b);
+                mem::swap(&mut fa, &mut fb);
+            }
+            let mut c = b + (b - a) / GOLDEN;
+            let mut fc = at(f, &[c])?;
+            while fc < fb {
+                iterations += 1;
+                if iterations >= options.max_iter || !c.is_finite() {
+                    return Err(too_many(options, x0));
+                }
+                a = b;
+                b = c;
+                fb = fc;
+                c = b + (b - a) / GOLDEN;
+                fc = at(f, &[c])?;
+            }
+            (a.min(c), b, a.max(c))
+        }
+    };
+    brent(f, (lo, x, hi), iterations, options)
+}
+
+/// Brent's method for a minimum between `a` and `b`, starting from `x`
+/// between them. Combines golden section steps, which always shrink the
+/// bracket, with parabolic steps, which converge quickly near the minimum.
+fn brent(
+    f: &mut Objective,
+    (mut a, mut x, mut b): (f64, f64, f64),
+    mut iterations: usize,
+    options: Options,
+) -> Result<Minimum, MathParseError> {
+    let mut fx = at(f, &[x])?;
+    // The points with the second and third lowest values so far.
+    let (mut w, mut fw, mut v, mut fv) = (x, fx, x, fx);
+    // The last step and the one before it.
+    let (mut d, mut e): (f64, f64) = (0.0, 0.0);
+    while iterations < options.max_iter {
+        let m = a + (b - a) / 2.0;
+        let tol = options.tol * x.abs().max(1.0);
+        if (x - m).abs() <= 2.0 * tol - (b - a) / 2.0 {
+            return Ok(Minimum {
+                x: vec![x],
+                value: fx,
+                iterations,
+            });
+        }
+        iterations += 1;
+        let mut parabolic = false;
+        if e.abs() > tol {
+            // Fit a parabola through x, w and v.
+            let r = (x - w) * (fx - fv);
+            let q = (x - v) * (fx - fw);
+            let mut p = (x - v) * q - (x - w) * r;
+            let mut q = 2.0 * (q - r);
+            if q > 0.0 {
+                p = -p;
+            } else {
+                q = -q;
+            }
+            if p.abs() < (0.5 * q * e).abs() && p > q * (a - x) && p < q * (b - x) {
+                e = d;
+                d = p / q;
+                // Stay away from the ends of the bracket.
+                if x + d - a < 2.0 * tol || b - (x + d) < 2.0 * tol {
+                    d = tol.copysign(m - x);
+                }
+                parabolic = true;
+            }
+        }
+        if !parabolic {
+            e = if x >= m { a - x } else { b - x };
+            d = GOLDEN * e;
+        }
+        let u = x + if d.abs() >= tol { d } else { tol.copysign(d) };
+        let fu = at(f, &[u])?;
+        if fu <= fx {
+            if u >= x {
+                a = x;
+            } else {
+                b = x;
+            }
+            v = w;
+            fv = fw;
+            w = x;
+            fw = fx;
+            x = u;
+            fx = fu;
+        } else {
+            if u < x {
+                a = u;
+            } else {
+                b = u;
+            }
+            if fu <= fw || w == x {
+                v = w;
+                fv = fw;
+                w = u;
+                fw = fu;
+            } else if fu <= fv || v == x || v == w {
+                v = u;
+                fv = fu;
+            }
+        }
+    }
+    Err(too_many(options, x))
+}
+
+/// Finds a minimum of a function of several variables with the
+/// Nelder-Mead method, which moves a simplex of `n + 1` points downhill by
+/// reflecting, expanding and contracting it, and needs no derivatives. It
+/// stops once both the points and their values agree to within the
+/// tolerance.
+pub(crate) fn nelder_mead(
+    f: &mut Objective,
+    x0: Vec<f64>,
+    options: Options,
+) -> Result<Minimum, MathParseError> {
+    let n = x0.len();
+    let mut simplex = vec![(at(f, &x0)?, x0.clone())];
+    for i in 0..n {
+        let mut x = x0.clone();
+        x[i] = if x[i] == 0.0 { 0.00025 } else { 1.05 * x[i] };
+        simplex.push((at(f, &x)?, x));
+    }
+    // `a + t * (b - a)`, for each coordinate.
+    let towards = |a: &[f64], b: &[f64], t: f64| -> Vec<f64> {
+        a.iter()
+            .zip(b.iter())
+            .map(|(a, b)| a + t * (b - a))
+            .collect()
+    };
+    for iterations in 0..options.max_iter {
+        simplex.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let (best, ref x_best) = simplex[0];
+        let spread = simplex[1..]
+            .iter()
+            .flat_map(|(_, x)| x.iter().zip(x_best.iter()).map(|(a, b)| (a - b).abs()))
+            .fold(0.0, f64::max);
+        let scale = x_best.iter().fold(1.0, |acc: f64, x| acc.max(x.abs()));
+        let worst = simplex[n].0;
+        if spread <= options.tol * scale && worst - best <= options.tol * best.abs().max(1.0) {
+            return Ok(Minimum {
+                x: x_best.clone(),
+                value: best,
+                iterations,
+            });
+        }
+
+        let centroid: Vec<f64> = (0..n)
+            .map(|j| simplex[..n].iter().map(|(_, x)| x[j]).sum::<f64>() / n as f64)
+            .collect();
+        let reflected = towards(&centroid, &simplex[n].1, -1.0);
+        let fr = at(f, &reflected)?;
+        if fr < best {
+            let expanded = towards(&centroid, &simplex[n].1, -2.0);
+            let fe = at(f, &expanded)?;
+            simplex[n] = if fe < fr {
+                (fe, expanded)
+            } else {
+                (fr, reflected)
+            };
+        } else if fr < simplex[n - 1].0 {
+            simplex[n] = (fr, reflected);
+        } else {
+            // Contract towards the better of the worst point and its
+            // reflection, or failing that shrink everything towards the
+            // best point.
+            let (outside, target) = if fr < worst {
+                (true, reflected)
+            } else {
+                (false, simplex[n].1.clone())
+            };
+            let contracted = towards(&centroid, &target, 0.5);
+            let fc = at(f, &contracted)?;
+            if (outside && fc <= fr) || (!outside && fc < worst) {
+                simplex[n] = (fc, contracted);
+            } else {
+                let best = simplex[0].1.clone();
+                for point in simplex[1..].iter_mut() {
+                    let x = towards(&best, &point.1, 0.5);
+                    *point = (at(f, &x)?, x);
+                }
+            }
+        }
+    }
+    simplex.sort_by(|a, b| a.0.total_cmp(&b.0));
+    Err(MathParseError::DidNotConverge(format!(
+        "no minimum found from {} in {} steps; the best point was {}",
+        show(&x0),
+        options.max_iter,
+        show(&simplex[0].1)
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nums(x: &[Value]) -> Vec<f64> {
+        x.iter().map(|x| x.as_num().unwrap()).collect()
+    }
+
+    fn scalar(f: fn(f64) -> f64, start: Start, tol: f64) -> Result<Minimum, MathParseError> {
+        let options = Options { tol, ..DEFAULTS };
+        minimize_scalar(&mut |x| Ok(Value::Num(f(nums(x)[0]))), start, options)
+    }
+
+    fn several(f: fn(&[f64]) -> f64, x0: &[f64], tol: f64) -> Minimum {
+        let options = Options { tol, ..DEFAULTS };
+        nelder_mead(&mut |x| Ok(Value::Num(f(&nums(x)))), x0.to_vec(), options).unwrap()
+    }
+
+    #[test]
+    fn brent_from_a_guess_or_a_bracket() {
+        let parabola: fn(f64) -> f64 = |x| (x - 3.0) * (x - 3.0) + 1.0;
+        for &start in [
+            Start::Guess(0.0),
+            Start::Guess(10.0),
+            Start::Bracket(-5.0, 5.0),
+        ]
+        .iter()
+        {
+            let min = scalar(parabola, start, 1e-8).unwrap();
+            assert!((min.x[0] - 3.0).abs() < 1e-6, "{:?}", min);
+            assert!((min.value - 1.0).abs() < 1e-12, "{:?}", min);
+        }
+        let min = scalar(f64::cos, Start::Bracket(2.0, 4.0), 1e-8).unwrap();
+        assert!((min.x[0] - f64::consts::PI).abs() < 1e-6, "{:?}", min);
+        assert!((min.value + 1.0).abs() < 1e-12, "{:?}", min);
+    }
+
+    #[test]
+    fn a_looser_tolerance_takes_fewer_steps() {
+        let f: fn(f64) -> f64 = |x| (x - 1.0).cosh();
+        let loose = scalar(f, Start::Guess(0.0), 1e-3).unwrap();
+        let tight = scalar(f, Start::Guess(0.0), 1e-10).unwrap();
+        assert!(loose.iterations < tight.iterations);
+        assert!((loose.x[0] - 1.0).abs() < 1e-2, "{:?}", loose);
+        assert!((tight.x[0] - 1.0).abs() < 1e-5, "{:?}", tight);
+    }
+
+    #[test]
+    fn nelder_mead_in_several_dimensions() {
+        let rosenbrock: fn(&[f64]) -> f64 =
+            |x| (1.0 - x[0]).powi(2) + 100.0 * (x[1] - x[0] * x[0]).powi(2);
+        let min = several(rosenbrock, &[-1.2, 1.0], 1e-10);
+        assert!(
+            (min.x[0] - 1.0).abs() < 1e-4 && (min.x[1] - 1.0).abs() < 1e-4,
+            "{:?}",
+            min
+        );
+        assert!(min.value < 1e-8, "{:?}", min);
+
+        let bowl: fn(&[f64]) -> f64 = |x| {
+            x.iter()
+                .enumerate()
+                .map(|(i, x)| (x - i as f64 - 1.0).powi(2))
+                .sum()
+        };
+        let min = several(bowl, &[0.0, 0.0, 0.0], 1e-10);
+        for (i, x) in min.x.iter().enumerate() {
+            assert!((x - i as f64 - 1.0).abs() < 1e-4, "{:?}", min);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert!(scalar(|x| x, Start::Guess(0.0), 1e-8).is_err());
+        assert!(scalar(|x| (x - 1.0).sqrt(), Start::Guess(0.0), 1e-8).is_err());
+        let options = Options {
+            max_iter: 5,
+            ..DEFAULTS
+        };
+        let mut cone = |x: &[Value]| Ok(Value::Num(nums(x).iter().map(|x| x.abs()).sum()));
+        assert!(nelder_mead(&mut cone, vec![-1.2, 1.0], options).is_err());
+    }
+}
@@ -0,0 +1,670 @@
+use std::cmp::Ordering;
+use std::f64;
+use std::fmt::{Debug, Display};
+use std::str::FromStr;
+
+use builtins::{self, NativeError, NativeFn};
+use complex::{self, Complex};
+use decimal::{self, Decimal, DecimalContext};
+use dual::{self, Dual};
+use eval::EvalContext;
+use interval::{self, Interval};
+use parser::MathParseError;
+use rational::{self, Rational};
+use uncertain::Uncertain;
+use value::Value;
+
+/// A kind of number that formulas can compute with: `f64`, `Complex`,
+/// `Rational`, `Decimal`, `Interval`, `Uncertain` or `Dual`.
+///
+/// `CompiledExpr` and the legacy parsers are generic over the kind of
+/// number. The tree-walking evaluator works on `Value`, which uses this
+/// trait for the arithmetic, literals, formatting and builtins of each kind
+/// of number it can hold.
+pub trait Number: Clone + PartialEq + Debug + Display + Sized + 'static {
+    /// Settings for reading and rounding numbers of this kind, such as a
+    /// decimal precision.
+    type Context: Copy + Default;
+
+    /// Whether the numbers can be ordered with `<` and the like.
+    const ORDERED: bool = true;
+
+    /// The settings `ctx` uses for this kind of number.
+    fn context(_: &EvalContext) -> Self::Context {
+        Self::Context::default()
+    }
+
+    /// Reads a number literal such as `12` or `0.125`.
+    fn parse(lit: &str, ctx: Self::Context) -> Option<Self>;
+
+    /// The number nearest to `x`, or `None` if this kind has nothing near
+    /// it, as for an infinity. Results that have to be computed with floats
+    /// are converted back with this.
+    fn from_f64(x: f64, ctx: Self::Context) -> Option<Self>;
+
+    fn to_f64(&self) -> f64;
+
+    fn add(&self, other: &Self) -> Self;
+
+    fn sub(&self, other: &Self) -> Self;
+
+    fn mul(&self, other: &Self) -> Self;
+
+    /// Kinds of numbers without an infinity report division by zero as an
+    /// error.
+    fn div(&self, other: &Self) -> Result<Self, MathParseError>;
+
+    fn neg(&self) -> Self;
+
+    /// `self ^ other`, or `Ok(None)` if the power has no result of this
+    /// kind, such as a rational to a fractional power.
+    fn pow(&self, other: &Self) -> Result<Option<Self>, MathParseError>;
+
+    /// Compares two numbers, giving `None` if they are unordered, like NaN.
+    fn compare(&self, other: &Self) -> Option<Ordering>;
+
+    /// Writes the number with `places` digits after the point.
+    fn to_fixed(&self, places: usize) -> String;
+
+    /// Writes the number in scientific notation, with `places` digits after
+    /// the point if given.
+    fn to_sci(&self, places: Option<usize>) -> String {
+        sci(self.to_f64(), places)
+    }
+
+    /// Calls the version of the builtin `name` for this kind of number.
+    /// `None` means it has no such version, or no result of this kind, and
+    /// the builtin should be computed with floats. The arity has already
+    /// been checked.
+    fn call_builtin(name: &str, args: &[Self]) -> Option<Value>;
+
+    /// Calls a numeric native function, using the version for this kind of
+    /// number if it is a builtin that has one, and floats otherwise.
+    fn call_native(
+        native: &NativeFn,
+        args: &[Self],
+        ctx: Self::Context,
+    ) -> Result<Self, NativeError> {
+        if let Some(val) = native
+            .builtin_name()
+            .and_then(|name| Self::call_builtin(name, args))
+        {
+            if let Some(x) = Self::from_value(&val) {
+                return Ok(x);
+            }
+        }
+        let floats: Vec<f64> = args.iter().map(Self::to_f64).collect();
+        let x = call_floats(native, &floats)?;
+        Self::from_f64(x, ctx)
+            .ok_or_else(|| NativeError::Other(Box::from(format!("no result near {}", x))))
+    }
+
+    fn into_value(self) -> Value;
+
+    /// The number held by a value of this kind. Complex numbers also accept
+    /// real values.
+    fn from_value(val: &Value) -> Option<Self>;
+}
+
+/// Calls a native function with floats. Functions made with `NativeFn::new`
+/// take values instead, and report an error.
+fn call_floats(native: &NativeFn, args: &[f64]) -> Result<f64, NativeError> {
+    native
+        .call_numeric(args)
+        .unwrap_or_else(|| Err(NativeError::Other(Box::from("expected numbers, not values"))))
+}
+
+fn sci(x: f64, places: Option<usize>) -> String {
+    match places {
+        Some(p) => format!("{:.*e}", p, x),
+        None => format!("{:e}", x),
+    }
+}
+
+/// An exponent for which exact and decimal powers are computed. Larger
+/// exponents would give unreasonably large numbers.
+fn small_int(q: &Rational) -> Option<i32> {
+    if q.is_integer() && q.numer().bit_len() <= 16 {
+        Some(q.numer().to_f64() as i32)
+    } else {
+        None
+    }
+}
+
+impl Number for f64 {
+    type Context = ();
+
+    fn parse(lit: &str, _: ()) -> Option<Self> {
+        f64::from_str(lit).ok()
+    }
+
+    fn from_f64(x: f64, _: ()) -> Option<Self> {
+        Some(x)
+    }
+
+    fn to_f64(&self) -> f64 {
+        *self
+    }
+
+    fn add(&self, other: &f64) -> Self {
+        self + other
+    }
+
+    fn sub(&self, other: &f64) -> Self {
+        self - other
+    }
+
+    fn mul(&self, other: &f64) -> Self {
+        self * other
+    }
+
+    fn div(&self, other: &f64) -> Result<Self, MathParseError> {
+        Ok(self / other)
+    }
+
+    fn neg(&self) -> Self {
+        -self
+    }
+
+    fn pow(&self, other: &f64) -> Result<Option<Self>, MathParseError> {
+        Ok(Some(self.powf(*other)))
+    }
+
+    fn compare(&self, other: &f64) -> Option<Ordering> {
+        PartialOrd::partial_cmp(self, other)
+    }
+
+    fn to_fixed(&self, places: usize) -> String {
+        format!("{:.*}", places, self)
+    }
+
+    fn call_builtin(name: &str, args: &[f64]) -> Option<Value> {
+        builtins::lookup(name).map(|i| Value::Num((builtins::BUILTINS[i].func)(args)))
+    }
+
+    fn call_native(native: &NativeFn, args: &[f64], _: ()) -> Result<Self, NativeError> {
+        call_floats(native, args)
+    }
+
+    fn into_value(self) -> Value {
+        Value::Num(self)
+    }
+
+    fn from_value(val: &Value) -> Option<Self> {
+        match *val {
+            Value::Num(x) => Some(x),
+            _ => None,
+        }
+    }
+}
+
+impl Number for Complex {
+    type Context = ();
+
+    const ORDERED: bool = false;
+
+    fn parse(lit: &str, _: ()) -> Option<Self> {
+        f64::from_str(lit).ok().map(Complex::from_real)
+    }
+
+    fn from_f64(x: f64, _: ()) -> Option<Self> {
+        Some(Complex::from_real(x))
+    }
+
+    /// The real part.
+    fn to_f64(&self) -> f64 {
+        self.re
+    }
+
+    fn add(&self, other: &Complex) -> Self {
+        *self + *other
+    }
+
+    fn sub(&self, other: &Complex) -> Self {
+        *self - *other
+    }
+
+    fn mul(&self, other: &Complex) -> Self {
+        *self * *other
+    }
+
+    fn div(&self, other: &Complex) -> Result<Self, MathParseError> {
+        Ok(*self / *other)
+    }
+
+    fn neg(&self) -> Self {
+        -*self
+    }
+
+    fn pow(&self, other: &Complex) -> Result<Option<Self>, MathParseError> {
+        Ok(Some(Complex::pow(*self, *other)))
+    }
+
+    fn compare(&self, _: &Complex) -> Option<Ordering> {
+        None
+    }
+
+    fn to_fixed(&self, places: usize) -> String {
+        complex_parts(self, |x| x.to_fixed(places))
+    }
+
+    fn to_sci(&self, places: Option<usize>) -> String {
+        complex_parts(self, |x| sci(x, places))
+    }
+
+    fn call_builtin(name: &str, args: &[Complex]) -> Option<Value> {
+        complex::builtin(name).map(|func| func(args))
+    }
+
+    fn into_value(self) -> Value {
+        Value::Complex(self)
+    }
+
+    fn from_value(val: &Value) -> Option<Self> {
+        match *val {
+            Value::Complex(z) => Some(z),
+            _ => val.as_num().map(Complex::from_real),
+        }
+    }
+}
+
+/// Writes a complex number like `3+4i`, formatting each part with `part`.
+fn complex_parts<F: Fn(f64) -> String>(z: &Complex, part: F) -> String {
+    let sign = if z.im.is_sign_negative() && !z.im.is_nan() {
+        '-'
+    } else {
+        '+'
+    };
+    format!("{}{}{}i", part(z.re), sign, part(z.im.abs()))
+}
+
+impl Number for Rational {
+    type Context = ();
+
+    fn parse(lit: &str, _: ()) -> Option<Self> {
+        Rational::parse(lit)
+    }
+
+    /// The exact value of the float.
+    fn from_f64(x: f64, _: ()) -> Option<Self> {
+        Rational::from_f64(x)
+    }
+
+    fn to_f64(&self) -> f64 {
+        Rational::to_f64(self)
+    }
+
+    fn add(&self, other: &Rational) -> Self {
+        Rational::add(self, other)
+    }
+
+    fn sub(&self, other: &Rational) -> Self {
+        Rational::sub(self, other)
+    }
+
+    fn mul(&self, other: &Rational) -> Self {
+        Rational::mul(self, other)
+    }
+
+    fn div(&self, other: &Rational) -> Result<Self, MathParseError> {
+        Rational::div(self, other).ok_or(MathParseError::DivisionByZero)
+    }
+
+    fn neg(&self) -> Self {
+        Rational::neg(self)
+    }
+
+    fn pow(&self, other: &Rational) -> Result<Option<Self>, MathParseError> {
+        match small_int(other) {
+            Some(n) => self.powi(n).map(Some).ok_or(MathParseError::DivisionByZero),
+            None => Ok(None),
+        }
+    }
+
+    fn compare(&self, other: &Rational) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+
+    fn to_fixed(&self, places: usize) -> String {
+        self.to_decimal(places)
+    }
+
+    fn call_builtin(name: &str, args: &[Rational]) -> Option<Value> {
+        rational::builtin(name)?(args).map(Value::from)
+    }
+
+    fn into_value(self) -> Value {
+        Value::from(self)
+    }
+
+    fn from_value(val: &Value) -> Option<Self> {
+        match val {
+            Value::Rational(q) => Some(Rational::clone(q)),
+            _ => None,
+        }
+    }
+}
+
+impl Number for Decimal {
+    type Context = DecimalContext;
+
+    fn context(ctx: &EvalContext) -> DecimalContext {
+        ctx.decimal_mode().unwrap_or_default()
+    }
+
+    fn parse(lit: &str, ctx: DecimalContext) -> Option<Self> {
+        Decimal::parse(lit, ctx)
+    }
+
+    /// The float rounded to the precision of `ctx`.
+    fn from_f64(x: f64, ctx: DecimalContext) -> Option<Self> {
+        Rational::from_f64(x).map(|q| Decimal::from_rational(&q, ctx))
+    }
+
+    fn to_f64(&self) -> f64 {
+        Decimal::to_f64(self)
+    }
+
+    fn add(&self, other: &Decimal) -> Self {
+        Decimal::add(self, other)
+    }
+
+    fn sub(&self, other: &Decimal) -> Self {
+        Decimal::sub(self, other)
+    }
+
+    fn mul(&self, other: &Decimal) -> Self {
+        Decimal::mul(self, other)
+    }
+
+    fn div(&self, other: &Decimal) -> Result<Self, MathParseError> {
+        Decimal::div(self, other).ok_or(MathParseError::DivisionByZero)
+    }
+
+    fn neg(&self) -> Self {
+        Decimal::neg(self)
+    }
+
+    fn pow(&self, other: &Decimal) -> Result<Option<Self>, MathParseError> {
+        match small_int(&other.to_rational()) {
+            Some(n) => self.powi(n).map(Some).ok_or(MathParseError::DivisionByZero),
+            None => Ok(None),
+        }
+    }
+
+    fn compare(&self, other: &Decimal) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+
+    fn to_fixed(&self, places: usize) -> String {
+        Decimal::to_fixed(self, places)
+    }
+
+    fn call_builtin(name: &str, args: &[Decimal]) -> Option<Value> {
+        decimal::builtin(name)?(args).map(Value::from)
+    }
+
+    fn into_value(self) -> Value {
+        Value::from(self)
+    }
+
+    fn from_value(val: &Value) -> Option<Self> {
+        match val {
+            Value::Decimal(d) => Some(Decimal::clone(d)),
+            _ => None,
+        }
+    }
+}
+
+impl Number for Interval {
+    type Context = ();
+
+    /// The smallest interval containing the decimal literal.
+    fn parse(lit: &str, _: ()) -> Option<Self> {
+        match Rational::parse(lit) {
+            Some(q) => Some(Interval::from_rational(&q)),
+            None => f64::from_str(lit).ok().map(Interval::point),
+        }
+    }
+
+    fn from_f64(x: f64, _: ()) -> Option<Self> {
+        Some(Interval::point(x))
+    }
+
+    /// The midpoint.
+    fn to_f64(&self) -> f64 {
+        self.mid()
+    }
+
+    fn add(&self, other: &Interval) -> Self {
+        *self + *other
+    }
+
+    fn sub(&self, other: &Interval) -> Self {
+        *self - *other
+    }
+
+    fn mul(&self, other: &Interval) -> Self {
+        *self * *other
+    }
+
+    fn div(&self, other: &Interval) -> Result<Self, MathParseError> {
+        Ok(*self / *other)
+    }
+
+    fn neg(&self) -> Self {
+        -*self
+    }
+
+    fn pow(&self, other: &Interval) -> Result<Option<Self>, MathParseError> {
+        Ok(Some(Interval::pow(*self, *other)))
+    }
+
+    /// Intervals are ordered when every number in one is below every number
+    /// in the other, so overlapping intervals compare false both ways.
+    fn compare(&self, other: &Interval) -> Option<Ordering> {
+        if self.hi < other.lo {
+            Some(Ordering::Less)
+        } else if self.lo > other.hi {
+            Some(Ordering::Greater)
+        } else if self.lo == self.hi && self == other {
+            Some(Ordering::Equal)
+        } else {
+            None
+        }
+    }
+
+    fn to_fixed(&self, places: usize) -> String {
+        Interval::to_fixed(*self, places)
+    }
+
+    fn to_sci(&self, places: Option<usize>) -> String {
+        format!("[{}, {}]", sci(self.lo, places), sci(self.hi, places))
+    }
+
+    fn call_builtin(name: &str, args: &[Interval]) -> Option<Value> {
+        interval::builtin(name).map(|func| Value::Interval(func(args)))
+    }
+
+    /// Only builtins can be called with intervals, since other functions
+    /// cannot be bounded.
+    fn call_native(native: &NativeFn, args: &[Interval], _: ()) -> Result<Self, NativeError> {
+        match native.builtin_name().and_then(interval::builtin) {
+            Some(func) => Ok(func(args)),
+            None => Err(NativeError::Other(Box::from("not defined for intervals"))),
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::Interval(self)
+    }
+
+    /// Real values become the smallest interval containing them.
+    fn from_value(val: &Value) -> Option<Self> {
+        match val {
+            Value::Interval(x) => Some(*x),
+            Value::Num(x) => Some(Interval::point(*x)),
+            Value::Rational(q) => Some(Interval::from_rational(q)),
+            Value::Decimal(d) => Some(Interval::from_rational(&d.to_rational())),
+            _ => None,
+        }
+    }
+}
+
+impl Number for Uncertain {
+    type Context = ();
+
+    fn parse(lit: &str, _: ()) -> Option<Self> {
+        f64::from_str(lit).ok().map(Uncertain::exact)
+    }
+
+    fn from_f64(x: f64, _: ()) -> Option<Self> {
+        Some(Uncertain::exact(x))
+    }
+
+    /// The nominal value.
+    fn to_f64(&self) -> f64 {
+        self.value()
+    }
+
+    fn add(&self, other: &Uncertain) -> Self {
+        Uncertain::add(self, other)
+    }
+
+    fn sub(&self, other: &Uncertain) -> Self {
+        Uncertain::sub(self, other)
+    }
+
+    fn mul(&self, other: &Uncertain) -> Self {
+        Uncertain::mul(self, other)
+    }
+
+    fn div(&self, other: &Uncertain) -> Result<Self, MathParseError> {
+        Ok(Uncertain::div(self, other))
+    }
+
+    fn neg(&self) -> Self {
+        Uncertain::neg(self)
+    }
+
+    fn pow(&self, other: &Uncertain) -> Result<Option<Self>, MathParseError> {
+        Ok(Some(Uncertain::pow(self, other)))
+    }
+
+    /// Compares the nominal values.
+    fn compare(&self, other: &Uncertain) -> Option<Ordering> {
+        self.value().partial_cmp(&other.value())
+    }
+
+    fn to_fixed(&self, places: usize) -> String {
+        Uncertain::to_fixed(self, places)
+    }
+
+    fn to_sci(&self, places: Option<usize>) -> String {
+        format!(
+            "{} ± {}",
+            sci(self.value(), places),
+            sci(self.stddev(), places)
+        )
+    }
+
+    fn call_builtin(name: &str, args: &[Uncertain]) -> Option<Value> {
+        let func = builtins::BUILTINS[builtins::lookup(name)?].func;
+        let result = Uncertain::apply(|xs| Ok(func(xs)), args).ok()?;
+        Some(result.into_value())
+    }
+
+    /// Any numeric function can be called, with the uncertainty propagated
+    /// through a numerical derivative.
+    fn call_native(native: &NativeFn, args: &[Uncertain], _: ()) -> Result<Self, NativeError> {
+        Uncertain::apply(|xs| native.call_numeric(xs).unwrap(), args)
+    }
+
+    fn into_value(self) -> Value {
+        Value::from(self)
+    }
+
+    /// Real values have no uncertainty.
+    fn from_value(val: &Value) -> Option<Self> {
+        match val {
+            Value::Uncertain(u) => Some(Uncertain::clone(u)),
+            _ => val.as_num().map(Uncertain::exact),
+        }
+    }
+}
+
+impl Number for Dual {
+    type Context = ();
+
+    fn parse(lit: &str, _: ()) -> Option<Self> {
+        f64::from_str(lit).ok().map(Dual::constant)
+    }
+
+    fn from_f64(x: f64, _: ()) -> Option<Self> {
+        Some(Dual::constant(x))
+    }
+
+    /// The value, without the derivatives.
+    fn to_f64(&self) -> f64 {
+        self.value()
+    }
+
+    fn add(&self, other: &Dual) -> Self {
+        Dual::add(self, other)
+    }
+
+    fn sub(&self, other: &Dual) -> Self {
+        Dual::sub(self, other)
+    }
+
+    fn mul(&self, other: &Dual) -> Self {
+        Dual::mul(self, other)
+    }
+
+    fn div(&self, other: &Dual) -> Result<Self, MathParseError> {
+        Ok(Dual::div(self, other))
+    }
+
+    fn neg(&self) -> Self {
+        Dual::neg(self)
+    }
+
+    fn pow(&self, other: &Dual) -> Result<Option<Self>, MathParseError> {
+        Ok(Some(Dual::pow(self, other)))
+    }
+
+    /// Compares the values, so that branches take the path they would for
+    /// plain numbers.
+    fn compare(&self, other: &Dual) -> Option<Ordering> {
+        self.value().partial_cmp(&other.value())
+    }
+
+    fn to_fixed(&self, places: usize) -> String {
+        Dual::to_fixed(self, places)
+    }
+
+    fn call_builtin(name: &str, args: &[Dual]) -> Option<Value> {
+        dual::builtin(name).map(|func| func(args).into_value())
+    }
+
+    /// Only builtins can be called with dual numbers, since the derivatives
+    /// of other functions are not known.
+    fn call_native(native: &NativeFn, args: &[Dual], _: ()) -> Result<Self, NativeError> {
+        match native.builtin_name().and_then(dual::builtin) {
+            Some(func) => Ok(func(args)),
+            None => Err(NativeError::Other(Box::from("cannot be differentiated"))),
+        }
+    }
+
+    fn into_value(self) -> Value {
+        Value::from(self)
+    }
+
+    /// Real values are constants.
+    fn from_value(val: &Value) -> Option<Self> {
+        match val {
+            Value::Dual(x) => Some(Dual::clone(x)),
+            _ => val.as_num().map(Dual::constant),
+        }
+    }
+}
@@ -0,0 +1,324 @@
+use std::f64;
+
+use parser::MathParseError;
+use solve::Options;
+
+/// The right-hand side `f(t, y)` of a system `y' = f(t, y)`.
+pub(crate) type System<'a> = dyn FnMut(f64, &[f64]) -> Result<Vec<f64>, MathParseError> + 'a;
+
+/// The defaults for `odesolve`. The tolerance applies to each component,
+/// both relative to its size and as an absolute floor.
+pub(crate) const DEFAULTS: Options = Options {
+    tol: 1e-8,
+    max_iter: 10_000,
+    error: None,
+};
+
+/// Where to report the solution.
+#[derive(Debug, Clone)]
+pub(crate) enum Output {
+    /// After every step, up to the given time.
+    Steps(f64),
+    /// At each of the given times, which must all lie on the same side of
+    /// the start and be in order away from it.
+    Times(Vec<f64>),
+}
+
+// The Dormand-Prince coefficients. The last stage is evaluated at the new
+// point, so it is also the first stage of the next step.
+const C: [f64; 6] = [1.0 / 5.0, 3.0 / 10.0, 4.0 / 5.0, 8.0 / 9.0, 1.0, 1.0];
+
+const A: [&[f64]; 6] = [
+    &[1.0 / 5.0],
+    &[3.0 / 40.0, 9.0 / 40.0],
+    &[44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0],
+    &[
+        19372.0 / 6561.0,
+        -25360.0 / 2187.0,
+        64448.0 / 6561.0,
+        -212.0 / 729.0,
+    ],
+    &[
+        9017.0 / 3168.0,
+        -355.0 / 33.0,
+        46732.0 / 5247.0,
+        49.0 / 176.0,
+        -5103.0 / 18656.0,
+    ],
+    &[
+        35.0 / 384.0,
+        0.0,
+        500.0 / 1113.0,
+        125.0 / 192.0,
+        -2187.0 / 6784.0,
+        11.0 / 84.0,
+    ],
+];
+
+/// The difference between the fifth and fourth order solutions, per stage.
+const E: [f64; 7] = [
+    71.0 / 57600.0,
+    0.0,
+    -71.0 / 16695.0,
+    71.0 / 1920.0,
+    -17253.0 / 339200.0,
+    22.0 / 525.0,
+    -1.0 / 40.0,
+];
+
+fn derivative(f: &mut System, t: f64, y: &[f64]) -> Result<Vec<f64>, MathParseError> {
+    let dy = f(t, y)?;
+    match dy.iter().find(|d| !d.is_finite()) {
+        Some(d) => Err(MathParseError::DidNotConverge(format!(
+            "the derivative is {} at t = {}",
+            d, t
+        ))),
+        None => Ok(dy),
+    }
+}
+
+/// `y + h * sum(a[i] * k[i])`.
+fn combine(y: &[f64], h: f64, a: &[f64], k: &[Vec<f64>]) -> Vec<f64> {
+    (0..y.len())
+        .map(|j| y[j] + h * a.iter().zip(k.iter()).map(|(a, k)| a * k[j]).sum::<f64>())
+        .collect()
+}
+
+/// The root mean square of the components, each measured against the
+/// tolerance for it.
+fn error_norm(y: &[f64], err: &[f64], tol: f64) -> f64 {
+    if y.is_empty() {
+        return 0.0;
+    }
+    let sum: f64 = y
+        .iter()
+        .zip(err.iter())
+        .map(|(y, e)| (e / (tol + tol * y.abs())).powi(2))
+        .sum();
+    (sum / y.len() as f64).sqrt()
+}
+
+/// A first step that changes `y` by about 1% according to its derivative.
+fn initial_step(y: &[f64], dy: &[f64], span: f64) -> f64 {
+    let norm = |v: &[f64]| v.iter().fold(0.0, |acc: f64, x| acc.max(x.abs()));
+    let (d0, d1) = (norm(y), norm(dy));
+    let h = if d0 < 1e-5 || d1 < 1e-5 {
+        1e-6
+    } else {
+        0.01 * d0 / d1
+    };
+    h.min(span.abs()).copysign(span)
+}
+
+/// Solves `y' = f(t, y)` from `y(t0) = y0` with the Dormand-Prince method,
+/// a Runge-Kutta method of order 5 that estimates its error with an
+/// embedded one of order 4 and adapts the step size to keep it within the
+/// tolerance. Gives the solution as pairs of a time and the value there,
+/// starting with `t0`.
+pub(crate) fn solve(
+    f: &mut System,
+    t0: f64,
+    y0: Vec<f64>,
+    output: Output,
+    options: Options,
+) -> Result<Vec<(f64, Vec<f64>)>, MathParseError> {
+    let (stops, every_step) = match output {
+        Output::Steps(t1) => (vec![t1], true),
+        Output::Times(times) => (times, false),
+    };
+    if t0.is_nan() || stops.iter().any(|t| t.is_nan()) {
+        return Err(MathParseError::TypeError(String::from(
+            "times cannot be NaN",
+        )));
+    }
+    let end = stops.last().cloned().unwrap_or(t0);
+    let forward = end >= t0;
+    let mut prev = t0;
+    for &stop in stops.iter() {
+        if (stop < prev && forward) || (stop > prev && !forward) {
+            return Err(MathParseError::TypeError(format!(
+                "output times must run in order away from {}",
+                t0
+            )));
+        }
+        prev = stop;
+    }
+
+    let (mut t, mut y) = (t0, y0);
+    let mut dy = derivative(f, t, &y)?;
+    let mut h = initial_step(&y, &dy, end - t0);
+    let mut rows = vec![(t, y.clone())];
+    let mut steps = 0;
+    for &stop in stops.iter() {
+        while t != stop {
+            if steps == options.max_iter {
+                return Err(MathParseError::DidNotConverge(format!(
+                    "reached the limit of {} steps at t = {}",
+                    options.max_iter, t
+                )));
+            }
+            steps += 1;
+            // Land exactly on the stop rather than stepping past it, without
+            // letting the shortened step hold back the ones after it.
+            let last = (stop - t).abs() <= h.abs();
+            let step = if last { stop - t } else { h };
+            let mut k = vec![dy.clone()];
+            for (c, a) in C.iter().zip(A.iter()) {
+                let stage = combine(&y, step, a, &k);
+                k.push(derivative(f, t + c * step, &stage)?);
+            }
+            let next = combine(&y, step, A[5], &k);
+            let err: Vec<f64> = (0..y.len())
+                .map(|j| step * E.iter().zip(k.iter()).map(|(e, k)| e * k[j]).sum::<f64>())
+                .collect();
+            let scale: Vec<f64> = y
+                .iter()
+                .zip(next.iter())
+                .map(|(a, b)| a.abs().max(b.abs()))
+                .collect();
+            let norm = error_norm(&scale, &err, options.tol);
+            let factor = if norm == 0.0 {
+                5.0
+            } else {
+                (0.9 * norm.powf(-0.2)).clamp(0.2, 5.0)
+            };
+            if norm <= 1.0 {
+                t = if last { stop } else { t + h };
+                y = next;
+                dy = k.pop().unwrap();
+                if every_step {
+                    rows.push((t, y.clone()));
+                }
+                if !last {
+                    h *= factor;
+                }
+            } else {
+                h = step * factor;
+                if h.abs() <= 16.0 * f64::EPSILON * t.abs() {
+                    return Err(MathParseError::DidNotConverge(format!(
+                        "the step size became too small at t = {}",
+                        t
+                    )));
+                }
+            }
+        }
+        if !every_step {
+            rows.push((t, y.clone()));
+        }
+    }
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solve_with_tol(
+        f: fn(f64, &[f64]) -> Vec<f64>,
+        t0: f64,
+        y0: &[f64],
+        output: Output,
+        tol: f64,
+    ) -> Result<Vec<(f64, Vec<f64>)>, MathParseError> {
+        let options = Options { tol, ..DEFAULTS };
+        solve(&mut |t, y| Ok(f(t, y)), t0, y0.to_vec(), output, options)
+    }
+
+    #[test]
+    fn exponential_decay() {
+        let rows =
+            solve_with_tol(|_, y| vec![-y[0]], 0.0, &[1.0], Output::Steps(1.0), 1e-8).unwrap();
+        assert_eq!(rows[0], (0.0, vec![1.0]));
+        let (t, ref y) = rows[rows.len() - 1];
+        assert_eq!(t, 1.0);
+        assert!((y[0] - (-1f64).exp()).abs() < 1e-8, "{}", y[0]);
+        // Every step is reported, in order.
+        assert!(rows.len() > 2);
+        assert!(rows.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn oscillator_at_given_times() {
+        let pi = f64::consts::PI;
+        let times = vec![pi / 2.0, pi, 2.0 * pi];
+        let rows = solve_with_tol(
+            |_, y| vec![y[1], -y[0]],
+            0.0,
+            &[0.0, 1.0],
+            Output::Times(times.clone()),
+            1e-10,
+        )
+        .unwrap();
+        let expected = [[0.0, 1.0], [1.0, 0.0], [0.0, -1.0], [0.0, 1.0]];
+        assert_eq!(rows.len(), 4);
+        for (row, exact) in rows.iter().zip(expected.iter()) {
+            assert!((row.1[0] - exact[0]).abs() < 1e-8, "{:?}", row);
+            assert!((row.1[1] - exact[1]).abs() < 1e-8, "{:?}", row);
+        }
+        assert_eq!(rows[1..].iter().map(|row| row.0).collect::<Vec<_>>(), times);
+    }
+
+    #[test]
+    fn backward_in_time() {
+        let rows = solve_with_tol(
+            |_, y| vec![y[0]],
+            1.0,
+            &[f64::consts::E],
+            Output::Times(vec![0.5, 0.0]),
+            1e-10,
+        )
+        .unwrap();
+        assert!((rows[2].1[0] - 1.0).abs() < 1e-9, "{:?}", rows);
+    }
+
+    #[test]
+    fn error_follows_the_tolerance() {
+        // y' = y cos(t) has the solution exp(sin(t)).
+        let exact = 5f64.sin().exp();
+        for &tol in [1e-4, 1e-6, 1e-8, 1e-10].iter() {
+            let rows = solve_with_tol(
+                |t, y| vec![y[0] * t.cos()],
+                0.0,
+                &[1.0],
+                Output::Steps(5.0),
+                tol,
+            )
+            .unwrap();
+            let y = rows.last().unwrap().1[0];
+            assert!(
+                (y - exact).abs() < 100.0 * tol * exact,
+                "tol {}: {}",
+                tol,
+                y
+            );
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let decay: fn(f64, &[f64]) -> Vec<f64> = |_, y| vec![-y[0]];
+        let out_of_order = Output::Times(vec![1.0, 0.5]);
+        assert!(solve_with_tol(decay, 0.0, &[1.0], out_of_order, 1e-8).is_err());
+        assert!(solve_with_tol(decay, f64::NAN, &[1.0], Output::Steps(1.0), 1e-8).is_err());
+        let options = Options {
+            max_iter: 3,
+            ..DEFAULTS
+        };
+        let limited = solve(
+            &mut |_, y| Ok(vec![-y[0]]),
+            0.0,
+            vec![1.0],
+            Output::Steps(100.0),
+            options,
+        );
+        assert!(limited.is_err());
+        let blow_up = solve_with_tol(
+            |_, y| vec![y[0] * y[0]],
+            0.0,
+            &[1.0],
+            Output::Steps(2.0),
+            1e-8,
+        );
+        assert!(blow_up.is_err());
+    }
+}
@@ -1,9 +1,12 @@
 use std::error::Error;
+use std::collections::HashSet;
 use lexer::*;
 use std::num::ParseFloatError;
 use std::fmt::{self, Display};
 use std::str::FromStr;
 use ast::{self, *};
+use builtins::NativeError;
+use units::Unit;
 
 macro_rules! some_token {
     ($token_value : pat) => {
@@ -34,8 +37,18 @@ macro_rules! some_token {
 // }
 
 pub fn parse_repl(input: &str) -> Result<ReplTree, MathParseError> {
+    parse_repl_with_vars(input, None)
+}
+
+/// Parses a line as `parse_repl` does, with `vars` already defined, so that
+/// a number followed by one of them is not read as a quantity with a unit.
+pub fn parse_repl_with_vars<'a, I>(input: &str, vars: I) -> Result<ReplTree, MathParseError>
+where
+    I: IntoIterator<Item = &'a str>,
+{
     let tokens = scan(String::from(input));
     let mut parser = Parser::new(tokens);
+    parser.vars.extend(vars.into_iter().map(String::from));
     let exp = parser.parse_expression_or_eof()?;
     if parser.is_at_end() {
         Ok(exp)
@@ -56,25 +69,32 @@ pub fn parse_file(contents: &str) -> Result<Block, MathParseError> {
 struct Parser {
     tokens: Vec<Token>,
     cursor: usize,
+    /// The names defined so far, as variables, parameters or functions,
+    /// which are never read as units.
+    vars: HashSet<String>,
 }
 
 impl Parser {
     fn new(tokens: Vec<Token>) -> Self {
-        Parser { tokens, cursor: 0 }
+        Parser {
+            tokens,
+            cursor: 0,
+            vars: HashSet::new(),
+        }
     }
 
     fn current_token(&self) -> Option<Token> {
-        self.tokens.get(self.cursor).map(|t| t.clone())
+        self.tokens.get(self.cursor).cloned()
     }
 
     fn advance(&mut self) -> Option<Token> {
-        let t = self.tokens.get(self.cursor).map(|t| t.clone());
+        let t = self.tokens.get(self.cursor).cloned();
         self.cursor += 1;
         t
     }
 
     fn look_ahead(&self, offset: usize) -> Option<Token> {
-        self.tokens.get(self.cursor + offset).map(|t| t.clone())
+        self.tokens.get(self.cursor + offset).cloned()
     }
 
     fn is_at_end(&self) -> bool {
@@ -102,7 +122,7 @@ impl Parser {
                 Some(_) => exprs.push(self.parse_expression()?),
             }
         }
-        if exprs.len() > 0 {
+        if !exprs.is_empty() {
             Ok(ast::Block::Exprs(exprs))
         } else {
             Ok(ast::Block::Empty)
@@ -139,7 +159,7 @@ impl Parser {
                 _ => exprs.push(self.parse_expression()?),
             }
         }
-        if exprs.len() > 0 {
+        if !exprs.is_empty() {
             Ok(ast::Block::Exprs(exprs))
         } else {
             Ok(ast::Block::Empty)
@@ -149,16 +169,278 @@ impl Parser {
     fn parse_expression(&mut self) -> Result<Expr, MathParseError> {
         match self.current_token() {
             some_token!(TokenValue::Keyword(KeywordValue::DEF)) => self.parse_funcdef(),
+            some_token!(TokenValue::Keyword(KeywordValue::IF)) => self.parse_if(),
+            some_token!(TokenValue::Identifier(ref name)) if name == "solve" && self.is_solve() => {
+                self.parse_solve()
+            }
             _ => match self.look_ahead(1) {
                 Some(Token {
                     token_value: TokenValue::Equal,
                     ..
                 }) => self.parse_assign(),
-                _ => self.parse_add(),
+                some_token!(TokenValue::Period) if self.is_field_assign() => {
+                    self.parse_field_assign()
+                }
+                _ if self.is_destructure() => self.parse_destructure(),
+                _ => self.parse_conversion(),
             },
         }
     }
 
+    /// Whether `solve` starts an equation, as in `solve x^2 = 2 for x`,
+    /// rather than naming the function in `solve(f, 1)` or a variable. The
+    /// equation must start on the same line with a name, a number or a minus
+    /// sign. One that starts with `(`, as in `solve (x - 1)^2 = 4 for x`, is
+    /// told apart from a call by trying to parse it as an equation.
+    fn is_solve(&mut self) -> bool {
+        let line = self.current_token().map_or(0, |t| t.line);
+        match self.look_ahead(1) {
+            Some(Token {
+                token_value: TokenValue::Identifier(_),
+                line: next,
+                ..
+            })
+            | Some(Token {
+                token_value: TokenValue::NumberLiteral(_),
+                line: next,
+                ..
+            })
+            | Some(Token {
+                token_value: TokenValue::Minus,
+                line: next,
+                ..
+            }) => next == line,
+            Some(Token {
+                token_value: TokenValue::LeftParen,
+                line: next,
+                ..
+            }) if next == line => {
+                let (cursor, vars) = (self.cursor, self.vars.clone());
+                let is_equation = self.parse_solve().is_ok();
+                self.cursor = cursor;
+                self.vars = vars;
+                is_equation
+            }
+            _ => false,
+        }
+    }
+
+    /// `solve lhs = rhs for x`. The sides are sums, so that `=` is not read
+    /// as part of either.
+    fn parse_solve(&mut self) -> Result<Expr, MathParseError> {
+        let line = self.advance().map_or(0, |t| t.line);
+        let lhs = self.parse_plus_minus()?;
+        match self.advance() {
+            some_token!(TokenValue::Equal) => (),
+            Some(t) => return Err(MathParseError::ExpectedButGot(String::from("'='"), t)),
+            None => return Err(MathParseError::UnexpectedEOF),
+        }
+        let rhs = self.parse_plus_minus()?;
+        match self.advance() {
+            some_token!(TokenValue::Identifier(ref word)) if word == "for" => (),
+            Some(t) => return Err(MathParseError::ExpectedButGot(String::from("'for'"), t)),
+            None => return Err(MathParseError::UnexpectedEOF),
+        }
+        let var = match self.advance() {
+            some_token!(TokenValue::Identifier(name)) => name,
+            Some(t) => return Err(MathParseError::ExpectedButGot(String::from("[identifier]"), t)),
+            None => return Err(MathParseError::UnexpectedEOF),
+        };
+        Ok(Expr {
+            line,
+            expr_type: ExprType::Solve(Box::new(lhs), Box::new(rhs), var),
+        })
+    }
+
+    /// Whether the tokens ahead are a bracketed pattern followed by `=`.
+    fn is_destructure(&self) -> bool {
+        let mut depth = 0;
+        let mut i = 0;
+        loop {
+            match self.look_ahead(i) {
+                some_token!(TokenValue::LeftParen) | some_token!(TokenValue::LeftSquare) => {
+                    depth += 1
+                }
+                some_token!(TokenValue::RightParen) | some_token!(TokenValue::RightSquare) => {
+                    depth -= 1
+                }
+                some_token!(TokenValue::Identifier(_)) | some_token!(TokenValue::Comma)
+                    if depth > 0 => {}
+                _ => return false,
+            }
+            i += 1;
+            if depth == 0 {
+                return matches!(self.look_ahead(i), some_token!(TokenValue::Equal));
+            }
+        }
+    }
+
+    fn parse_destructure(&mut self) -> Result<Expr, MathParseError> {
+        let line = self.current_token().map_or(0, |t| t.line);
+        let pattern = self.parse_pattern()?;
+        match self.advance() {
+            some_token!(TokenValue::Equal) => (),
+            Some(t) => return Err(MathParseError::ExpectedButGot(String::from("'='"), t)),
+            None => return Err(MathParseError::UnexpectedEOF),
+        }
+        Ok(Expr {
+            line,
+            expr_type: ExprType::Destructure(pattern, Box::new(self.parse_expression()?)),
+        })
+    }
+
+    fn parse_pattern(&mut self) -> Result<Pattern, MathParseError> {
+        let (close, is_tuple) = match self.advance() {
+            some_token!(TokenValue::Identifier(name)) => {
+                self.vars.insert(name.clone());
+                return Ok(Pattern::Name(name));
+            }
+            some_token!(TokenValue::LeftParen) => (")", true),
+            some_token!(TokenValue::LeftSquare) => ("]", false),
+            Some(t) => {
+                return Err(MathParseError::ExpectedButGot(
+                    String::from("[identifier], '(' or '['"),
+                    t,
+                ))
+            }
+            None => return Err(MathParseError::UnexpectedEOF),
+        };
+        let mut items = Vec::new();
+        loop {
+            match self.current_token() {
+                some_token!(TokenValue::RightParen) | some_token!(TokenValue::RightSquare) => {
+                    break
+                }
+                _ => items.push(self.parse_pattern()?),
+            }
+            match self.current_token() {
+                some_token!(TokenValue::Comma) => {
+                    self.advance();
+                }
+                _ => break,
+            }
+        }
+        match self.advance() {
+            some_token!(TokenValue::RightParen) if is_tuple => Ok(Pattern::Tuple(items)),
+            some_token!(TokenValue::RightSquare) if !is_tuple => Ok(Pattern::List(items)),
+            Some(t) => Err(MathParseError::ExpectedButGot(format!("',' or '{}'", close), t)),
+            None => Err(MathParseError::UnexpectedEOF),
+        }
+    }
+
+    /// Whether the tokens ahead are `IDENTIFIER {"." IDENTIFIER} "="`.
+    fn is_field_assign(&self) -> bool {
+        match self.current_token() {
+            some_token!(TokenValue::Identifier(_)) => (),
+            _ => return false,
+        }
+        let mut i = 1;
+        loop {
+            match (self.look_ahead(i), self.look_ahead(i + 1)) {
+                (some_token!(TokenValue::Period), some_token!(TokenValue::Identifier(_))) => i += 2,
+                (some_token!(TokenValue::Equal), _) => return i > 1,
+                _ => return false,
+            }
+        }
+    }
+
+    fn parse_field_assign(&mut self) -> Result<Expr, MathParseError> {
+        let (name, line) = match self.advance() {
+            some_token!(TokenValue::Identifier(name), line) => (name, line),
+            Some(t) => return Err(MathParseError::ExpectedButGot(String::from("[identifier]"), t)),
+            None => return Err(MathParseError::UnexpectedEOF),
+        };
+        let mut path = Vec::new();
+        while let some_token!(TokenValue::Period) = self.advance() {
+            match self.advance() {
+                some_token!(TokenValue::Identifier(field)) => path.push(field),
+                Some(t) => return Err(MathParseError::ExpectedButGot(String::from("[identifier]"), t)),
+                None => return Err(MathParseError::UnexpectedEOF),
+            }
+        }
+        // The loop above has consumed the `=`.
+        Ok(Expr {
+            line,
+            expr_type: ExprType::AssignField(name, path, Box::new(self.parse_expression()?)),
+        })
+    }
+
+    fn parse_if(&mut self) -> Result<Expr, MathParseError> {
+        let line = match self.advance() {
+            some_token!(TokenValue::Keyword(KeywordValue::IF), line) => line,
+            Some(t) => return Err(MathParseError::ExpectedButGot(String::from("'if'"), t)),
+            None => return Err(MathParseError::UnexpectedEOF),
+        };
+        let cond = self.parse_expression()?;
+        let then_block = self.parse_block()?;
+        let else_block = match self.current_token() {
+            some_token!(TokenValue::Keyword(KeywordValue::ELSE)) => {
+                self.advance();
+                match self.current_token() {
+                    some_token!(TokenValue::Keyword(KeywordValue::IF)) => {
+                        ast::Block::Exprs(vec![self.parse_if()?])
+                    }
+                    _ => self.parse_block()?,
+                }
+            }
+            _ => ast::Block::Empty,
+        };
+        Ok(Expr {
+            line,
+            expr_type: ExprType::If(Box::new(cond), then_block, else_block),
+        })
+    }
+
+    /// `x to km/h`, which binds most loosely of all operators.
+    fn parse_conversion(&mut self) -> Result<Expr, MathParseError> {
+        let mut expr = self.parse_comparison()?;
+        while let some_token!(TokenValue::Keyword(KeywordValue::TO), line) = self.current_token() {
+            self.advance();
+            let unit = self.parse_unit(line)?;
+            expr = Expr {
+                line: expr.line,
+                expr_type: ExprType::ConvertTo(Box::new(expr), unit),
+            };
+        }
+        Ok(expr)
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, MathParseError> {
+        let mut expr = self.parse_plus_minus()?;
+        loop {
+            let op = match self.current_token() {
+                some_token!(TokenValue::EqualEqual) => BinOp::Equal,
+                some_token!(TokenValue::BangEqual) => BinOp::NotEqual,
+                some_token!(TokenValue::Less) => BinOp::Less,
+                some_token!(TokenValue::LessEqual) => BinOp::LessEqual,
+                some_token!(TokenValue::Greater) => BinOp::Greater,
+                some_token!(TokenValue::GreaterEqual) => BinOp::GreaterEqual,
+                _ => break,
+            };
+            self.advance();
+            let rhs = self.parse_plus_minus()?;
+            expr = Expr {
+                line: expr.line,
+                expr_type: ExprType::Binary(op, Box::new(expr), Box::new(rhs)),
+            };
+        }
+        Ok(expr)
+    }
+
+    /// `±` binds more loosely than `+`, so `a + b ± e` is `(a + b) ± e`.
+    fn parse_plus_minus(&mut self) -> Result<Expr, MathParseError> {
+        let mut expr = self.parse_add()?;
+        while let some_token!(TokenValue::PlusMinus) = self.current_token() {
+            self.advance();
+            let rhs = self.parse_add()?;
+            expr = Expr {
+                line: expr.line,
+                expr_type: ExprType::Binary(BinOp::PlusMinus, Box::new(expr), Box::new(rhs)),
+            };
+        }
+        Ok(expr)
+    }
+
     fn parse_add(&mut self) -> Result<Expr, MathParseError> {
         let first_term = self.parse_mult()?;
         let mut terms: Vec<(BinOp, Expr)> = Vec::new();
@@ -216,6 +498,20 @@ impl Parser {
 
     fn parse_assign(&mut self) -> Result<Expr, MathParseError> {
         match self.current_token() {
+            some_token!(TokenValue::True, line) => {
+                self.advance();
+                Ok(Expr {
+                    line,
+                    expr_type: ExprType::BoolLit(true),
+                })
+            }
+            some_token!(TokenValue::False, line) => {
+                self.advance();
+                Ok(Expr {
+                    line,
+                    expr_type: ExprType::BoolLit(false),
+                })
+            }
             Some(Token {
                 token_value: TokenValue::Identifier(name),
                 line,
@@ -229,9 +525,10 @@ impl Parser {
                     }) => {
                         self.advance();
                         let expr_value = self.parse_expression()?;
+                        self.vars.insert(name.clone());
                         Ok(Expr {
                             line,
-                            expr_type: ExprType::Assign(String::from(name), Box::new(expr_value)),
+                            expr_type: ExprType::Assign(name, Box::new(expr_value)),
                         })
                     }
                     Some(t) => Err(MathParseError::ExpectedButGot(
@@ -274,12 +571,14 @@ impl Parser {
             None => return Err(MathParseError::UnexpectedEOF),
         }
         self.advance();
+        self.vars.insert(name.clone());
 
         let mut params: Vec<String> = Vec::new();
 
         loop {
             match self.current_token() {
                 some_token!(TokenValue::Identifier(param)) => {
+                    self.vars.insert(param.clone());
                     params.push(param);
                     self.advance();
                     match self.current_token() {
@@ -311,7 +610,7 @@ impl Parser {
     }
 
     fn parse_mult(&mut self) -> Result<Expr, MathParseError> {
-        let first_term = self.parse_exp()?;
+        let first_term = self.parse_unary()?;
         let mut terms: Vec<(BinOp, Expr)> = Vec::new();
         loop {
             match self.current_token() {
@@ -320,14 +619,14 @@ impl Parser {
                     ..
                 }) => {
                     self.advance();
-                    terms.push((BinOp::Times, self.parse_exp()?));
+                    terms.push((BinOp::Times, self.parse_unary()?));
                 }
                 Some(Token {
                     token_value: TokenValue::Slash,
                     ..
                 }) => {
                     self.advance();
-                    terms.push((BinOp::Slash, self.parse_exp()?));
+                    terms.push((BinOp::Slash, self.parse_unary()?));
                 }
                 _ => break,
             }
@@ -369,20 +668,29 @@ impl Parser {
         }
     }
 
+    fn parse_unary(&mut self) -> Result<Expr, MathParseError> {
+        let (op, line) = match self.current_token() {
+            some_token!(TokenValue::Minus, line) => (UnOp::Neg, line),
+            some_token!(TokenValue::Bang, line) => (UnOp::Not, line),
+            _ => return self.parse_exp(),
+        };
+        self.advance();
+        Ok(Expr {
+            line,
+            expr_type: ExprType::Unary(op, Box::new(self.parse_unary()?)),
+        })
+    }
+
     fn parse_exp(&mut self) -> Result<Expr, MathParseError> {
-        let first_term = self.parse_factor()?;
+        let first_term = self.parse_postfix()?;
         let mut terms: Vec<(BinOp, Expr)> = Vec::new();
-        loop {
-            match self.current_token() {
-                Some(Token {
-                    token_value: TokenValue::Caret,
-                    ..
-                }) => {
-                    self.advance();
-                    terms.push((BinOp::Exp, self.parse_factor()?));
-                }
-                _ => break,
-            }
+        while let Some(Token {
+            token_value: TokenValue::Caret,
+            ..
+        }) = self.current_token()
+        {
+            self.advance();
+            terms.push((BinOp::Exp, self.parse_exponent()?));
         }
 
         // println!("Ter: {:?}", terms);
@@ -418,22 +726,103 @@ impl Parser {
         }
     }
 
+    /// The right operand of `^`, which may be negated as in `2^-1`.
+    fn parse_exponent(&mut self) -> Result<Expr, MathParseError> {
+        match self.current_token() {
+            some_token!(TokenValue::Minus, line) => {
+                self.advance();
+                Ok(Expr {
+                    line,
+                    expr_type: ExprType::Unary(UnOp::Neg, Box::new(self.parse_exponent()?)),
+                })
+            }
+            _ => self.parse_postfix(),
+        }
+    }
+
+    /// A factor followed by any number of `[index]`, `[a:b]` or `.field`
+    /// suffixes. The `[` must be on the same line as the end of the factor, so
+    /// that a list literal starting a new line is not taken as an index.
+    fn parse_postfix(&mut self) -> Result<Expr, MathParseError> {
+        let mut expr = self.parse_factor()?;
+        loop {
+            let prev_line = self.tokens[self.cursor - 1].line;
+            match self.current_token() {
+                some_token!(TokenValue::LeftSquare, line) if line == prev_line => {
+                    self.advance();
+                }
+                some_token!(TokenValue::Period) => {
+                    self.advance();
+                    let field = match self.advance() {
+                        some_token!(TokenValue::Identifier(field)) => field,
+                        Some(t) => {
+                            return Err(MathParseError::ExpectedButGot(
+                                String::from("[identifier]"),
+                                t,
+                            ))
+                        }
+                        None => return Err(MathParseError::UnexpectedEOF),
+                    };
+                    expr = Expr {
+                        line: expr.line,
+                        expr_type: ExprType::Field(Box::new(expr), field),
+                    };
+                    continue;
+                }
+                _ => break,
+            }
+            let start = match self.current_token() {
+                some_token!(TokenValue::Colon) => None,
+                _ => Some(Box::new(self.parse_expression()?)),
+            };
+            let line = expr.line;
+            let expr_type = match (self.advance(), start) {
+                (some_token!(TokenValue::RightSquare), Some(index)) => {
+                    ExprType::Index(Box::new(expr), index)
+                }
+                (some_token!(TokenValue::Colon), start) => {
+                    let end = match self.current_token() {
+                        some_token!(TokenValue::RightSquare) => None,
+                        _ => Some(Box::new(self.parse_expression()?)),
+                    };
+                    match self.advance() {
+                        some_token!(TokenValue::RightSquare) => (),
+                        Some(t) => return Err(MathParseError::ExpectedButGot(String::from("']'"), t)),
+                        None => return Err(MathParseError::UnexpectedEOF),
+                    }
+                    ExprType::Slice(Box::new(expr), start, end)
+                }
+                (Some(t), _) => {
+                    return Err(MathParseError::ExpectedButGot(String::from("']' or ':'"), t))
+                }
+                (None, _) => return Err(MathParseError::UnexpectedEOF),
+            };
+            expr = Expr { line, expr_type };
+        }
+        Ok(expr)
+    }
+
     fn parse_factor(&mut self) -> Result<Expr, MathParseError> {
         match self.current_token() {
-            Some(Token {
-                token_value: TokenValue::LeftParen,
-                ..
-            }) => {
+            some_token!(TokenValue::LeftParen, line) => {
                 self.advance();
-                let expr = self.parse_expression();
+                if let some_token!(TokenValue::RightParen) = self.current_token() {
+                    self.advance();
+                    return Ok(Expr {
+                        line,
+                        expr_type: ExprType::TupleLit(Vec::new()),
+                    });
+                }
+                let expr = self.parse_expression()?;
                 match self.current_token() {
                     Some(Token {
                         token_value: TokenValue::RightParen,
                         ..
                     }) => {
                         self.advance();
-                        expr
+                        Ok(expr)
                     }
+                    some_token!(TokenValue::Comma) => self.parse_tuple(expr),
                     Some(t) => Err(MathParseError::ExpectedButGot(String::from("')'"), t)),
                     None => Err(MathParseError::UnexpectedEOF),
                 }
@@ -445,12 +834,53 @@ impl Parser {
             }) => {
                 self.advance();
                 let num =
-                    f64::from_str(&lit).map_err(|err| MathParseError::CouldNotParseFloat(err))?;
+                    f64::from_str(&lit).map_err(MathParseError::CouldNotParseFloat)?;
+                let expr = Expr {
+                    line,
+                    expr_type: ExprType::NumLit(num, lit),
+                };
+                if self.unit_at(0, line).is_none() {
+                    return Ok(expr);
+                }
+                let unit = self.parse_unit(line)?;
                 Ok(Expr {
                     line,
-                    expr_type: ExprType::NumLit(num),
+                    expr_type: ExprType::WithUnit(Box::new(expr), unit),
                 })
             }
+            some_token!(TokenValue::True, line) => {
+                self.advance();
+                Ok(Expr {
+                    line,
+                    expr_type: ExprType::BoolLit(true),
+                })
+            }
+            some_token!(TokenValue::False, line) => {
+                self.advance();
+                Ok(Expr {
+                    line,
+                    expr_type: ExprType::BoolLit(false),
+                })
+            }
+            some_token!(TokenValue::ImaginaryLiteral(lit), line) => {
+                self.advance();
+                let num = f64::from_str(&lit).map_err(MathParseError::CouldNotParseFloat)?;
+                Ok(Expr {
+                    line,
+                    expr_type: ExprType::ImagLit(num),
+                })
+            }
+            some_token!(TokenValue::StringLiteral(s), line) => {
+                self.advance();
+                Ok(Expr {
+                    line,
+                    expr_type: ExprType::StrLit(s),
+                })
+            }
+            some_token!(TokenValue::LeftSquare, line) => self.parse_list(line),
+            // Blocks only follow `def`, `if` and `else`, so a brace here
+            // starts a record.
+            some_token!(TokenValue::LeftBracket, line) => self.parse_record(line),
             Some(Token {
                 token_value: TokenValue::Identifier(name),
                 line,
@@ -470,13 +900,179 @@ impl Parser {
             }
             // Some(t) => panic_because_expected("'(' or number literal", t),
             Some(t) => Err(MathParseError::ExpectedButGot(
-                String::from("'(', number literal, or variable"),
+                String::from("'(', '[', '{', literal, or variable"),
                 t,
             )),
             None => Err(MathParseError::UnexpectedEOF),
         }
     }
 
+    /// The unit named by the token `offset` ahead, if it is on `line`.
+    /// Units must follow their number on the same line, so that a variable
+    /// like `m` at the start of the next line is not taken for one, and a
+    /// name defined earlier is always the variable, so `n = 3 2 m` with `m`
+    /// set keeps its meaning.
+    fn unit_at(&self, offset: usize, line: usize) -> Option<Unit> {
+        match self.look_ahead(offset) {
+            Some(Token {
+                token_value: TokenValue::Identifier(ref name),
+                line: l,
+                ..
+            }) if l == line && !self.vars.contains(name) => Unit::lookup(name),
+            _ => None,
+        }
+    }
+
+    /// A unit such as `m/s^2` or `kg m^2`: units with optional integer
+    /// powers, multiplied by writing them next to each other. `/` divides
+    /// by the one unit after it, and only counts as part of the unit when
+    /// a unit follows, so `3 m / 2 s` is `(3 m) / (2 s)`.
+    fn parse_unit(&mut self, line: usize) -> Result<Unit, MathParseError> {
+        let mut unit = match self.unit_at(0, line) {
+            Some(unit) => {
+                self.advance();
+                self.parse_unit_power(unit)?
+            }
+            None => {
+                return match self.current_token() {
+                    Some(t) => Err(MathParseError::ExpectedButGot(String::from("unit"), t)),
+                    None => Err(MathParseError::UnexpectedEOF),
+                }
+            }
+        };
+        loop {
+            if let Some(next) = self.unit_at(0, line) {
+                self.advance();
+                unit = unit.mul(&self.parse_unit_power(next)?);
+            } else if let (some_token!(TokenValue::Slash), Some(next)) =
+                (self.current_token(), self.unit_at(1, line))
+            {
+                self.advance();
+                self.advance();
+                unit = unit.div(&self.parse_unit_power(next)?);
+            } else {
+                return Ok(unit);
+            }
+        }
+    }
+
+    fn parse_unit_power(&mut self, unit: Unit) -> Result<Unit, MathParseError> {
+        if let some_token!(TokenValue::Caret) = self.current_token() {
+            self.advance();
+        } else {
+            return Ok(unit);
+        }
+        let sign = if let some_token!(TokenValue::Minus) = self.current_token() {
+            self.advance();
+            -1
+        } else {
+            1
+        };
+        match self.advance() {
+            some_token!(TokenValue::NumberLiteral(ref lit)) if lit.parse::<i32>().is_ok() => {
+                Ok(unit.powi(sign * lit.parse::<i32>().unwrap()))
+            }
+            Some(t) => Err(MathParseError::ExpectedButGot(
+                String::from("integer power of unit"),
+                t,
+            )),
+            None => Err(MathParseError::UnexpectedEOF),
+        }
+    }
+
+    fn parse_list(&mut self, line: usize) -> Result<Expr, MathParseError> {
+        self.advance();
+        let mut items: Vec<Expr> = Vec::new();
+        loop {
+            match self.current_token() {
+                some_token!(TokenValue::RightSquare) => break,
+                _ => {
+                    items.push(self.parse_expression()?);
+                    match self.current_token() {
+                        some_token!(TokenValue::RightSquare) => break,
+                        some_token!(TokenValue::Comma) => {
+                            self.advance();
+                        }
+                        Some(t) => {
+                            return Err(MathParseError::ExpectedButGot(
+                                String::from("',' or ']'"),
+                                t,
+                            ))
+                        }
+                        None => return Err(MathParseError::UnexpectedEOF),
+                    }
+                }
+            }
+        }
+        self.advance();
+        Ok(Expr {
+            line,
+            expr_type: ExprType::ListLit(items),
+        })
+    }
+
+    /// The rest of a tuple after its first item, starting at the comma. A
+    /// trailing comma is allowed, so `(x,)` has one item.
+    fn parse_tuple(&mut self, first: Expr) -> Result<Expr, MathParseError> {
+        let line = first.line;
+        let mut items = vec![first];
+        loop {
+            match self.advance() {
+                some_token!(TokenValue::RightParen) => break,
+                some_token!(TokenValue::Comma) => (),
+                Some(t) => return Err(MathParseError::ExpectedButGot(String::from("',' or ')'"), t)),
+                None => return Err(MathParseError::UnexpectedEOF),
+            }
+            match self.current_token() {
+                some_token!(TokenValue::RightParen) => {
+                    self.advance();
+                    break;
+                }
+                _ => items.push(self.parse_expression()?),
+            }
+        }
+        Ok(Expr {
+            line,
+            expr_type: ExprType::TupleLit(items),
+        })
+    }
+
+    fn parse_record(&mut self, line: usize) -> Result<Expr, MathParseError> {
+        self.advance();
+        let mut fields: Vec<(String, Expr)> = Vec::new();
+        loop {
+            let name = match self.advance() {
+                some_token!(TokenValue::RightBracket) => break,
+                some_token!(TokenValue::Identifier(name)) => name,
+                Some(t) => {
+                    return Err(MathParseError::ExpectedButGot(
+                        String::from("[identifier] or '}'"),
+                        t,
+                    ))
+                }
+                None => return Err(MathParseError::UnexpectedEOF),
+            };
+            match self.advance() {
+                some_token!(TokenValue::Colon) => (),
+                Some(t) => return Err(MathParseError::ExpectedButGot(String::from("':'"), t)),
+                None => return Err(MathParseError::UnexpectedEOF),
+            }
+            fields.push((name, self.parse_expression()?));
+            match self.current_token() {
+                some_token!(TokenValue::RightBracket) => (),
+                some_token!(TokenValue::Comma) => {
+                    self.advance();
+                }
+                Some(t) => return Err(MathParseError::ExpectedButGot(String::from("',' or '}'"), t)),
+                None => return Err(MathParseError::UnexpectedEOF),
+            }
+        }
+        Ok(Expr {
+            line,
+            expr_type: ExprType::RecordLit(fields),
+        })
+    }
+
     fn parse_function_starting_at_argument_list(
         &mut self,
         name: &str,
@@ -510,6 +1106,33 @@ impl Parser {
             }
         }
         self.advance();
+        // `sum(i, a, b, term)` and `prod(i, a, b, term)` bind `i` in the
+        // term rather than calling the builtins for lists. A function of the
+        // same name is only known when it runs, so hiding the notation is
+        // left to the evaluator and compilers.
+        let op = match name {
+            "sum" => Some(BinOp::Plus),
+            "prod" => Some(BinOp::Times),
+            _ => None,
+        };
+        if let (Some(op), 4) = (op, args.len()) {
+            if let ExprType::Var(ref index) = args[0].expr_type {
+                let index = index.clone();
+                let term = args.pop().unwrap();
+                let hi = args.pop().unwrap();
+                let lo = args.pop().unwrap();
+                return Ok(Expr {
+                    line,
+                    expr_type: ExprType::Iterated(
+                        op,
+                        index,
+                        Box::new(lo),
+                        Box::new(hi),
+                        Box::new(term),
+                    ),
+                });
+            }
+        }
         Ok(Expr {
             line,
             expr_type: ExprType::FuncCall(String::from(name), args),
@@ -524,38 +1147,71 @@ pub enum MathParseError {
     UnexpectedEOF,
     CouldNotParseFloat(ParseFloatError),
     UnknownIdentifier(String),
-    WrongNumberOfArguments(usize, usize)
+    WrongNumberOfArguments(usize, usize),
+    ExpectedValue,
+    NativeFunctionError(String, NativeError),
+    UnsupportedByCompiler(String),
+    TypeError(String),
+    /// An index and the length of the list it was used on.
+    IndexOutOfBounds(i64, usize),
+    NoSuchField(String),
+    /// Exact division by zero, which has no infinity to fall back on.
+    DivisionByZero,
+    /// Quantities whose units measure different things, like metres and
+    /// seconds, described by `Unit::describe`.
+    DimensionMismatch(String, String),
+    /// An expression with no symbolic derivative, written as source.
+    CannotDifferentiate(String),
+    /// An iterative method that gave up, and why.
+    DidNotConverge(String),
+    /// Two points that should bracket a root but where the function has the
+    /// same sign.
+    NotBracketed(f64, f64),
 }
 
 impl Display for MathParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            &MathParseError::ExpectedButGot(ref e, ref g) => {
+            MathParseError::ScanError => write!(f, "Could not scan input."),
+            MathParseError::ExpectedButGot(e, g) => {
                 write!(f, "Expected {} but got {:?}", e, g)
             }
-            &MathParseError::UnknownIdentifier(ref name) => {
+            MathParseError::UnexpectedEOF => write!(f, "Unexpected end of input"),
+            MathParseError::CouldNotParseFloat(_) => write!(f, "Could not parse float"),
+            MathParseError::UnknownIdentifier(name) => {
                 write!(f, "Unknown identifier '{}'", name)
             }
-            _ => write!(f, "{}", self.description()),
+            MathParseError::WrongNumberOfArguments(..) => write!(f, "Wrong number of arguments"),
+            MathParseError::ExpectedValue => write!(f, "Expression does not produce a value"),
+            MathParseError::NativeFunctionError(name, err) => {
+                write!(f, "Error in function '{}': {}", name, err)
+            }
+            MathParseError::UnsupportedByCompiler(what) => {
+                write!(f, "Not supported by the compiler: {}", what)
+            }
+            MathParseError::TypeError(msg) => write!(f, "Type error: {}", msg),
+            MathParseError::IndexOutOfBounds(i, len) => {
+                write!(f, "Index {} out of bounds for length {}", i, len)
+            }
+            MathParseError::NoSuchField(name) => write!(f, "No field '{}'", name),
+            MathParseError::DivisionByZero => write!(f, "Division by zero"),
+            MathParseError::DimensionMismatch(a, b) => {
+                write!(f, "Dimension mismatch between {} and {}", a, b)
+            }
+            MathParseError::CannotDifferentiate(e) => write!(f, "Cannot differentiate '{}'", e),
+            MathParseError::DidNotConverge(why) => write!(f, "Did not converge: {}", why),
+            MathParseError::NotBracketed(a, b) => {
+                write!(f, "No sign change between {} and {} to bracket a root", a, b)
+            }
         }
     }
 }
 
 impl Error for MathParseError {
-    fn description(&self) -> &str {
-        match self {
-            &MathParseError::ScanError => "Could not scan input.",
-            &MathParseError::ExpectedButGot(..) => "Unexpected token",
-            &MathParseError::UnexpectedEOF => "Unexpected end of input",
-            &MathParseError::CouldNotParseFloat(_) => "Could not parse float",
-            &MathParseError::UnknownIdentifier(_) => "Unknown identifier",
-            &MathParseError::WrongNumberOfArguments(..) => "Wrong number of arguments"
-        }
-    }
-
-    fn cause(&self) -> Option<&Error> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
         match self {
-            &MathParseError::CouldNotParseFloat(ref pferr) => Some(pferr),
+            MathParseError::CouldNotParseFloat(pferr) => Some(pferr),
+            MathParseError::NativeFunctionError(_, err) => Some(err),
             _ => None,
         }
     }
@@ -565,11 +1221,32 @@ impl Error for MathParseError {
 Grammar:
 
 block = {expression}
-expression = mult {add_op mult} | assignment | fundef
-mult = factor {mult_op factor}
-factor = "(" expression ")" | NUMBER | IDENTIFIER
+expression = conversion | assignment | field_assignment | destructure | fundef | if | solve
+conversion = comparison {"to" unit}
+comparison = plus_minus {comp_op plus_minus}
+plus_minus = sum {"±" sum}
+sum = mult {add_op mult}
+mult = unary {mult_op unary}
+unary = unary_op unary | exp
+exp = postfix {"^" ["-"] postfix}
+postfix = factor {"[" (expression | [expression] ":" [expression]) "]" | "." IDENTIFIER}
+factor = "(" expression ")" | tuple | NUMBER [unit] | NUMBER "i" | STRING | IDENTIFIER | "true" | "false" | list | record | iterated
+iterated = ("sum" | "prod") "(" IDENTIFIER "," expression "," expression "," expression ")"
+unit = unit_power {unit_power | "/" unit_power}
+unit_power = UNIT ["^" ["-"] NUMBER]
+tuple = "(" ")" | "(" expression "," [expression {"," expression} [","]] ")"
+list = "[" [expression {"," expression}] "]"
+record = "{" [IDENTIFIER ":" expression {"," IDENTIFIER ":" expression}] "}"
+comp_op = "==" | "!=" | "<" | "<=" | ">" | ">="
 add_op = "+" | "-"
 mult_op = "*" | "/"
+unary_op = "-" | "!"
 assignment = IDENTIFIER "=" expression
+field_assignment = IDENTIFIER "." IDENTIFIER {"." IDENTIFIER} "=" expression
+destructure = ("(" patterns ")" | "[" patterns "]") "=" expression
+patterns = [pattern {"," pattern} [","]]
+pattern = IDENTIFIER | "(" patterns ")" | "[" patterns "]"
+if = "if" expression "{" block "}" ["else" ("{" block "}" | if)]
+solve = "solve" plus_minus "=" plus_minus "for" IDENTIFIER
 
 */
@@ -0,0 +1,363 @@
+use std::cmp::Ordering;
+use std::fmt::{self, Display};
+
+use bigint::{self, BigInt};
+
+/// An exact fraction in lowest terms, with a positive denominator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rational {
+    num: BigInt,
+    den: BigInt,
+}
+
+impl Rational {
+    /// `num / den` in lowest terms, or `None` if `den` is zero.
+    pub fn new(num: BigInt, den: BigInt) -> Option<Self> {
+        if den.is_zero() {
+            return None;
+        }
+        let g = num.gcd(&den);
+        let (mut num, mut den) = (num.div_rem(&g)?.0, den.div_rem(&g)?.0);
+        if den.is_negative() {
+            num = num.neg();
+            den = den.neg();
+        }
+        Some(Rational { num, den })
+    }
+
+    pub fn from_int(num: BigInt) -> Self {
+        Rational {
+            num,
+            den: BigInt::one(),
+        }
+    }
+
+    pub fn zero() -> Self {
+        Rational::from_int(BigInt::zero())
+    }
+
+    /// Parses a decimal literal such as `12` or `0.125` exactly.
+    pub fn parse(text: &str) -> Option<Self> {
+        let (int, frac) = match text.find('.') {
+            Some(i) => (&text[..i], &text[i + 1..]),
+            None => (text, ""),
+        };
+        let mut digits = String::with_capacity(int.len() + frac.len());
+        digits.push_str(if int.is_empty() { "0" } else { int });
+        digits.push_str(frac);
+        let num = digits.parse().ok()?;
+        Rational::new(num, BigInt::from(10).pow(frac.len() as u32))
+    }
+
+    /// The exact value of a float, or `None` for infinities and NaN.
+    pub fn from_f64(x: f64) -> Option<Self> {
+        if !x.is_finite() {
+            return None;
+        }
+        let bits = x.to_bits();
+        let exp = ((bits >> 52) & 0x7ff) as i64;
+        let frac = (bits & ((1 << 52) - 1)) as i64;
+        let (mantissa, exp) = if exp == 0 {
+            (frac, -1074)
+        } else {
+            (frac | (1 << 52), exp - 1075)
+        };
+        let mantissa = BigInt::from(if x < 0.0 { -mantissa } else { mantissa });
+        if exp >= 0 {
+            Some(Rational::from_int(mantissa.shl(exp as usize)))
+        } else {
+            Rational::new(mantissa, BigInt::one().shl(-exp as usize))
+        }
+    }
+
+    pub fn numer(&self) -> &BigInt {
+        &self.num
+    }
+
+    pub fn denom(&self) -> &BigInt {
+        &self.den
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.den.is_one()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.num.is_zero()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.num.is_negative()
+    }
+
+    pub fn add(&self, other: &Rational) -> Self {
+        Rational::new(
+            self.num.mul(&other.den).add(&other.num.mul(&self.den)),
+            self.den.mul(&other.den),
+        )
+        .unwrap()
+    }
+
+    pub fn sub(&self, other: &Rational) -> Self {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &Rational) -> Self {
+        Rational::new(self.num.mul(&other.num), self.den.mul(&other.den)).unwrap()
+    }
+
+    /// `self / other`, or `None` when dividing by zero.
+    pub fn div(&self, other: &Rational) -> Option<Self> {
+        Rational::new(self.num.mul(&other.den), self.den.mul(&other.num))
+    }
+
+    pub fn neg(&self) -> Self {
+        Rational {
+            num: self.num.neg(),
+            den: self.den.clone(),
+        }
+    }
+
+    pub fn abs(&self) -> Self {
+        Rational {
+            num: self.num.abs(),
+            den: self.den.clone(),
+        }
+    }
+
+    /// Raises to an integer power, or `None` for a negative power of zero.
+    pub fn powi(&self, exp: i32) -> Option<Self> {
+        let num = self.num.pow(exp.unsigned_abs());
+        let den = self.den.pow(exp.unsigned_abs());
+        if exp < 0 {
+            Rational::new(den, num)
+        } else {
+            Rational::new(num, den)
+        }
+    }
+
+    pub fn floor(&self) -> Self {
+        Rational::from_int(self.num.div_floor(&self.den).unwrap())
+    }
+
+    pub fn ceil(&self) -> Self {
+        self.neg().floor().neg()
+    }
+
+    pub fn trunc(&self) -> Self {
+        Rational::from_int(self.num.div_rem(&self.den).unwrap().0)
+    }
+
+    /// Rounds to the nearest integer, with halves rounded away from zero.
+    pub fn round(&self) -> Self {
+        let half = Rational::new(BigInt::one(), BigInt::from(2)).unwrap();
+        let rounded = self.abs().add(&half).floor();
+        if self.is_negative() {
+            rounded.neg()
+        } else {
+            rounded
+        }
+    }
+
+    pub fn signum(&self) -> Self {
+        Rational::from_int(BigInt::from(match self.num.cmp(&BigInt::zero()) {
+            Ordering::Less => -1,
+            Ordering::Equal => 0,
+            Ordering::Greater => 1,
+        }))
+    }
+
+    /// The square root, if it is rational.
+    pub fn sqrt(&self) -> Option<Self> {
+        let num = self.num.isqrt()?;
+        let den = self.den.isqrt()?;
+        if num.mul(&num) == self.num && den.mul(&den) == self.den {
+            Some(Rational { num, den })
+        } else {
+            None
+        }
+    }
+
+    /// The nearest f64.
+    pub fn to_f64(&self) -> f64 {
+        if self.is_zero() {
+            return 0.0;
+        }
+        // Scale the numerator so that the integer quotient has about 64
+        // significant bits, and keep a sticky bit for an inexact division.
+        let shift = 64 + self.den.bit_len() as i64 - self.num.bit_len() as i64;
+        let (num, den) = if shift >= 0 {
+            (self.num.abs().shl(shift as usize), self.den.clone())
+        } else {
+            (self.num.abs(), self.den.shl(-shift as usize))
+        };
+        let (q, r) = num.div_rem(&den).unwrap();
+        let q = q.shl(1).add(&BigInt::from(if r.is_zero() { 0 } else { 1 }));
+        let x = bigint::scale_by_pow2(q.to_f64(), -(shift as i32) - 1);
+        if self.is_negative() {
+            -x
+        } else {
+            x
+        }
+    }
+
+    /// Writes the value as a decimal with `digits` digits after the point,
+    /// rounding halves away from zero.
+    pub fn to_decimal(&self, digits: usize) -> String {
+        let scale = BigInt::from(10).pow(digits as u32);
+        let (q, r) = self.num.abs().mul(&scale).div_rem(&self.den).unwrap();
+        let q = if r.shl(1) >= self.den {
+            q.add(&BigInt::one())
+        } else {
+            q
+        };
+        let mut text = q.to_string();
+        if text.len() <= digits {
+            text = format!("{}{}", "0".repeat(digits + 1 - text.len()), text);
+        }
+        if digits > 0 {
+            text.insert(text.len() - digits, '.');
+        }
+        if self.is_negative() && !q.is_zero() {
+            text.insert(0, '-');
+        }
+        text
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Rational) -> Ordering {
+        self.num.mul(&other.den).cmp(&other.num.mul(&self.den))
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Rational) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Shows integers as they are and other values as fractions, like `1/3`.
+impl Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_integer() {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+pub type ExactFn = fn(&[Rational]) -> Option<Rational>;
+
+/// The exact versions of the builtins that have them, used when a builtin is
+/// called with only rational arguments. They give `None` when the result is
+/// not rational, in which case the builtin falls back to floats. The arity
+/// has already been checked.
+pub fn builtin(name: &str) -> Option<ExactFn> {
+    macro_rules! unary {
+        ($f: expr) => {
+            Some(|args: &[Rational]| Some($f(&args[0])))
+        };
+    }
+    match name {
+        "abs" => unary!(Rational::abs),
+        "sign" => unary!(Rational::signum),
+        "floor" => unary!(Rational::floor),
+        "ceil" => unary!(Rational::ceil),
+        "round" => unary!(Rational::round),
+        "trunc" => unary!(Rational::trunc),
+        "sqrt" => Some(|args: &[Rational]| args[0].sqrt()),
+        "min" => Some(|args: &[Rational]| args.iter().min().cloned()),
+        "max" => Some(|args: &[Rational]| args.iter().max().cloned()),
+        "clamp" => Some(|args: &[Rational]| {
+            Some(args[0].clone().max(args[1].clone()).min(args[2].clone()))
+        }),
+        "conj" | "re" => unary!(Rational::clone),
+        "im" => Some(|_: &[Rational]| Some(Rational::zero())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn q(num: i64, den: i64) -> Rational {
+        Rational::new(BigInt::from(num), BigInt::from(den)).unwrap()
+    }
+
+    #[test]
+    fn new_reduces_and_makes_the_denominator_positive() {
+        let x = q(-6, -4);
+        assert_eq!(
+            (x.numer().to_string(), x.denom().to_string()),
+            ("3".into(), "2".into())
+        );
+        assert_eq!(q(6, -4), q(-3, 2));
+        assert_eq!(q(0, -5), Rational::zero());
+        assert!(q(0, -5).denom().is_one());
+        assert_eq!(Rational::new(BigInt::one(), BigInt::zero()), None);
+    }
+
+    #[test]
+    fn arithmetic_stays_in_lowest_terms() {
+        assert_eq!(q(1, 3).add(&q(1, 6)), q(1, 2));
+        assert_eq!(q(-1, 3).sub(&q(1, 6)), q(-1, 2));
+        assert_eq!(q(-4, 9).mul(&q(3, -8)), q(1, 6));
+        assert_eq!(q(1, 2).div(&q(-1, 4)), Some(q(-2, 1)));
+        assert_eq!(q(1, 2).div(&Rational::zero()), None);
+        assert_eq!(q(1, 3).add(&q(2, 3)).to_string(), "1");
+    }
+
+    #[test]
+    fn large_powers() {
+        assert_eq!(
+            q(2, 3).powi(100).unwrap().to_string(),
+            "1267650600228229401496703205376/515377520732011331036461129765621272702107522001"
+        );
+        assert_eq!(q(-2, 5).powi(-3), Some(q(-125, 8)));
+        assert_eq!(Rational::zero().powi(-1), None);
+    }
+
+    #[test]
+    fn parse_and_display() {
+        assert_eq!(Rational::parse("0.125"), Some(q(1, 8)));
+        assert_eq!(Rational::parse(".5"), Some(q(1, 2)));
+        assert_eq!(Rational::parse("12"), Some(q(12, 1)));
+        assert_eq!(Rational::parse("1.2.3"), None);
+        assert_eq!(q(-3, 2).to_string(), "-3/2");
+        assert_eq!(q(1, 3).to_decimal(5), "0.33333");
+        assert_eq!(q(-2, 3).to_decimal(3), "-0.667");
+        assert_eq!(q(-1, 3000).to_decimal(2), "0.00");
+        assert_eq!(q(5, 2).to_decimal(0), "3");
+    }
+
+    #[test]
+    fn to_f64_rounds_correctly() {
+        assert_eq!(Rational::parse("0.1").unwrap().to_f64(), 0.1);
+        assert_eq!(q(1, 3).to_f64(), 1.0 / 3.0);
+        assert_eq!(q(-22, 7).to_f64(), -22.0 / 7.0);
+    }
+
+    #[test]
+    fn floats_convert_exactly() {
+        let tenth = Rational::from_f64(0.1).unwrap();
+        assert_eq!(tenth.to_string(), "3602879701896397/36028797018963968");
+        assert_eq!(tenth.to_f64(), 0.1);
+        assert_eq!(Rational::from_f64(f64::NAN), None);
+    }
+
+    #[test]
+    fn rounding() {
+        let x = q(-7, 2);
+        assert_eq!(x.floor(), q(-4, 1));
+        assert_eq!(x.ceil(), q(-3, 1));
+        assert_eq!(x.trunc(), q(-3, 1));
+        assert_eq!(x.round(), q(-4, 1));
+        assert_eq!(q(5, 2).round(), q(3, 1));
+        assert_eq!(q(49, 64).sqrt(), Some(q(7, 8)));
+        assert_eq!(q(2, 1).sqrt(), None);
+        assert!(q(-1, 2) < q(-1, 3));
+    }
+}
@@ -0,0 +1,521 @@
+use std::cmp::Ordering;
+
+use ast::{BinOp, Expr, ExprType, UnOp};
+use bigint::BigInt;
+use rational::{self, Rational};
+
+/// Simplifies an expression algebraically. Constants are folded exactly,
+/// terms like `0 * x`, `x * 1` and `x + 0` are dropped, like terms are
+/// collected as in `x + 2 * x` to `3 * x`, powers of the same base are
+/// merged as in `x * x^2` to `x^3`, and terms and factors are put in a
+/// canonical order. Sums are not multiplied out, except by constants.
+///
+/// Variables are taken to be finite real numbers. A rule that would remove
+/// a point where the expression is undefined is only used when it cannot:
+/// `0 * e` and `e - e` become `0` only when `e` is known to be finite, and
+/// a power with a negative exponent is not merged with one with a positive
+/// exponent, so `x / x` is left as it is rather than becoming `1`.
+pub fn simplify(expr: &Expr) -> Expr {
+    simplify_sum(expr).to_expr(expr.line)
+}
+
+/// A sum of terms in canonical form. The empty sum is zero.
+#[derive(Debug, Clone)]
+struct Sum {
+    terms: Vec<Term>,
+}
+
+/// A coefficient times a product of powers. Factors are kept sorted by key
+/// and each base appears at most once.
+#[derive(Debug, Clone)]
+struct Term {
+    coef: Rational,
+    factors: Vec<Factor>,
+}
+
+#[derive(Debug, Clone)]
+struct Factor {
+    base: Expr,
+    /// The base written as source, which identifies like factors.
+    key: String,
+    exp: Sum,
+}
+
+fn int(n: i64) -> Rational {
+    Rational::from_int(BigInt::from(n))
+}
+
+impl Sum {
+    fn constant(q: Rational) -> Sum {
+        let terms = if q.is_zero() {
+            Vec::new()
+        } else {
+            vec![Term {
+                coef: q,
+                factors: Vec::new(),
+            }]
+        };
+        Sum { terms }
+    }
+
+    /// An expression that is not simplified any further, as a sum.
+    fn atom(base: Expr) -> Sum {
+        Sum {
+            terms: vec![Term {
+                coef: int(1),
+                factors: vec![Factor {
+                    key: base.to_string(),
+                    base,
+                    exp: Sum::constant(int(1)),
+                }],
+            }],
+        }
+    }
+
+    fn as_constant(&self) -> Option<Rational> {
+        match self.terms.len() {
+            0 => Some(Rational::zero()),
+            1 if self.terms[0].factors.is_empty() => Some(self.terms[0].coef.clone()),
+            _ => None,
+        }
+    }
+
+    /// The constant as an `i32`, if it is an integer in range.
+    fn as_int(&self) -> Option<i32> {
+        let q = self.as_constant()?;
+        let x = q.to_f64();
+        if q.is_integer() && x.abs() <= f64::from(i32::MAX) {
+            Some(x as i32)
+        } else {
+            None
+        }
+    }
+
+    fn add(mut self, other: Sum) -> Sum {
+        for term in other.terms {
+            let key = term.key();
+            match self.terms.iter_mut().find(|t| t.key() == key) {
+                Some(t) => t.coef = t.coef.add(&term.coef),
+                None => self.terms.push(term),
+            }
+        }
+        self.terms.retain(|t| !t.coef.is_zero() || !t.is_finite());
+        self
+    }
+
+    fn is_finite(&self) -> bool {
+        self.terms.iter().all(Term::is_finite)
+    }
+
+    fn scale(mut self, q: &Rational) -> Sum {
+        if q.is_zero() {
+            return Sum::constant(Rational::zero());
+        }
+        for term in self.terms.iter_mut() {
+            term.coef = term.coef.mul(q);
+        }
+        self
+    }
+
+    fn neg(self) -> Sum {
+        self.scale(&int(-1))
+    }
+
+    /// The sum as a single term, treating a sum of several terms as a base
+    /// of its own.
+    fn into_term(self, line: usize) -> Term {
+        if self.terms.len() == 1 {
+            self.terms.into_iter().next().unwrap()
+        } else {
+            Sum::atom(self.to_expr(line)).terms.pop().unwrap()
+        }
+    }
+
+    fn mul(self, other: Sum, line: usize) -> Sum {
+        // Zero times something that may be undefined is left alone.
+        match (self.as_constant(), other.as_constant()) {
+            (Some(q), _) if !q.is_zero() || other.is_finite() => return other.scale(&q),
+            (_, Some(q)) if !q.is_zero() || self.is_finite() => return self.scale(&q),
+            _ => (),
+        }
+        Sum {
+            terms: vec![self.into_term(line).mul(other.into_term(line))],
+        }
+    }
+
+    /// `self / other`, or `None` for a division by zero, which is left as
+    /// it is.
+    fn div(self, other: Sum, line: usize) -> Option<Sum> {
+        if let Some(q) = other.as_constant() {
+            return Some(self.scale(&int(1).div(&q)?));
+        }
+        let inverse = other.pow(Sum::constant(int(-1)), line);
+        Some(self.mul(inverse, line))
+    }
+
+    fn pow(self, exp: Sum, line: usize) -> Sum {
+        match (self.as_constant(), exp.as_int()) {
+            (_, Some(0)) => return Sum::constant(int(1)),
+            (_, Some(1)) => return self,
+            (Some(ref b), Some(n)) => {
+                if let Some(q) = b.powi(n) {
+                    return Sum::constant(q);
+                }
+            }
+            (Some(ref b), None) if *b == int(1) => return Sum::constant(int(1)),
+            _ => (),
+        }
+        // A product can be raised to an integer power factor by factor, as
+        // long as its factors have integer powers and no division by one of
+        // them is undone.
+        if let (1, Some(n)) = (self.terms.len(), exp.as_int()) {
+            let term = &self.terms[0];
+            let distributes = term.factors.iter().all(|f| match f.exp.as_int() {
+                Some(k) => n > 0 || k >= 0,
+                None => false,
+            });
+            if let (true, Some(coef)) = (distributes, term.coef.powi(n)) {
+                let factors = term
+                    .factors
+                    .iter()
+                    .map(|f| Factor {
+                        exp: f.exp.clone().scale(&int(i64::from(n))),
+                        ..f.clone()
+                    })
+                    .collect();
+                return Sum {
+                    terms: vec![Term { coef, factors }],
+                };
+            }
+        }
+        // A lone base to a power, like `x`, keeps its identity so that
+        // `x^n * x` can be merged.
+        let factor = match self.terms.first() {
+            Some(term)
+                if self.terms.len() == 1
+                    && term.coef == int(1)
+                    && term.factors.len() == 1
+                    && term.factors[0].exp.as_int() == Some(1) =>
+            {
+                Factor {
+                    exp,
+                    ..term.factors[0].clone()
+                }
+            }
+            _ => {
+                let base = self.to_expr(line);
+                Factor {
+                    key: base.to_string(),
+                    base,
+                    exp,
+                }
+            }
+        };
+        Sum {
+            terms: vec![Term {
+                coef: int(1),
+                factors: vec![factor],
+            }],
+        }
+    }
+
+    fn to_expr(&self, line: usize) -> Expr {
+        let mut terms: Vec<&Term> = self.terms.iter().collect();
+        terms.sort_by(|a, b| Term::order(a, b));
+        let mut expr: Option<Expr> = None;
+        for term in terms {
+            expr = Some(match expr {
+                None => term.to_expr(line, true),
+                Some(acc) => {
+                    let op = if term.coef.is_negative() {
+                        BinOp::Minus
+                    } else {
+                        BinOp::Plus
+                    };
+                    binary(line, op, acc, term.to_expr(line, false))
+                }
+            });
+        }
+        expr.unwrap_or_else(|| constant(line, &Rational::zero()))
+    }
+}
+
+impl Term {
+    /// Identifies like terms, which differ only in their coefficients.
+    fn key(&self) -> String {
+        let mut key = String::new();
+        for factor in self.factors.iter() {
+            key.push_str(&format!("({})^({});", factor.key, factor.exp.to_expr(0)));
+        }
+        key
+    }
+
+    /// The sum of the constant exponents, by which terms are ordered.
+    fn degree(&self) -> Rational {
+        self.factors.iter().fold(Rational::zero(), |acc, f| {
+            acc.add(&f.exp.as_constant().unwrap_or_else(Rational::zero))
+        })
+    }
+
+    /// Orders terms by decreasing degree, with the constant last.
+    fn order(a: &Term, b: &Term) -> Ordering {
+        match (a.factors.is_empty(), b.factors.is_empty()) {
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            _ => b.degree().cmp(&a.degree()).then_with(|| a.key().cmp(&b.key())),
+        }
+    }
+
+    /// Whether the term is defined for all finite values of its variables.
+    fn is_finite(&self) -> bool {
+        self.factors.iter().all(|f| match f.exp.as_int() {
+            Some(n) => n >= 0 && is_finite(&f.base),
+            None => false,
+        })
+    }
+
+    /// Multiplies the terms. Powers of a base are merged only when both
+    /// exponents are constants of the same sign, since `x^2 / x` is not
+    /// defined at zero but `x` is.
+    fn mul(mut self, other: Term) -> Term {
+        self.coef = self.coef.mul(&other.coef);
+        for factor in other.factors {
+            let sign = exp_sign(&factor.exp);
+            let like = |f: &&mut Factor| {
+                f.key == factor.key && sign.is_some() && exp_sign(&f.exp) == sign
+            };
+            match self.factors.iter_mut().find(like) {
+                Some(f) => f.exp = f.exp.clone().add(factor.exp),
+                None => self.factors.push(factor),
+            }
+        }
+        self.factors.retain(|f| f.exp.as_int() != Some(0));
+        self.factors.sort_by(|a, b| a.key.cmp(&b.key));
+        self
+    }
+
+    /// Writes the term. Unless it comes first in a sum, the sign is left
+    /// to the `+` or `-` before it.
+    fn to_expr(&self, line: usize, first: bool) -> Expr {
+        let coef = if first {
+            self.coef.clone()
+        } else {
+            self.coef.abs()
+        };
+        let mut numer: Vec<Expr> = Vec::new();
+        let mut denom: Vec<Expr> = Vec::new();
+        // Coefficients with no short decimal form are split, so that
+        // `x / 3` is written instead of `1 / 3 * x`.
+        let upper = if is_decimal(&coef) {
+            coef
+        } else {
+            denom.push(constant(line, &Rational::from_int(coef.denom().clone())));
+            Rational::from_int(coef.numer().clone())
+        };
+        let unit = upper == int(1) || upper == int(-1);
+        if !unit || self.factors.is_empty() {
+            numer.push(constant(line, &upper));
+        }
+        for factor in self.factors.iter() {
+            let negative = factor
+                .exp
+                .as_constant()
+                .is_some_and(|q| q.is_negative());
+            if negative {
+                denom.push(power(line, factor, &factor.exp.clone().neg()));
+            } else {
+                numer.push(power(line, factor, &factor.exp));
+            }
+        }
+        if numer.is_empty() {
+            numer.push(constant(line, &upper));
+        } else if unit && upper.is_negative() && !self.factors.is_empty() {
+            let first = numer.remove(0);
+            numer.insert(0, unary(line, UnOp::Neg, first));
+        }
+        let product = |exprs: Vec<Expr>| {
+            exprs
+                .into_iter()
+                .reduce(|acc, e| binary(line, BinOp::Times, acc, e))
+                .unwrap()
+        };
+        let numer = product(numer);
+        if denom.is_empty() {
+            numer
+        } else {
+            binary(line, BinOp::Slash, numer, product(denom))
+        }
+    }
+}
+
+/// Whether a constant exponent is negative, or `None` if it is not constant.
+fn exp_sign(exp: &Sum) -> Option<bool> {
+    exp.as_constant().map(|q| q.is_negative())
+}
+
+/// Builtins that are finite for all finite arguments.
+const FINITE_BUILTINS: &[&str] = &[
+    "sin", "cos", "atan", "tanh", "abs", "sign", "floor", "ceil", "round", "trunc", "min", "max",
+    "clamp",
+];
+
+/// Whether an expression is known to be finite for all finite values of its
+/// variables. Divisions, powers other than natural numbers and calls to
+/// most functions may not be.
+fn is_finite(e: &Expr) -> bool {
+    match e.expr_type {
+        ExprType::NumLit(x, _) => x.is_finite(),
+        ExprType::Var(_) => true,
+        ExprType::Unary(UnOp::Neg, ref a) => is_finite(a),
+        ExprType::Binary(BinOp::Plus, ref a, ref b)
+        | ExprType::Binary(BinOp::Minus, ref a, ref b)
+        | ExprType::Binary(BinOp::Times, ref a, ref b) => is_finite(a) && is_finite(b),
+        ExprType::Binary(BinOp::Exp, ref a, ref b) => match b.expr_type {
+            ExprType::NumLit(n, _) => n >= 0.0 && n.fract() == 0.0 && is_finite(a),
+            _ => false,
+        },
+        ExprType::FuncCall(ref name, ref args) => {
+            FINITE_BUILTINS.contains(&name.as_str()) && args.iter().all(is_finite)
+        }
+        _ => false,
+    }
+}
+
+fn power(line: usize, factor: &Factor, exp: &Sum) -> Expr {
+    if exp.as_int() == Some(1) {
+        factor.base.clone()
+    } else {
+        binary(line, BinOp::Exp, factor.base.clone(), exp.to_expr(line))
+    }
+}
+
+/// Whether the number has a finite decimal expansion, so that its
+/// denominator has no prime factors but 2 and 5.
+fn is_decimal(q: &Rational) -> bool {
+    let mut den = q.denom().clone();
+    for p in [2, 5].iter() {
+        let p = BigInt::from(*p);
+        loop {
+            match den.div_rem(&p) {
+                Some((d, r)) if r.is_zero() => den = d,
+                _ => break,
+            }
+        }
+    }
+    den.is_one()
+}
+
+/// Writes a number exactly, as a decimal if it has a finite expansion and
+/// as a fraction otherwise.
+fn constant(line: usize, q: &Rational) -> Expr {
+    let num = |q: &Rational, lit: String| Expr {
+        line,
+        expr_type: ExprType::NumLit(q.to_f64(), lit),
+    };
+    if q.is_integer() {
+        return num(q, q.numer().to_string());
+    }
+    if is_decimal(q) {
+        let mut digits = 1;
+        while !q.mul(&int(10).powi(digits).unwrap()).is_integer() {
+            digits += 1;
+        }
+        return num(q, q.to_decimal(digits as usize));
+    }
+    let n = Rational::from_int(q.numer().clone());
+    let d = Rational::from_int(q.denom().clone());
+    binary(
+        line,
+        BinOp::Slash,
+        num(&n, n.to_string()),
+        num(&d, d.to_string()),
+    )
+}
+
+fn simplify_sum(e: &Expr) -> Sum {
+    let line = e.line;
+    match e.expr_type {
+        ExprType::NumLit(x, ref lit) => {
+            match Rational::parse(lit).or_else(|| Rational::from_f64(x)) {
+                Some(q) => Sum::constant(q),
+                None => Sum::atom(e.clone()),
+            }
+        }
+        ExprType::Binary(op, ref a, ref b) => {
+            let (x, y) = (simplify_sum(a), simplify_sum(b));
+            match op {
+                BinOp::Plus => x.add(y),
+                BinOp::Minus => x.add(y.neg()),
+                BinOp::Times => x.mul(y, line),
+                BinOp::Slash => {
+                    let (a, b) = (x.to_expr(line), y.to_expr(line));
+                    match x.div(y, line) {
+                        Some(q) => q,
+                        None => Sum::atom(binary(line, op, a, b)),
+                    }
+                }
+                BinOp::Exp => x.pow(y, line),
+                _ => Sum::atom(binary(line, op, x.to_expr(line), y.to_expr(line))),
+            }
+        }
+        ExprType::Unary(UnOp::Neg, ref a) => simplify_sum(a).neg(),
+        ExprType::FuncCall(ref name, ref args) => {
+            let args: Vec<Sum> = args.iter().map(simplify_sum).collect();
+            let consts: Option<Vec<Rational>> = args.iter().map(Sum::as_constant).collect();
+            if let (Some(f), Some(consts)) = (rational::builtin(name), consts) {
+                if let Some(q) = f(&consts) {
+                    return Sum::constant(q);
+                }
+            }
+            let args = args.iter().map(|arg| arg.to_expr(line)).collect();
+            Sum::atom(Expr {
+                line,
+                expr_type: ExprType::FuncCall(name.clone(), args),
+            })
+        }
+        _ => Sum::atom(simplify_children(e)),
+    }
+}
+
+/// Simplifies the parts of an expression that is not arithmetic itself.
+fn simplify_children(e: &Expr) -> Expr {
+    let all = |exprs: &[Expr]| exprs.iter().map(simplify).collect();
+    let expr_type = match e.expr_type {
+        ExprType::Unary(op, ref a) => ExprType::Unary(op, Box::new(simplify(a))),
+        ExprType::Assign(ref name, ref a) => ExprType::Assign(name.clone(), Box::new(simplify(a))),
+        ExprType::ListLit(ref items) => ExprType::ListLit(all(items)),
+        ExprType::TupleLit(ref items) => ExprType::TupleLit(all(items)),
+        ExprType::ConvertTo(ref a, ref unit) => {
+            ExprType::ConvertTo(Box::new(simplify(a)), unit.clone())
+        }
+        ExprType::Index(ref a, ref b) => {
+            ExprType::Index(Box::new(simplify(a)), Box::new(simplify(b)))
+        }
+        ExprType::Iterated(op, ref index, ref lo, ref hi, ref term) => ExprType::Iterated(
+            op,
+            index.clone(),
+            Box::new(simplify(lo)),
+            Box::new(simplify(hi)),
+            Box::new(simplify(term)),
+        ),
+        ref other => other.clone(),
+    };
+    Expr {
+        line: e.line,
+        expr_type,
+    }
+}
+
+fn unary(line: usize, op: UnOp, a: Expr) -> Expr {
+    Expr {
+        line,
+        expr_type: ExprType::Unary(op, Box::new(a)),
+    }
+}
+
+fn binary(line: usize, op: BinOp, a: Expr, b: Expr) -> Expr {
+    Expr {
+        line,
+        expr_type: ExprType::Binary(op, Box::new(a), Box::new(b)),
+    }
+}
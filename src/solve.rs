@@ -0,0 +1,333 @@
+use std::f64;
+use std::mem;
+
+use dual::Dual;
+use parser::MathParseError;
+use value::{Record, Value};
+
+/// A function of one number, given as a value, that a solver calls.
+pub(crate) type Objective<'a> = dyn FnMut(Value) -> Result<Value, MathParseError> + 'a;
+
+/// When an iterative method stops, set from a record like
+/// `{tol: 0.000001, maxiter: 50}` passed as the last argument.
+#[derive(Debug, Copy, Clone)]
+pub struct Options {
+    /// The relative accuracy to reach.
+    pub tol: f64,
+    /// The number of steps after which to give up.
+    pub max_iter: usize,
+    /// Whether to return an estimate of the error along with the result.
+    /// `None` for methods that make no such estimate, which reject the
+    /// option.
+    pub error: Option<bool>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            tol: 1e-12,
+            max_iter: 100,
+            error: None,
+        }
+    }
+}
+
+impl Options {
+    /// These options with the tolerance `tol`, which must be positive.
+    pub(crate) fn with_tol(self, tol: f64) -> Result<Self, String> {
+        if tol > 0.0 && tol.is_finite() {
+            Ok(Options { tol, ..self })
+        } else {
+            Err(String::from("tol must be positive"))
+        }
+    }
+
+    /// Reads the fields `tol`, `maxiter` and `error` of a record, keeping
+    /// these options for those left out.
+    pub(crate) fn read(self, record: &Record) -> Result<Self, String> {
+        let mut options = self;
+        for (name, val) in record.iter() {
+            let num = || {
+                val.as_num().ok_or_else(|| {
+                    format!("expected number for '{}', got {}", name, val.type_name())
+                })
+            };
+            match name {
+                "tol" => options = options.with_tol(num()?)?,
+                "maxiter" => match num()? {
+                    x if x >= 1.0 && x.fract() == 0.0 && x <= 1e9 => options.max_iter = x as usize,
+                    _ => return Err(String::from("maxiter must be a positive integer")),
+                },
+                "error" if options.error.is_some() => {
+                    let error = val.as_bool().ok_or_else(|| {
+                        format!("expected bool for 'error', got {}", val.type_name())
+                    })?;
+                    options.error = Some(error);
+                }
+                _ => return Err(format!("unknown option '{}'", name)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Where to look for a root.
+#[derive(Debug, Copy, Clone)]
+pub(crate) enum Start {
+    Guess(f64),
+    /// Two points where the function has opposite signs.
+    Bracket(f64, f64),
+}
+
+fn expect_num(val: &Value) -> Result<f64, MathParseError> {
+    val.as_num().ok_or_else(|| {
+        MathParseError::TypeError(format!(
+            "expected function to return a number, got {}",
+            val.type_name()
+        ))
+    })
+}
+
+fn at(f: &mut Objective, x: f64) -> Result<f64, MathParseError> {
+    expect_num(&f(Value::Num(x))?)
+}
+
+/// The value and derivative of `f` at `x`. The derivative is exact when
+/// `f` can be called with a dual number, and a finite difference when it
+/// calls a native function that cannot.
+fn with_derivative(f: &mut Objective, x: f64) -> Result<(f64, f64), MathParseError> {
+    match f(Value::from(Dual::variable(x, 0, 1))) {
+        Ok(Value::Dual(ref y)) => Ok((y.value(), y.deriv(0))),
+        Ok(ref y) => Ok((expect_num(y)?, 0.0)),
+        Err(_) => {
+            let h = f64::EPSILON.sqrt() * x.abs().max(1.0);
+            let y = at(f, x)?;
+            Ok((y, (at(f, x + h)? - y) / h))
+        }
+    }
+}
+
+/// Whether `a` and `b` lie on opposite sides of zero.
+fn opposite(a: f64, b: f64) -> bool {
+    (a < 0.0 && b > 0.0) || (a > 0.0 && b < 0.0)
+}
+
+/// Finds a root of `f` with Newton's method, starting from a guess or the
+/// middle of a bracket. Once the root is bracketed, either from the start
+/// or because two steps landed on opposite sides of it, Newton steps that
+/// leave the bracket or stall hand over to Brent's method, which always
+/// converges.
+pub(crate) fn find_root(
+    f: &mut Objective,
+    start: Start,
+    options: Options,
+) -> Result<f64, MathParseError> {
+    let (mut x, mut bracket) = match start {
+        Start::Guess(x) => (x, None),
+        Start::Bracket(a, b) => {
+            let (fa, fb) = (at(f, a)?, at(f, b)?);
+            if fa == 0.0 {
+                return Ok(a);
+            } else if fb == 0.0 {
+                return Ok(b);
+            } else if !opposite(fa, fb) {
+                return Err(MathParseError::NotBracketed(a, b));
+            }
+            (a + (b - a) / 2.0, Some((a, fa, b, fb)))
+        }
+    };
+    let mut last: Option<(f64, f64)> = None;
+    for _ in 0..options.max_iter {
+        let (fx, dfx) = with_derivative(f, x)?;
+        if fx == 0.0 {
+            return Ok(x);
+        }
+        bracket = match (bracket, last) {
+            // Keep the end of the bracket on the other side of the root.
+            (Some((a, fa, b, fb)), _) => Some(if opposite(fa, fx) {
+                (a, fa, x, fx)
+            } else {
+                (x, fx, b, fb)
+            }),
+            (None, Some((px, pfx))) if opposite(pfx, fx) => Some((px, pfx, x, fx)),
+            (None, _) => None,
+        };
+        let next = x - fx / dfx;
+        let outside = bracket.is_some_and(|(a, _, b, _)| next < a.min(b) || next > a.max(b));
+        if !next.is_finite() || outside {
+            break;
+        }
+        if (next - x).abs() <= options.tol * x.abs().max(1.0) {
+            return Ok(next);
+        }
+        last = Some((x, fx));
+        x = next;
+    }
+    match bracket {
+        Some((a, fa, b, fb)) => brent(f, (a, fa), (b, fb), options),
+        None => Err(MathParseError::DidNotConverge(format!(
+            "no root found from {} in {} steps",
+            match start {
+                Start::Guess(x) | Start::Bracket(x, _) => x,
+            },
+            options.max_iter
+        ))),
+    }
+}
+
+/// Brent's method for a root between `a` and `b`, where `f` has opposite
+/// signs. Combines bisection, which always converges, with secant and
+/// inverse quadratic steps, which converge quickly near the root.
+fn brent(
+    f: &mut Objective,
+    (mut a, mut fa): (f64, f64),
+    (mut b, mut fb): (f64, f64),
+    options: Options,
+) -> Result<f64, MathParseError> {
+    let (mut c, mut fc) = (a, fa);
+    let mut d = b - a;
+    let mut e = d;
+    for _ in 0..options.max_iter {
+        if !opposite(fb, fc) {
+            c = a;
+            fc = fa;
+            d = b - a;
+            e = d;
+        }
+        // `b` is the best estimate so far.
+        if fc.abs() < fb.abs() {
+            a = b;
+            fa = fb;
+            mem::swap(&mut b, &mut c);
+            mem::swap(&mut fb, &mut fc);
+        }
+        let tol = 2.0 * f64::EPSILON * b.abs() + 0.5 * options.tol * b.abs().max(1.0);
+        let m = (c - b) / 2.0;
+        if m.abs() <= tol || fb == 0.0 {
+            return Ok(b);
+        }
+        if e.abs() < tol || fa.abs() <= fb.abs() {
+            d = m;
+            e = m;
+        } else {
+            let s = fb / fa;
+            let (mut p, mut q) = if a == c {
+                (2.0 * m * s, 1.0 - s)
+            } else {
+                let q = fa / fc;
+                let r = fb / fc;
+                (
+                    s * (2.0 * m * q * (q - r) - (b - a) * (r - 1.0)),
+                    (q - 1.0) * (r - 1.0) * (s - 1.0),
+                )
+            };
+            if p > 0.0 {
+                q = -q;
+            } else {
+                p = -p;
+            }
+            if 2.0 * p < (3.0 * m * q - (tol * q).abs()).min((e * q).abs()) {
+                e = d;
+                d = p / q;
+            } else {
+                d = m;
+                e = m;
+            }
+        }
+        a = b;
+        fa = fb;
+        b += if d.abs() > tol { d } else { tol.copysign(m) };
+        fb = at(f, b)?;
+    }
+    Err(MathParseError::DidNotConverge(format!(
+        "no root found between {} and {} in {} steps",
+        a.min(c),
+        a.max(c),
+        options.max_iter
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Finds a root of `g`, counting how often it is called.
+    fn root<G: Fn(f64) -> f64>(
+        g: G,
+        start: Start,
+        options: Options,
+    ) -> (Result<f64, MathParseError>, usize) {
+        let mut calls = 0;
+        let result = find_root(
+            // Refusing dual numbers makes the derivatives finite differences.
+            &mut |x: Value| {
+                calls += 1;
+                match x {
+                    Value::Num(x) => Ok(Value::Num(g(x))),
+                    _ => Err(MathParseError::TypeError(String::from("expected float"))),
+                }
+            },
+            start,
+            options,
+        );
+        (result, calls)
+    }
+
+    #[test]
+    fn newton_from_a_guess() {
+        let (x, _) = root(|x| x * x - 2.0, Start::Guess(1.0), Options::default());
+        assert!((x.unwrap() - 2f64.sqrt()).abs() < 1e-12);
+        let (x, _) = root(|x| x.cos() - x, Start::Guess(0.0), Options::default());
+        assert!((x.unwrap() - 0.739_085_133_215_160_6).abs() < 1e-12);
+    }
+
+    #[test]
+    fn brent_within_a_bracket() {
+        // The kink at the root defeats Newton's method, but not bisection.
+        let (x, _) = root(
+            |x| (x - 1.0).abs().sqrt().copysign(x - 1.0),
+            Start::Bracket(0.0, 3.0),
+            Options::default(),
+        );
+        assert!((x.unwrap() - 1.0).abs() < 1e-9);
+        let (x, _) = root(|x| x - 2.0, Start::Bracket(2.0, 5.0), Options::default());
+        assert_eq!(x.unwrap(), 2.0);
+        match root(
+            |x| x * x + 1.0,
+            Start::Bracket(-1.0, 1.0),
+            Options::default(),
+        )
+        .0
+        {
+            Err(MathParseError::NotBracketed(a, b)) => assert_eq!((a, b), (-1.0, 1.0)),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn tolerance_and_steps() {
+        let loose = Options::default().with_tol(1e-3).unwrap();
+        let (x, few) = root(|x| x.powi(3) - 2.0, Start::Bracket(0.0, 2.0), loose);
+        assert!((x.unwrap() - 2f64.cbrt()).abs() < 1e-3);
+        let (_, many) = root(
+            |x| x.powi(3) - 2.0,
+            Start::Bracket(0.0, 2.0),
+            Options::default(),
+        );
+        assert!(few < many);
+        assert!(Options::default().with_tol(0.0).is_err());
+        assert!(Options::default().with_tol(f64::NAN).is_err());
+    }
+
+    #[test]
+    fn no_root() {
+        let options = Options {
+            max_iter: 20,
+            ..Options::default()
+        };
+        match root(|x| x * x + 1.0, Start::Guess(1.0), options).0 {
+            Err(MathParseError::DidNotConverge(_)) => {}
+            other => panic!("{:?}", other),
+        }
+    }
+}
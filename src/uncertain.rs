@@ -0,0 +1,185 @@
+use std::cell::Cell;
+use std::f64;
+use std::fmt::{self, Display};
+
+use builtins::NativeError;
+
+thread_local! {
+    static NEXT_SOURCE: Cell<u64> = const { Cell::new(0) };
+}
+
+/// A value with a Gaussian uncertainty, given as a standard deviation and
+/// propagated to first order.
+///
+/// Each `±` is an independent source of uncertainty, and a value keeps its
+/// sensitivity to each source it depends on. This way uncertainties that
+/// are correlated combine correctly: `x - x` is exactly zero, and `x * x`
+/// has twice the relative uncertainty of `x`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Uncertain {
+    value: f64,
+    /// Pairs of a source and the sensitivity to it, sorted by source.
+    terms: Vec<(u64, f64)>,
+}
+
+impl Uncertain {
+    /// A value with a new, independent uncertainty.
+    pub fn new(value: f64, stddev: f64) -> Self {
+        if stddev == 0.0 {
+            return Uncertain::exact(value);
+        }
+        let source = NEXT_SOURCE.with(|next| {
+            let source = next.get();
+            next.set(source + 1);
+            source
+        });
+        Uncertain {
+            value,
+            terms: vec![(source, stddev.abs())],
+        }
+    }
+
+    pub fn exact(value: f64) -> Self {
+        Uncertain {
+            value,
+            terms: Vec::new(),
+        }
+    }
+
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    pub fn stddev(&self) -> f64 {
+        self.terms
+            .iter()
+            .fold(0.0, |acc: f64, &(_, c)| acc.hypot(c))
+    }
+
+    /// A value with the given sensitivities to other values.
+    fn linear(value: f64, parts: &[(f64, &Uncertain)]) -> Self {
+        let mut terms: Vec<(u64, f64)> = Vec::new();
+        for &(d, u) in parts.iter() {
+            if d != 0.0 {
+                terms.extend(u.terms.iter().map(|&(source, c)| (source, d * c)));
+            }
+        }
+        terms.sort_by_key(|&(source, _)| source);
+        let mut merged: Vec<(u64, f64)> = Vec::with_capacity(terms.len());
+        for (source, c) in terms {
+            match merged.last_mut() {
+                Some(last) if last.0 == source => last.1 += c,
+                _ => merged.push((source, c)),
+            }
+        }
+        merged.retain(|&(_, c)| c != 0.0);
+        Uncertain {
+            value,
+            terms: merged,
+        }
+    }
+
+    pub fn add(&self, other: &Uncertain) -> Self {
+        Uncertain::linear(self.value + other.value, &[(1.0, self), (1.0, other)])
+    }
+
+    pub fn sub(&self, other: &Uncertain) -> Self {
+        Uncertain::linear(self.value - other.value, &[(1.0, self), (-1.0, other)])
+    }
+
+    pub fn mul(&self, other: &Uncertain) -> Self {
+        Uncertain::linear(
+            self.value * other.value,
+            &[(other.value, self), (self.value, other)],
+        )
+    }
+
+    pub fn div(&self, other: &Uncertain) -> Self {
+        let q = self.value / other.value;
+        Uncertain::linear(q, &[(1.0 / other.value, self), (-q / other.value, other)])
+    }
+
+    pub fn neg(&self) -> Self {
+        Uncertain::linear(-self.value, &[(-1.0, self)])
+    }
+
+    pub fn pow(&self, other: &Uncertain) -> Self {
+        let (x, y) = (self.value, other.value);
+        let p = x.powf(y);
+        // The sensitivity to the exponent is only needed, and only defined
+        // for a positive base, when the exponent is uncertain.
+        let dy = if other.terms.is_empty() {
+            0.0
+        } else {
+            p * x.ln()
+        };
+        Uncertain::linear(p, &[(y * x.powf(y - 1.0), self), (dy, other)])
+    }
+
+    /// Applies a numeric function, finding its sensitivity to each uncertain
+    /// argument by numerical differentiation.
+    pub fn apply<F>(func: F, args: &[Uncertain]) -> Result<Self, NativeError>
+    where
+        F: Fn(&[f64]) -> Result<f64, NativeError>,
+    {
+        let mut xs: Vec<f64> = args.iter().map(Uncertain::value).collect();
+        let y = func(&xs)?;
+        let mut derivs = Vec::with_capacity(args.len());
+        for (i, arg) in args.iter().enumerate() {
+            derivs.push(if arg.terms.is_empty() {
+                0.0
+            } else {
+                derivative(&func, &mut xs, i, y)
+            });
+        }
+        let parts: Vec<(f64, &Uncertain)> = derivs.into_iter().zip(args.iter()).collect();
+        Ok(Uncertain::linear(y, &parts))
+    }
+
+    /// Writes the value and its uncertainty with `places` digits after the
+    /// point.
+    pub fn to_fixed(&self, places: usize) -> String {
+        format!("{:.*} ± {:.*}", places, self.value, places, self.stddev())
+    }
+}
+
+/// The partial derivative of `func` in argument `i` at `xs`, where it has
+/// the value `y`. Uses a central difference, or a one-sided one at the edge
+/// of the function's domain.
+fn derivative<F>(func: &F, xs: &mut [f64], i: usize, y: f64) -> f64
+where
+    F: Fn(&[f64]) -> Result<f64, NativeError>,
+{
+    let x = xs[i];
+    let h = f64::EPSILON.cbrt() * x.abs().max(1.0);
+    let mut at = |x: f64| {
+        xs[i] = x;
+        func(xs).unwrap_or(f64::NAN)
+    };
+    let (above, below) = (at(x + h), at(x - h));
+    xs[i] = x;
+    let central = (above - below) / (2.0 * h);
+    if central.is_finite() {
+        central
+    } else if (above - y).is_finite() {
+        (above - y) / h
+    } else {
+        (y - below) / h
+    }
+}
+
+/// Shows the uncertainty to one significant digit, or two if the first is
+/// a 1, and the value to the same number of places, like `9.81 ± 0.02`.
+impl Display for Uncertain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let stddev = self.stddev();
+        if stddev == 0.0 || !stddev.is_finite() || !self.value.is_finite() {
+            return write!(f, "{} ± {}", self.value, stddev);
+        }
+        let sci = format!("{:e}", stddev);
+        let exp: i64 = sci[sci.find('e').unwrap() + 1..].parse().unwrap();
+        let digits = if sci.starts_with('1') { 2 } else { 1 };
+        let places = (digits - 1 - exp).max(0) as usize;
+        write!(f, "{}", self.to_fixed(places))
+    }
+}
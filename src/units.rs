@@ -0,0 +1,335 @@
+use std::f64;
+use std::fmt::{self, Display};
+
+/// Powers of the SI base units: metre, kilogram, second, ampere, kelvin,
+/// mole and candela.
+pub type Dims = [i32; 7];
+
+const NONE: Dims = [0, 0, 0, 0, 0, 0, 0];
+
+struct NamedUnit {
+    name: &'static str,
+    /// The size of the unit in SI base units.
+    scale: f64,
+    dims: Dims,
+    /// Whether SI prefixes can be put in front of it, as in `km`.
+    prefixed: bool,
+}
+
+macro_rules! unit {
+    ($name: expr, $scale: expr, $dims: expr) => {
+        NamedUnit {
+            name: $name,
+            scale: $scale,
+            dims: $dims,
+            prefixed: true,
+        }
+    };
+    ($name: expr, $scale: expr, $dims: expr, unprefixed) => {
+        NamedUnit {
+            name: $name,
+            scale: $scale,
+            dims: $dims,
+            prefixed: false,
+        }
+    };
+}
+
+static UNITS: &[NamedUnit] = &[
+    unit!("m", 1.0, [1, 0, 0, 0, 0, 0, 0]),
+    unit!("g", 1e-3, [0, 1, 0, 0, 0, 0, 0]),
+    unit!("s", 1.0, [0, 0, 1, 0, 0, 0, 0]),
+    unit!("A", 1.0, [0, 0, 0, 1, 0, 0, 0]),
+    unit!("K", 1.0, [0, 0, 0, 0, 1, 0, 0]),
+    unit!("mol", 1.0, [0, 0, 0, 0, 0, 1, 0]),
+    unit!("cd", 1.0, [0, 0, 0, 0, 0, 0, 1]),
+    unit!("Hz", 1.0, [0, 0, -1, 0, 0, 0, 0]),
+    unit!("N", 1.0, [1, 1, -2, 0, 0, 0, 0]),
+    unit!("Pa", 1.0, [-1, 1, -2, 0, 0, 0, 0]),
+    unit!("J", 1.0, [2, 1, -2, 0, 0, 0, 0]),
+    unit!("W", 1.0, [2, 1, -3, 0, 0, 0, 0]),
+    unit!("C", 1.0, [0, 0, 1, 1, 0, 0, 0]),
+    unit!("V", 1.0, [2, 1, -3, -1, 0, 0, 0]),
+    unit!("ohm", 1.0, [2, 1, -3, -2, 0, 0, 0]),
+    unit!("F", 1.0, [-2, -1, 4, 2, 0, 0, 0]),
+    unit!("T", 1.0, [0, 1, -2, -1, 0, 0, 0]),
+    unit!("Wb", 1.0, [2, 1, -2, -1, 0, 0, 0]),
+    unit!("H", 1.0, [2, 1, -2, -2, 0, 0, 0]),
+    unit!("L", 1e-3, [3, 0, 0, 0, 0, 0, 0]),
+    unit!("eV", 1.602_176_634e-19, [2, 1, -2, 0, 0, 0, 0]),
+    unit!("cal", 4.184, [2, 1, -2, 0, 0, 0, 0]),
+    unit!("bar", 1e5, [-1, 1, -2, 0, 0, 0, 0]),
+    unit!("min", 60.0, [0, 0, 1, 0, 0, 0, 0], unprefixed),
+    unit!("h", 3600.0, [0, 0, 1, 0, 0, 0, 0], unprefixed),
+    unit!("day", 86400.0, [0, 0, 1, 0, 0, 0, 0], unprefixed),
+    unit!("rad", 1.0, NONE, unprefixed),
+    unit!("deg", f64::consts::PI / 180.0, NONE, unprefixed),
+    unit!("in", 0.0254, [1, 0, 0, 0, 0, 0, 0], unprefixed),
+    unit!("ft", 0.3048, [1, 0, 0, 0, 0, 0, 0], unprefixed),
+    unit!("mi", 1609.344, [1, 0, 0, 0, 0, 0, 0], unprefixed),
+    unit!("lb", 0.453_592_37, [0, 1, 0, 0, 0, 0, 0], unprefixed),
+    unit!("atm", 101_325.0, [-1, 1, -2, 0, 0, 0, 0], unprefixed),
+];
+
+/// SI prefixes and their powers of ten, with `u` standing for micro.
+static PREFIXES: &[(&str, i32)] = &[
+    ("Y", 24),
+    ("Z", 21),
+    ("E", 18),
+    ("P", 15),
+    ("T", 12),
+    ("G", 9),
+    ("M", 6),
+    ("k", 3),
+    ("h", 2),
+    ("da", 1),
+    ("d", -1),
+    ("c", -2),
+    ("m", -3),
+    ("u", -6),
+    ("n", -9),
+    ("p", -12),
+    ("f", -15),
+    ("a", -18),
+    ("z", -21),
+    ("y", -24),
+];
+
+/// One of the units a compound unit is made of, like the `s` in `m/s^2`.
+#[derive(Debug, Clone, PartialEq)]
+struct Factor {
+    name: String,
+    /// The size of the unit without its prefix, in SI base units.
+    scale: f64,
+    /// The power of ten of the prefix. Prefixes are kept apart from the
+    /// scale so that converting between them is exact.
+    prefix: i32,
+    dims: Dims,
+    power: i32,
+}
+
+/// A unit of measure: a product of powers of named units, such as `km/h`
+/// or `kg m^2/s^2`. The empty product is a plain number.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Unit {
+    factors: Vec<Factor>,
+}
+
+impl Unit {
+    pub fn one() -> Self {
+        Unit {
+            factors: Vec::new(),
+        }
+    }
+
+    /// Finds a unit by name, which may start with an SI prefix. Names of
+    /// units win over prefixed names, so `Pa` is pascals and `cd` candelas.
+    pub fn lookup(name: &str) -> Option<Unit> {
+        let found = UNITS
+            .iter()
+            .find(|unit| unit.name == name)
+            .map(|unit| (unit, 0))
+            .or_else(|| {
+                PREFIXES.iter().find_map(|&(prefix, exp)| {
+                    let rest = name.strip_prefix(prefix)?;
+                    UNITS
+                        .iter()
+                        .find(|unit| unit.prefixed && unit.name == rest)
+                        .map(|unit| (unit, exp))
+                })
+            });
+        found.map(|(unit, prefix)| Unit {
+            factors: vec![Factor {
+                name: String::from(name),
+                scale: unit.scale,
+                prefix,
+                dims: unit.dims,
+                power: 1,
+            }],
+        })
+    }
+
+    pub fn is_one(&self) -> bool {
+        self.factors.is_empty()
+    }
+
+    /// The size of the unit in SI base units, as the product of the scales
+    /// with positive powers, that of those with negative powers, and a
+    /// power of ten.
+    fn scale(&self) -> (f64, f64, i32) {
+        let (mut num, mut den, mut exp) = (1.0, 1.0, 0);
+        for factor in self.factors.iter() {
+            if factor.power > 0 {
+                num *= factor.scale.powi(factor.power);
+            } else {
+                den *= factor.scale.powi(-factor.power);
+            }
+            exp += factor.prefix * factor.power;
+        }
+        (num, den, exp)
+    }
+
+    pub fn dims(&self) -> Dims {
+        let mut dims = NONE;
+        for factor in self.factors.iter() {
+            for (d, &f) in dims.iter_mut().zip(factor.dims.iter()) {
+                *d += f * factor.power;
+            }
+        }
+        dims
+    }
+
+    pub fn is_dimensionless(&self) -> bool {
+        self.dims() == NONE
+    }
+
+    /// Whether a quantity in this unit is just a number, because the unit
+    /// is empty or a ratio of units of the same dimension like `km/m`.
+    /// Dimensionless units like `deg` that are kept on purpose do not
+    /// count.
+    pub fn cancels(&self) -> bool {
+        self.is_one()
+            || (self.is_dimensionless() && self.factors.iter().any(|factor| factor.dims != NONE))
+    }
+
+    pub fn mul(&self, other: &Unit) -> Unit {
+        let mut factors = self.factors.clone();
+        for factor in other.factors.iter() {
+            match factors.iter_mut().find(|f| f.name == factor.name) {
+                Some(f) => f.power += factor.power,
+                None => factors.push(factor.clone()),
+            }
+        }
+        factors.retain(|factor| factor.power != 0);
+        Unit { factors }
+    }
+
+    pub fn div(&self, other: &Unit) -> Unit {
+        self.mul(&other.powi(-1))
+    }
+
+    pub fn powi(&self, n: i32) -> Unit {
+        self.powf(f64::from(n)).unwrap()
+    }
+
+    /// The unit to a power, if every power in it stays an integer, as with
+    /// `sqrt` of an area.
+    pub fn powf(&self, n: f64) -> Option<Unit> {
+        let mut factors = Vec::with_capacity(self.factors.len());
+        for factor in self.factors.iter() {
+            let power = f64::from(factor.power) * n;
+            if power.fract() != 0.0 || power.abs() > f64::from(i32::MAX) {
+                return None;
+            }
+            if power != 0.0 {
+                factors.push(Factor {
+                    power: power as i32,
+                    ..factor.clone()
+                });
+            }
+        }
+        Some(Unit { factors })
+    }
+
+    /// Describes the unit for error messages.
+    pub fn describe(&self) -> String {
+        if self.is_one() {
+            String::from("a plain number")
+        } else {
+            format!("'{}'", self)
+        }
+    }
+}
+
+/// Writes units the way they are written in formulas, as in `kg m^2/s^2`.
+/// A unit with only negative powers is written like `s^-1`.
+impl Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let has_numerator = self.factors.iter().any(|factor| factor.power > 0);
+        if self.is_one() {
+            return write!(f, "1");
+        }
+        let numerator = self
+            .factors
+            .iter()
+            .filter(|factor| factor.power > 0 || !has_numerator);
+        for (i, factor) in numerator.enumerate() {
+            if i > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}", factor.name)?;
+            if factor.power != 1 {
+                write!(f, "^{}", factor.power)?;
+            }
+        }
+        if has_numerator {
+            for factor in self.factors.iter().filter(|factor| factor.power < 0) {
+                write!(f, "/{}", factor.name)?;
+                if factor.power != -1 {
+                    write!(f, "^{}", -factor.power)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A number in a unit, like `3 m`.
+#[derive(Debug, Clone)]
+pub struct Quantity {
+    pub value: f64,
+    pub unit: Unit,
+}
+
+impl Quantity {
+    pub fn new(value: f64, unit: Unit) -> Self {
+        Quantity { value, unit }
+    }
+
+    /// The value in SI base units.
+    pub fn to_si(&self) -> f64 {
+        self.convert(&Unit::one())
+    }
+
+    /// The value in another unit, or `None` if the units measure different
+    /// things.
+    pub fn value_in(&self, unit: &Unit) -> Option<f64> {
+        if self.unit == *unit {
+            Some(self.value)
+        } else if self.unit.dims() == unit.dims() {
+            Some(self.convert(unit))
+        } else {
+            None
+        }
+    }
+
+    /// Converts the value to a unit of the same dimension, dividing only
+    /// once so that conversions like `1.5 m/s` to `km/h` come out exact.
+    fn convert(&self, unit: &Unit) -> f64 {
+        let (from_num, from_den, from_exp) = self.unit.scale();
+        let (to_num, to_den, to_exp) = unit.scale();
+        let mut num = self.value * from_num * to_den;
+        let mut den = from_den * to_num;
+        let exp = from_exp - to_exp;
+        if exp > 0 {
+            num *= 10f64.powi(exp);
+        } else {
+            den *= 10f64.powi(-exp);
+        }
+        num / den
+    }
+}
+
+/// Quantities are equal if they are the same amount of the same dimension,
+/// so `1 km == 1000 m`.
+impl PartialEq for Quantity {
+    fn eq(&self, other: &Quantity) -> bool {
+        self.value_in(&other.unit) == Some(other.value)
+    }
+}
+
+impl Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.value, self.unit)
+    }
+}
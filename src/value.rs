@@ -0,0 +1,766 @@
+use std::cmp::Ordering;
+use std::fmt::{self, Display};
+use std::rc::Rc;
+
+use ast::{BinOp, UnOp};
+use bigint::BigInt;
+use builtins::NativeFn;
+use complex::Complex;
+use decimal::Decimal;
+use eval::UserFn;
+use interval::Interval;
+use intrinsics::Intrinsic;
+use number::Number;
+use parser::MathParseError;
+use rational::Rational;
+use uncertain::Uncertain;
+use dual::Dual;
+use units::{Quantity, Unit};
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    Nil,
+    Num(f64),
+    Complex(Complex),
+    /// An exact number, from exact mode.
+    Rational(Rc<Rational>),
+    /// A base-10 number, from decimal mode.
+    Decimal(Rc<Decimal>),
+    /// An interval with outward rounding, from `interval(lo, hi)`.
+    Interval(Interval),
+    /// A value with an uncertainty, from `x ± e`.
+    Uncertain(Rc<Uncertain>),
+    /// A number with a unit, like `3 m`.
+    Quantity(Rc<Quantity>),
+    /// A number with its derivatives, passed to functions by `deriv` and
+    /// `grad`.
+    Dual(Rc<Dual>),
+    Bool(bool),
+    Str(Rc<str>),
+    List(Rc<Vec<Value>>),
+    Tuple(Rc<Vec<Value>>),
+    Record(Rc<Record>),
+    Func(Function),
+}
+
+/// Named fields, kept in the order they were first set.
+#[derive(Debug, Clone, Default)]
+pub struct Record {
+    fields: Vec<(String, Value)>,
+}
+
+impl Record {
+    pub fn new() -> Self {
+        Record { fields: Vec::new() }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.fields
+            .iter()
+            .find(|field| field.0 == name)
+            .map(|field| &field.1)
+    }
+
+    /// Sets a field, adding it if it is not there yet.
+    pub fn set(&mut self, name: &str, val: Value) {
+        match self.fields.iter_mut().find(|field| field.0 == name) {
+            Some(field) => field.1 = val,
+            None => self.fields.push((String::from(name), val)),
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.fields.iter().map(|field| field.0.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.fields.iter().map(|field| (field.0.as_str(), &field.1))
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+}
+
+/// Records are equal if they have the same fields, in any order.
+impl PartialEq for Record {
+    fn eq(&self, other: &Record) -> bool {
+        self.len() == other.len() && self.iter().all(|(name, val)| other.get(name) == Some(val))
+    }
+}
+
+#[derive(Clone)]
+pub enum Function {
+    Native(Rc<str>, Rc<NativeFn>),
+    User(Rc<str>, Rc<UserFn>),
+    Intrinsic(&'static Intrinsic),
+}
+
+impl Function {
+    pub fn name(&self) -> &str {
+        match self {
+            Function::Native(name, _) | Function::User(name, _) => name,
+            Function::Intrinsic(intrinsic) => intrinsic.name,
+        }
+    }
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<fn {}>", self.name())
+    }
+}
+
+impl PartialEq for Function {
+    fn eq(&self, other: &Function) -> bool {
+        match (self, other) {
+            (Function::Native(_, a), Function::Native(_, b)) => Rc::ptr_eq(a, b),
+            (Function::User(_, a), Function::User(_, b)) => Rc::ptr_eq(a, b),
+            (Function::Intrinsic(a), Function::Intrinsic(b)) => a.name == b.name,
+            _ => false,
+        }
+    }
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nil => "nil",
+            Value::Num(_) => "number",
+            Value::Complex(_) => "complex",
+            Value::Rational(_) => "rational",
+            Value::Decimal(_) => "decimal",
+            Value::Interval(_) => "interval",
+            Value::Uncertain(_) => "uncertain",
+            Value::Quantity(_) => "quantity",
+            Value::Dual(_) => "dual",
+            Value::Bool(_) => "bool",
+            Value::Str(_) => "string",
+            Value::List(_) => "list",
+            Value::Tuple(_) => "tuple",
+            Value::Record(_) => "record",
+            Value::Func(_) => "function",
+        }
+    }
+
+    pub fn as_num(&self) -> Option<f64> {
+        match *self {
+            Value::Num(x) => Some(x),
+            Value::Rational(ref q) => Some(q.to_f64()),
+            Value::Decimal(ref d) => Some(d.to_f64()),
+            Value::Quantity(ref q) if q.unit.is_dimensionless() => Some(q.to_si()),
+            _ => None,
+        }
+    }
+
+    /// The value as a quantity, where real numbers have no unit.
+    pub fn as_quantity(&self) -> Option<Quantity> {
+        match self {
+            Value::Quantity(q) => Some(Quantity::clone(q)),
+            v => v.as_num().map(|x| Quantity::new(x, Unit::one())),
+        }
+    }
+
+    /// Converts an exact number to the nearest float, leaving other values
+    /// as they are.
+    pub fn to_inexact(&self) -> Value {
+        match self {
+            Value::Rational(_) | Value::Decimal(_) => Value::Num(self.as_num().unwrap()),
+            v => v.clone(),
+        }
+    }
+
+    /// The integer `n` as the same kind of exact or decimal number as this
+    /// value, or `None` for other values.
+    pub fn same_kind_int(&self, n: i64) -> Option<Value> {
+        match self {
+            Value::Rational(_) => Some(Value::from(Rational::from_int(BigInt::from(n)))),
+            Value::Decimal(d) => Some(Value::from(Decimal::from_int(BigInt::from(n), d.context()))),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Value::Bool(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    /// The items of a list or tuple.
+    pub fn as_items(&self) -> Option<&[Value]> {
+        match self {
+            Value::List(items) | Value::Tuple(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn is_nil(&self) -> bool {
+        matches!(*self, Value::Nil)
+    }
+
+    /// Like `as_num`, but reports a type error.
+    pub fn expect_num(&self) -> Result<f64, MathParseError> {
+        self.as_num().ok_or_else(|| {
+            MathParseError::TypeError(format!("expected number, got {}", self.type_name()))
+        })
+    }
+
+    pub fn expect_bool(&self) -> Result<bool, MathParseError> {
+        self.as_bool().ok_or_else(|| {
+            MathParseError::TypeError(format!("expected bool, got {}", self.type_name()))
+        })
+    }
+
+    /// Formats the value the way it appears inside a list or record, with
+    /// strings quoted.
+    fn fmt_nested(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Str(s) => write!(f, "{:?}", s),
+            v => write!(f, "{}", v),
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Num(a), Value::Num(b)) => a == b,
+            (Value::Complex(a), Value::Complex(b)) => a == b,
+            (&Value::Num(a), &Value::Complex(b)) | (&Value::Complex(b), &Value::Num(a)) => {
+                b == Complex::from_real(a)
+            }
+            (Value::Rational(a), Value::Rational(b)) => a == b,
+            (Value::Decimal(a), Value::Decimal(b)) => a == b,
+            (Value::Interval(a), Value::Interval(b)) => a == b,
+            (Value::Uncertain(a), Value::Uncertain(b)) => a == b,
+            // Dual numbers compare by value, so `x == 0` can pick a branch.
+            (Value::Dual(_), _) | (_, Value::Dual(_)) => {
+                match (Dual::from_value(self), Dual::from_value(other)) {
+                    (Some(a), Some(b)) => a.value() == b.value(),
+                    _ => false,
+                }
+            }
+            (Value::Quantity(_), _) | (_, Value::Quantity(_)) => {
+                match (self.as_quantity(), other.as_quantity()) {
+                    (Some(a), Some(b)) => a == b,
+                    _ => false,
+                }
+            }
+            (Value::Decimal(d), Value::Rational(q)) | (Value::Rational(q), Value::Decimal(d)) => {
+                d.to_rational() == **q
+            }
+            (Value::Rational(_) | Value::Decimal(_), Value::Num(_) | Value::Complex(_))
+            | (Value::Num(_) | Value::Complex(_), Value::Rational(_) | Value::Decimal(_)) => {
+                self.to_inexact() == other.to_inexact()
+            }
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Str(a), Value::Str(b)) => a == b,
+            (Value::List(a), Value::List(b)) => a == b,
+            (Value::Tuple(a), Value::Tuple(b)) => a == b,
+            (Value::Record(a), Value::Record(b)) => a == b,
+            (Value::Func(a), Value::Func(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Nil => write!(f, "nil"),
+            Value::Num(x) => write!(f, "{}", x),
+            Value::Complex(z) => write!(f, "{}", z),
+            Value::Rational(q) => write!(f, "{}", q),
+            Value::Decimal(d) => write!(f, "{}", d),
+            Value::Interval(x) => write!(f, "{}", x),
+            Value::Uncertain(u) => write!(f, "{}", u),
+            Value::Quantity(q) => write!(f, "{}", q),
+            Value::Dual(x) => write!(f, "{}", x),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Str(s) => write!(f, "{}", s),
+            Value::List(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    item.fmt_nested(f)?;
+                }
+                write!(f, "]")
+            }
+            Value::Tuple(items) => {
+                write!(f, "(")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    item.fmt_nested(f)?;
+                }
+                if items.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")
+            }
+            Value::Record(record) => {
+                write!(f, "{{")?;
+                for (i, (name, val)) in record.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: ", name)?;
+                    val.fmt_nested(f)?;
+                }
+                write!(f, "}}")
+            }
+            Value::Func(func) => write!(f, "<fn {}>", func.name()),
+        }
+    }
+}
+
+impl From<f64> for Value {
+    fn from(x: f64) -> Self {
+        Value::Num(x)
+    }
+}
+
+impl From<Complex> for Value {
+    fn from(z: Complex) -> Self {
+        Value::Complex(z)
+    }
+}
+
+impl From<Rational> for Value {
+    fn from(q: Rational) -> Self {
+        Value::Rational(Rc::new(q))
+    }
+}
+
+impl From<Decimal> for Value {
+    fn from(d: Decimal) -> Self {
+        Value::Decimal(Rc::new(d))
+    }
+}
+
+impl From<Interval> for Value {
+    fn from(x: Interval) -> Self {
+        Value::Interval(x)
+    }
+}
+
+impl From<Dual> for Value {
+    fn from(x: Dual) -> Self {
+        Value::Dual(Rc::new(x))
+    }
+}
+
+impl From<Uncertain> for Value {
+    fn from(u: Uncertain) -> Self {
+        Value::Uncertain(Rc::new(u))
+    }
+}
+
+/// Quantities whose units cancel out, like `km/m`, become plain numbers.
+impl From<Quantity> for Value {
+    fn from(q: Quantity) -> Self {
+        if q.unit.cancels() {
+            Value::Num(q.to_si())
+        } else {
+            Value::Quantity(Rc::new(q))
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl<'a> From<&'a str> for Value {
+    fn from(s: &'a str) -> Self {
+        Value::Str(Rc::from(s))
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::Str(Rc::from(s))
+    }
+}
+
+impl From<Record> for Value {
+    fn from(record: Record) -> Self {
+        Value::Record(Rc::new(record))
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(items: Vec<Value>) -> Self {
+        Value::List(Rc::new(items))
+    }
+}
+
+fn type_error_binary(op: BinOp, a: &Value, b: &Value) -> MathParseError {
+    MathParseError::TypeError(format!(
+        "cannot apply '{}' to {} and {}",
+        op.symbol(),
+        a.type_name(),
+        b.type_name()
+    ))
+}
+
+pub fn binary_op(op: BinOp, a: &Value, b: &Value) -> Result<Value, MathParseError> {
+    match op {
+        BinOp::Equal => return Ok(Value::Bool(a == b)),
+        BinOp::NotEqual => return Ok(Value::Bool(a != b)),
+        BinOp::PlusMinus => return plus_minus(a, b),
+        _ => (),
+    }
+    match (a, b) {
+        (Value::Str(x), Value::Str(y)) => match op {
+            BinOp::Plus => {
+                let mut s = String::with_capacity(x.len() + y.len());
+                s.push_str(x);
+                s.push_str(y);
+                Ok(Value::from(s))
+            }
+            BinOp::Less => Ok(Value::Bool(x < y)),
+            BinOp::LessEqual => Ok(Value::Bool(x <= y)),
+            BinOp::Greater => Ok(Value::Bool(x > y)),
+            BinOp::GreaterEqual => Ok(Value::Bool(x >= y)),
+            _ => Err(type_error_binary(op, a, b)),
+        },
+        (Value::List(xs), Value::List(ys)) if op == BinOp::Plus => {
+            let mut items = Vec::with_capacity(xs.len() + ys.len());
+            items.extend(xs.iter().cloned());
+            items.extend(ys.iter().cloned());
+            Ok(Value::from(items))
+        }
+        (Value::Quantity(_), _) | (_, Value::Quantity(_)) => quantity_op(op, a, b),
+        (Value::Interval(_), _) | (_, Value::Interval(_)) => {
+            match (Interval::from_value(a), Interval::from_value(b)) {
+                (Some(x), Some(y)) => number_op(op, a, b, &x, &y),
+                _ => Err(type_error_binary(op, a, b)),
+            }
+        }
+        (Value::Uncertain(_), _) | (_, Value::Uncertain(_)) => {
+            match (Uncertain::from_value(a), Uncertain::from_value(b)) {
+                (Some(x), Some(y)) => number_op(op, a, b, &x, &y),
+                _ => Err(type_error_binary(op, a, b)),
+            }
+        }
+        (Value::Dual(_), _) | (_, Value::Dual(_)) => {
+            match (Dual::from_value(a), Dual::from_value(b)) {
+                (Some(x), Some(y)) => number_op(op, a, b, &x, &y),
+                _ => Err(type_error_binary(op, a, b)),
+            }
+        }
+        (Value::Rational(x), Value::Rational(y)) => number_op(op, a, b, &**x, &**y),
+        (Value::Decimal(x), Value::Decimal(y)) => number_op(op, a, b, &**x, &**y),
+        (Value::Decimal(x), Value::Rational(q)) => {
+            number_op(op, a, b, &**x, &Decimal::from_rational(q, x.context()))
+        }
+        (Value::Rational(q), Value::Decimal(y)) => {
+            number_op(op, a, b, &Decimal::from_rational(q, y.context()), &**y)
+        }
+        (Value::Rational(_) | Value::Decimal(_), Value::Num(_) | Value::Complex(_))
+        | (Value::Num(_) | Value::Complex(_), Value::Rational(_) | Value::Decimal(_)) => {
+            binary_op(op, &a.to_inexact(), &b.to_inexact())
+        }
+        (&Value::Complex(_), &Value::Num(_) | &Value::Complex(_))
+        | (&Value::Num(_), &Value::Complex(_)) => {
+            let (z, w) = (
+                Complex::from_value(a).unwrap(),
+                Complex::from_value(b).unwrap(),
+            );
+            number_op(op, a, b, &z, &w)
+        }
+        (&Value::Num(x), &Value::Num(y)) => number_op(op, a, b, &x, &y),
+        _ => Err(type_error_binary(op, a, b)),
+    }
+}
+
+/// Arithmetic on two numbers of the same kind, the values `a` and `b`.
+/// Powers with no result of that kind, such as non-integer powers of
+/// rationals, are taken in floating point, and numbers that cannot be
+/// ordered, like complex ones, cannot be compared.
+fn number_op<N: Number>(
+    op: BinOp,
+    a: &Value,
+    b: &Value,
+    x: &N,
+    y: &N,
+) -> Result<Value, MathParseError> {
+    let order = match op {
+        BinOp::Less | BinOp::LessEqual | BinOp::Greater | BinOp::GreaterEqual if !N::ORDERED => {
+            return Err(type_error_binary(op, a, b))
+        }
+        BinOp::Less => [Ordering::Less, Ordering::Less],
+        BinOp::LessEqual => [Ordering::Less, Ordering::Equal],
+        BinOp::Greater => [Ordering::Greater, Ordering::Greater],
+        BinOp::GreaterEqual => [Ordering::Greater, Ordering::Equal],
+        BinOp::Plus => return Ok(x.add(y).into_value()),
+        BinOp::Minus => return Ok(x.sub(y).into_value()),
+        BinOp::Times => return Ok(x.mul(y).into_value()),
+        BinOp::Slash => return Ok(x.div(y)?.into_value()),
+        BinOp::Exp => {
+            return Ok(match x.pow(y)? {
+                Some(z) => z.into_value(),
+                None => Value::Num(x.to_f64().powf(y.to_f64())),
+            })
+        }
+        BinOp::Equal | BinOp::NotEqual | BinOp::PlusMinus => unreachable!(),
+    };
+    Ok(Value::Bool(
+        x.compare(y).is_some_and(|ord| order.contains(&ord)),
+    ))
+}
+
+/// Arithmetic where either side has a unit. Sums and comparisons need
+/// units of the same dimension, and sums are given in the unit of the left
+/// side. Powers of quantities must keep integer powers of units, so
+/// `(4 m^2)^0.5` is `2 m` but `(2 m)^0.5` is an error.
+fn quantity_op(op: BinOp, a: &Value, b: &Value) -> Result<Value, MathParseError> {
+    let (x, y) = match (a.as_quantity(), b.as_quantity()) {
+        (Some(x), Some(y)) => (x, y),
+        _ => return Err(type_error_binary(op, a, b)),
+    };
+    let mismatch = || MathParseError::DimensionMismatch(x.unit.describe(), y.unit.describe());
+    match op {
+        BinOp::Times => Ok(Value::from(Quantity::new(
+            x.value * y.value,
+            x.unit.mul(&y.unit),
+        ))),
+        BinOp::Slash => Ok(Value::from(Quantity::new(
+            x.value / y.value,
+            x.unit.div(&y.unit),
+        ))),
+        BinOp::Exp => {
+            let n = b.as_num().ok_or_else(|| {
+                MathParseError::TypeError(format!(
+                    "exponent must be a plain number, got {}",
+                    y.unit.describe()
+                ))
+            })?;
+            if let Some(base) = a.as_num() {
+                return Ok(Value::Num(base.powf(n)));
+            }
+            match x.unit.powf(n) {
+                Some(unit) => Ok(Value::from(Quantity::new(x.value.powf(n), unit))),
+                None => Err(MathParseError::TypeError(format!(
+                    "cannot raise '{}' to the power {}",
+                    x.unit, n
+                ))),
+            }
+        }
+        _ => {
+            let y = y.value_in(&x.unit).ok_or_else(mismatch)?;
+            match op {
+                BinOp::Plus => Ok(Value::from(Quantity::new(x.value + y, x.unit))),
+                BinOp::Minus => Ok(Value::from(Quantity::new(x.value - y, x.unit))),
+                _ => number_op(op, a, b, &x.value, &y),
+            }
+        }
+    }
+}
+
+/// `x ± e` is `x` with an added independent uncertainty `e`.
+fn plus_minus(a: &Value, b: &Value) -> Result<Value, MathParseError> {
+    let err = match b.as_num() {
+        Some(err) => Uncertain::new(0.0, err),
+        None => return Err(type_error_binary(BinOp::PlusMinus, a, b)),
+    };
+    match Uncertain::from_value(a) {
+        Some(x) => Ok(Value::from(x.add(&err))),
+        None => Err(type_error_binary(BinOp::PlusMinus, a, b)),
+    }
+}
+
+pub fn unary_op(op: UnOp, a: &Value) -> Result<Value, MathParseError> {
+    match (op, a) {
+        (UnOp::Neg, &Value::Num(x)) => Ok(Value::Num(-x)),
+        (UnOp::Neg, &Value::Complex(z)) => Ok(Value::Complex(-z)),
+        (UnOp::Neg, Value::Rational(q)) => Ok(Value::from(q.neg())),
+        (UnOp::Neg, Value::Decimal(d)) => Ok(Value::from(d.neg())),
+        (UnOp::Neg, &Value::Interval(x)) => Ok(Value::from(x.neg())),
+        (UnOp::Neg, Value::Uncertain(u)) => Ok(Value::from(u.neg())),
+        (UnOp::Neg, Value::Dual(x)) => Ok(Value::from(x.neg())),
+        (UnOp::Neg, Value::Quantity(q)) => {
+            Ok(Value::Quantity(Rc::new(Quantity::new(-q.value, q.unit.clone()))))
+        }
+        (UnOp::Not, &Value::Bool(b)) => Ok(Value::Bool(!b)),
+        _ => Err(MathParseError::TypeError(format!(
+            "cannot apply '{}' to {}",
+            op.symbol(),
+            a.type_name()
+        ))),
+    }
+}
+
+/// Orders two values for sorting. Numbers other than NaN can be ordered, as
+/// can strings. Intervals can be ordered when they do not overlap, and
+/// uncertain values are ordered by their nominal values, and dual numbers
+/// by their values. Quantities can be ordered if they have the same
+/// dimension.
+pub fn compare(a: &Value, b: &Value) -> Result<Ordering, MathParseError> {
+    match (a, b) {
+        (Value::Quantity(_), _) | (_, Value::Quantity(_)) => {
+            match (a.as_quantity(), b.as_quantity()) {
+                (Some(x), Some(y)) => {
+                    let y = y.value_in(&x.unit).ok_or_else(|| {
+                        MathParseError::DimensionMismatch(x.unit.describe(), y.unit.describe())
+                    })?;
+                    compare(&Value::Num(x.value), &Value::Num(y))
+                }
+                _ => Err(MathParseError::TypeError(format!(
+                    "cannot order {} and {}",
+                    a.type_name(),
+                    b.type_name()
+                ))),
+            }
+        }
+        (Value::Interval(_), _) | (_, Value::Interval(_)) => compare_as::<Interval>(a, b),
+        (Value::Uncertain(_), _) | (_, Value::Uncertain(_)) => compare_as::<Uncertain>(a, b),
+        (Value::Dual(_), _) | (_, Value::Dual(_)) => compare_as::<Dual>(a, b),
+        (Value::Rational(x), Value::Rational(y)) => Ok(x.cmp(y)),
+        (Value::Decimal(x), Value::Decimal(y)) => Ok(x.cmp(y)),
+        (
+            Value::Num(_) | Value::Rational(_) | Value::Decimal(_),
+            Value::Num(_) | Value::Rational(_) | Value::Decimal(_),
+        ) => a
+            .expect_num()?
+            .partial_cmp(&b.expect_num()?)
+            .ok_or_else(|| MathParseError::TypeError(String::from("cannot order NaN"))),
+        (Value::Str(x), Value::Str(y)) => Ok(x.cmp(y)),
+        _ => Err(MathParseError::TypeError(format!(
+            "cannot order {} and {}",
+            a.type_name(),
+            b.type_name()
+        ))),
+    }
+}
+
+fn compare_as<N: Number>(a: &Value, b: &Value) -> Result<Ordering, MathParseError> {
+    match (N::from_value(a), N::from_value(b)) {
+        (Some(x), Some(y)) => x.compare(&y),
+        _ => None,
+    }
+    .ok_or_else(|| {
+        MathParseError::TypeError(format!(
+            "cannot order {} and {}",
+            a.type_name(),
+            b.type_name()
+        ))
+    })
+}
+
+fn expect_integer(index: &Value) -> Result<i64, MathParseError> {
+    let x = index.expect_num()?;
+    if x.fract() != 0.0 {
+        return Err(MathParseError::TypeError(format!(
+            "index must be an integer, got {}",
+            x
+        )));
+    }
+    Ok(x as i64)
+}
+
+/// Converts a possibly negative index into an offset into `len` items.
+fn resolve_index(index: &Value, len: usize) -> Result<usize, MathParseError> {
+    let i = expect_integer(index)?;
+    let offset = if i < 0 { i + len as i64 } else { i };
+    if offset < 0 || offset >= len as i64 {
+        Err(MathParseError::IndexOutOfBounds(i, len))
+    } else {
+        Ok(offset as usize)
+    }
+}
+
+/// Like `resolve_index` for a slice bound, but out of range bounds are
+/// clamped to the list instead of being an error.
+fn resolve_bound(bound: &Value, len: usize) -> Result<usize, MathParseError> {
+    let i = expect_integer(bound)?;
+    let offset = if i < 0 { i + len as i64 } else { i };
+    Ok(offset.max(0).min(len as i64) as usize)
+}
+
+/// `a.name`.
+pub fn field(a: &Value, name: &str) -> Result<Value, MathParseError> {
+    match a {
+        Value::Record(record) => record
+            .get(name)
+            .cloned()
+            .ok_or_else(|| MathParseError::NoSuchField(String::from(name))),
+        _ => Err(MathParseError::TypeError(format!(
+            "cannot get field '{}' of {}",
+            name,
+            a.type_name()
+        ))),
+    }
+}
+
+/// `a.path = val`, giving the updated copy of `a`. The last field in the
+/// path is added if it is missing.
+pub fn with_field(a: &Value, path: &[String], val: Value) -> Result<Value, MathParseError> {
+    let mut record = match a {
+        Value::Record(record) => Record::clone(record),
+        _ => {
+            return Err(MathParseError::TypeError(format!(
+                "cannot set field '{}' of {}",
+                path[0],
+                a.type_name()
+            )))
+        }
+    };
+    let val = if path.len() == 1 {
+        val
+    } else {
+        with_field(&field(a, &path[0])?, &path[1..], val)?
+    };
+    record.set(&path[0], val);
+    Ok(Value::from(record))
+}
+
+fn expect_items(a: &Value) -> Result<&[Value], MathParseError> {
+    a.as_items()
+        .ok_or_else(|| MathParseError::TypeError(format!("cannot index into {}", a.type_name())))
+}
+
+/// `a[index]`, where negative indices count from the end. Records can be
+/// indexed by field name.
+pub fn index(a: &Value, index: &Value) -> Result<Value, MathParseError> {
+    if let (Value::Record(_), Value::Str(name)) = (a, index) {
+        return field(a, name);
+    }
+    let items = expect_items(a)?;
+    Ok(items[resolve_index(index, items.len())?].clone())
+}
+
+/// `a[start:end]`, where missing bounds default to the start and end of the
+/// list or tuple. As in Python, bounds past either end select up to that end.
+pub fn slice(
+    a: &Value,
+    start: Option<&Value>,
+    end: Option<&Value>,
+) -> Result<Value, MathParseError> {
+    let items = expect_items(a)?;
+    let start = match start {
+        Some(start) => resolve_bound(start, items.len())?,
+        None => 0,
+    };
+    let end = match end {
+        Some(end) => resolve_bound(end, items.len())?,
+        None => items.len(),
+    };
+    let items = items[start..end.max(start)].to_vec();
+    match a {
+        Value::Tuple(_) => Ok(Value::Tuple(Rc::new(items))),
+        _ => Ok(Value::from(items)),
+    }
+}
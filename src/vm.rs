@@ -1,6 +1,10 @@
-use std::mem::{size_of, transmute};
-use std::ptr::copy_nonoverlapping;
-use std::ops::Deref;
+use std::io::{self, Write};
+use std::mem::size_of;
+use builtins::{NativeError, BUILTINS};
+use format;
+use intrinsics;
+use parser::MathParseError;
+use value::Value;
 
 pub const NOP: u8 = 0x00;
 pub const ADD_F64: u8 = 0x01;
@@ -31,6 +35,25 @@ pub const RET: u8 = 0x19;
 pub const POP_64_U8: u8 = 0x1A;
 pub const EXIT: u8 = 0x1B;
 pub const RET_F64: u8 = 0x1C;
+pub const POW_F64: u8 = 0x1D;
+pub const CALL_BUILTIN: u8 = 0x1E;
+pub const ENTER: u8 = 0x1F;
+pub const LEAVE_F64: u8 = 0x20;
+pub const NEG_F64: u8 = 0x21;
+pub const CONST_STR: u8 = 0x22;
+pub const CONCAT_STR: u8 = 0x23;
+pub const PRINT_STR: u8 = 0x24;
+pub const FORMAT: u8 = 0x25;
+pub const LEN_STR: u8 = 0x26;
+pub const CHECK_BOUNDS_F64: u8 = 0x27;
+pub const JUMP_GT_F64: u8 = 0x28;
+pub const JUMP_BACK: u8 = 0x29;
+
+/// The kinds of `format` arguments.
+pub const KIND_F64: u8 = 0;
+pub const KIND_STR: u8 = 1;
+
+const USIZE_SIZE: usize = size_of::<usize>();
 
 pub struct VM<'p> {
     program: &'p [u8],
@@ -38,61 +61,89 @@ pub struct VM<'p> {
     iptr: usize,
     len: usize,
     ctx: usize,
+    /// Strings are kept here and referred to on the stack by their index,
+    /// stored like an f64. The program's string constants come first.
+    strings: Vec<String>,
+    out: Box<dyn Write + 'p>,
 }
 
 impl<'p> VM<'p> {
-    pub fn new(program: &'p [u8]) -> Self {
+    pub fn new(program: &'p [u8], strings: &[String]) -> Self {
+        VM::with_output(program, strings, Box::new(io::stdout()))
+    }
+
+    /// Creates a VM that prints to `out` instead of stdout.
+    pub fn with_output(program: &'p [u8], strings: &[String], out: Box<dyn Write + 'p>) -> Self {
         VM {
             program,
             stack: Vec::new(),
             iptr: 0,
             len: program.len(),
             ctx: 0,
+            strings: strings.to_vec(),
+            out,
         }
     }
 
     fn peek_f64(&self) -> f64 {
         let len = self.stack.len();
-        let (_, last8) = self.stack.split_at(len - 8);
-        unsafe {
-            return *(last8.as_ptr() as *const f64);
-        }
+        read_f64(&self.stack[len - 8..])
     }
 
     fn pop_f64(&mut self) -> f64 {
         let len = self.stack.len();
-        let last8 = self.stack.split_off(len - 8);
-        unsafe {
-            return *(last8.as_ptr() as *const f64);
-        }
+        let x = read_f64(&self.stack[len - 8..]);
+        self.stack.truncate(len - 8);
+        x
     }
 
     fn pop_usize(&mut self) -> usize {
         let len = self.stack.len();
-        let last = self.stack.split_off(len - size_of::<usize>());
-        unsafe {
-            return *(last.as_ptr() as *const usize);
-        }
+        let x = read_usize(&self.stack[len - USIZE_SIZE..]);
+        self.stack.truncate(len - USIZE_SIZE);
+        x
     }
 
     fn push_f64(&mut self, x: f64) {
-        let bytes: [u8; 8] = unsafe { transmute(x) };
-        self.stack.extend_from_slice(&bytes);
+        self.stack.extend_from_slice(&x.to_ne_bytes());
     }
 
     fn push_usize(&mut self, x: usize) {
-        let bytes: [u8; size_of::<usize>()] = unsafe { transmute(x) };
-        self.stack.extend_from_slice(&bytes);
+        self.stack.extend_from_slice(&x.to_ne_bytes());
     }
 
-    fn push_f64_get_ref(&mut self, x: f64) -> &mut f64 {
-        let bytes: [u8; 8] = unsafe { transmute(x) };
-        self.stack.extend_from_slice(&bytes);
-        let len = self.stack.len();
-        unsafe { (&mut self.stack[len - 1] as *mut u8 as *mut f64).as_mut() }.unwrap()
+    fn pop_str(&mut self) -> &str {
+        let i = self.pop_f64() as usize;
+        &self.strings[i]
+    }
+
+    fn push_str(&mut self, s: String) {
+        self.strings.push(s);
+        let i = self.strings.len() - 1;
+        self.push_f64(i as f64);
     }
 
-    pub fn run(&mut self) {
+    /// Reads the `i`th f64 slot of the current context.
+    fn load_slot(&self, i: usize) -> f64 {
+        let offset = self.ctx + i * 8;
+        read_f64(&self.stack[offset..offset + 8])
+    }
+
+    fn store_slot(&mut self, i: usize, x: f64) {
+        let offset = self.ctx + i * 8;
+        self.stack[offset..offset + 8].copy_from_slice(&x.to_ne_bytes());
+    }
+
+    fn read_u8_operand(&mut self) -> u8 {
+        self.iptr += 1;
+        self.program[self.iptr]
+    }
+
+    fn read_usize_operand(&self) -> usize {
+        read_usize(&self.program[self.iptr + 1..self.iptr + 1 + USIZE_SIZE])
+    }
+
+    pub fn run(&mut self) -> Result<(), MathParseError> {
         loop {
             if self.iptr >= self.len {
                 break;
@@ -120,17 +171,20 @@ impl<'p> VM<'p> {
                     self.push_f64(a / b);
                 }
                 CONST_F64 => {
-                    self.iptr = self.iptr + 1;
+                    self.iptr += 1;
                     let bytes = &self.program[self.iptr..self.iptr + 8];
                     self.stack.extend_from_slice(bytes);
-                    self.iptr = self.iptr + 7;
+                    self.iptr += 7;
+                }
+                PRINT_F64 => {
+                    let x = self.peek_f64();
+                    writeln!(self.out, "{}", x).expect("failed printing to output");
                 }
-                PRINT_F64 => println!("{}", self.peek_f64()),
                 POP_F64 => {
                     self.pop_f64();
                 }
                 CONST_U8 => {
-                    self.iptr = self.iptr + 1;
+                    self.iptr += 1;
                     self.stack.push(self.program[self.iptr]);
                 }
                 POP_U8 => {
@@ -152,85 +206,59 @@ impl<'p> VM<'p> {
                     self.stack.extend_from_slice(&[0; 8]);
                 }
                 LOAD_0_F64 => {
-                    let len = self.stack.len();
-                    self.stack.reserve(8);
-                    unsafe {
-                        self.stack.set_len(len + 8);
-                        *transmute::<_, *mut f64>(&mut self.stack[len]) =
-                            *transmute::<_, *const f64>(&self.stack[self.ctx]);
-                    }
+                    let x = self.load_slot(0);
+                    self.push_f64(x);
                 }
                 LOAD_1_F64 => {
-                    let len = self.stack.len();
-                    self.stack.reserve(8);
-                    unsafe {
-                        self.stack.set_len(len + 8);
-                        *transmute::<_, *mut f64>(&mut self.stack[len]) =
-                            *transmute::<_, *const f64>(&self.stack[self.ctx]).offset(1);
-                    }
+                    let x = self.load_slot(1);
+                    self.push_f64(x);
                 }
                 LOAD_2_F64 => {
-                    let len = self.stack.len();
-                    self.stack.reserve(8);
-                    unsafe {
-                        self.stack.set_len(len + 8);
-                        *transmute::<_, *mut f64>(&mut self.stack[len]) =
-                            *transmute::<_, *const f64>(&self.stack[self.ctx]).offset(2);
-                    }
+                    let x = self.load_slot(2);
+                    self.push_f64(x);
                 }
                 LOAD_F64_U8 => {
-                    self.iptr = self.iptr + 1;
-                    let len = self.stack.len();
-                    self.stack.reserve(8);
-                    unsafe {
-                        self.stack.set_len(len + 8);
-                        *transmute::<_, *mut f64>(&mut self.stack[len]) =
-                            *transmute::<_, *const f64>(&self.stack[self.ctx])
-                                .offset(self.program[self.iptr] as isize);
-                    }
+                    let i = self.read_u8_operand();
+                    let x = self.load_slot(i as usize);
+                    self.push_f64(x);
+                }
+                STORE_0_F64 => {
+                    let x = self.peek_f64();
+                    self.store_slot(0, x);
+                }
+                STORE_1_F64 => {
+                    let x = self.peek_f64();
+                    self.store_slot(1, x);
+                }
+                STORE_2_F64 => {
+                    let x = self.peek_f64();
+                    self.store_slot(2, x);
                 }
-                STORE_0_F64 => unsafe {
-                    *transmute::<_, *mut f64>(&mut self.stack[self.ctx]) = self.peek_f64();
-                },
-                STORE_1_F64 => unsafe {
-                    *transmute::<_, *mut f64>(&mut self.stack[self.ctx]).offset(1) =
-                        self.peek_f64();
-                },
-                STORE_2_F64 => unsafe {
-                    *transmute::<_, *mut f64>(&mut self.stack[self.ctx]).offset(2) =
-                        self.peek_f64();
-                },
                 STORE_F64_U8 => {
-                    self.iptr = self.iptr + 1;
-                    unsafe {
-                        *transmute::<_, *mut f64>(&mut self.stack[self.ctx])
-                            .offset(self.program[self.iptr] as isize) = self.peek_f64();
-                    }
+                    let i = self.read_u8_operand();
+                    let x = self.peek_f64();
+                    self.store_slot(i as usize, x);
                 }
                 ZERO_64 => {
-                    let num =
-                        unsafe { *transmute::<_, *const usize>(&self.program[self.iptr + 1]) };
-                    self.iptr = self.iptr + size_of::<usize>();
+                    let num = self.read_usize_operand();
+                    self.iptr += USIZE_SIZE;
                     let len = self.stack.len();
                     self.stack.resize(len + num * 8, 0);
                 }
                 ZERO_64_U8 => {
-                    self.iptr += 1;
+                    let num = self.read_u8_operand() as usize;
                     let len = self.stack.len();
-                    self.stack
-                        .resize(len + (self.program[self.iptr] * 8) as usize, 0);
+                    self.stack.resize(len + num * 8, 0);
                 }
                 POP_64_U8 => {
-                    self.iptr += 1;
+                    let num = self.read_u8_operand() as usize;
                     let len = self.stack.len();
-                    self.stack
-                        .truncate(len - (self.program[self.iptr] * 8) as usize);
+                    self.stack.truncate(len - num * 8);
                 }
                 CALL => {
-                    let oldiptr = self.iptr + size_of::<usize>();
+                    let oldiptr = self.iptr + USIZE_SIZE;
                     self.push_usize(oldiptr);
-                    self.iptr =
-                        unsafe { *transmute::<_, *const usize>(&self.program[self.iptr + 1]) - 1};
+                    self.iptr = self.read_usize_operand() - 1;
                 }
                 RET => {
                     self.iptr = self.pop_usize();
@@ -241,14 +269,128 @@ impl<'p> VM<'p> {
                     self.iptr = self.pop_usize();
                     self.push_f64(val);
                 }
+                POW_F64 => {
+                    let b = self.pop_f64();
+                    let a = self.pop_f64();
+                    self.push_f64(a.powf(b));
+                }
+                NEG_F64 => {
+                    let a = self.pop_f64();
+                    self.push_f64(-a);
+                }
+                CALL_BUILTIN => {
+                    let builtin = &BUILTINS[self.read_u8_operand() as usize];
+                    let argc = self.read_u8_operand() as usize;
+                    let mut args = vec![0.0; argc];
+                    for arg in args.iter_mut().rev() {
+                        *arg = self.pop_f64();
+                    }
+                    let res = (builtin.func)(&args);
+                    self.push_f64(res);
+                }
+                ENTER => {
+                    let argc = self.read_u8_operand() as usize;
+                    let nlocals = self.read_u8_operand() as usize;
+                    let ret = self.pop_usize();
+                    let args_start = self.stack.len() - argc * 8;
+                    let args = self.stack.split_off(args_start);
+                    self.push_usize(ret);
+                    let old = self.ctx;
+                    self.push_usize(old);
+                    self.ctx = self.stack.len();
+                    self.stack.extend_from_slice(&args);
+                    let len = self.stack.len();
+                    self.stack.resize(len + nlocals * 8, 0);
+                }
+                LEAVE_F64 => {
+                    let nslots = self.read_u8_operand() as usize;
+                    let val = self.pop_f64();
+                    let len = self.stack.len();
+                    self.stack.truncate(len - nslots * 8);
+                    self.ctx = self.pop_usize();
+                    self.iptr = self.pop_usize();
+                    self.push_f64(val);
+                }
+                CONST_STR => {
+                    let i = self.read_usize_operand();
+                    self.iptr += USIZE_SIZE;
+                    self.push_f64(i as f64);
+                }
+                CONCAT_STR => {
+                    let b = self.pop_str().to_owned();
+                    let a = self.pop_str().to_owned();
+                    self.push_str(a + &b);
+                }
+                PRINT_STR => {
+                    let i = self.peek_f64() as usize;
+                    writeln!(self.out, "{}", self.strings[i]).expect("failed printing to output");
+                }
+                FORMAT => {
+                    let argc = self.read_u8_operand() as usize;
+                    let mut args = vec![Value::Nil; argc];
+                    for (i, arg) in args.iter_mut().enumerate().rev() {
+                        *arg = match self.program[self.iptr + 1 + i] {
+                            KIND_STR => Value::from(self.pop_str()),
+                            _ => Value::Num(self.pop_f64()),
+                        };
+                    }
+                    self.iptr += argc;
+                    let res = format::format(self.pop_str(), &args).map_err(|msg| {
+                        MathParseError::NativeFunctionError(
+                            String::from("format"),
+                            NativeError::InvalidArgument(0, msg),
+                        )
+                    })?;
+                    self.push_str(res);
+                }
+                LEN_STR => {
+                    let len = self.pop_str().chars().count();
+                    self.push_f64(len as f64);
+                }
+                CHECK_BOUNDS_F64 => {
+                    let name = if self.read_u8_operand() == MUL_F64 {
+                        "prod"
+                    } else {
+                        "sum"
+                    };
+                    let len = self.stack.len();
+                    let lo = read_f64(&self.stack[len - 16..]);
+                    let hi = self.peek_f64();
+                    intrinsics::count_terms(name, lo, hi)?;
+                }
+                JUMP_GT_F64 => {
+                    let b = self.pop_f64();
+                    let a = self.pop_f64();
+                    if a > b {
+                        self.iptr += self.read_usize_operand();
+                        continue;
+                    }
+                    self.iptr += USIZE_SIZE;
+                }
+                JUMP_BACK => {
+                    self.iptr -= self.read_usize_operand();
+                    continue;
+                }
                 c => panic!("Unsupported opcode: {:X}", c),
             }
-            self.iptr = self.iptr + 1;
-            println!("{:?}", self.stack);
+            self.iptr += 1;
         }
+        Ok(())
     }
 }
 
+fn read_f64(bytes: &[u8]) -> f64 {
+    let mut buf = [0; 8];
+    buf.copy_from_slice(&bytes[..8]);
+    f64::from_ne_bytes(buf)
+}
+
+fn read_usize(bytes: &[u8]) -> usize {
+    let mut buf = [0; USIZE_SIZE];
+    buf.copy_from_slice(&bytes[..USIZE_SIZE]);
+    usize::from_ne_bytes(buf)
+}
+
 // Opcodes:
 // 0x00 - nop
 // 0x01 - add_f64 - Stack: [a: f64, b: f64] -> [a + b : f64]
@@ -278,4 +420,49 @@ impl<'p> VM<'p> {
 // 0x19 - ret
 // 0x1A - pop_64_u8 <n: u8>
 // 0x1B - exit
-// 0x1C - ret_f64
\ No newline at end of file
+// 0x1C - ret_f64
+// 0x1D - pow_f64 - Stack: [a: f64, b: f64] -> [a ^ b : f64]
+// 0x1E - call_builtin <i: u8> <argc: u8> - Stack: [args: f64 * argc] -> [builtins::BUILTINS[i](args) : f64]
+// 0x1F - enter <argc: u8> <nlocals: u8> - Stack: [args: f64 * argc, ret: usize] -> [ret: usize, ctx: usize | args: f64 * argc, 0: f64 * nlocals]
+// 0x20 - leave_f64 <nslots: u8> - undoes enter, keeps the result and returns to ret
+// 0x21 - neg_f64 - Stack: [a: f64] -> [-a : f64]
+// 0x22 - const_str <i: usize> - Stack: [] -> [string constant i : str]
+// 0x23 - concat_str - Stack: [a: str, b: str] -> [a + b : str]
+// 0x24 - print_str - print top string on stack
+// 0x25 - format <argc: u8> <kinds: u8 * argc> - Stack: [fmt: str, args * argc] -> [formatted : str]
+// 0x26 - len_str - Stack: [s: str] -> [number of chars in s : f64]
+// 0x27 - check_bounds_f64 <combine: u8> - Stack: [lo: f64, hi: f64] -> [lo: f64, hi: f64], an error unless both are finite and the loop that combines terms with the instruction combine has at most intrinsics::MAX_RANGE of them
+// 0x28 - jump_gt_f64 <n: usize> - Stack: [a: f64, b: f64] -> [], then jumps n bytes ahead of this instruction if a > b
+// 0x29 - jump_back <n: usize> - jumps n bytes back from this instruction
+
+#[cfg(test)]
+mod tests {
+    use super::VM;
+    use compiler;
+    use eval::EvalContext;
+    use parser;
+
+    fn run(source: &str) -> f64 {
+        let block = parser::parse_file(source).unwrap();
+        let program = compiler::compile(&block).unwrap();
+        let mut vm = VM::new(program.bytes(), &[]);
+        vm.run().unwrap();
+        vm.peek_f64()
+    }
+
+    #[test]
+    fn builtins_agree_with_evaluator() {
+        for source in &[
+            "sin(1) + cos(2) * tan(0.5)",
+            "log(2, 1024) - log2(8)",
+            "max(1, 5, 3) + min(4, 0 - 2)",
+            "hypot(3, 4) ^ 2",
+            "clamp(sign(0 - 3) * 10, 0 - 1, 1)",
+            "atan2(1, 2) / sqrt(2)",
+        ] {
+            let block = parser::parse_file(source).unwrap();
+            let expected = EvalContext::new().eval_file(&block).unwrap().expect_num().unwrap();
+            assert_eq!(run(source), expected, "{}", source);
+        }
+    }
+}
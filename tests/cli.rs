@@ -0,0 +1,48 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+fn script(name: &str, contents: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("raffa_parser_{}_{}.txt", name, std::process::id()));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+fn raffa_parser(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_raffa_parser"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn runs_a_file_on_the_vm() {
+    let path = script("vm", "def f(x) { x * 2 }\nprint(f(21))\n");
+    let out = raffa_parser(&[path.to_str().unwrap()]);
+    fs::remove_file(&path).unwrap();
+    assert!(out.status.success());
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "42\n");
+}
+
+#[test]
+fn evaluates_a_file() {
+    let path = script("eval", "def f(x) { x * 2 }\nf(21)\n");
+    let out = raffa_parser(&["--eval", path.to_str().unwrap()]);
+    fs::remove_file(&path).unwrap();
+    assert!(out.status.success());
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "42\n");
+}
+
+#[test]
+fn reports_errors() {
+    let path = script("error", "nope(1)\n");
+    let out = raffa_parser(&[path.to_str().unwrap()]);
+    fs::remove_file(&path).unwrap();
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stderr).starts_with("Error: "));
+
+    let out = raffa_parser(&["--bogus"]);
+    assert_eq!(out.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&out.stderr).starts_with("Usage: "));
+}
@@ -0,0 +1,25 @@
+// Each test crate uses only some of these helpers.
+#![allow(dead_code)]
+
+use raffa_parser::{compile, parse_file, EvalContext, EvalResult, MathParseError};
+
+/// Evaluates `source` as a file with the tree-walking evaluator.
+pub fn eval(source: &str) -> EvalResult {
+    EvalContext::new().eval_file(&parse_file(source)?)
+}
+
+/// Compiles `source` and runs it on the VM, returning what it printed.
+pub fn run(source: &str) -> Result<String, MathParseError> {
+    let program = compile(&parse_file(source)?)?;
+    let mut out = Vec::new();
+    program.run_with_output(&mut out)?;
+    Ok(String::from_utf8(out).unwrap())
+}
+
+/// Checks that the VM prints what the evaluator returns for `expr`, after
+/// running the definitions in `prelude`.
+pub fn assert_agree(prelude: &str, expr: &str) {
+    let expected = eval(&format!("{}\n{}", prelude, expr)).unwrap();
+    let printed = run(&format!("{}\nprint({})", prelude, expr)).unwrap();
+    assert_eq!(printed, format!("{}\n", expected), "{}", expr);
+}
@@ -0,0 +1,133 @@
+extern crate raffa_parser;
+
+mod common;
+
+use common::run;
+use raffa_parser::{
+    parse_file, parse_repl, Arity, CompiledExpr, Complex, EvalContext, MathParseError, NativeError,
+    NativeFn, Rational, Value,
+};
+
+fn context(prelude: &str) -> EvalContext {
+    let mut ctx = EvalContext::new();
+    ctx.eval_file(&parse_file(prelude).unwrap()).unwrap();
+    ctx
+}
+
+/// Checks that `expr` gives the same value for each `x` through the
+/// evaluator, a `CompiledExpr` and the VM.
+fn assert_agree(prelude: &str, expr: &str, xs: &[f64]) {
+    let mut ctx = context(prelude);
+    let compiled = CompiledExpr::parse(&ctx, expr, &["x"]).unwrap();
+    for &x in xs.iter() {
+        ctx.set_var("x", x);
+        let expected = ctx.eval_repltree(&parse_repl(expr).unwrap()).unwrap();
+        assert_eq!(
+            Value::Num(compiled.eval(&[x])),
+            expected,
+            "{} at x = {}",
+            expr,
+            x
+        );
+        let printed = run(&format!("{}\nx = {}\nprint({})", prelude, x, expr)).unwrap();
+        assert_eq!(printed, format!("{}\n", expected), "{} at x = {}", expr, x);
+    }
+}
+
+#[test]
+fn agrees_with_evaluator_and_vm() {
+    let prelude = "
+        def square(x) { x * x }
+        def f(x, y) {
+            t = square(x) - y
+            t / 2 + sin(t)
+        }
+    ";
+    let xs = [0.0, 0.5, 2.0, 3.25];
+    assert_agree(prelude, "x ^ 3 - 2 * x + 1", &xs);
+    assert_agree(prelude, "f(x, 1) * square(x + 1)", &xs);
+    assert_agree(prelude, "max(x, 1, cos(x)) + hypot(x, 2)", &xs);
+}
+
+#[test]
+fn sum_and_prod_agree() {
+    let prelude = "def square(x) { x * x }";
+    let ns = [-1.0, 0.0, 1.0, 5.0, 10.5];
+    assert_agree(prelude, "sum(i, 1, x, i^2)", &ns);
+    assert_agree(prelude, "prod(k, 2, x, 1 - 1/k^2)", &ns);
+    assert_agree(prelude, "sum(i, 0.5, x, square(i)) / 2", &ns);
+    // The index hides `x` in the term only.
+    assert_agree(prelude, "sum(x, 1, x, x) + x", &ns);
+}
+
+#[test]
+fn globals_are_snapshotted() {
+    let mut ctx = context("k = 2");
+    let compiled = CompiledExpr::parse(&ctx, "k * x", &["x"]).unwrap();
+    ctx.set_var("k", 10.0);
+    assert_eq!(compiled.eval(&[3.0]), 6.0);
+    assert_eq!(compiled.num_params(), 1);
+}
+
+#[test]
+fn compile_errors() {
+    let ctx = context("def f(a) { a }");
+    match CompiledExpr::<f64>::parse(&ctx, "y + 1", &["x"]) {
+        Err(MathParseError::UnknownIdentifier(ref name)) if name == "y" => {}
+        Err(err) => panic!("{:?}", err),
+        Ok(_) => panic!("compiled"),
+    }
+    match CompiledExpr::<f64>::parse(&ctx, "f(x, x)", &["x"]) {
+        Err(MathParseError::WrongNumberOfArguments(1, 2)) => {}
+        Err(err) => panic!("{:?}", err),
+        Ok(_) => panic!("compiled"),
+    }
+}
+
+#[test]
+fn failing_constant_calls_are_left_for_evaluation() {
+    let mut ctx = EvalContext::new();
+    ctx.register_fn("checked_sqrt", Arity::Fixed(1), |args| {
+        if args[0] < 0.0 {
+            Err(NativeError::InvalidArgument(0, String::from("negative")))
+        } else {
+            Ok(args[0].sqrt())
+        }
+    })
+    .set_pure(true);
+    let compiled = CompiledExpr::parse(&ctx, "x * checked_sqrt(0 - 4)", &["x"]).unwrap();
+    match compiled.try_eval(&[1.0]) {
+        Err(MathParseError::NativeFunctionError(ref name, _)) if name == "checked_sqrt" => {}
+        other => panic!("{:?}", other),
+    }
+    assert!(compiled.eval(&[1.0]).is_nan());
+
+    let folded = CompiledExpr::parse(&ctx, "x * checked_sqrt(4)", &["x"]).unwrap();
+    assert_eq!(folded.eval(&[3.0]), 6.0);
+}
+
+#[test]
+fn value_natives_are_not_compiled() {
+    let mut ctx = EvalContext::new();
+    ctx.register_native(
+        "first",
+        NativeFn::new(Arity::Fixed(1), |args| Ok(args[0].clone())),
+    );
+    match CompiledExpr::<f64>::parse(&ctx, "first(x)", &["x"]) {
+        Err(MathParseError::UnsupportedByCompiler(_)) => {}
+        Err(err) => panic!("{:?}", err),
+        Ok(_) => panic!("compiled"),
+    }
+}
+
+#[test]
+fn other_kinds_of_numbers() {
+    let ctx = context("");
+    let compiled = CompiledExpr::<Rational>::parse(&ctx, "x / 3 + 1 / 6", &["x"]).unwrap();
+    let one = Rational::parse("1").unwrap();
+    assert_eq!(compiled.try_eval(&[one]).unwrap().to_string(), "1/2");
+
+    let compiled = CompiledExpr::<Complex>::parse(&ctx, "sqrt(x)", &["x"]).unwrap();
+    let root = compiled.try_eval(&[Complex::from_real(-4.0)]).unwrap();
+    assert_eq!(root, Complex::new(0.0, 2.0));
+}
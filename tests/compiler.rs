@@ -0,0 +1,74 @@
+extern crate raffa_parser;
+
+mod common;
+
+use common::{assert_agree, eval, run};
+use raffa_parser::{MathParseError, Value};
+
+#[test]
+fn variables_and_functions() {
+    let prelude = "
+        def square(x) { x * x }
+        def hyp(a, b) { sqrt(square(a) + square(b)) }
+        def poly(x) {
+            y = x + 1
+            y * y - x
+        }
+        r = 3
+    ";
+    assert_agree(prelude, "hyp(r, 4)");
+    assert_agree(prelude, "poly(r) / 2");
+    assert_agree(prelude, "square(poly(2)) - r ^ 2");
+}
+
+#[test]
+fn print() {
+    assert_eq!(run("x = 2\nprint(x)\nprint(x ^ 10)").unwrap(), "2\n1024\n");
+}
+
+#[test]
+fn functions_can_call_later_functions() {
+    let source = "def f(x) { g(x) + 1 }\ndef g(x) { x * 2 }\n";
+    assert_eq!(eval(&format!("{}f(3)", source)).unwrap(), Value::Num(7.0));
+    assert_eq!(run(&format!("{}print(f(3))", source)).unwrap(), "7\n");
+}
+
+#[test]
+fn compile_errors() {
+    match run("nope(1)") {
+        Err(MathParseError::UnknownIdentifier(ref name)) if name == "nope" => {}
+        other => panic!("{:?}", other),
+    }
+    match run("def f(x) { x }\nf(1, 2)") {
+        Err(MathParseError::WrongNumberOfArguments(1, 2)) => {}
+        other => panic!("{:?}", other),
+    }
+    match run("def f(x) { x }\ndef f(y) { y }") {
+        Err(MathParseError::UnsupportedByCompiler(_)) => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+fn assignments(n: usize) -> String {
+    (0..n).map(|i| format!("v{} = {}\n", i, i)).collect()
+}
+
+#[test]
+fn at_most_255_variables() {
+    let source = assignments(255) + "print(v254)";
+    assert_eq!(run(&source).unwrap(), "254\n");
+    match run(&assignments(256)) {
+        Err(MathParseError::UnsupportedByCompiler(_)) => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn at_most_255_arguments() {
+    let args = |n: usize| vec!["1"; n].join(", ");
+    assert_eq!(run(&format!("print(max({}))", args(255))).unwrap(), "1\n");
+    match run(&format!("max({})", args(256))) {
+        Err(MathParseError::UnsupportedByCompiler(_)) => {}
+        other => panic!("{:?}", other),
+    }
+}
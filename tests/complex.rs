@@ -0,0 +1,68 @@
+extern crate raffa_parser;
+
+mod common;
+
+use common::eval;
+use raffa_parser::{parse_file, Complex, EvalContext, MathParseError, Value};
+
+fn eval_complex(source: &str) -> Result<Value, MathParseError> {
+    let mut ctx = EvalContext::new();
+    ctx.set_complex_mode(true);
+    ctx.eval_file(&parse_file(source).unwrap())
+}
+
+fn show(val: Value) -> String {
+    val.to_string()
+}
+
+#[test]
+fn imaginary_literals() {
+    assert_eq!(eval("2i").unwrap(), Value::from(Complex::new(0.0, 2.0)));
+    assert_eq!(show(eval("1 + 2i").unwrap()), "1+2i");
+    assert_eq!(show(eval("(1 + 2i) * (3 - 1i)").unwrap()), "5+5i");
+    assert_eq!(show(eval("1i ^ 2").unwrap()), "-1+0i");
+    assert_eq!(show(eval("conj(1 + 2i)").unwrap()), "1-2i");
+    assert_eq!(eval("abs(3 + 4i)").unwrap(), Value::Num(5.0));
+    assert_eq!(eval("re(3 + 4i) + im(3 + 4i)").unwrap(), Value::Num(7.0));
+    // An `i` that starts a word is not an imaginary unit.
+    assert_eq!(eval("def id(x) { x }\nid(2)").unwrap(), Value::Num(2.0));
+    match eval("(1 + 2i) < 3") {
+        Err(MathParseError::TypeError(_)) => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn complex_mode() {
+    assert!(eval("sqrt(-4)").unwrap().as_num().unwrap().is_nan());
+    assert_eq!(show(eval_complex("sqrt(-4)").unwrap()), "0+2i");
+    assert_eq!(
+        show(eval_complex("ln(-1)").unwrap()),
+        format!("0+{}i", std::f64::consts::PI)
+    );
+    match eval_complex("(-8) ^ (1 / 3)").unwrap() {
+        Value::Complex(z) => {
+            assert!((z.re - 1.0).abs() < 1e-12);
+            assert!((z.im - 3f64.sqrt()).abs() < 1e-12);
+        }
+        other => panic!("{:?}", other),
+    }
+    // Real results stay real.
+    assert_eq!(
+        eval_complex("sqrt(4) + 2 ^ 0.5 * 0").unwrap(),
+        Value::Num(2.0)
+    );
+    // Builtins with no complex version still give NaN.
+    assert_eq!(
+        eval_complex("acos(2) == acos(2)").unwrap(),
+        Value::Bool(false)
+    );
+}
+
+#[test]
+fn mode_survives_reset() {
+    let mut ctx = EvalContext::new();
+    ctx.set_complex_mode(true);
+    ctx.reset();
+    assert!(ctx.complex_mode());
+}
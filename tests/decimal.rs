@@ -0,0 +1,54 @@
+extern crate raffa_parser;
+
+use raffa_parser::{parse_file, DecimalContext, EvalContext, EvalResult, Rounding, Value};
+
+fn eval_decimal(ctx: DecimalContext, source: &str) -> EvalResult {
+    let mut context = EvalContext::new();
+    context.set_decimal_mode(Some(ctx));
+    context.eval_file(&parse_file(source)?)
+}
+
+fn show(source: &str) -> String {
+    eval_decimal(DecimalContext::default(), source)
+        .unwrap()
+        .to_string()
+}
+
+fn show_with(precision: usize, rounding: Rounding, source: &str) -> String {
+    eval_decimal(DecimalContext::new(precision, rounding), source)
+        .unwrap()
+        .to_string()
+}
+
+#[test]
+fn literals_are_decimal() {
+    assert_eq!(
+        eval_decimal(DecimalContext::default(), "0.1 + 0.2 == 0.3").unwrap(),
+        Value::Bool(true)
+    );
+    assert_eq!(show("0.1 + 0.2"), "0.3");
+    assert_eq!(show("1.10 * 3"), "3.3");
+    assert_eq!(show("1/3"), "0.3333333333333333333333333333");
+}
+
+#[test]
+fn precision_and_rounding() {
+    assert_eq!(show_with(5, Rounding::HalfEven, "2/3"), "0.66667");
+    assert_eq!(show_with(5, Rounding::Truncate, "2/3"), "0.66666");
+    assert_eq!(show_with(1, Rounding::HalfEven, "0.25 * 10"), "2");
+    assert_eq!(show_with(1, Rounding::HalfUp, "0.25 * 10"), "3");
+    assert_eq!(show_with(3, Rounding::HalfEven, "1234 + 0"), "1230");
+}
+
+#[test]
+fn builtins() {
+    assert_eq!(show("sqrt(2.25)"), "1.5");
+    assert_eq!(show("abs(-1.5) + floor(2.7)"), "3.5");
+    assert_eq!(show("max(0.1, 0.25)"), "0.25");
+    // Builtins without a decimal version give floats.
+    assert_eq!(
+        eval_decimal(DecimalContext::default(), "sin(0)").unwrap(),
+        Value::Num(0.0)
+    );
+    assert_eq!(show("sum([0.1, 0.1, 0.1])"), "0.3");
+}
@@ -0,0 +1,74 @@
+extern crate raffa_parser;
+
+mod common;
+
+use common::eval;
+use raffa_parser::{parse_file, EvalContext, MathParseError, Value};
+
+fn num(source: &str) -> f64 {
+    match eval(source) {
+        Ok(Value::Num(x)) => x,
+        other => panic!("{}: {:?}", source, other),
+    }
+}
+
+#[test]
+fn derivatives() {
+    assert_eq!(num("def f(x) { x^3 + 2 * x }\nderiv(f, 2)"), 14.0);
+    assert_eq!(num("deriv(sin, 0)"), 1.0);
+    assert_eq!(num("def f(x) { exp(2 * x) }\nderiv(f, 0)"), 2.0);
+    // Branches and loops are followed along the path taken.
+    assert_eq!(
+        num("def f(x) { if x < 0 { -x } else { x^2 } }\nderiv(f, -3)"),
+        -1.0
+    );
+    assert_eq!(
+        num("def f(x) { if x < 0 { -x } else { x^2 } }\nderiv(f, 3)"),
+        6.0
+    );
+    assert_eq!(num("def p(x, n) { if n == 0 { 1 } else { x * p(x, n - 1) } }\ndef f(x) { p(x, 4) }\nderiv(f, 2)"), 32.0);
+    // A function that ignores its argument is flat.
+    assert_eq!(num("def f(x) { 7 }\nderiv(f, 1)"), 0.0);
+}
+
+#[test]
+fn powers_of_zero() {
+    assert_eq!(num("def f(x) { 0^x }\nderiv(f, 1)"), 0.0);
+    assert_eq!(num("def f(x) { x^0 }\nderiv(f, 0)"), 0.0);
+    assert_eq!(num("def f(x) { x^2 }\nderiv(f, 0)"), 0.0);
+}
+
+#[test]
+fn gradients() {
+    assert_eq!(
+        eval("def f(x, y) { x^2 * y + y }\ngrad(f, [3, 2])")
+            .unwrap()
+            .to_string(),
+        "[12, 10]"
+    );
+    assert_eq!(
+        eval("grad(hypot, [3, 4])").unwrap().to_string(),
+        "[0.6, 0.8]"
+    );
+}
+
+#[test]
+fn from_rust() {
+    let mut ctx = EvalContext::new();
+    ctx.eval_file(&parse_file("def f(x, y) { x * y }").unwrap())
+        .unwrap();
+    assert_eq!(ctx.deriv("sin", 0.0).unwrap(), 1.0);
+    assert_eq!(ctx.grad("f", &[2.0, 5.0]).unwrap(), vec![5.0, 2.0]);
+    match ctx.deriv("nope", 0.0) {
+        Err(MathParseError::UnknownIdentifier(_)) => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn errors() {
+    match eval("def f(x) { [x] }\nderiv(f, 1)") {
+        Err(MathParseError::TypeError(_)) => {}
+        other => panic!("{:?}", other),
+    }
+}
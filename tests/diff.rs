@@ -0,0 +1,75 @@
+extern crate raffa_parser;
+
+mod common;
+
+use common::eval;
+use raffa_parser::{MathParseError, NativeError, Value};
+
+/// The derivative with respect to `x` of `formula`, after the definitions
+/// in `prelude`.
+fn diff(prelude: &str, formula: &str) -> String {
+    match eval(&format!("{}\ndiff(\"{}\", \"x\")", prelude, formula)) {
+        Ok(Value::Str(s)) => s.to_string(),
+        other => panic!("{}: {:?}", formula, other),
+    }
+}
+
+#[test]
+fn sums_and_constants() {
+    assert_eq!(diff("", "x + 3"), "1 + 0");
+    assert_eq!(diff("", "x - y"), "1 - 0");
+    assert_eq!(diff("", "-x"), "-1");
+    assert_eq!(diff("", "y ^ 2"), "0");
+}
+
+#[test]
+fn products_and_quotients() {
+    assert_eq!(diff("", "x * sin(x)"), "1 * sin(x) + x * (cos(x) * 1)");
+    assert_eq!(diff("", "1 / x"), "(0 * x - 1 * 1) / x^2");
+}
+
+#[test]
+fn powers() {
+    assert_eq!(diff("", "x^2"), "2 * x^1 * 1");
+    assert_eq!(diff("", "2^x"), "2^x * ln(2) * 1");
+    assert_eq!(diff("", "x^x"), "x^x * (1 * ln(x) + x * 1 / x)");
+}
+
+#[test]
+fn chain_rule() {
+    assert_eq!(diff("", "sin(x^2)"), "cos(x^2) * (2 * x^1 * 1)");
+    assert_eq!(diff("", "exp(2 * x)"), "exp(2 * x) * (0 * x + 2 * 1)");
+}
+
+#[test]
+fn defs_are_inlined() {
+    assert_eq!(diff("def f(t) { t^3 }", "f(x) + y"), "3 * x^2 * 1 + 0");
+    assert_eq!(
+        diff("def f(t) { t^3 }\ndef g(t) { 2 * f(t) }", "g(x)"),
+        "0 * f(x) + 2 * (3 * x^2 * 1)"
+    );
+    // A recursive function cannot be inlined.
+    match eval("def g(t) { g(t) }\ndiff(\"g(x)\", \"x\")") {
+        Err(MathParseError::CannotDifferentiate(_)) => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn errors() {
+    match eval("diff(\"x < 1\", \"x\")") {
+        Err(MathParseError::CannotDifferentiate(ref e)) if e == "x < 1" => {}
+        other => panic!("{:?}", other),
+    }
+    for (source, index) in &[
+        ("diff(\"x +\", \"x\")", 0),
+        ("diff(1, \"x\")", 0),
+        ("diff(\"x\", 1)", 1),
+    ] {
+        match eval(source) {
+            Err(MathParseError::NativeFunctionError(_, NativeError::InvalidArgument(i, _)))
+                if i == *index => {}
+            other => panic!("{}: {:?}", source, other),
+        }
+    }
+}
@@ -0,0 +1,70 @@
+extern crate raffa_parser;
+
+use raffa_parser::{parse_file, EvalContext, EvalResult, MathParseError, NativeError, Value};
+
+fn eval_exact(source: &str) -> EvalResult {
+    let mut context = EvalContext::new();
+    context.set_exact_mode(true);
+    context.eval_file(&parse_file(source)?)
+}
+
+fn show(source: &str) -> String {
+    eval_exact(source).unwrap().to_string()
+}
+
+#[test]
+fn fractions_stay_exact() {
+    assert_eq!(show("1/3 + 1/6"), "1/2");
+    assert_eq!(show("0.1 + 0.2"), "3/10");
+    assert_eq!(show("-(2/4)"), "-1/2");
+    assert_eq!(show("(2/3)^3"), "8/27");
+    assert_eq!(show("(2/3)^-2"), "9/4");
+    assert_eq!(eval_exact("1/3 + 1/6 == 1/2").unwrap(), Value::Bool(true));
+}
+
+#[test]
+fn big_integers() {
+    assert_eq!(show("2^100"), "1267650600228229401496703205376");
+    assert_eq!(show("2^64 - 1 + 1 - 2^64"), "0");
+    assert_eq!(
+        show("(10^18 + 1) * (10^18 - 1)"),
+        "999999999999999999999999999999999999"
+    );
+}
+
+#[test]
+fn conversions() {
+    assert_eq!(show("decimal(1/3, 5)"), "0.33333");
+    assert_eq!(show("decimal(2/3)"), "0.6666666667");
+    assert_eq!(eval_exact("float(1/4)").unwrap(), Value::Num(0.25));
+    // Mixing with a float gives a float.
+    assert_eq!(eval_exact("1/2 + float(1/4)").unwrap(), Value::Num(0.75));
+    for source in &["decimal(1, 1.5)", "decimal(1, -1)", "decimal(1, 1001)"] {
+        match eval_exact(source) {
+            Err(MathParseError::NativeFunctionError(_, NativeError::InvalidArgument(1, _))) => {}
+            other => panic!("{}: {:?}", source, other),
+        }
+    }
+}
+
+#[test]
+fn division_by_zero() {
+    for source in &["1/0", "0^-1", "x = 0\n2/x"] {
+        match eval_exact(source) {
+            Err(MathParseError::DivisionByZero) => {}
+            other => panic!("{}: {:?}", source, other),
+        }
+    }
+}
+
+#[test]
+fn builtins() {
+    assert_eq!(show("sqrt(49/64)"), "7/8");
+    assert_eq!(eval_exact("sqrt(2)").unwrap(), Value::Num(2f64.sqrt()));
+    assert_eq!(show("abs(-7/2)"), "7/2");
+    assert_eq!(show("floor(-7/2)"), "-4");
+    assert_eq!(show("max(1/3, 1/4)"), "1/3");
+    assert_eq!(show("range(0, 1, 1/4)"), "[0, 1/4, 1/2, 3/4]");
+    assert_eq!(show("sum([1/2, 1/3, 1/6])"), "1");
+    assert_eq!(show("prod([2/3, 3/4])"), "1/2");
+}
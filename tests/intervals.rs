@@ -0,0 +1,65 @@
+extern crate raffa_parser;
+
+mod common;
+
+use common::eval;
+use raffa_parser::{MathParseError, NativeError, Value};
+
+fn num(source: &str) -> f64 {
+    match eval(source) {
+        Ok(Value::Num(x)) => x,
+        other => panic!("{}: {:?}", source, other),
+    }
+}
+
+#[test]
+fn intervals_enclose_the_result() {
+    assert_eq!(
+        eval("interval(1, 2) + interval(3, 4)").unwrap().to_string(),
+        "[4, 6]"
+    );
+    assert_eq!(
+        eval("interval(-1, 2) * interval(3, 4)")
+            .unwrap()
+            .to_string(),
+        "[-4, 8]"
+    );
+    assert_eq!(
+        eval("interval(1, 2) - interval(1, 2)").unwrap().to_string(),
+        "[-1, 1]"
+    );
+    // A third has no exact float, so it is rounded outward.
+    assert!(num("x = interval(1) / 3\nupper(x) - lower(x)") > 0.0);
+    assert!(num("x = interval(1) / 3\nlower(x) * 3") <= 1.0);
+    assert!(num("x = interval(1) / 3\nupper(x) * 3") >= 1.0);
+    // Builtins take intervals too.
+    assert_eq!(eval("sqrt(interval(4, 9))").unwrap().to_string(), "[2, 3]");
+    assert_eq!(num("lower(interval(2, 3) ^ 2)"), 4.0);
+}
+
+#[test]
+fn interval_errors() {
+    match eval("interval(2, 1)") {
+        Err(MathParseError::NativeFunctionError(_, NativeError::InvalidArgument(1, _))) => {}
+        other => panic!("{:?}", other),
+    }
+    match eval("interval(\"a\")") {
+        Err(MathParseError::NativeFunctionError(_, NativeError::InvalidArgument(0, _))) => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn uncertainty_propagates() {
+    assert_eq!(num("nominal(2 ± 0.1)"), 2.0);
+    assert_eq!(num("uncertainty(2 ± 0.1)"), 0.1);
+    // `±` binds more loosely than `+`.
+    assert_eq!(num("nominal(1 + 2 ± 0.5)"), 3.0);
+    // Independent errors add in quadrature.
+    assert!((num("uncertainty((1 ± 0.3) + (2 ± 0.4))") - 0.5).abs() < 1e-12);
+    // Correlated terms are kept track of, so x - x is exact.
+    assert_eq!(num("x = 5 ± 0.2\nuncertainty(x - x)"), 0.0);
+    assert!((num("x = 5 ± 0.2\nuncertainty(2 * x)") - 0.4).abs() < 1e-12);
+    assert!((num("uncertainty(sqrt(4 ± 0.4))") - 0.1).abs() < 1e-12);
+    assert_eq!(eval("1.234 ± 0.05").unwrap().to_string(), "1.23 ± 0.05");
+}
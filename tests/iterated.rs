@@ -0,0 +1,95 @@
+extern crate raffa_parser;
+
+mod common;
+
+use common::{eval, run};
+use raffa_parser::{
+    parse_file, parse_repl, Arity, CompiledExpr, EvalContext, MathParseError, NativeError, Value,
+};
+
+fn num(source: &str) -> f64 {
+    match eval(source) {
+        Ok(Value::Num(x)) => x,
+        other => panic!("{}: {:?}", source, other),
+    }
+}
+
+#[test]
+fn empty_ranges() {
+    assert_eq!(num("sum(i, 3, 1, i)"), 0.0);
+    assert_eq!(num("prod(i, 3, 1, i)"), 1.0);
+    assert_eq!(
+        run("print(sum(i, 3, 1, i))\nprint(prod(i, 3, 1, i))").unwrap(),
+        "0\n1\n"
+    );
+}
+
+#[test]
+fn index_is_local_to_the_term() {
+    assert_eq!(num("i = 7\nsum(i, 1, 3, i) + i"), 13.0);
+    assert_eq!(run("i = 7\nprint(sum(i, 1, 3, i) + i)").unwrap(), "13\n");
+    match eval("sum(j, 1, 3, j)\nj") {
+        Err(MathParseError::UnknownIdentifier(ref name)) if name == "j" => {}
+        other => panic!("{:?}", other),
+    }
+    // The bounds are outside the term, so they see the outer variable.
+    assert_eq!(num("i = 2\nsum(i, i, 2 * i, i)"), 9.0);
+}
+
+#[test]
+fn non_integer_bounds() {
+    // The index steps by 1 from the lower bound, up to the upper one.
+    assert_eq!(num("sum(i, 0.5, 3, i)"), 4.5);
+    assert_eq!(num("sum(i, 1, 3.9, i)"), 6.0);
+    assert_eq!(run("print(sum(i, 0.5, 3, i))").unwrap(), "4.5\n");
+    for source in &["sum(i, 1, 1/0, i)", "prod(i, 0 - 1/0, 1, i)"] {
+        match eval(source) {
+            Err(MathParseError::TypeError(_)) => {}
+            other => panic!("{}: {:?}", source, other),
+        }
+    }
+}
+
+#[test]
+fn too_many_terms() {
+    let source = "sum(i, 0, 10^9, i)";
+    match eval(source) {
+        Err(MathParseError::NativeFunctionError(ref name, NativeError::InvalidArgument(2, _)))
+            if name == "sum" => {}
+        other => panic!("{:?}", other),
+    }
+    match run(&format!("print({})", source)) {
+        Err(MathParseError::NativeFunctionError(ref name, _)) if name == "sum" => {}
+        other => panic!("{:?}", other),
+    }
+    let ctx = EvalContext::new();
+    let compiled = CompiledExpr::<f64>::parse(&ctx, "prod(k, 1, x, 1)", &["x"]).unwrap();
+    match compiled.try_eval(&[1e9]) {
+        Err(MathParseError::NativeFunctionError(ref name, _)) if name == "prod" => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn functions_hide_the_notation() {
+    let def = "def sum(a, b, c, d) { a + b + c + d }\ni = 10\n";
+    assert_eq!(num(&format!("{}sum(i, 1, 2, 3)", def)), 16.0);
+    assert_eq!(
+        run(&format!("{}print(sum(i, 1, 2, 3))", def)).unwrap(),
+        "16\n"
+    );
+
+    let mut ctx = EvalContext::new();
+    ctx.register_fn("prod", Arity::Fixed(4), |args| Ok(args[0] - args[3]));
+    ctx.set_var("x", 5.0);
+    let tree = parse_repl("prod(x, 1, 2, 3)").unwrap();
+    assert_eq!(ctx.eval_repltree(&tree).unwrap(), Value::Num(2.0));
+    let compiled = CompiledExpr::parse(&ctx, "prod(x, 1, 2, 3)", &["x"]).unwrap();
+    assert_eq!(compiled.eval(&[5.0]), 2.0);
+    // Without a function of that name, it is the notation again.
+    let block = parse_file("prod(x, 1, 2, 3)").unwrap();
+    assert_eq!(
+        EvalContext::new().eval_file(&block).unwrap(),
+        Value::Num(9.0)
+    );
+}
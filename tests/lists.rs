@@ -0,0 +1,91 @@
+extern crate raffa_parser;
+
+mod common;
+
+use common::{eval, run};
+use raffa_parser::{MathParseError, Value};
+
+fn list(xs: &[f64]) -> Value {
+    Value::from(xs.iter().map(|&x| Value::Num(x)).collect::<Vec<_>>())
+}
+
+fn with_xs(expr: &str) -> Result<Value, MathParseError> {
+    eval(&format!("xs = [10, 20, 30, 40]\n{}", expr))
+}
+
+#[test]
+fn literals() {
+    assert_eq!(eval("[1, 1 + 1, 3]").unwrap(), list(&[1.0, 2.0, 3.0]));
+    assert_eq!(eval("[]").unwrap(), list(&[]));
+    assert_eq!(
+        eval("[[1], true]").unwrap(),
+        Value::from(vec![list(&[1.0]), Value::Bool(true)])
+    );
+    assert_eq!(eval("[1, 2] + [3]").unwrap(), list(&[1.0, 2.0, 3.0]));
+    assert_eq!(eval("[1, 2] == [1, 2]").unwrap(), Value::Bool(true));
+    assert_eq!(eval("[1, [2, 3]]").unwrap().to_string(), "[1, [2, 3]]");
+}
+
+#[test]
+fn indexing() {
+    assert_eq!(with_xs("xs[0]").unwrap(), Value::Num(10.0));
+    assert_eq!(with_xs("xs[3]").unwrap(), Value::Num(40.0));
+    assert_eq!(with_xs("xs[-1]").unwrap(), Value::Num(40.0));
+    assert_eq!(with_xs("xs[-4]").unwrap(), Value::Num(10.0));
+    assert_eq!(eval("[[1, 2], [3, 4]][1][0]").unwrap(), Value::Num(3.0));
+    for index in &["4", "-5"] {
+        match with_xs(&format!("xs[{}]", index)) {
+            Err(MathParseError::IndexOutOfBounds(_, 4)) => {}
+            other => panic!("xs[{}]: {:?}", index, other),
+        }
+    }
+    for source in &["xs[0.5]", "xs[true]", "xs[0][0]"] {
+        match with_xs(source) {
+            Err(MathParseError::TypeError(_)) => {}
+            other => panic!("{}: {:?}", source, other),
+        }
+    }
+}
+
+#[test]
+fn slicing() {
+    assert_eq!(with_xs("xs[1:3]").unwrap(), list(&[20.0, 30.0]));
+    assert_eq!(with_xs("xs[:2]").unwrap(), list(&[10.0, 20.0]));
+    assert_eq!(with_xs("xs[2:]").unwrap(), list(&[30.0, 40.0]));
+    assert_eq!(with_xs("xs[:]").unwrap(), list(&[10.0, 20.0, 30.0, 40.0]));
+    assert_eq!(with_xs("xs[-2:]").unwrap(), list(&[30.0, 40.0]));
+    assert_eq!(with_xs("xs[1:-1]").unwrap(), list(&[20.0, 30.0]));
+    assert_eq!(with_xs("xs[3:1]").unwrap(), list(&[]));
+}
+
+#[test]
+fn slice_bounds_are_clamped() {
+    assert_eq!(with_xs("xs[1:10]").unwrap(), list(&[20.0, 30.0, 40.0]));
+    assert_eq!(with_xs("xs[-10:2]").unwrap(), list(&[10.0, 20.0]));
+    assert_eq!(with_xs("xs[5:]").unwrap(), list(&[]));
+    assert_eq!(with_xs("xs[:-9]").unwrap(), list(&[]));
+    match with_xs("xs[0.5:]") {
+        Err(MathParseError::TypeError(_)) => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn len() {
+    assert_eq!(with_xs("len(xs)").unwrap(), Value::Num(4.0));
+    assert_eq!(eval("len([])").unwrap(), Value::Num(0.0));
+    match eval("len(3)") {
+        Err(MathParseError::NativeFunctionError(ref name, _)) if name == "len" => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn compilers_reject_lists() {
+    for source in &["x = [1]", "print([1, 2][0])"] {
+        match run(source) {
+            Err(MathParseError::UnsupportedByCompiler(_)) => {}
+            other => panic!("{}: {:?}", source, other),
+        }
+    }
+}
@@ -0,0 +1,86 @@
+extern crate raffa_parser;
+
+mod common;
+
+use common::eval;
+use raffa_parser::Value;
+
+fn num(source: &str) -> f64 {
+    match eval(source) {
+        Ok(Value::Num(x)) => x,
+        other => panic!("{}: {:?}", source, other),
+    }
+}
+
+#[test]
+fn integrate_tolerance() {
+    for source in &[
+        "integrate(sin, 0, pi, 0.001)",
+        "integrate(sin, 0, pi, {tol: 0.001})",
+        "integrate(sin, 0, pi)",
+    ] {
+        assert!((num(source) - 2.0).abs() < 1e-3, "{}", source);
+    }
+    match eval("integrate(sin, 0, pi, {error: true})").unwrap() {
+        Value::Tuple(ref items) => assert!(items[1].as_num().unwrap() < 1e-9),
+        other => panic!("expected a tuple, got {}", other),
+    }
+    for source in &[
+        "integrate(sin, 0, pi, 0)",
+        "integrate(sin, 0, pi, 0.1, 0.1)",
+        "integrate(sin, 0, pi, true)",
+    ] {
+        assert!(eval(source).is_err(), "{}", source);
+    }
+}
+
+#[test]
+fn odesolve_tolerance() {
+    for call in &[
+        "odesolve(f, 0, 1, [1], 0.000001)",
+        "odesolve(f, 0, 1, [1], {tol: 0.000001})",
+        "odesolve(f, 0, 1, 1, 0.000001)",
+    ] {
+        let source = format!(
+            "def f(t, y) {{ -y }}\nrows = {}\nrows[len(rows) - 1][1]",
+            call
+        );
+        let y = num(&source);
+        assert!((y - (-1f64).exp()).abs() < 1e-5, "{} gives {}", call, y);
+    }
+    assert!(eval("def f(t, y) { -y }\nodesolve(f, 0, 1, 1, -1)").is_err());
+}
+
+#[test]
+fn minimize_tolerance() {
+    let f = "def f(x) { (x - 3)^2 }\n";
+    for call in &[
+        "minimize(f, 2, 4, {tol: 0.001})",
+        "minimize(f, 0, {tol: 0.001})",
+        "minimize(f, 0)",
+    ] {
+        let x = num(&format!("{}{}[0]", f, call));
+        assert!((x - 3.0).abs() < 1e-3, "{} gives {}", call, x);
+    }
+    // A third number is the end of a bracket, never a tolerance.
+    let x = num(&format!("{}minimize(f, 0, 0.001)[0]", f));
+    assert!((0.0..=0.001).contains(&x), "{}", x);
+    assert!(eval(&format!("{}minimize(f, 2, 4, 0.001)", f)).is_err());
+
+    let g = "def g(x, y) { (x - 1)^2 + (y + 2)^2 }\n";
+    for call in &[
+        "minimize(g, [0, 0], 0.000001)",
+        "minimize(g, [0, 0], {tol: 0.000001})",
+    ] {
+        let x = num(&format!("{}{}[0][0]", g, call));
+        let y = num(&format!("{}{}[0][1]", g, call));
+        assert!(
+            (x - 1.0).abs() < 1e-4 && (y + 2.0).abs() < 1e-4,
+            "{} gives {}, {}",
+            call,
+            x,
+            y
+        );
+    }
+    assert!(eval(&format!("{}minimize(g, [0, 0], 0)", g)).is_err());
+}
@@ -0,0 +1,86 @@
+extern crate raffa_parser;
+
+mod common;
+
+use common::{eval, run};
+use raffa_parser::{MathParseError, Value};
+
+fn with_point(expr: &str) -> Result<Value, MathParseError> {
+    eval(&format!("p = {{x: 1, y: {{z: 2}}}}\n{}", expr))
+}
+
+#[test]
+fn literals_and_fields() {
+    assert_eq!(with_point("p.x + p.y.z").unwrap(), Value::Num(3.0));
+    assert_eq!(with_point("p[\"x\"]").unwrap(), Value::Num(1.0));
+    assert_eq!(with_point("p").unwrap().to_string(), "{x: 1, y: {z: 2}}");
+    assert_eq!(eval("{}").unwrap().to_string(), "{}");
+    assert_eq!(
+        eval("{a: \"s\", b: [1]}").unwrap().to_string(),
+        "{a: \"s\", b: [1]}"
+    );
+    // Field order does not matter for equality.
+    assert_eq!(
+        eval("{a: 1, b: 2} == {b: 2, a: 1}").unwrap(),
+        Value::Bool(true)
+    );
+    assert_eq!(eval("{a: 1} == {a: 2}").unwrap(), Value::Bool(false));
+}
+
+#[test]
+fn field_update_copies() {
+    assert_eq!(
+        with_point("q = p\nq.x = 5\n[p.x, q.x]")
+            .unwrap()
+            .to_string(),
+        "[1, 5]"
+    );
+    assert_eq!(with_point("p.y.z = 3\np.y.z").unwrap(), Value::Num(3.0));
+    assert_eq!(
+        with_point("p.w = 4\np").unwrap().to_string(),
+        "{x: 1, y: {z: 2}, w: 4}"
+    );
+    assert_eq!(
+        with_point("p.y.w = 0\nkeys(p.y)").unwrap().to_string(),
+        "[\"z\", \"w\"]"
+    );
+}
+
+#[test]
+fn builtins() {
+    assert_eq!(with_point("keys(p)").unwrap().to_string(), "[\"x\", \"y\"]");
+    assert_eq!(with_point("len(p)").unwrap(), Value::Num(2.0));
+    assert_eq!(with_point("has(p, \"y\")").unwrap(), Value::Bool(true));
+    assert_eq!(with_point("has(p, \"w\")").unwrap(), Value::Bool(false));
+    match with_point("has(p, 1)") {
+        Err(MathParseError::NativeFunctionError(ref name, _)) if name == "has" => {}
+        other => panic!("{:?}", other),
+    }
+    match eval("keys([1])") {
+        Err(MathParseError::NativeFunctionError(ref name, _)) if name == "keys" => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn errors() {
+    match with_point("p.w") {
+        Err(MathParseError::NoSuchField(ref name)) if name == "w" => {}
+        other => panic!("{:?}", other),
+    }
+    // Only the last field in an update path may be missing.
+    match with_point("p.w.v = 1") {
+        Err(MathParseError::NoSuchField(ref name)) if name == "w" => {}
+        other => panic!("{:?}", other),
+    }
+    for source in &["x = 1\nx.y", "x = 1\nx.y = 2", "p.x.z = 1"] {
+        match with_point(source) {
+            Err(MathParseError::TypeError(_)) => {}
+            other => panic!("{}: {:?}", source, other),
+        }
+    }
+    match run("p = {x: 1}") {
+        Err(MathParseError::UnsupportedByCompiler(_)) => {}
+        other => panic!("{:?}", other),
+    }
+}
@@ -0,0 +1,111 @@
+extern crate raffa_parser;
+
+mod common;
+
+use common::eval;
+use raffa_parser::{MathParseError, NativeError, Value};
+
+fn list(xs: &[f64]) -> Value {
+    Value::from(xs.iter().map(|&x| Value::Num(x)).collect::<Vec<_>>())
+}
+
+fn invalid_argument(source: &str, name: &str, index: usize) {
+    match eval(source) {
+        Err(MathParseError::NativeFunctionError(ref f, NativeError::InvalidArgument(i, _)))
+            if f == name && i == index => {}
+        other => panic!("{}: {:?}", source, other),
+    }
+}
+
+#[test]
+fn range() {
+    assert_eq!(eval("range(4)").unwrap(), list(&[0.0, 1.0, 2.0, 3.0]));
+    assert_eq!(eval("range(2, 5)").unwrap(), list(&[2.0, 3.0, 4.0]));
+    assert_eq!(
+        eval("range(0, 1, 0.25)").unwrap(),
+        list(&[0.0, 0.25, 0.5, 0.75])
+    );
+    assert_eq!(eval("range(3, 0, -1)").unwrap(), list(&[3.0, 2.0, 1.0]));
+    assert_eq!(eval("range(5, 2)").unwrap(), list(&[]));
+    assert_eq!(eval("range(-2)").unwrap(), list(&[]));
+}
+
+#[test]
+fn range_errors_name_the_argument() {
+    invalid_argument("range(1 / 0)", "range", 0);
+    invalid_argument("range(1 / 0, 2)", "range", 0);
+    invalid_argument("range(0, 1 / 0)", "range", 1);
+    invalid_argument("range(0, 1, 0)", "range", 2);
+    invalid_argument("range(0, true)", "range", 1);
+    // A range that is too long blames its end, or its step if it has one.
+    invalid_argument("range(10 ^ 18)", "range", 0);
+    invalid_argument("range(0, 10 ^ 18)", "range", 1);
+    invalid_argument("range(0, 1, 10 ^ -18)", "range", 2);
+    assert_eq!(
+        eval("len(range(16777216))").unwrap(),
+        Value::Num(16777216.0)
+    );
+}
+
+#[test]
+fn map_filter_reduce() {
+    let prelude = "def sq(x) { x * x }
+        def odd(x) { x - 2 * floor(x / 2) == 1 }
+        def add(a, b) { a + b }
+        xs = range(1, 6)\n";
+    let eval = |expr: &str| eval(&format!("{}{}", prelude, expr)).unwrap();
+    assert_eq!(eval("map(sq, xs)"), list(&[1.0, 4.0, 9.0, 16.0, 25.0]));
+    assert_eq!(eval("map(sqrt, [4, 9])"), list(&[2.0, 3.0]));
+    assert_eq!(eval("filter(odd, xs)"), list(&[1.0, 3.0, 5.0]));
+    assert_eq!(eval("reduce(add, 100, xs)"), Value::Num(115.0));
+    assert_eq!(eval("reduce(add, 100, [])"), Value::Num(100.0));
+    assert_eq!(eval("sum(map(sq, xs))"), Value::Num(55.0));
+    assert_eq!(eval("prod(xs)"), Value::Num(120.0));
+    assert_eq!(eval("sum([]) + prod([])"), Value::Num(1.0));
+    assert_eq!(eval("any(odd, [2, 4, 5])"), Value::Bool(true));
+    assert_eq!(eval("all(odd, [1, 2])"), Value::Bool(false));
+    assert_eq!(eval("any([])"), Value::Bool(false));
+    assert_eq!(eval("all([])"), Value::Bool(true));
+}
+
+#[test]
+fn higher_order_errors() {
+    invalid_argument("map(1, [1])", "map", 0);
+    invalid_argument("map(sin, 1)", "map", 1);
+    invalid_argument("reduce(max, 0, 5)", "reduce", 2);
+    match eval("def f(x) { x }\nfilter(f, [1])") {
+        Err(MathParseError::TypeError(_)) => {}
+        other => panic!("{:?}", other),
+    }
+    // Errors raised inside the function are passed on unchanged.
+    match eval("def f(x) { y }\nmap(f, [1])") {
+        Err(MathParseError::UnknownIdentifier(ref name)) if name == "y" => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn zip_and_sort() {
+    assert_eq!(
+        eval("zip([1, 2, 3], [4, 5])").unwrap(),
+        Value::from(vec![list(&[1.0, 4.0]), list(&[2.0, 5.0])])
+    );
+    assert_eq!(eval("sort([3, 1, 2])").unwrap(), list(&[1.0, 2.0, 3.0]));
+    assert_eq!(
+        eval("def neg(x) { -x }\nsort([3, 1, 2], neg)").unwrap(),
+        list(&[3.0, 2.0, 1.0])
+    );
+    // The sort is stable.
+    assert_eq!(
+        eval("def first(p) { p[0] }\nsort([[2, 1], [1, 2], [2, 3]], first)").unwrap(),
+        Value::from(vec![
+            list(&[1.0, 2.0]),
+            list(&[2.0, 1.0]),
+            list(&[2.0, 3.0]),
+        ])
+    );
+    match eval("sort([1, true])") {
+        Err(MathParseError::TypeError(_)) => {}
+        other => panic!("{:?}", other),
+    }
+}
@@ -0,0 +1,81 @@
+extern crate raffa_parser;
+
+mod common;
+
+use common::eval;
+use raffa_parser::{MathParseError, NativeError, Value};
+
+fn simplify(formula: &str) -> String {
+    match eval(&format!("simplify(\"{}\")", formula)) {
+        Ok(Value::Str(s)) => s.to_string(),
+        other => panic!("{}: {:?}", formula, other),
+    }
+}
+
+#[test]
+fn constants_are_folded_exactly() {
+    assert_eq!(simplify("sqrt(4) + 2^10"), "1026");
+    assert_eq!(simplify("1/3 + 1/6 + x"), "x + 0.5");
+    assert_eq!(simplify("x / 2 + x / 3"), "5 * x / 6");
+    assert_eq!(simplify("-x / 3"), "-x / 3");
+    assert_eq!(simplify("(2 * x)^3"), "8 * x^3");
+    // A division by zero is left as it is.
+    assert_eq!(simplify("1 / 0"), "1 / 0");
+}
+
+#[test]
+fn identities_and_annihilators() {
+    assert_eq!(simplify("x * 1 + 0"), "x");
+    assert_eq!(simplify("x^1 - 0"), "x");
+    assert_eq!(simplify("x^0"), "1");
+    assert_eq!(simplify("0 * x"), "0");
+    assert_eq!(simplify("0 * sin(x + 1)"), "0");
+    assert_eq!(simplify("x - x"), "0");
+}
+
+#[test]
+fn undefined_points_are_kept() {
+    assert_eq!(simplify("0 * (1 / 0)"), "0 * (1 / 0)");
+    assert_eq!(simplify("0 * (1 / x)"), "0 / x");
+    assert_eq!(simplify("0 * ln(x)"), "0 * ln(x)");
+    assert_eq!(simplify("1 / x - 1 / x"), "0 / x");
+    assert_eq!(simplify("x / x"), "x / x");
+    assert_eq!(simplify("x^2 / x"), "x^2 / x");
+    assert_eq!(simplify("x / y * y"), "x * y / y");
+    assert_eq!(simplify("(x^-1)^-1"), "1 / (1 / x)");
+}
+
+#[test]
+fn like_terms_are_collected() {
+    assert_eq!(simplify("x + 2 * x"), "3 * x");
+    assert_eq!(simplify("x * y + 2 * y * x - x * y"), "2 * x * y");
+    assert_eq!(simplify("2 * (x + 1)"), "2 * x + 2");
+    assert_eq!(simplify("x - 2 * y / 3"), "x - 2 * y / 3");
+}
+
+#[test]
+fn powers_are_merged() {
+    assert_eq!(simplify("x * x^2"), "x^3");
+    assert_eq!(simplify("(x + 1) * (x + 1)"), "(x + 1)^2");
+    assert_eq!(simplify("1 / x * x^-2"), "1 / x^3");
+    assert_eq!(simplify("(x^2)^-1"), "1 / x^2");
+    // Symbolic exponents are not merged.
+    assert_eq!(simplify("x^y * x^z"), "x^y * x^z");
+}
+
+#[test]
+fn canonical_order() {
+    assert_eq!(simplify("b * a * c"), "a * b * c");
+    assert_eq!(simplify("3 + y * x + x^2"), "x * y + x^2 + 3");
+    assert_eq!(simplify("1 + x + x^3"), simplify("x^3 + 1 + x"));
+}
+
+#[test]
+fn errors() {
+    for source in &["simplify(1)", "simplify(\"x +\")", "simplify(\"\")"] {
+        match eval(source) {
+            Err(MathParseError::NativeFunctionError(_, NativeError::InvalidArgument(0, _))) => {}
+            other => panic!("{}: {:?}", source, other),
+        }
+    }
+}
@@ -0,0 +1,86 @@
+extern crate raffa_parser;
+
+mod common;
+
+use common::eval;
+use raffa_parser::{MathParseError, NativeError, Value};
+
+fn num(source: &str) -> f64 {
+    match eval(source) {
+        Ok(Value::Num(x)) => x,
+        other => panic!("{}: {:?}", source, other),
+    }
+}
+
+const F: &str = "def f(x) { x^2 - 2 }\n";
+
+#[test]
+fn from_a_guess() {
+    assert!((num(&format!("{}solve(f, 1)", F)) - 2f64.sqrt()).abs() < 1e-12);
+    assert!((num(&format!("{}solve(f, -1)", F)) + 2f64.sqrt()).abs() < 1e-12);
+    assert!((num("solve(cos, 1)") - std::f64::consts::FRAC_PI_2).abs() < 1e-12);
+}
+
+#[test]
+fn within_a_bracket() {
+    assert!((num(&format!("{}solve(f, 0, 2)", F)) - 2f64.sqrt()).abs() < 1e-12);
+    match eval(&format!("{}solve(f, 2, 3)", F)) {
+        Err(MathParseError::NotBracketed(a, b)) => assert_eq!((a, b), (2.0, 3.0)),
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn options() {
+    let loose = num(&format!("{}solve(f, 0, 2, {{tol: 0.01}})", F));
+    assert!((loose - 2f64.sqrt()).abs() < 0.01);
+    assert!((num(&format!("{}solve(f, 1, {{maxiter: 20}})", F)) - 2f64.sqrt()).abs() < 1e-12);
+    for (options, msg) in &[
+        ("{tol: 0}", "tol must be positive"),
+        ("{maxiter: 1.5}", "maxiter must be a positive integer"),
+        ("{foo: 1}", "unknown option 'foo'"),
+    ] {
+        match eval(&format!("{}solve(f, 1, {})", F, options)) {
+            Err(MathParseError::NativeFunctionError(_, NativeError::InvalidArgument(2, ref m)))
+                if m == msg => {}
+            other => panic!("{}: {:?}", options, other),
+        }
+    }
+}
+
+#[test]
+fn no_root() {
+    for source in &[
+        "def g(x) { x^2 + 1 }\nsolve(g, 1)",
+        "def f(x) { x^2 - 2 }\nsolve(f, 1, {maxiter: 1})",
+    ] {
+        match eval(source) {
+            Err(MathParseError::DidNotConverge(_)) => {}
+            other => panic!("{}: {:?}", source, other),
+        }
+    }
+}
+
+#[test]
+fn equations() {
+    assert!((num("solve x^2 = 2 for x") - 2f64.sqrt()).abs() < 1e-12);
+    assert_eq!(num("solve -x = 2 for x"), -2.0);
+    assert_eq!(num("solve 3 * y + 1 = 7 for y"), 2.0);
+    // The variable's value is the starting guess, and is kept afterwards.
+    assert_eq!(num("x = 2\nsolve (x - 1)^2 = 4 for x"), 3.0);
+    assert_eq!(num("x = -2\nsolve (x - 1)^2 = 4 for x"), -1.0);
+    assert_eq!(num("x = 5\nsolve 2 * x = 3 for x\nx"), 5.0);
+    // Other names are read from the context.
+    assert_eq!(num("a = 4\nsolve t * a = 2 for t"), 0.5);
+}
+
+#[test]
+fn solve_is_still_a_function() {
+    assert_eq!(num("def f(x) { x - 1 }\nsolve(f, 2)"), 1.0);
+    assert_eq!(num("def f(x) { x - 1 }\nsolve (f, 2)"), 1.0);
+    assert_eq!(
+        num("def f(x) { x - 3 }\ndef g(h) { h(f, 0) }\ng(solve)"),
+        3.0
+    );
+    assert_eq!(num("s = solve\ndef f(x) { x - 1 }\ns(f, 0)"), 1.0);
+}
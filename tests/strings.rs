@@ -0,0 +1,80 @@
+extern crate raffa_parser;
+
+mod common;
+
+use common::{eval, run};
+use raffa_parser::{MathParseError, NativeError, Value};
+
+/// Checks that the VM prints what the evaluator formats.
+fn assert_agree(fmt_args: &str) {
+    let expected = eval(&format!("format({})", fmt_args)).unwrap();
+    let printed = run(&format!("print({})", fmt_args)).unwrap();
+    assert_eq!(printed, format!("{}\n", expected), "{}", fmt_args);
+}
+
+#[test]
+fn literals_and_concatenation() {
+    assert_eq!(
+        eval("\"a\\tb\\n\\\"c\\\"\\\\\"").unwrap(),
+        Value::from("a\tb\n\"c\"\\")
+    );
+    assert_eq!(
+        eval("s = \"ab\"\ns + \"cd\" + s").unwrap(),
+        Value::from("abcdab")
+    );
+    assert_eq!(eval("\"ab\" == \"a\" + \"b\"").unwrap(), Value::Bool(true));
+    assert_eq!(eval("len(\"héllo\")").unwrap(), Value::Num(5.0));
+    assert_eq!(eval("[\"a\", 1]").unwrap().to_string(), "[\"a\", 1]");
+    match eval("\"a\" * 2") {
+        Err(MathParseError::TypeError(_)) => {}
+        other => panic!("{:?}", other),
+    }
+    match eval("\"a\" + 1") {
+        Err(MathParseError::TypeError(_)) => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn format() {
+    assert_eq!(
+        eval("format(\"{} is {:.2}\", \"pi\", pi)").unwrap(),
+        Value::from("pi is 3.14")
+    );
+    match eval("format(\"{} {}\", 1)") {
+        Err(MathParseError::NativeFunctionError(ref name, NativeError::InvalidArgument(0, _)))
+            if name == "format" => {}
+        other => panic!("{:?}", other),
+    }
+    match eval("format(1)") {
+        Err(MathParseError::NativeFunctionError(..)) => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn vm_agrees_with_evaluator() {
+    assert_agree("\"{} is {:.2}\", \"pi\", pi");
+    assert_agree("\"{:e} {{}}\", 1500");
+    assert_agree("\"{}\", \"ab\" + \"cd\"");
+    assert_eq!(
+        run("s = \"n\"\nprint(s + \"=\")\nprint(len(s + \"ab\"))").unwrap(),
+        "n=\n3\n"
+    );
+    assert_eq!(run("print(format(\"{:.1}\", 2.25))").unwrap(), "2.2\n");
+}
+
+#[test]
+fn vm_errors() {
+    // Format errors are found when the program runs.
+    match run("print(\"{} {}\", 1)") {
+        Err(MathParseError::NativeFunctionError(ref name, _)) if name == "format" => {}
+        other => panic!("{:?}", other),
+    }
+    for source in &["x = 1\nx = \"a\"", "print(\"a\" * 2)", "print(1, 2)"] {
+        match run(source) {
+            Err(MathParseError::UnsupportedByCompiler(_)) | Err(MathParseError::TypeError(_)) => {}
+            other => panic!("{}: {:?}", source, other),
+        }
+    }
+}
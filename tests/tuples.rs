@@ -0,0 +1,60 @@
+extern crate raffa_parser;
+
+mod common;
+
+use common::eval;
+use raffa_parser::{MathParseError, NativeError, Value};
+
+fn show(source: &str) -> String {
+    eval(source).unwrap().to_string()
+}
+
+#[test]
+fn literals() {
+    assert_eq!(show("(1, \"a\")"), "(1, \"a\")");
+    assert_eq!(show("(1,)"), "(1,)");
+    assert_eq!(show("()"), "()");
+    assert_eq!(show("(1, 2,)"), "(1, 2)");
+    // Parentheses alone do not make a tuple.
+    assert_eq!(eval("(1)").unwrap(), Value::Num(1.0));
+    assert_eq!(eval("(1, 2) == (1, 2)").unwrap(), Value::Bool(true));
+    assert_eq!(eval("(1, 2) == [1, 2]").unwrap(), Value::Bool(false));
+    assert_eq!(
+        eval("t = (4, 5, 6)\nt[-1] + len(t)").unwrap(),
+        Value::Num(9.0)
+    );
+    assert_eq!(show("(4, 5, 6)[1:]"), "(5, 6)");
+    assert_eq!(show("sum((1, 2, 3))"), "6");
+}
+
+#[test]
+fn destructuring() {
+    assert_eq!(show("(a, b) = (1, 2)\n[b, a]"), "[2, 1]");
+    assert_eq!(show("[a, (b, c)] = [1, (2, 3)]\na + b * c"), "7");
+    assert_eq!(show("(q, r) = divmod(17, 5)\n[q, r]"), "[3, 2]");
+    // The assignment gives the whole value.
+    assert_eq!(show("x = (a, b) = (1, 2)\nx"), "(1, 2)");
+    for source in &["(a, b) = (1, 2, 3)", "(a, b) = 1", "[a] = []"] {
+        match eval(source) {
+            Err(MathParseError::TypeError(_)) => {}
+            other => panic!("{}: {:?}", source, other),
+        }
+    }
+}
+
+#[test]
+fn divmod_follows_the_divisor_sign() {
+    assert_eq!(show("divmod(7, 2)"), "(3, 1)");
+    assert_eq!(show("divmod(-7, 2)"), "(-4, 1)");
+    assert_eq!(show("divmod(7, -2)"), "(-4, -1)");
+    assert_eq!(show("divmod(-7, -2)"), "(3, -1)");
+    assert_eq!(show("divmod(6, -3)"), "(-2, 0)");
+    assert_eq!(show("divmod(7.5, 2)"), "(3, 1.5)");
+    // For every sign, q * b + r == a.
+    assert_eq!(show("(q, r) = divmod(-13, 4)\nq * 4 + r"), "-13");
+    match eval("divmod(1, \"a\")") {
+        Err(MathParseError::NativeFunctionError(ref name, NativeError::InvalidArgument(1, _)))
+            if name == "divmod" => {}
+        other => panic!("{:?}", other),
+    }
+}
@@ -0,0 +1,69 @@
+extern crate raffa_parser;
+
+mod common;
+
+use common::eval;
+use raffa_parser::{parse_repl_with_vars, EvalContext, MathParseError, Value};
+
+fn show(source: &str) -> String {
+    eval(source).unwrap().to_string()
+}
+
+#[test]
+fn prefixes() {
+    assert_eq!(show("1.5 km to m"), "1500 m");
+    assert_eq!(show("250 mg to g"), "0.25 g");
+    assert_eq!(show("3 us to ns"), "3000 ns");
+    assert_eq!(show("2 MHz to kHz"), "2000 kHz");
+    assert_eq!(show("90 min to h"), "1.5 h");
+    // `h` takes no prefixes, so `mh` is not a unit.
+    match eval("1 mh") {
+        Err(MathParseError::UnknownIdentifier(ref name)) if name == "mh" => {}
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn derived_units() {
+    assert_eq!(show("2 kg * 3 m/s^2 to N"), "6 N");
+    assert_eq!(show("4 N * 2 m to J"), "8 J");
+    assert_eq!(show("10 J / 2 s to W"), "5 W");
+    assert_eq!(show("6 N / 2 m^2 to Pa"), "3 Pa");
+    assert_eq!(show("1 kW h to MJ"), "3.6 MJ");
+    assert_eq!(eval("1 N == 1 kg m/s^2").unwrap(), Value::Bool(true));
+}
+
+#[test]
+fn conversion() {
+    assert_eq!(show("36 km/h to m/s"), "10 m/s");
+    assert_eq!(show("x = 3 mi\nx to ft"), "15840 ft");
+    assert_eq!(show("(2 m + 50 cm) to cm"), "250 cm");
+    // Units that cancel leave a plain number.
+    assert_eq!(eval("6 m / 3 m").unwrap(), Value::Num(2.0));
+}
+
+#[test]
+fn dimension_mismatch() {
+    for source in &["1 m + 1 s", "1 m to s", "1 kg < 1 m", "2 J to W"] {
+        match eval(source) {
+            Err(MathParseError::DimensionMismatch(_, _)) => {}
+            other => panic!("{}: {:?}", source, other),
+        }
+    }
+}
+
+#[test]
+fn variables_are_not_units() {
+    // Once `m` and `s` are defined, they are the variables, so a number
+    // followed by one of them is two expressions, as it was before units.
+    assert_eq!(eval("m = 4\nn = 3 2 m").unwrap(), Value::Num(4.0));
+    assert_eq!(eval("def f(s) { 2 s }\nf(5)").unwrap(), Value::Num(5.0));
+    assert_eq!(eval("(s, t) = (7, 1)\n3 s").unwrap(), Value::Num(7.0));
+    // Before it is defined, `m` is still a unit.
+    assert_eq!(show("x = 2 m\nm = 1\nx"), "2 m");
+
+    let mut ctx = EvalContext::new();
+    ctx.set_var("m", 4.0);
+    let vars = ctx.vars().map(|(name, _)| name).collect::<Vec<_>>();
+    assert!(parse_repl_with_vars("2 m", vars).is_err());
+}
@@ -0,0 +1,75 @@
+extern crate raffa_parser;
+
+mod common;
+
+use common::{eval, run};
+use raffa_parser::{CompiledExpr, EvalContext, MathParseError, Value};
+
+fn type_error(source: &str) {
+    match eval(source) {
+        Err(MathParseError::TypeError(_)) => {}
+        other => panic!("{}: {:?}", source, other),
+    }
+}
+
+#[test]
+fn comparisons() {
+    assert_eq!(eval("1 < 2").unwrap(), Value::Bool(true));
+    assert_eq!(eval("2 <= 2").unwrap(), Value::Bool(true));
+    assert_eq!(eval("1 + 1 > 3").unwrap(), Value::Bool(false));
+    assert_eq!(eval("3 >= 4").unwrap(), Value::Bool(false));
+    assert_eq!(eval("2 == 1 + 1").unwrap(), Value::Bool(true));
+    assert_eq!(eval("true != false").unwrap(), Value::Bool(true));
+    // Values of different types are never equal.
+    assert_eq!(eval("1 == true").unwrap(), Value::Bool(false));
+    type_error("true < false");
+    type_error("1 < 2 < 3");
+}
+
+#[test]
+fn unary_operators() {
+    assert_eq!(eval("!true").unwrap(), Value::Bool(false));
+    assert_eq!(eval("x = 3\ny = -x ^ 2").unwrap(), Value::Num(-9.0));
+    assert_eq!(eval("2 ^ -1").unwrap(), Value::Num(0.5));
+    type_error("!1");
+    type_error("-false");
+    type_error("true + 1");
+}
+
+#[test]
+fn if_expressions() {
+    let sign = "def sgn(x) {
+        if x > 0 { 1 } else if x < 0 { -1 } else { 0 }
+    }\n";
+    assert_eq!(eval(&format!("{}sgn(-5)", sign)).unwrap(), Value::Num(-1.0));
+    assert_eq!(eval(&format!("{}sgn(0)", sign)).unwrap(), Value::Num(0.0));
+    assert_eq!(eval(&format!("{}sgn(2)", sign)).unwrap(), Value::Num(1.0));
+    assert_eq!(eval("if false { 1 }").unwrap(), Value::Nil);
+    assert_eq!(
+        eval("y = if 1 < 2 { 10 } else { 20 }\ny + 1").unwrap(),
+        Value::Num(11.0)
+    );
+    type_error("if 1 { 2 }");
+}
+
+#[test]
+fn compiled_if() {
+    let ctx = EvalContext::new();
+    let abs = CompiledExpr::parse(&ctx, "if x < 0 { -x } else { x }", &["x"]).unwrap();
+    assert_eq!(abs.eval(&[-2.5]), 2.5);
+    assert_eq!(abs.eval(&[4.0]), 4.0);
+    let clip = CompiledExpr::parse(&ctx, "if !(x <= 1) { 1 } else { x }", &["x"]).unwrap();
+    assert_eq!(clip.eval(&[3.0]), 1.0);
+    assert_eq!(clip.eval(&[0.5]), 0.5);
+}
+
+#[test]
+fn vm_supports_negation_only() {
+    assert_eq!(run("x = 2\nprint(-x * 3)").unwrap(), "-6\n");
+    for source in &["print(1 < 2)", "if 1 < 2 { 1 }", "true"] {
+        match run(source) {
+            Err(MathParseError::UnsupportedByCompiler(_)) => {}
+            other => panic!("{}: {:?}", source, other),
+        }
+    }
+}